@@ -0,0 +1,210 @@
+use crate::expr::limits::LimitExceeded;
+use crate::lexer::Span;
+use crate::types::Type;
+use std::error::Error;
+use std::fmt;
+
+// Errors of reading and evaluating lisp. Failures without a kind of their own, including the
+// ones from values and types, are runtime errors. Evaluation errors get the span of the
+// innermost form they happened in, which is in the source the form was read from. For
+// functions defined by earlier evaluations that is not the source being evaluated.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DovahkiinError {
+    Lex {
+        message: String,
+        span: Span,
+    },
+    Parse {
+        message: String,
+        span: Span,
+    },
+    UnboundSymbol {
+        name: String,
+        span: Option<Span>,
+    },
+    Arity {
+        expected: Arity,
+        found: usize,
+        span: Option<Span>,
+    },
+    Type {
+        expected: Expected,
+        found: Type,
+        span: Option<Span>,
+    },
+    Runtime {
+        message: String,
+        span: Option<Span>,
+    },
+    Limit(LimitExceeded),
+}
+
+// Values expected where a type did not match, tensors and sketches have no `Type` of their own
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Expected {
+    Type(Type),
+    Tensor,
+    Sketch(&'static str),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arity {
+    Exactly(usize),
+    AtLeast(usize),
+    AtMost(usize),
+}
+
+impl DovahkiinError {
+    pub fn runtime<S: Into<String>>(message: S) -> Self {
+        DovahkiinError::Runtime {
+            message: message.into(),
+            span: None,
+        }
+    }
+
+    pub fn parse<S: Into<String>>(message: S, span: Span) -> Self {
+        DovahkiinError::Parse {
+            message: message.into(),
+            span,
+        }
+    }
+
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            DovahkiinError::Lex { span, .. } | DovahkiinError::Parse { span, .. } => Some(*span),
+            DovahkiinError::UnboundSymbol { span, .. }
+            | DovahkiinError::Arity { span, .. }
+            | DovahkiinError::Type { span, .. }
+            | DovahkiinError::Runtime { span, .. } => *span,
+            DovahkiinError::Limit(_) => None,
+        }
+    }
+
+    // Sets the span of the form the error happened in, unless a form inside it was set already
+    pub fn at(mut self, form: Option<Span>) -> Self {
+        match &mut self {
+            DovahkiinError::UnboundSymbol { span, .. }
+            | DovahkiinError::Arity { span, .. }
+            | DovahkiinError::Type { span, .. }
+            | DovahkiinError::Runtime { span, .. }
+                if span.is_none() =>
+            {
+                *span = form
+            }
+            _ => {}
+        }
+        self
+    }
+
+    // The error followed by its line of the source, with carets under the form
+    pub fn report(&self, source: &str) -> String {
+        let excerpt = self.span().and_then(|span| {
+            let before = source.get(..span.start)?;
+            let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+            let line_end = source[span.start..]
+                .find('\n')
+                .map_or(source.len(), |i| span.start + i);
+            let marked = source.get(span.start..span.end.clamp(span.start, line_end))?;
+            let number = span.line.to_string();
+            let gutter = " ".repeat(number.len());
+            Some(format!(
+                "{} |\n{} | {}\n{} | {}{}",
+                gutter,
+                number,
+                &source[line_start..line_end],
+                gutter,
+                " ".repeat(before[line_start..].chars().count()),
+                "^".repeat(marked.chars().count().max(1))
+            ))
+        });
+        match excerpt {
+            Some(excerpt) => format!("{}\n{}", self, excerpt),
+            None => self.to_string(),
+        }
+    }
+
+    fn write_message(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DovahkiinError::Lex { message, .. }
+            | DovahkiinError::Parse { message, .. }
+            | DovahkiinError::Runtime { message, .. } => f.write_str(message),
+            DovahkiinError::UnboundSymbol { name, .. } => {
+                write!(f, "Cannot find symbol '{}'", name)
+            }
+            DovahkiinError::Arity {
+                expected, found, ..
+            } => write!(
+                f,
+                "Parameter number not match, expected {} but found {}",
+                expected, found
+            ),
+            DovahkiinError::Type {
+                expected, found, ..
+            } => write!(f, "Type not match, expect {} found {:?}", expected, found),
+            DovahkiinError::Limit(limit) => write!(f, "{}", limit),
+        }
+    }
+}
+
+impl fmt::Display for Arity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Arity::Exactly(num) => write!(f, "{}", num),
+            Arity::AtLeast(num) => write!(f, "at least {}", num),
+            Arity::AtMost(num) => write!(f, "at most {}", num),
+        }
+    }
+}
+
+impl fmt::Display for Expected {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expected::Type(t) => write!(f, "{:?}", t),
+            Expected::Tensor => f.write_str("tensor"),
+            Expected::Sketch(kind) => write!(f, "{} sketch", kind),
+        }
+    }
+}
+
+impl From<Type> for Expected {
+    fn from(t: Type) -> Self {
+        Expected::Type(t)
+    }
+}
+
+impl fmt::Display for DovahkiinError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write_message(f)?;
+        match self.span() {
+            Some(span) => write!(f, " at line {}, column {}", span.line, span.column),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Error for DovahkiinError {}
+
+impl From<String> for DovahkiinError {
+    fn from(message: String) -> Self {
+        DovahkiinError::runtime(message)
+    }
+}
+
+impl From<&str> for DovahkiinError {
+    fn from(message: &str) -> Self {
+        DovahkiinError::runtime(message)
+    }
+}
+
+impl From<LimitExceeded> for DovahkiinError {
+    fn from(limit: LimitExceeded) -> Self {
+        DovahkiinError::Limit(limit)
+    }
+}
+
+// For schemas, formats and other code that reports errors as strings
+impl From<DovahkiinError> for String {
+    fn from(err: DovahkiinError) -> Self {
+        err.to_string()
+    }
+}
@@ -0,0 +1,234 @@
+use crate::expr::constraints::record_expr;
+use crate::expr::interpreter::Interpreter;
+use crate::expr::serde::Expr;
+use crate::expr::{SExpr, Value};
+use crate::types::*;
+use bifrost_hasher::hash_str;
+use std::collections::HashMap;
+
+// Fills the defaults and computed fields of records. Computed fields read the record through
+// keywords like constraints do, so they may depend on each other. They are ordered once
+// when the schema is compiled, and a cycle among them is an error of the schema.
+
+#[derive(Debug, Clone)]
+pub struct Materializer {
+    defaults: Vec<(String, OwnedValue)>,
+    // In dependency order
+    computed: Vec<(Field, Expr)>,
+}
+
+// Fields read by the expression, as `(get record :field)` after keywords are resolved
+fn dependencies(expr: &Expr, deps: &mut Vec<String>) {
+    if let Expr::List(exprs, _) = expr {
+        if let [Expr::Symbol(_, get), Expr::Symbol(_, record), Expr::Keyword(_, field)] =
+            exprs.as_slice()
+        {
+            if get == "get" && record == "record" {
+                deps.push(field.clone());
+                return;
+            }
+        }
+        exprs.iter().for_each(|e| dependencies(e, deps));
+    }
+}
+
+fn visit(
+    name: &str,
+    deps: &HashMap<String, Vec<String>>,
+    visiting: &mut Vec<String>,
+    order: &mut Vec<String>,
+) -> Result<(), String> {
+    if order.iter().any(|n| n == name) {
+        return Ok(());
+    }
+    if let Some(pos) = visiting.iter().position(|n| n == name) {
+        let mut cycle = visiting[pos..].to_vec();
+        cycle.push(name.to_string());
+        return Err(format!(
+            "Computed fields depend on each other: {}",
+            cycle.join(" -> ")
+        ));
+    }
+    visiting.push(name.to_string());
+    for dep in &deps[name] {
+        if deps.contains_key(dep) {
+            visit(dep, deps, visiting, order)?;
+        }
+    }
+    visiting.pop();
+    order.push(name.to_string());
+    Ok(())
+}
+
+impl Materializer {
+    pub fn new(schema: &Schema) -> Result<Self, String> {
+        let mut exprs = HashMap::new();
+        let mut deps = HashMap::new();
+        for field in &schema.fields {
+            if let Some(source) = &field.computed {
+                let expr = record_expr(source)
+                    .map_err(|e| format!("Invalid computed field {}: {}", field.name, e))?;
+                let mut field_deps = vec![];
+                dependencies(&expr, &mut field_deps);
+                deps.insert(field.name.clone(), field_deps);
+                exprs.insert(field.name.clone(), (field.clone(), expr));
+            }
+        }
+        let mut order = vec![];
+        for field in &schema.fields {
+            if field.computed.is_some() {
+                visit(&field.name, &deps, &mut vec![], &mut order)?;
+            }
+        }
+        Ok(Self {
+            defaults: schema
+                .fields
+                .iter()
+                .filter_map(|f| f.default.clone().map(|d| (f.name.clone(), d)))
+                .collect(),
+            computed: order
+                .into_iter()
+                .map(|name| exprs.remove(&name).unwrap())
+                .collect(),
+        })
+    }
+
+    // Defaults go first so computed fields can read them. The record is bound once, computed
+    // fields are set on the bound copy too so the fields after them read them.
+    pub fn materialize(&self, record: &mut OwnedMap) -> Result<(), String> {
+        for (name, default) in &self.defaults {
+            if record.get(name) == &OwnedValue::Null {
+                record.try_insert(name, default.clone())?;
+            }
+        }
+        if self.computed.is_empty() {
+            return Ok(());
+        }
+        let record_id = hash_str("record");
+        let mut interpreter = Interpreter::new();
+        interpreter.bind_by_id(
+            record_id,
+            SExpr::owned_value(OwnedValue::Map(record.clone())),
+        );
+        for (field, expr) in &self.computed {
+            let value = compute(&mut interpreter, field, expr)
+                .map_err(|e| format!("Cannot compute field {}: {}", field.name, e))?;
+            if let Some(SExpr::Value(Value::Owned(OwnedValue::Map(bound)))) =
+                interpreter.get_env().lookup_mut(record_id)
+            {
+                set_field(bound, &field.name, value.clone())?;
+            }
+            set_field(record, &field.name, value)?;
+        }
+        Ok(())
+    }
+}
+
+fn set_field(record: &mut OwnedMap, name: &str, value: OwnedValue) -> Result<(), String> {
+    if value == OwnedValue::Null {
+        record.remove(name);
+    } else {
+        record.try_insert(name, value)?;
+    }
+    Ok(())
+}
+
+fn compute(
+    interpreter: &mut Interpreter,
+    field: &Field,
+    expr: &Expr,
+) -> Result<OwnedValue, String> {
+    let res = interpreter.eval(vec![expr.clone().to_sexpr()])?;
+    let value = match res {
+        SExpr::Vec(exprs) | SExpr::List(exprs, _) if field.is_array => {
+            let values = exprs
+                .into_iter()
+                .map(|e| {
+                    e.owned_val()
+                        .ok_or_else(|| "Elements are not values".to_string())
+                        .and_then(|v| widen_value(v, field.data_type))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            match OwnedPrimArray::from_typed_values(field.data_type, values.clone()) {
+                Ok(array) => OwnedValue::PrimArray(array),
+                Err(_) => OwnedValue::Array(values),
+            }
+        }
+        res => match res.owned_val() {
+            Some(value @ OwnedValue::PrimArray(_)) | Some(value @ OwnedValue::Array(_))
+                if field.is_array =>
+            {
+                value
+            }
+            Some(value) if !field.is_array => widen_value(value, field.data_type)?,
+            other => {
+                return Err(format!(
+                    "Expect {}{}, found {:?}",
+                    get_type(field.data_type),
+                    if field.is_array { " array" } else { "" },
+                    other
+                ))
+            }
+        },
+    };
+    Ok(value)
+}
+
+pub fn materialize(schema: &Schema, record: &mut OwnedMap) -> Result<(), String> {
+    Materializer::new(schema)?.materialize(record)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn defaults_and_computed() {
+        let schema = Schema::new(
+            "person",
+            vec![
+                Field::new("first", Type::String),
+                Field::new("last", Type::String).default(OwnedValue::String("Doe".to_string())),
+                Field::new("greeting", Type::String).computed("(str \"Hi \" :full_name)"),
+                Field::new("full_name", Type::String).computed("(str :first \" \" :last)"),
+                Field::new("age", Type::U8).default(OwnedValue::U8(18)),
+                Field::new("next_age", Type::U64).computed("(+ :age 1u8)"),
+            ],
+        )
+        .unwrap();
+        let mut record = OwnedMap::new();
+        record.insert_value("first", "John".to_string());
+        materialize(&schema, &mut record).unwrap();
+        assert_eq!(record.get("last"), &OwnedValue::String("Doe".to_string()));
+        assert_eq!(
+            record.get("full_name"),
+            &OwnedValue::String("John Doe".to_string())
+        );
+        assert_eq!(
+            record.get("greeting"),
+            &OwnedValue::String("Hi John Doe".to_string())
+        );
+        assert_eq!(record.get("next_age"), &OwnedValue::U64(19));
+
+        let cyclic = Schema::new(
+            "cyclic",
+            vec![
+                Field::new("a", Type::U8).computed("(+ :b 1u8)"),
+                Field::new("b", Type::U8).computed("(+ :c 1u8)"),
+                Field::new("c", Type::U8).computed("(+ :a 1u8)"),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            Materializer::new(&cyclic).unwrap_err(),
+            "Computed fields depend on each other: a -> b -> c -> a"
+        );
+
+        let mistyped = Schema::new(
+            "mistyped",
+            vec![Field::new("a", Type::U8).computed("(str \"a\")")],
+        )
+        .unwrap();
+        assert!(materialize(&mistyped, &mut OwnedMap::new()).is_err());
+    }
+}
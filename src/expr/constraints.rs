@@ -0,0 +1,297 @@
+use crate::expr::interpreter::Interpreter;
+use crate::expr::serde::Expr;
+use crate::expr::symbols::bindings::unbind;
+use crate::expr::symbols::utils::is_true;
+use crate::expr::SExpr;
+use crate::integrated::lisp::parse_to_serde_expr;
+use crate::types::*;
+use bifrost_hasher::hash_str;
+
+// Validation of records against the constraints of their schema. Every constraint is
+// turned into a lisp predicate and evaluated by the interpreter, so the declarative ones
+// mean exactly what the same expression would mean in a query.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    // Field name, with the index for elements of array fields. Empty for record predicates.
+    pub path: String,
+    pub constraint: String,
+    pub message: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Validator {
+    checks: Vec<Check>,
+}
+
+#[derive(Debug, Clone)]
+struct Check {
+    field: Option<String>,
+    description: String,
+    expr: Expr,
+    per_element: bool,
+    // Only the nullability check sees null values
+    on_null: bool,
+}
+
+fn symbol(name: &str) -> Expr {
+    Expr::Symbol(hash_str(name), name.to_string())
+}
+
+fn call(name: &str, mut args: Vec<Expr>) -> Expr {
+    args.insert(0, symbol(name));
+    Expr::List(args, None)
+}
+
+fn value(val: OwnedValue) -> Expr {
+    Expr::Value(val)
+}
+
+// `(> :age 0u8)` reads `(> (get record :age) 0u8)`, only keywords given as arguments are
+// replaced so vectors of keywords keep working as paths
+fn resolve_keywords(expr: Expr) -> Expr {
+    match expr {
+        Expr::List(exprs, span) => {
+            let mut exprs = exprs.into_iter();
+            let mut res = vec![];
+            if let Some(head) = exprs.next() {
+                res.push(resolve_keywords(head));
+            }
+            for expr in exprs {
+                res.push(match expr {
+                    kw @ Expr::Keyword(_, _) => call("get", vec![symbol("record"), kw]),
+                    expr => resolve_keywords(expr),
+                });
+            }
+            Expr::List(res, span)
+        }
+        expr => expr,
+    }
+}
+
+// Parses a predicate or computed field over the record
+pub fn record_expr(source: &str) -> Result<Expr, String> {
+    let mut exprs = parse_to_serde_expr(source)?;
+    if exprs.len() != 1 {
+        return Err(format!("Expect one expression, found {}", exprs.len()));
+    }
+    Ok(resolve_keywords(exprs.pop().unwrap()))
+}
+
+// The lisp predicate of a constraint over `value`
+pub fn constraint_expr(constraint: &Constraint) -> Result<Expr, String> {
+    Ok(match constraint {
+        Constraint::Range(min, max) => {
+            let mut bounds = vec![];
+            if let Some(min) = min {
+                bounds.push(call(">=", vec![symbol("value"), value(min.clone())]));
+            }
+            if let Some(max) = max {
+                bounds.push(call("<=", vec![symbol("value"), value(max.clone())]));
+            }
+            call("and", bounds)
+        }
+        Constraint::Length(min, max) => {
+            let size = || call("size", vec![symbol("value")]);
+            let mut bounds = vec![];
+            if let Some(min) = min {
+                bounds.push(call(
+                    ">=",
+                    vec![size(), value(OwnedValue::U64(*min as u64))],
+                ));
+            }
+            if let Some(max) = max {
+                bounds.push(call(
+                    "<=",
+                    vec![size(), value(OwnedValue::U64(*max as u64))],
+                ));
+            }
+            call("and", bounds)
+        }
+        Constraint::OneOf(values) => call(
+            "or",
+            values
+                .iter()
+                .map(|v| call("=", vec![symbol("value"), value(v.clone())]))
+                .collect(),
+        ),
+        Constraint::Predicate(source) => record_expr(source)?,
+    })
+}
+
+fn describe(constraint: &Constraint) -> String {
+    match constraint {
+        Constraint::Range(min, max) => format!("range {:?}..={:?}", min, max),
+        Constraint::Length(min, max) => format!("length {:?}..={:?}", min, max),
+        Constraint::OneOf(values) => format!("one of {:?}", values),
+        Constraint::Predicate(source) => source.clone(),
+    }
+}
+
+impl Validator {
+    // Predicates are parsed once here, errors in them are reported before any record
+    pub fn new(schema: &Schema) -> Result<Self, String> {
+        let mut checks = vec![];
+        for field in &schema.fields {
+            if !field.nullable {
+                checks.push(Check {
+                    field: Some(field.name.clone()),
+                    description: "not null".to_string(),
+                    expr: call("not", vec![call("nil?", vec![symbol("value")])]),
+                    per_element: false,
+                    on_null: true,
+                });
+            }
+            for constraint in &field.constraints {
+                let expr = constraint_expr(constraint)
+                    .map_err(|e| format!("Invalid constraint on {}: {}", field.name, e))?;
+                checks.push(Check {
+                    field: Some(field.name.clone()),
+                    description: describe(constraint),
+                    expr,
+                    per_element: field.is_array
+                        && matches!(constraint, Constraint::Range(_, _) | Constraint::OneOf(_)),
+                    on_null: false,
+                });
+            }
+        }
+        for source in &schema.constraints {
+            checks.push(Check {
+                field: None,
+                description: source.clone(),
+                expr: record_expr(source)
+                    .map_err(|e| format!("Invalid constraint {}: {}", source, e))?,
+                per_element: false,
+                on_null: false,
+            });
+        }
+        Ok(Self { checks })
+    }
+
+    // Every violated constraint, empty when the record is valid
+    pub fn validate(&self, record: &OwnedMap) -> Vec<Violation> {
+        let record_value = OwnedValue::Map(record.clone());
+        let mut interpreter = Interpreter::new();
+        interpreter.bind("record", SExpr::owned_value(record_value.clone()));
+        let mut violations = vec![];
+        for check in &self.checks {
+            let (path, value) = match &check.field {
+                Some(name) => (name.clone(), record.get(name).clone()),
+                None => (String::new(), record_value.clone()),
+            };
+            if value == OwnedValue::Null && !check.on_null {
+                continue;
+            }
+            let subjects = match (&value, check.per_element) {
+                (OwnedValue::PrimArray(array), true) => (0..array.len())
+                    .map(|i| (format!("{}[{}]", path, i), array.get_owned(i).unwrap()))
+                    .collect(),
+                (OwnedValue::Array(values), true) => values
+                    .iter()
+                    .enumerate()
+                    .map(|(i, v)| (format!("{}[{}]", path, i), v.clone()))
+                    .collect(),
+                _ => vec![(path, value)],
+            };
+            for (path, value) in subjects {
+                let message = match evaluate(&mut interpreter, &check.expr, value) {
+                    Ok(true) => continue,
+                    Ok(false) => "constraint not met".to_string(),
+                    Err(e) => e,
+                };
+                violations.push(Violation {
+                    path,
+                    constraint: check.description.clone(),
+                    message,
+                });
+            }
+        }
+        violations
+    }
+}
+
+// Evaluates a check with `value` bound for it alone
+fn evaluate(interpreter: &mut Interpreter, expr: &Expr, value: OwnedValue) -> Result<bool, String> {
+    interpreter.bind("value", SExpr::owned_value(value));
+    let res = interpreter.eval(vec![expr.clone().to_sexpr()]);
+    unbind(interpreter.get_env(), hash_str("value"));
+    Ok(is_true(&res?))
+}
+
+pub fn validate(schema: &Schema, record: &OwnedMap) -> Result<Vec<Violation>, String> {
+    Ok(Validator::new(schema)?.validate(record))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn violations() {
+        let schema = Schema::new(
+            "person",
+            vec![
+                Field::new("name", Type::String).constraint(Constraint::Length(Some(1), Some(8))),
+                Field::new("age", Type::U8)
+                    .constraint(Constraint::Range(
+                        Some(OwnedValue::U8(0)),
+                        Some(OwnedValue::U8(150)),
+                    ))
+                    .constraint(Constraint::Predicate("(> :age 0u8)".to_string())),
+                Field::new("scores", Type::U8)
+                    .array()
+                    .constraint(Constraint::Range(None, Some(OwnedValue::U8(100)))),
+                Field::new("role", Type::String)
+                    .nullable()
+                    .constraint(Constraint::OneOf(vec![OwnedValue::String(
+                        "admin".to_string(),
+                    )])),
+            ],
+        )
+        .unwrap()
+        .constraint("(< (size :scores) 3u64)");
+        let validator = Validator::new(&schema).unwrap();
+
+        let mut record = OwnedMap::new();
+        record.insert_value("name", "Dova".to_string());
+        record.insert_value("age", 30u8);
+        record.insert_value("scores", vec![90u8, 80u8]);
+        assert_eq!(validator.validate(&record), vec![]);
+
+        record.insert_value("name", "Dovahkiin".to_string());
+        record.insert_value("age", 0u8);
+        record.insert_value("scores", vec![90u8, 120u8, 101u8]);
+        record.insert_value("role", "guest".to_string());
+        let paths: Vec<_> = validator
+            .validate(&record)
+            .into_iter()
+            .map(|v| (v.path, v.constraint))
+            .collect();
+        assert_eq!(
+            paths,
+            vec![
+                ("name".to_string(), "length Some(1)..=Some(8)".to_string()),
+                ("age".to_string(), "(> :age 0u8)".to_string()),
+                (
+                    "scores[1]".to_string(),
+                    "range None..=Some(U8(100))".to_string()
+                ),
+                (
+                    "scores[2]".to_string(),
+                    "range None..=Some(U8(100))".to_string()
+                ),
+                ("role".to_string(), "one of [String(\"admin\")]".to_string()),
+                ("".to_string(), "(< (size :scores) 3u64)".to_string()),
+            ]
+        );
+
+        let empty = validator.validate(&OwnedMap::new());
+        assert_eq!(empty.len(), 4);
+        assert!(empty
+            .iter()
+            .all(|v| v.constraint == "not null" || v.path.is_empty()));
+
+        let invalid = Schema::new("bad", vec![]).unwrap().constraint("(> :age");
+        assert!(Validator::new(&invalid).is_err());
+    }
+}
@@ -0,0 +1,215 @@
+use crate::expr::constraints::record_expr;
+use crate::expr::interpreter::Interpreter;
+use crate::expr::serde::Expr;
+use crate::expr::SExpr;
+use crate::types::*;
+use std::convert::TryFrom;
+
+// Secondary index definitions and the entries they hold for a record. Entries are made of
+// value features, so they sort like the values for numbers and string prefixes. Null values
+// are not indexed, except as parts of composite entries.
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "IndexDefParts")]
+pub struct IndexDef {
+    pub name: String,
+    // Fixed once made, so the parsed expression always follows it
+    kind: IndexKind,
+    // Parsed expression of `IndexKind::Expr`, parsed again when deserialized
+    #[serde(skip)]
+    parsed: Option<Expr>,
+}
+
+#[derive(Deserialize)]
+struct IndexDefParts {
+    name: String,
+    kind: IndexKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum IndexKind {
+    Field(String),
+    // One entry of the concatenated features of the fields
+    Composite(Vec<String>),
+    // One entry for every element of an array field
+    Elements(String),
+    // Lisp expression over the record, an entry per element when it returns a collection
+    Expr(String),
+}
+
+impl IndexDef {
+    pub fn field(name: &str, field: &str) -> Self {
+        Self::new(name, IndexKind::Field(field.to_string()))
+    }
+
+    pub fn composite(name: &str, fields: &[&str]) -> Self {
+        let fields = fields.iter().map(|f| f.to_string()).collect();
+        Self::new(name, IndexKind::Composite(fields))
+    }
+
+    pub fn elements(name: &str, field: &str) -> Self {
+        Self::new(name, IndexKind::Elements(field.to_string()))
+    }
+
+    // The expression is parsed here so extracting keys can only fail on evaluation
+    pub fn expr(name: &str, source: &str) -> Result<Self, String> {
+        let mut def = Self::new(name, IndexKind::Expr(source.to_string()));
+        def.parsed = Some(record_expr(source)?);
+        Ok(def)
+    }
+
+    fn new(name: &str, kind: IndexKind) -> Self {
+        Self {
+            name: name.to_string(),
+            kind,
+            parsed: None,
+        }
+    }
+
+    pub fn kind(&self) -> &IndexKind {
+        &self.kind
+    }
+
+    // Index entries of the record, sorted and without duplicates. An expression failing on
+    // the record is an error, not a record without entries.
+    pub fn extract_keys(&self, record: &OwnedValue) -> Result<Vec<Vec<u8>>, String> {
+        let mut keys = match &self.kind {
+            IndexKind::Field(field) => single_key(&record[field.as_str()]),
+            IndexKind::Composite(fields) => {
+                let values: Vec<_> = fields.iter().map(|f| &record[f.as_str()]).collect();
+                if values.iter().all(|v| **v == OwnedValue::Null) {
+                    vec![]
+                } else {
+                    vec![values.iter().flat_map(|v| v.feature()).collect()]
+                }
+            }
+            IndexKind::Elements(field) => element_keys(&record[field.as_str()]),
+            IndexKind::Expr(source) => {
+                let expr = match &self.parsed {
+                    Some(expr) => expr.clone(),
+                    None => record_expr(source)?,
+                };
+                let mut interpreter = Interpreter::new();
+                interpreter.bind("record", SExpr::owned_value(record.clone()));
+                match interpreter.eval(vec![expr.to_sexpr()])? {
+                    SExpr::Vec(exprs) | SExpr::List(exprs, _) => exprs
+                        .into_iter()
+                        .filter_map(|e| e.owned_val())
+                        .flat_map(|v| single_key(&v))
+                        .collect(),
+                    res => match res.owned_val() {
+                        Some(value) if is_collection(&value) => element_keys(&value),
+                        Some(value) => single_key(&value),
+                        None => return Err(format!("Index {} is not on values", self.name)),
+                    },
+                }
+            }
+        };
+        keys.sort();
+        keys.dedup();
+        Ok(keys)
+    }
+}
+
+impl TryFrom<IndexDefParts> for IndexDef {
+    type Error = String;
+
+    fn try_from(parts: IndexDefParts) -> Result<Self, String> {
+        match parts.kind {
+            IndexKind::Expr(source) => Self::expr(&parts.name, &source),
+            kind => Ok(Self::new(&parts.name, kind)),
+        }
+    }
+}
+
+// The parsed expression follows from the kind
+impl PartialEq for IndexDef {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name && self.kind == other.kind
+    }
+}
+
+fn is_collection(value: &OwnedValue) -> bool {
+    matches!(
+        value,
+        OwnedValue::Array(_)
+            | OwnedValue::PrimArray(_)
+            | OwnedValue::PVec(_)
+            | OwnedValue::Tensor(_)
+    )
+}
+
+fn single_key(value: &OwnedValue) -> Vec<Vec<u8>> {
+    match value {
+        OwnedValue::Null => vec![],
+        value => vec![value.feature().to_vec()],
+    }
+}
+
+fn element_keys(value: &OwnedValue) -> Vec<Vec<u8>> {
+    match value {
+        OwnedValue::Null => vec![],
+        OwnedValue::Array(values) => values.iter().flat_map(single_key).collect(),
+        OwnedValue::PVec(values) => values.iter().flat_map(single_key).collect(),
+        value => value.features().into_iter().map(|f| f.to_vec()).collect(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bifrost::utils::serde::{deserialize, serialize};
+
+    #[test]
+    fn keys() {
+        let mut map = OwnedMap::new();
+        map.insert_value("name", "Dova".to_string());
+        map.insert_value("age", 30u8);
+        map.insert_value("tags", vec![3u32, 1, 3]);
+        let record = OwnedValue::Map(map);
+        let feature = |v: OwnedValue| v.feature().to_vec();
+
+        let by_age = IndexDef::field("by_age", "age");
+        assert_eq!(
+            by_age.extract_keys(&record).unwrap(),
+            vec![feature(OwnedValue::U8(30))]
+        );
+        assert!(IndexDef::field("by_nick", "nick")
+            .extract_keys(&record)
+            .unwrap()
+            .is_empty());
+
+        let composite = IndexDef::composite("by_name_age", &["name", "age", "nick"]);
+        let mut key = feature(OwnedValue::String("Dova".to_string()));
+        key.extend(feature(OwnedValue::U8(30)));
+        key.extend([0u8; 8]);
+        assert_eq!(composite.extract_keys(&record).unwrap(), vec![key]);
+
+        let tags = IndexDef::elements("by_tag", "tags");
+        let expected = vec![feature(OwnedValue::U32(1)), feature(OwnedValue::U32(3))];
+        assert_eq!(tags.extract_keys(&record).unwrap(), expected);
+        let shifted = IndexDef::expr("by_next_tag", "(map (lambda [t] (+ t 1u32)) :tags)").unwrap();
+        let expected = vec![feature(OwnedValue::U32(2)), feature(OwnedValue::U32(4))];
+        assert_eq!(shifted.extract_keys(&record).unwrap(), expected);
+        let next = IndexDef::expr("by_next_age", "(+ :age 1u8)").unwrap();
+        assert_eq!(
+            next.extract_keys(&record).unwrap(),
+            vec![feature(OwnedValue::U8(31))]
+        );
+        assert!(IndexDef::expr("broken", "(+ :age").is_err());
+        let failing = IndexDef::expr("failing", "(+ :name 1u8)").unwrap();
+        assert!(failing.extract_keys(&record).is_err());
+
+        let defs = vec![by_age, composite, tags, next];
+        let decoded: Vec<IndexDef> = deserialize(&serialize(&defs)).unwrap();
+        assert_eq!(decoded, defs);
+        assert_eq!(
+            decoded[3].extract_keys(&record).unwrap(),
+            vec![feature(OwnedValue::U8(31))]
+        );
+        assert_eq!(
+            decoded[3].kind(),
+            &IndexKind::Expr("(+ :age 1u8)".to_string())
+        );
+    }
+}
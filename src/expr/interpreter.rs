@@ -1,30 +1,145 @@
+use crate::error::DovahkiinError;
+use crate::expr::limits::{CancelHandle, LimitExceeded, Limits};
+use crate::expr::record_source::RecordSource;
 use crate::expr::symbols::misc;
-use crate::expr::SExpr;
+use crate::expr::{SExpr, Value};
+use crate::types::HeapSize;
 use std::collections::{HashMap, LinkedList};
 use std::rc::Rc;
+use std::time::Instant;
+
+use super::symbols::bindings::bind_global;
+use super::symbols::bindings::bind_global_by_name;
+
+#[inline(never)]
+fn stack_position() -> usize {
+    let marker = 0u8;
+    std::hint::black_box(&marker) as *const u8 as usize
+}
+
+fn collection_size(expr: &SExpr) -> Option<usize> {
+    match expr {
+        SExpr::List(exprs, _) | SExpr::Vec(exprs) => Some(exprs.len()),
+        SExpr::Value(Value::Owned(value)) => value.len(),
+        SExpr::Value(Value::Shared(value)) => value.len(),
+        _ => None,
+    }
+}
 
-use super::symbols::bindings::bind;
-use super::symbols::bindings::bind_by_name;
 #[derive(Debug)]
 pub struct Envorinment<'a> {
     pub bindings: HashMap<u64, LinkedList<Rc<SExpr<'a>>>>,
+    pub record_source: Option<Rc<dyn RecordSource>>,
+    // Under lexical scoping `bindings` only holds local bindings, lambdas see the ones they
+    // captured and the definitions in `globals`
+    pub lexical: bool,
+    pub globals: HashMap<u64, Rc<SExpr<'a>>>,
+    pub depth: usize,
+    pub limits: Limits,
+    pub cancel: CancelHandle,
+    steps: u64,
+    deadline: Option<Instant>,
+    stack_base: usize,
 }
 
 impl<'a> Envorinment<'a> {
     pub fn new() -> Self {
         Envorinment {
             bindings: HashMap::new(),
+            record_source: None,
+            lexical: false,
+            globals: HashMap::new(),
+            depth: 0,
+            limits: Limits::default(),
+            cancel: CancelHandle::new(),
+            steps: 0,
+            deadline: None,
+            stack_base: 0,
         }
     }
     pub fn get_mut_bindings(&mut self) -> &mut HashMap<u64, LinkedList<Rc<SExpr<'a>>>> {
         &mut self.bindings
     }
+    pub fn lookup(&self, id: u64) -> Option<Rc<SExpr<'a>>> {
+        self.bindings
+            .get(&id)
+            .and_then(|list| list.front().cloned())
+            .or_else(|| self.globals.get(&id).cloned())
+    }
+    // The binding `lookup` finds, copied first if anything else still holds it
+    pub fn lookup_mut(&mut self, id: u64) -> Option<&mut SExpr<'a>> {
+        match self.bindings.get_mut(&id).and_then(|list| list.front_mut()) {
+            Some(binding) => Some(Rc::make_mut(binding)),
+            None => self.globals.get_mut(&id).map(Rc::make_mut),
+        }
+    }
+    // Starts an evaluation with the steps and time of the limits
+    pub fn start(&mut self) {
+        self.steps = 0;
+        self.deadline = self.limits.timeout.map(|timeout| Instant::now() + timeout);
+    }
+    // Taken for every call, loop iteration and tail call
+    pub fn step(&mut self) -> Result<(), DovahkiinError> {
+        self.steps += 1;
+        if let Some(steps) = self.limits.steps {
+            if self.steps > steps {
+                return Err(DovahkiinError::Limit(LimitExceeded::Steps(steps)));
+            }
+        }
+        if self.cancel.is_cancelled() {
+            return Err(DovahkiinError::Limit(LimitExceeded::Cancelled));
+        }
+        if let (Some(deadline), Some(timeout)) = (self.deadline, self.limits.timeout) {
+            if Instant::now() >= deadline {
+                return Err(DovahkiinError::Limit(LimitExceeded::Timeout(timeout)));
+            }
+        }
+        Ok(())
+    }
+    pub fn enter(&mut self) -> Result<(), DovahkiinError> {
+        self.step()?;
+        let position = stack_position();
+        if self.depth == 0 {
+            self.stack_base = position;
+        }
+        // Stacks grow downwards on every platform we run on
+        let stack = self.stack_base.saturating_sub(position);
+        if self.depth >= self.limits.max_depth || stack > self.limits.max_stack {
+            let depth = self.depth;
+            return Err(DovahkiinError::Limit(LimitExceeded::Depth { depth, stack }));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+    pub fn leave(&mut self) {
+        self.depth -= 1;
+    }
+    // Values are checked as functions return them, before they can grow any further
+    pub fn check_size(&mut self, expr: &SExpr<'a>) -> Result<(), DovahkiinError> {
+        if let Some(max) = self.limits.max_size {
+            match collection_size(expr) {
+                Some(size) if size > max => {
+                    return Err(DovahkiinError::Limit(LimitExceeded::Size { size, max }))
+                }
+                _ => {}
+            }
+        }
+        if let Some(max) = self.limits.max_bytes {
+            // Every value is measured in full, no more work than the copies of its arguments
+            // evaluating the call already made
+            let bytes = expr.heap_size();
+            if bytes > max {
+                return Err(DovahkiinError::Limit(LimitExceeded::Bytes { bytes, max }));
+            }
+        }
+        Ok(())
+    }
 }
 
 pub fn eval_all<'a>(
     exprs: Vec<SExpr<'a>>,
     env: &mut Envorinment<'a>,
-) -> Result<Vec<SExpr<'a>>, String> {
+) -> Result<Vec<SExpr<'a>>, DovahkiinError> {
     let mut result = Vec::with_capacity(exprs.len());
     for expr in exprs {
         result.push(expr.eval(env)?);
@@ -32,7 +147,10 @@ pub fn eval_all<'a>(
     Ok(result)
 }
 
-pub fn do_eval<'a>(exprs: Vec<SExpr<'a>>, env: &mut Envorinment<'a>) -> Result<SExpr<'a>, String> {
+pub fn do_eval<'a>(
+    exprs: Vec<SExpr<'a>>,
+    env: &mut Envorinment<'a>,
+) -> Result<SExpr<'a>, DovahkiinError> {
     misc::do_(exprs, env)
 }
 
@@ -47,14 +165,45 @@ impl<'a> Interpreter<'a> {
             env: Envorinment::new(),
         }
     }
-    pub fn eval(&mut self, exprs: Vec<SExpr<'a>>) -> Result<SExpr<'a>, String> {
+    pub fn eval(&mut self, exprs: Vec<SExpr<'a>>) -> Result<SExpr<'a>, DovahkiinError> {
+        self.env.start();
         do_eval(exprs, &mut self.env)
     }
     pub fn bind<'b>(&mut self, name: &'b str, expr: SExpr<'a>) {
-        bind_by_name(&mut self.env, name, expr)
+        bind_global_by_name(&mut self.env, name, expr)
     }
     pub fn bind_by_id(&mut self, id: u64, expr: SExpr<'a>) {
-        bind(&mut self.env, id, expr)
+        bind_global(&mut self.env, id, expr)
+    }
+    // Opt in to lexical scoping, lambdas capture the local bindings they refer to when they
+    // are made. Scoping is dynamic by default. Bindings made before the switch move with it.
+    pub fn set_lexical_scoping(&mut self, lexical: bool) {
+        let env = &mut self.env;
+        if lexical && !env.lexical {
+            for (id, mut list) in env.bindings.drain() {
+                if let Some(val) = list.pop_front() {
+                    env.globals.insert(id, val);
+                }
+            }
+        } else if !lexical && env.lexical {
+            for (id, val) in env.globals.drain() {
+                env.bindings.entry(id).or_default().push_front(val);
+            }
+        }
+        env.lexical = lexical;
+    }
+    // Limits of every following evaluation
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.env.limits = limits;
+    }
+    pub fn get_limits(&self) -> &Limits {
+        &self.env.limits
+    }
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.env.cancel.clone()
+    }
+    pub fn set_record_source(&mut self, source: Rc<dyn RecordSource>) {
+        self.env.record_source = Some(source);
     }
     pub fn get_env(&mut self) -> &mut Envorinment<'a> {
         &mut self.env
@@ -0,0 +1,135 @@
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+// Limits on a single evaluation of the interpreter, for running expressions that cannot be
+// trusted. Only nesting is limited by default, every level of it takes Rust stack.
+
+pub const DEFAULT_MAX_DEPTH: usize = 10_000;
+// Fits the 2MB stack of spawned threads, frames are a lot larger in debug builds
+pub const DEFAULT_MAX_STACK: usize = 1 << 20;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Limits {
+    // Calls, loop iterations and tail calls the evaluation may take
+    pub steps: Option<u64>,
+    pub max_depth: usize,
+    // Bytes of stack evaluations may use, from where the outermost one started
+    pub max_stack: usize,
+    // Elements of a collection returned by a function
+    pub max_size: Option<usize>,
+    // Heap bytes of a value returned by a function
+    pub max_bytes: Option<usize>,
+    pub timeout: Option<Duration>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            steps: None,
+            max_depth: DEFAULT_MAX_DEPTH,
+            max_stack: DEFAULT_MAX_STACK,
+            max_size: None,
+            max_bytes: None,
+            timeout: None,
+        }
+    }
+}
+
+impl Limits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn steps(mut self, steps: u64) -> Self {
+        self.steps = Some(steps);
+        self
+    }
+
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    pub fn max_stack(mut self, max_stack: usize) -> Self {
+        self.max_stack = max_stack;
+        self
+    }
+
+    pub fn max_size(mut self, max_size: usize) -> Self {
+        self.max_size = Some(max_size);
+        self
+    }
+
+    pub fn max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LimitExceeded {
+    Steps(u64),
+    Depth { depth: usize, stack: usize },
+    Size { size: usize, max: usize },
+    Bytes { bytes: usize, max: usize },
+    Timeout(Duration),
+    Cancelled,
+}
+
+impl fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LimitExceeded::Steps(steps) => write!(f, "Evaluation ran out of {} steps", steps),
+            LimitExceeded::Depth { depth, stack } => write!(
+                f,
+                "Evaluation too deep, at {} nested calls and {} bytes of stack. \
+                 Use loop/recur or tail calls for deep recursion",
+                depth, stack
+            ),
+            LimitExceeded::Size { size, max } => write!(
+                f,
+                "Collection of {} elements is over the limit of {}",
+                size, max
+            ),
+            LimitExceeded::Bytes { bytes, max } => {
+                write!(f, "Value of {} bytes is over the limit of {}", bytes, max)
+            }
+            LimitExceeded::Timeout(timeout) => {
+                write!(f, "Evaluation took longer than {:?}", timeout)
+            }
+            LimitExceeded::Cancelled => write!(f, "Evaluation cancelled"),
+        }
+    }
+}
+
+// Cancels evaluations of an interpreter from any thread. It stays cancelled until reset, so
+// cancelling just before an evaluation starts is not lost.
+#[derive(Debug, Clone, Default)]
+pub struct CancelHandle {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelHandle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn reset(&self) {
+        self.cancelled.store(false, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
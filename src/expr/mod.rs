@@ -1,14 +1,21 @@
 use std::borrow::Borrow;
 use std::rc::Rc;
 use bifrost_hasher::hash_str;
-use crate::types::{OwnedValue, SharedValue};
+use crate::error::DovahkiinError;
+use crate::types::{HeapSize, OwnedValue, SharedValue};
 use crate::parser::lisp::ParserExpr;
+use crate::lexer::Span;
 
 use self::interpreter::Envorinment;
 
 #[macro_use]
 pub mod symbols;
 pub mod interpreter;
+pub mod limits;
+pub mod constraints;
+pub mod computed;
+pub mod index;
+pub mod record_source;
 pub mod serde;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -38,43 +45,41 @@ impl<'a> Value<'a> {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone)]
 pub enum SExpr<'a> {
     Symbol(String),
     ISymbol(u64, String),
     Keyword(u64, String),
     Value(Value<'a>),
-    List(Vec<SExpr<'a>>),
+    // Lists read from source have their span, which errors in evaluating them are reported at.
+    // Symbols, values and vectors have none, errors in them are reported at the innermost
+    // list around them, if any.
+    List(Vec<SExpr<'a>>, Option<Span>),
     Vec(Vec<SExpr<'a>>),
     META(Vec<SExpr<'a>>),
-    LAMBDA(Vec<SExpr<'a>>, Vec<SExpr<'a>>),
+    // Parameters, body and the bindings captured under lexical scoping, as pairs of symbol
+    // and value like the form of `let`
+    LAMBDA(Vec<SExpr<'a>>, Vec<SExpr<'a>>, Vec<SExpr<'a>>),
 }
 
 impl<'a> SExpr<'a> {
-    pub fn eval(self, env: &mut Envorinment<'a>) -> Result<SExpr<'a>, String> {
+    pub fn eval(self, env: &mut Envorinment<'a>) -> Result<SExpr<'a>, DovahkiinError> {
         match self {
-            SExpr::List(exprs) => {
+            SExpr::List(exprs, span) => {
                 if exprs.len() == 0 {
                     Ok(SExpr::Value(Value::null()))
                 } else {
+                    env.enter()?;
                     let mut iter = exprs.into_iter();
-                    let func = iter.next().unwrap().eval(env)?;
-                    Ok(symbols::functions::eval_function(
-                        &func,
-                        iter.collect(),
-                        env,
-                    )?)
+                    let res = iter.next().unwrap().eval(env).and_then(|func| {
+                        symbols::functions::eval_function(&func, iter.collect(), env)
+                    });
+                    env.leave();
+                    res.map_err(|e| e.at(span))
                 }
             }
             SExpr::ISymbol(symbol_id, _) => {
-                let env_bind_ref;
-                let bindings = env.get_mut_bindings();
-                env_bind_ref = if let Some(binding_list) = bindings.get(&symbol_id) {
-                    binding_list.front().cloned()
-                } else {
-                    None
-                };
-                if let Some(binding) = env_bind_ref {
+                if let Some(binding) = env.lookup(symbol_id) {
                     let bind_expr: &SExpr = binding.borrow();
                     Ok(bind_expr.clone())
                 } else {
@@ -116,16 +121,84 @@ impl<'a> SExpr<'a> {
     }
     pub fn is_empty(&self) -> bool {
         match self {
-            &SExpr::List(ref l) => l.is_empty(),
+            &SExpr::List(ref l, _) => l.is_empty(),
             &SExpr::Vec(ref v) => v.is_empty(),
             _ => false
         }
     }
+
+    // Same as the size of the `serde::Expr` it turns into
+    pub fn encoded_size(&self) -> usize {
+        4 + match self {
+            SExpr::Symbol(name) | SExpr::ISymbol(_, name) | SExpr::Keyword(_, name) => {
+                16 + name.len()
+            }
+            SExpr::Value(Value::Owned(val)) => val.encoded_size(),
+            SExpr::Value(Value::Shared(val)) => val.encoded_size(),
+            SExpr::List(list, _) | SExpr::Vec(list) | SExpr::META(list) => {
+                sexprs_encoded_size(list)
+            }
+            SExpr::LAMBDA(params, body, captured) => {
+                sexprs_encoded_size(params)
+                    + sexprs_encoded_size(body)
+                    + sexprs_encoded_size(captured)
+            }
+        }
+    }
+}
+
+fn sexprs_encoded_size(exprs: &[SExpr]) -> usize {
+    8 + exprs.iter().map(SExpr::encoded_size).sum::<usize>()
+}
+
+impl<'a> HeapSize for Value<'a> {
+    fn heap_size(&self) -> usize {
+        match self {
+            Value::Owned(v) => v.heap_size(),
+            Value::Shared(v) => v.heap_size(),
+        }
+    }
+}
+
+impl<'a> HeapSize for SExpr<'a> {
+    fn heap_size(&self) -> usize {
+        match self {
+            SExpr::Symbol(name) | SExpr::ISymbol(_, name) | SExpr::Keyword(_, name) => {
+                name.heap_size()
+            }
+            SExpr::Value(val) => val.heap_size(),
+            SExpr::List(list, _) | SExpr::Vec(list) | SExpr::META(list) => list.heap_size(),
+            SExpr::LAMBDA(params, body, captured) => {
+                params.heap_size() + body.heap_size() + captured.heap_size()
+            }
+        }
+    }
 }
 
+// Spans are where forms were read, not part of what they are
+impl<'a> PartialEq for SExpr<'a> {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (SExpr::Symbol(a), SExpr::Symbol(b)) => a == b,
+            (SExpr::ISymbol(a, a_name), SExpr::ISymbol(b, b_name)) => a == b && a_name == b_name,
+            (SExpr::Keyword(a, a_name), SExpr::Keyword(b, b_name)) => a == b && a_name == b_name,
+            (SExpr::Value(a), SExpr::Value(b)) => a == b,
+            (SExpr::List(a, _), SExpr::List(b, _)) => a == b,
+            (SExpr::Vec(a), SExpr::Vec(b)) => a == b,
+            (SExpr::META(a), SExpr::META(b)) => a == b,
+            (SExpr::LAMBDA(a, a_body, a_captured), SExpr::LAMBDA(b, b_body, b_captured)) => {
+                a == b && a_body == b_body && a_captured == b_captured
+            }
+            _ => false,
+        }
+    }
+}
+
+impl<'a> Eq for SExpr<'a> {}
+
 impl ParserExpr for SExpr<'_> {
-    fn list(data: Vec<Self>) -> Self {
-        Self::List(data)
+    fn list(data: Vec<Self>, span: Span) -> Self {
+        Self::List(data, Some(span))
     }
 
     fn vec(data: Vec<Self>) -> Self {
@@ -0,0 +1,42 @@
+use crate::types::{Id, OwnedValue};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::Debug;
+
+// Resolves records by their ids, so expressions can follow links between records.
+// Implementations backed by a store should override `get_batch` to fetch in one round trip.
+pub trait RecordSource: Debug {
+    fn get(&self, id: &Id) -> Option<OwnedValue>;
+    fn get_batch(&self, ids: &[Id]) -> Vec<Option<OwnedValue>> {
+        ids.iter().map(|id| self.get(id)).collect()
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct MemorySource {
+    records: RefCell<HashMap<Id, OwnedValue>>,
+}
+
+impl MemorySource {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&self, id: Id, record: OwnedValue) -> Option<OwnedValue> {
+        self.records.borrow_mut().insert(id, record)
+    }
+
+    pub fn remove(&self, id: &Id) -> Option<OwnedValue> {
+        self.records.borrow_mut().remove(id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.borrow().len()
+    }
+}
+
+impl RecordSource for MemorySource {
+    fn get(&self, id: &Id) -> Option<OwnedValue> {
+        self.records.borrow().get(id).cloned()
+    }
+}
@@ -1,5 +1,6 @@
 use bifrost_hasher::hash_str;
-use crate::types::OwnedValue;
+use crate::lexer::Span;
+use crate::types::{HeapSize, OwnedValue};
 
 use crate::expr::Value;
 
@@ -9,11 +10,13 @@ use super::{SExpr, symbols::ParserExpr};
 pub enum Expr {
     Symbol(u64, String),
     Value(OwnedValue),
-    List(Vec<Expr>),
+    // Spans are not shipped
+    List(Vec<Expr>, #[serde(skip)] Option<Span>),
     Vec(Vec<Expr>),
     Keyword(u64, String),
     META(Vec<Expr>),
-    LAMBDA(Vec<Expr>, Vec<Expr>),
+    // Parameters, body and captured bindings
+    LAMBDA(Vec<Expr>, Vec<Expr>, Vec<Expr>),
 }
 
 fn sexpr_list_to_expr_list(list: Vec<SExpr>) -> Vec<Expr> {
@@ -34,13 +37,14 @@ impl Expr {
                 Value::Owned(o) => o,
                 Value::Shared(s) => s.owned(),
             }),
-            SExpr::List(l) => Self::List(sexpr_list_to_expr_list(l)),
+            SExpr::List(l, span) => Self::List(sexpr_list_to_expr_list(l), span),
             SExpr::Vec(v) => Self::Vec(sexpr_list_to_expr_list(v)),
             SExpr::META(v) => Self::META(sexpr_list_to_expr_list(v)),
-            SExpr::LAMBDA(p, b) => {
+            SExpr::LAMBDA(p, b, c) => {
                 Self::LAMBDA(
                     sexpr_list_to_expr_list(p),
-                    sexpr_list_to_expr_list(b)
+                    sexpr_list_to_expr_list(b),
+                    sexpr_list_to_expr_list(c)
                 )
             },
         }
@@ -51,33 +55,65 @@ impl Expr {
             Expr::Symbol(i, s) => SExpr::ISymbol(i, s),
             Expr::Keyword(id, name) => SExpr::Keyword(id, name),
             Expr::Value(v) => SExpr::Value(Value::Owned(v)),
-            Expr::List(l) => SExpr::List(expr_list_to_sexpr_list(l)),
+            Expr::List(l, span) => SExpr::List(expr_list_to_sexpr_list(l), span),
             Expr::Vec(v) => SExpr::Vec(expr_list_to_sexpr_list(v)),
             Expr::META(v) => SExpr::META(expr_list_to_sexpr_list(v)),
-            Expr::LAMBDA(p, b) => {
+            Expr::LAMBDA(p, b, c) => {
                 SExpr::LAMBDA(
                     expr_list_to_sexpr_list(p),
-                    expr_list_to_sexpr_list(b)
+                    expr_list_to_sexpr_list(b),
+                    expr_list_to_sexpr_list(c)
                 )
             },
         }
     }
     pub fn is_empty(&self) -> bool {
         match self {
-            &Expr::List(ref l) => l.is_empty(),
+            &Expr::List(ref l, _) => l.is_empty(),
             &Expr::Vec(ref v) => v.is_empty(),
             _ => false,
         }
     }
 
     pub fn nothing() -> Self {
-      Self::List(vec![])
+      Self::List(vec![], None)
+    }
+
+    // Size of the expression shipped to other nodes, counted over the tree
+    pub fn encoded_size(&self) -> usize {
+        4 + match self {
+            Expr::Symbol(_, name) | Expr::Keyword(_, name) => 16 + name.len(),
+            Expr::Value(val) => val.encoded_size(),
+            Expr::List(list, _) | Expr::Vec(list) | Expr::META(list) => exprs_encoded_size(list),
+            Expr::LAMBDA(params, body, captured) => {
+                exprs_encoded_size(params)
+                    + exprs_encoded_size(body)
+                    + exprs_encoded_size(captured)
+            }
+        }
+    }
+}
+
+fn exprs_encoded_size(exprs: &[Expr]) -> usize {
+    8 + exprs.iter().map(Expr::encoded_size).sum::<usize>()
+}
+
+impl HeapSize for Expr {
+    fn heap_size(&self) -> usize {
+        match self {
+            Expr::Symbol(_, name) | Expr::Keyword(_, name) => name.heap_size(),
+            Expr::Value(val) => val.heap_size(),
+            Expr::List(list, _) | Expr::Vec(list) | Expr::META(list) => list.heap_size(),
+            Expr::LAMBDA(params, body, captured) => {
+                params.heap_size() + body.heap_size() + captured.heap_size()
+            }
+        }
     }
 }
 
 impl ParserExpr for Expr {
-    fn list(data: Vec<Self>) -> Self {
-        Self::List(data)
+    fn list(data: Vec<Self>, span: Span) -> Self {
+        Self::List(data, Some(span))
     }
 
     fn vec(data: Vec<Self>) -> Self {
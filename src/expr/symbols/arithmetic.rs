@@ -1,4 +1,8 @@
+use super::utils::type_of;
 use super::*;
+use crate::error::DovahkiinError;
+use crate::types::TensorOp;
+use crate::types::Type;
 
 macro_rules! reduce {
     ($type: ident, $values: ident, $exp: expr) => {{
@@ -6,27 +10,26 @@ macro_rules! reduce {
             if let SharedValue::$type(n) = first {
                 let mut result = *n;
                 for val in elements {
-                    let val = val.val();
-                    if let Some(SharedValue::$type(n)) = val {
+                    if let Some(SharedValue::$type(n)) = val.val() {
                         result = $exp(result, n);
                     } else {
-                        return Err(format!(
-                            "Type not match, expect {} found {:?}",
-                            stringify!($type),
-                            val
-                        ));
+                        return Err(DovahkiinError::Type {
+                            expected: Type::$type.into(),
+                            found: type_of(val),
+                            span: None,
+                        });
                     }
                 }
                 Ok(SExpr::owned_value(OwnedValue::$type(result)))
             } else {
-                Err(format!(
-                    "Type not match on the first value, expect {} found {:?}",
-                    stringify!($type),
-                    first
-                ))
+                Err(DovahkiinError::Type {
+                    expected: Type::$type.into(),
+                    found: first.base_type(),
+                    span: None,
+                })
             }
         } else {
-            Err("Cannot do reduce on values".to_string())
+            Err("Cannot do reduce on values".into())
         }
     }};
 }
@@ -55,7 +58,10 @@ macro_rules! divide_ {
     }};
 }
 
-pub fn add(values: Vec<SExpr>) -> Result<SExpr, String> {
+pub fn add(values: Vec<SExpr>) -> Result<SExpr, DovahkiinError> {
+    if values.iter().any(vectorized::is_array) {
+        return vectorized::arith(values, TensorOp::Add);
+    }
     match values.get(0).unwrap().val() {
         Some(SharedValue::U8(_)) => add_!(U8, values),
         Some(SharedValue::U16(_)) => add_!(U16, values),
@@ -67,11 +73,14 @@ pub fn add(values: Vec<SExpr>) -> Result<SExpr, String> {
         Some(SharedValue::I64(_)) => add_!(I64, values),
         Some(SharedValue::F32(_)) => add_!(F32, values),
         Some(SharedValue::F64(_)) => add_!(F64, values),
-        _ => Err(format!("Type cannot be added {:?}", values)),
+        _ => Err(format!("Type cannot be added {:?}", values).into()),
     }
 }
 
-pub fn subtract(values: Vec<SExpr>) -> Result<SExpr, String> {
+pub fn subtract(values: Vec<SExpr>) -> Result<SExpr, DovahkiinError> {
+    if values.iter().any(vectorized::is_array) {
+        return vectorized::arith(values, TensorOp::Sub);
+    }
     match values.get(0).unwrap().val() {
         Some(SharedValue::U8(_)) => subtract_!(U8, values),
         Some(SharedValue::U16(_)) => subtract_!(U16, values),
@@ -83,11 +92,14 @@ pub fn subtract(values: Vec<SExpr>) -> Result<SExpr, String> {
         Some(SharedValue::I64(_)) => subtract_!(I64, values),
         Some(SharedValue::F32(_)) => subtract_!(F32, values),
         Some(SharedValue::F64(_)) => subtract_!(F64, values),
-        _ => Err(format!("Type cannot be subtracted: {:?}", values)),
+        _ => Err(format!("Type cannot be subtracted: {:?}", values).into()),
     }
 }
 
-pub fn multiply(values: Vec<SExpr>) -> Result<SExpr, String> {
+pub fn multiply(values: Vec<SExpr>) -> Result<SExpr, DovahkiinError> {
+    if values.iter().any(vectorized::is_array) {
+        return vectorized::arith(values, TensorOp::Mul);
+    }
     match values.get(0).unwrap().val() {
         Some(SharedValue::U8(_)) => multiply_!(U8, values),
         Some(SharedValue::U16(_)) => multiply_!(U16, values),
@@ -99,11 +111,14 @@ pub fn multiply(values: Vec<SExpr>) -> Result<SExpr, String> {
         Some(SharedValue::I64(_)) => multiply_!(I64, values),
         Some(SharedValue::F32(_)) => multiply_!(F32, values),
         Some(SharedValue::F64(_)) => multiply_!(F64, values),
-        _ => Err(format!("Type cannot be multiplied: {:?}", values)),
+        _ => Err(format!("Type cannot be multiplied: {:?}", values).into()),
     }
 }
 
-pub fn divide(values: Vec<SExpr>) -> Result<SExpr, String> {
+pub fn divide(values: Vec<SExpr>) -> Result<SExpr, DovahkiinError> {
+    if values.iter().any(vectorized::is_array) {
+        return vectorized::arith(values, TensorOp::Div);
+    }
     match values.get(0).unwrap().val() {
         Some(SharedValue::U8(_)) => divide_!(U8, values),
         Some(SharedValue::U16(_)) => divide_!(U16, values),
@@ -115,11 +130,11 @@ pub fn divide(values: Vec<SExpr>) -> Result<SExpr, String> {
         Some(SharedValue::I64(_)) => divide_!(I64, values),
         Some(SharedValue::F32(_)) => divide_!(F32, values),
         Some(SharedValue::F64(_)) => divide_!(F64, values),
-        _ => Err(format!("Type cannot be divided: {:?}", values)),
+        _ => Err(format!("Type cannot be divided: {:?}", values).into()),
     }
 }
 
-pub fn inc(value: SExpr) -> Result<SExpr, String> {
+pub fn inc(value: SExpr) -> Result<SExpr, DovahkiinError> {
     let value = match value.val() {
         Some(SharedValue::U8(v)) => SExpr::owned_value(OwnedValue::U8(v + 1)),
         Some(SharedValue::U16(v)) => SExpr::owned_value(OwnedValue::U16(v + 1)),
@@ -129,7 +144,7 @@ pub fn inc(value: SExpr) -> Result<SExpr, String> {
         Some(SharedValue::I16(v)) => SExpr::owned_value(OwnedValue::I16(v + 1)),
         Some(SharedValue::I32(v)) => SExpr::owned_value(OwnedValue::I32(v + 1)),
         Some(SharedValue::I64(v)) => SExpr::owned_value(OwnedValue::I64(v + 1)),
-        _ => return Err(format!("Type cannot be increased: {:?}", value)),
+        _ => return Err(format!("Type cannot be increased: {:?}", value).into()),
     };
     Ok(value)
 }
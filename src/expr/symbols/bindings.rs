@@ -1,3 +1,4 @@
+use crate::error::DovahkiinError;
 use crate::expr::interpreter::Envorinment;
 
 use super::super::Value;
@@ -16,6 +17,19 @@ pub fn bind<'a>(env: &mut Envorinment<'a>, id: u64, val: SExpr<'a>) {
         .push_front(Rc::new(val));
 }
 
+// Top level bindings made by `def`, `defunc` and the host
+pub fn bind_global<'a>(env: &mut Envorinment<'a>, id: u64, val: SExpr<'a>) {
+    if env.lexical {
+        env.globals.insert(id, Rc::new(val));
+    } else {
+        bind(env, id, val)
+    }
+}
+
+pub fn bind_global_by_name<'a, 'b>(env: &mut Envorinment<'a>, name: &'b str, val: SExpr<'a>) {
+    bind_global(env, hash_str(name), val)
+}
+
 pub fn unbind<'a>(env: &mut Envorinment<'a>, id: u64) {
     let binding_map = &mut env.bindings;
     binding_map
@@ -27,65 +41,90 @@ pub fn unbind<'a>(env: &mut Envorinment<'a>, id: u64) {
 pub fn let_binding<'a>(
     env: &mut Envorinment<'a>,
     mut exprs: Vec<SExpr<'a>>,
-) -> Result<SExpr<'a>, String> {
+) -> Result<SExpr<'a>, DovahkiinError> {
     if exprs.len() < 2 {
         return Err(format!(
             "Too few parameters for let. Required at least 2 but found {}",
             exprs.len()
-        ));
-    }
-    let mut binded_ids = Vec::new();
-    {
-        let form_expr = exprs.remove(0);
-        let form = if let SExpr::Vec(vec) = form_expr {
-            vec
-        } else {
-            return Err(format!("Let need a vector as form, found {:?}", form_expr));
-        };
-        if form.len() % 2 == 1 {
-            return Err(format!(
-                "Let form require even number of parameters, but found {}",
-                form.len()
-            ));
-        }
-        let mut form_iter = form.into_iter();
-        while let Some(symbol) = form_iter.next() {
-            let symbol_id = match symbol {
-                SExpr::Symbol(ref sym_str) => hash_str(sym_str),
-                SExpr::ISymbol(id, _) => id,
-                _ => return Err(format!("Cannot bind to {:?}, need symbol", symbol)),
-            };
-            if let Some(expr) = form_iter.next() {
-                let val = expr.eval(env)?;
-                bind(env, symbol_id, val);
-                binded_ids.push(symbol_id);
-            } else {
-                return Err(format!("cannot bind to {:?}, no value", symbol));
-            }
-        }
+        )
+        .into());
     }
-    let mut body_result = SExpr::Value(Value::null());
+    let binded_ids = bind_form(env, exprs.remove(0))?;
+    let mut body_result = Ok(SExpr::Value(Value::null()));
     for body_line in exprs {
-        body_result = body_line.eval(env)?;
+        body_result = body_line.eval(env);
+        if body_result.is_err() {
+            break;
+        }
     }
     for binded_id in binded_ids {
         unbind(env, binded_id);
     }
-    return Ok(body_result);
+    body_result
+}
+
+// Binds the symbol and value pairs of a let form in order, returning the bound symbols
+pub fn bind_form<'a>(
+    env: &mut Envorinment<'a>,
+    form_expr: SExpr<'a>,
+) -> Result<Vec<u64>, DovahkiinError> {
+    let form = if let SExpr::Vec(vec) = form_expr {
+        vec
+    } else {
+        return Err(format!("Let need a vector as form, found {:?}", form_expr).into());
+    };
+    if form.len() % 2 == 1 {
+        return Err(format!(
+            "Let form require even number of parameters, but found {}",
+            form.len()
+        )
+        .into());
+    }
+    let mut binded_ids = Vec::new();
+    let res = bind_pairs(env, form, &mut binded_ids);
+    if res.is_err() {
+        for binded_id in binded_ids.drain(..) {
+            unbind(env, binded_id);
+        }
+    }
+    res.map(|_| binded_ids)
+}
+
+fn bind_pairs<'a>(
+    env: &mut Envorinment<'a>,
+    form: Vec<SExpr<'a>>,
+    binded_ids: &mut Vec<u64>,
+) -> Result<(), DovahkiinError> {
+    let mut form_iter = form.into_iter();
+    while let Some(symbol) = form_iter.next() {
+        let symbol_id = match symbol {
+            SExpr::Symbol(ref sym_str) => hash_str(sym_str),
+            SExpr::ISymbol(id, _) => id,
+            _ => return Err(format!("Cannot bind to {:?}, need symbol", symbol).into()),
+        };
+        if let Some(expr) = form_iter.next() {
+            let val = expr.eval(env)?;
+            bind(env, symbol_id, val);
+            binded_ids.push(symbol_id);
+        } else {
+            return Err(format!("cannot bind to {:?}, no value", symbol).into());
+        }
+    }
+    Ok(())
 }
 
 pub fn define<'a>(
     env: &mut Envorinment<'a>,
     mut exprs: Vec<SExpr<'a>>,
-) -> Result<SExpr<'a>, String> {
+) -> Result<SExpr<'a>, DovahkiinError> {
     let name = exprs.remove(0);
     let val = exprs.remove(0).eval(env)?;
     if let SExpr::Symbol(name) = name {
-        bind_by_name(env, &name, val);
+        bind_global_by_name(env, &name, val);
     } else if let SExpr::ISymbol(id, _) = name {
-        bind(env, id, val)
+        bind_global(env, id, val)
     } else {
-        return Err(format!("Cannot bind to {:?}", name));
+        return Err(format!("Cannot bind to {:?}", name).into());
     }
     return Ok(SExpr::Value(Value::null()));
 }
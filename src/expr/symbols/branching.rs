@@ -1,7 +1,11 @@
 use super::utils::is_true;
 use super::*;
+use crate::error::DovahkiinError;
 
-pub fn if_<'a>(env: &mut Envorinment<'a>, exprs: Vec<SExpr<'a>>) -> Result<SExpr<'a>, String> {
+pub fn if_<'a>(
+    env: &mut Envorinment<'a>,
+    exprs: Vec<SExpr<'a>>,
+) -> Result<SExpr<'a>, DovahkiinError> {
     let mut iter = exprs.into_iter();
     let tester = iter.next().unwrap();
     let then_expr = iter.next().unwrap();
@@ -15,7 +19,10 @@ pub fn if_<'a>(env: &mut Envorinment<'a>, exprs: Vec<SExpr<'a>>) -> Result<SExpr
     }
 }
 
-pub fn if_not<'a>(env: &mut Envorinment<'a>, exprs: Vec<SExpr<'a>>) -> Result<SExpr<'a>, String> {
+pub fn if_not<'a>(
+    env: &mut Envorinment<'a>,
+    exprs: Vec<SExpr<'a>>,
+) -> Result<SExpr<'a>, DovahkiinError> {
     let mut iter = exprs.into_iter();
     let tester = iter.next().unwrap();
     let then_expr = iter.next().unwrap();
@@ -29,7 +36,10 @@ pub fn if_not<'a>(env: &mut Envorinment<'a>, exprs: Vec<SExpr<'a>>) -> Result<SE
     }
 }
 
-pub fn when<'a>(env: &mut Envorinment<'a>, exprs: Vec<SExpr<'a>>) -> Result<SExpr<'a>, String> {
+pub fn when<'a>(
+    env: &mut Envorinment<'a>,
+    exprs: Vec<SExpr<'a>>,
+) -> Result<SExpr<'a>, DovahkiinError> {
     let mut iter = exprs.into_iter();
     let tester = iter.next().unwrap();
     let then_expr = iter.next().unwrap();
@@ -40,7 +50,10 @@ pub fn when<'a>(env: &mut Envorinment<'a>, exprs: Vec<SExpr<'a>>) -> Result<SExp
     }
 }
 
-pub fn when_not<'a>(env: &mut Envorinment<'a>, exprs: Vec<SExpr<'a>>) -> Result<SExpr<'a>, String> {
+pub fn when_not<'a>(
+    env: &mut Envorinment<'a>,
+    exprs: Vec<SExpr<'a>>,
+) -> Result<SExpr<'a>, DovahkiinError> {
     let mut iter = exprs.into_iter();
     let tester = iter.next().unwrap();
     let then_expr = iter.next().unwrap();
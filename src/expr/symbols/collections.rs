@@ -1,11 +1,13 @@
 use log::kv;
 
+use crate::error::DovahkiinError;
+use crate::types::custom_types::map::check_field;
 use crate::types::{Map, OwnedMap};
 
 use super::*;
 use std::collections::HashMap;
 
-pub fn size_(vals: &Vec<SExpr>) -> Result<u64, String> {
+pub fn size_(vals: &Vec<SExpr>) -> Result<u64, DovahkiinError> {
     let mut result: u64 = 0;
     for val in vals {
         result += match &val {
@@ -14,22 +16,28 @@ pub fn size_(vals: &Vec<SExpr>) -> Result<u64, String> {
                 let v = val.norm();
                 match v {
                     SharedValue::Array(ref a) => a.len(),
+                    SharedValue::PrimArray(ref a) => a.len(),
                     SharedValue::String(ref s) => s.len(),
                     SharedValue::Map(ref m) => m.len(),
-                    _ => return Err(format!("Cannot measure size for value {:?}", val)),
+                    SharedValue::PMap(m) => m.len(),
+                    SharedValue::PVec(v) => v.len(),
+                    _ => return Err(format!("Cannot measure size for value {:?}", val).into()),
                 }
             }
-            _ => return Err(format!("Cannot measure size for {:?}", val)),
+            _ => return Err(format!("Cannot measure size for {:?}", val).into()),
         } as u64;
     }
     return Ok(result);
 }
 
-pub fn size(vals: Vec<SExpr>) -> Result<SExpr, String> {
+pub fn size(vals: Vec<SExpr>) -> Result<SExpr, DovahkiinError> {
     Ok(SExpr::owned_value(OwnedValue::U64(size_(&vals)?)))
 }
 
-pub fn concat(lists: Vec<SExpr>) -> Result<SExpr, String> {
+pub fn concat(lists: Vec<SExpr>) -> Result<SExpr, DovahkiinError> {
+    if lists.first().map(persistent::is_pvec).unwrap_or(false) {
+        return persistent::concat(lists);
+    }
     let total_size = size_(&lists)?;
     let mut result = Vec::with_capacity(total_size as usize);
     let mut vec_lists = Vec::new();
@@ -37,7 +45,7 @@ pub fn concat(lists: Vec<SExpr>) -> Result<SExpr, String> {
         vec_lists.push(if let SExpr::Vec(v) = stream::to_vec(list)? {
             v
         } else {
-            return Err("Unexpected error on concat".to_string());
+            return Err("Unexpected error on concat".into());
         });
     }
     for mut vec in vec_lists {
@@ -46,24 +54,24 @@ pub fn concat(lists: Vec<SExpr>) -> Result<SExpr, String> {
     return Ok(SExpr::Vec(result));
 }
 
-pub fn hashmap(mut exprs: Vec<SExpr>) -> Result<SExpr, String> {
+pub fn hashmap(mut exprs: Vec<SExpr>) -> Result<SExpr, DovahkiinError> {
     if exprs.len() == 1 {
         match exprs.into_iter().next().unwrap() {
-            SExpr::Vec(l) | SExpr::List(l) => {
+            SExpr::Vec(l) | SExpr::List(l, _) => {
                 exprs = l
             },
             v => {
-                return Err(format!("Single patameter only support list and seq, found {:?}", v))
+                return Err(format!("Single patameter only support list and seq, found {:?}", v).into())
             }
         }
     } else if exprs.len() & 2 == 0 {
         return Err(format!(
             "Map require even number of parameters. Found {}",
             exprs.len()
-        ));
+        ).into());
     }
     let mut exprs = exprs.into_iter();
-    let mut hashmap = HashMap::new();
+    let mut map = OwnedMap::new();
     while let (Some(k), Some(v)) = (exprs.next(), exprs.next()) {
         match (k, v) {
             (SExpr::Value(k_val), SExpr::Value(v)) => {
@@ -72,75 +80,87 @@ pub fn hashmap(mut exprs: Vec<SExpr>) -> Result<SExpr, String> {
                 match (k_str_opt, v) {
                     (Some(k_str), Value::Shared(v)) => {
                         // TODO: Try not own elements
-                        hashmap.insert(k_str.to_owned(), v.owned());
+                        map.try_insert(&k_str, v.owned())?;
                     }
                     (Some(k_str), Value::Owned(v)) => {
-                        hashmap.insert(k_str.to_owned(), v);
+                        map.try_insert(&k_str, v)?;
                     }
-                    (None, _) => return Err(format!("Only string key is allowed, got {:?}", k_val)),
+                    (None, _) => return Err(format!("Only string key is allowed, got {:?}", k_val).into()),
                 }
             }
             (SExpr::Keyword(_, kw), SExpr::Value(v)) => {
                 match v {
                     Value::Shared(v) => {
                         // TODO: Try not own elements
-                        hashmap.insert(kw, v.owned());
+                        map.try_insert(&kw, v.owned())?;
                     }
                     Value::Owned(v) => {
-                        hashmap.insert(kw, v);
+                        map.try_insert(&kw, v)?;
                     }
                 } 
             }
             _ => {
-                return Err(format!("Wrong hashmap key value data type. Key should be a string or keyword and value should be a value"));
+                return Err(format!("Wrong hashmap key value data type. Key should be a string or keyword and value should be a value").into());
             }
         }
     }
-    return Ok(SExpr::owned_value(OwnedValue::Map(
-        OwnedMap::from_hash_map(hashmap),
-    )));
+    return Ok(SExpr::owned_value(OwnedValue::Map(map)));
+}
+
+fn merge_field_names(names: &mut Vec<String>, fields: Vec<String>) -> Result<(), DovahkiinError> {
+    for field in fields {
+        let (_, new_field) = check_field(names, &field, true)?;
+        if new_field {
+            names.push(field);
+        }
+    }
+    Ok(())
 }
 
-pub fn merge<'a>(exprs: Vec<SExpr<'a>>) -> Result<SExpr<'a>, String> {
+pub fn merge<'a>(exprs: Vec<SExpr<'a>>) -> Result<SExpr<'a>, DovahkiinError> {
+    if exprs.first().map(sketch::is_sketch).unwrap_or(false) {
+        return sketch::merge(exprs);
+    }
+    if exprs.first().map(persistent::is_pmap).unwrap_or(false) {
+        return persistent::merge(exprs);
+    }
     let mut value_map = HashMap::new();
     let mut field_names = Vec::new();
     for expr in exprs {
         if let SExpr::Value(val) = expr {
             match val {
                 Value::Shared(SharedValue::Map(m)) => {
-                    let map = m.map;
-                    let mut fields = m.fields;
-                    for (k, v) in map.into_iter() {
+                    for (k, v) in m.iter() {
                         // TODO: try not own it
                         value_map.insert(k, v.owned());
                     }
-                    field_names.append(&mut fields);
+                    merge_field_names(&mut field_names, m.fields().to_vec())?;
                 }
                 Value::Owned(OwnedValue::Map(m)) => {
-                    let map = m.map;
-                    let mut fields = m.fields;
-                    for (k, v) in map.into_iter() {
+                    for (k, v) in m.map.into_iter() {
                         value_map.insert(k, v);
                     }
-                    field_names.append(&mut fields);
+                    merge_field_names(&mut field_names, m.fields)?;
                 }
-                _ => return Err(format!("Only map value can be merged. Found {:?}", val)),
+                _ => return Err(format!("Only map value can be merged. Found {:?}", val).into()),
             }
         }
     }
-    field_names.dedup();
     Ok(SExpr::owned_value(OwnedValue::Map(OwnedMap {
         map: value_map,
         fields: field_names,
     })))
 }
 
-pub fn conj(mut exprs: Vec<SExpr>) -> Result<SExpr, String> {
+pub fn conj(mut exprs: Vec<SExpr>) -> Result<SExpr, DovahkiinError> {
+    if exprs.first().map(persistent::is_pvec).unwrap_or(false) {
+        return persistent::conj(exprs);
+    }
     let list = stream::to_vec(exprs.remove(0));
     if let Ok(SExpr::Vec(mut vec)) = list {
         vec.append(&mut exprs);
         return Ok(SExpr::Vec(vec));
     } else {
-        return Err(format!("Cannot concat. {:?}", list));
+        return Err(format!("Cannot concat. {:?}", list).into());
     }
 }
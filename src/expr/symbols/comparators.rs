@@ -1,8 +1,12 @@
 use log::trace;
 
+use super::utils::type_of;
 use super::*;
+use crate::error::DovahkiinError;
+use crate::types::vectorized::CompareOp;
+use crate::types::Type;
 
-pub fn equals(mut exprs: Vec<SExpr>) -> Result<SExpr, String> {
+pub fn equals(mut exprs: Vec<SExpr>) -> Result<SExpr, DovahkiinError> {
     let last_expr = exprs.pop().unwrap();
     let last = last_expr.val();
     for expr in exprs {
@@ -15,7 +19,7 @@ pub fn equals(mut exprs: Vec<SExpr>) -> Result<SExpr, String> {
     return Ok(SExpr::owned_value(OwnedValue::Bool(true)));
 }
 
-pub fn not_equals(mut exprs: Vec<SExpr>) -> Result<SExpr, String> {
+pub fn not_equals(mut exprs: Vec<SExpr>) -> Result<SExpr, DovahkiinError> {
     return Ok(SExpr::owned_value(OwnedValue::Bool({
         let l = exprs.pop();
         let r = exprs.pop();
@@ -36,23 +40,23 @@ macro_rules! reduce {
                             return Ok(SExpr::owned_value(OwnedValue::Bool(false)));
                         }
                     } else {
-                        return Err(format!(
-                            "Type not match, expect {} found {:?}",
-                            stringify!($type),
-                            val
-                        ));
+                        return Err(DovahkiinError::Type {
+                            expected: Type::$type.into(),
+                            found: type_of(val),
+                            span: None,
+                        });
                     }
                 }
                 Ok(SExpr::owned_value(OwnedValue::Bool(true)))
             } else {
-                Err(format!(
-                    "Type not match on the first value, expect {} found {:?}",
-                    stringify!($type),
-                    first
-                ))
+                Err(DovahkiinError::Type {
+                    expected: Type::$type.into(),
+                    found: type_of(first),
+                    span: None,
+                })
             }
         } else {
-            Err("Cannot do reduce on values".to_string())
+            Err("Cannot do reduce on values".into())
         }
     }};
 }
@@ -81,7 +85,10 @@ macro_rules! gte_ {
     }};
 }
 
-pub fn lt(values: Vec<SExpr>) -> Result<SExpr, String> {
+pub fn lt(values: Vec<SExpr>) -> Result<SExpr, DovahkiinError> {
+    if values.iter().any(vectorized::is_array) {
+        return vectorized::compare(values, CompareOp::Lt);
+    }
     match values.get(0).unwrap().val() {
         Some(SharedValue::U8(_)) => lt_!(U8, values),
         Some(SharedValue::U16(_)) => lt_!(U16, values),
@@ -93,11 +100,15 @@ pub fn lt(values: Vec<SExpr>) -> Result<SExpr, String> {
         Some(SharedValue::I64(_)) => lt_!(I64, values),
         Some(SharedValue::F32(_)) => lt_!(F32, values),
         Some(SharedValue::F64(_)) => lt_!(F64, values),
-        _ => Err(format!("Type cannot be compared: {:?}", values)),
+        Some(SharedValue::Id(_)) => lt_!(Id, values),
+        _ => Err(format!("Type cannot be compared: {:?}", values).into()),
     }
 }
 
-pub fn lte(values: Vec<SExpr>) -> Result<SExpr, String> {
+pub fn lte(values: Vec<SExpr>) -> Result<SExpr, DovahkiinError> {
+    if values.iter().any(vectorized::is_array) {
+        return vectorized::compare(values, CompareOp::Lte);
+    }
     match values.get(0).unwrap().val() {
         Some(SharedValue::U8(_)) => lte_!(U8, values),
         Some(SharedValue::U16(_)) => lte_!(U16, values),
@@ -109,11 +120,15 @@ pub fn lte(values: Vec<SExpr>) -> Result<SExpr, String> {
         Some(SharedValue::I64(_)) => lte_!(I64, values),
         Some(SharedValue::F32(_)) => lte_!(F32, values),
         Some(SharedValue::F64(_)) => lte_!(F64, values),
-        _ => Err(format!("Type cannot be compared: {:?}", values)),
+        Some(SharedValue::Id(_)) => lte_!(Id, values),
+        _ => Err(format!("Type cannot be compared: {:?}", values).into()),
     }
 }
 
-pub fn gt(values: Vec<SExpr>) -> Result<SExpr, String> {
+pub fn gt(values: Vec<SExpr>) -> Result<SExpr, DovahkiinError> {
+    if values.iter().any(vectorized::is_array) {
+        return vectorized::compare(values, CompareOp::Gt);
+    }
     match values.get(0).unwrap().val() {
         Some(SharedValue::U8(_)) => gt_!(U8, values),
         Some(SharedValue::U16(_)) => gt_!(U16, values),
@@ -125,11 +140,15 @@ pub fn gt(values: Vec<SExpr>) -> Result<SExpr, String> {
         Some(SharedValue::I64(_)) => gt_!(I64, values),
         Some(SharedValue::F32(_)) => gt_!(F32, values),
         Some(SharedValue::F64(_)) => gt_!(F64, values),
-        _ => Err(format!("Type cannot be compared: {:?}", values)),
+        Some(SharedValue::Id(_)) => gt_!(Id, values),
+        _ => Err(format!("Type cannot be compared: {:?}", values).into()),
     }
 }
 
-pub fn gte(values: Vec<SExpr>) -> Result<SExpr, String> {
+pub fn gte(values: Vec<SExpr>) -> Result<SExpr, DovahkiinError> {
+    if values.iter().any(vectorized::is_array) {
+        return vectorized::compare(values, CompareOp::Gte);
+    }
     match values.get(0).unwrap().val() {
         Some(SharedValue::U8(_)) => gte_!(U8, values),
         Some(SharedValue::U16(_)) => gte_!(U16, values),
@@ -141,6 +160,7 @@ pub fn gte(values: Vec<SExpr>) -> Result<SExpr, String> {
         Some(SharedValue::I64(_)) => gte_!(I64, values),
         Some(SharedValue::F32(_)) => gte_!(F32, values),
         Some(SharedValue::F64(_)) => gte_!(F64, values),
-        _ => Err(format!("Type cannot be compared: {:?}", values)),
+        Some(SharedValue::Id(_)) => gte_!(Id, values),
+        _ => Err(format!("Type cannot be compared: {:?}", values).into()),
     }
 }
@@ -1,25 +1,21 @@
-use crate::expr::interpreter::Envorinment;
+use crate::error::DovahkiinError;
+use crate::expr::interpreter::{eval_all, Envorinment};
 use crate::types::Map;
 
-use super::bindings::{bind, bind_by_name};
-use super::lambda::{eval_lambda, lambda_placeholder};
+use super::bindings::{bind_global, bind_global_by_name};
+use super::lambda::lambda_placeholder;
+use super::tail::eval_lambda;
 use super::*;
 
 pub fn eval_function<'a>(
     func_expr: &SExpr<'a>,
     params: Vec<SExpr<'a>>,
     env: &mut Envorinment<'a>,
-) -> Result<SExpr<'a>, String> {
+) -> Result<SExpr<'a>, DovahkiinError> {
     match func_expr {
         &SExpr::ISymbol(symbol_id, ref name) => {
-            let env_bind;
-            let bindings = env.get_mut_bindings();
-            env_bind = if let Some(binding_list) = bindings.get(&symbol_id) {
-                binding_list.front().cloned()
-            } else {
-                None
-            };
-            if let Some(env_bind) = env_bind {
+            if let Some(env_bind) = env.lookup(symbol_id) {
+                let params = eval_all(params, env)?;
                 return eval_lambda(env_bind, params, env);
             } else {
                 // internal functions
@@ -36,13 +32,15 @@ pub fn eval_function<'a>(
                             }
                             evaled_params
                         };
-                        return symbol.eval(exprs, env);
+                        let res = symbol.eval(exprs, env)?;
+                        env.check_size(&res)?;
+                        return Ok(res);
                     }
                     _ => {
-                        return Err(format!(
-                            "Cannot find symbol \'{}\', id: {}",
-                            name, symbol_id
-                        ))
+                        return Err(DovahkiinError::UnboundSymbol {
+                            name: name.clone(),
+                            span: None,
+                        })
                     }
                 }
             }
@@ -52,14 +50,17 @@ pub fn eval_function<'a>(
             let symbol_name = symbol_name.clone();
             return eval_function(&SExpr::ISymbol(symbol_id, symbol_name), params, env);
         }
-        &SExpr::LAMBDA(_, _) => return eval_lambda(Rc::new(func_expr.clone()), params, env),
+        &SExpr::LAMBDA(_, _, _) => {
+            let params = eval_all(params, env)?;
+            return eval_lambda(Rc::new(func_expr.clone()), params, env);
+        }
         &SExpr::Value(ref v) => return eval_value(v, params),
         _ => {}
     }
-    return Err(format!("{:?} is not a function", func_expr));
+    return Err(format!("{:?} is not a function", func_expr).into());
 }
 
-fn eval_value<'a>(v: &Value<'a>, params: Vec<SExpr<'a>>) -> Result<SExpr<'a>, String> {
+fn eval_value<'a>(v: &Value<'a>, params: Vec<SExpr<'a>>) -> Result<SExpr<'a>, DovahkiinError> {
     match &v {
         &Value::Shared(sv) => {
             match sv {
@@ -69,7 +70,8 @@ fn eval_value<'a>(v: &Value<'a>, params: Vec<SExpr<'a>>) -> Result<SExpr<'a>, St
                         return Err(format!(
                             "get from map can only take one parameter, found {}",
                             params.len()
-                        ));
+                        )
+                        .into());
                     }
                     if let Some(Some(SharedValue::Map(ref m))) =
                         params.get(0).map(|expr| expr.val())
@@ -80,7 +82,8 @@ fn eval_value<'a>(v: &Value<'a>, params: Vec<SExpr<'a>>) -> Result<SExpr<'a>, St
                             "When use string value as function, \
                         only one map parameter is accepted, found {:?}",
                             params
-                        ));
+                        )
+                        .into());
                     }
                 }
                 SharedValue::U64(index) => {
@@ -89,22 +92,22 @@ fn eval_value<'a>(v: &Value<'a>, params: Vec<SExpr<'a>>) -> Result<SExpr<'a>, St
                         return Err(format!(
                             "get by index/id can only take one parameter, found {}",
                             params.len()
-                        ));
+                        )
+                        .into());
                     }
                     match params.get(0).map(|expr| expr.val()) {
                         Some(Some(SharedValue::Map(ref m))) => {
-                            let val = m.get_by_key_id(**index).clone();
+                            let val = m.get_by_key_id(**index);
                             return Ok(SExpr::owned_value(val.owned()));
                         }
                         Some(Some(SharedValue::Array(ref arr))) => {
                             return Ok(SExpr::owned_value(
                                 arr.get(**index as usize)
-                                    .cloned()
                                     .unwrap_or(SharedValue::Null)
                                     .owned(),
                             ))
                         }
-                        _ => return Err(format!("Data type not accepted for {:?}", params)),
+                        _ => return Err(format!("Data type not accepted for {:?}", params).into()),
                     }
                 }
                 SharedValue::Map(ref m) => {
@@ -112,21 +115,23 @@ fn eval_value<'a>(v: &Value<'a>, params: Vec<SExpr<'a>>) -> Result<SExpr<'a>, St
                         return Err(format!(
                             "get map can only take one parameter, found {}",
                             params.len()
-                        ));
+                        )
+                        .into());
                     }
                     match params.get(0).map(|v| v.val()) {
                         Some(Some(SharedValue::String(str_key))) => {
-                            return Ok(SExpr::shared_value(m.get(str_key).clone()))
+                            return Ok(SExpr::shared_value(m.get(str_key)))
                         }
                         Some(Some(SharedValue::U64(key_id))) => {
-                            return Ok(SExpr::shared_value(m.get_by_key_id(*key_id).clone()))
+                            return Ok(SExpr::shared_value(m.get_by_key_id(*key_id)))
                         }
                         _ => {
                             return Err(format!(
                                 "Key format not accepted, expect one string or u64\
                                  Found {:?}",
                                 params
-                            ));
+                            )
+                            .into());
                         }
                     }
                 }
@@ -135,14 +140,14 @@ fn eval_value<'a>(v: &Value<'a>, params: Vec<SExpr<'a>>) -> Result<SExpr<'a>, St
                         return Err(format!(
                             "get map can only take one parameter, found {}",
                             params.len()
-                        ));
+                        )
+                        .into());
                     }
                     match params.get(0).map(|v| v.val()) {
                         Some(Some(SharedValue::U64(key_id))) => {
                             return Ok(SExpr::shared_value(
                                 array
                                     .get(*key_id as usize)
-                                    .cloned()
                                     .unwrap_or(SharedValue::Null),
                             ))
                         }
@@ -151,12 +156,13 @@ fn eval_value<'a>(v: &Value<'a>, params: Vec<SExpr<'a>>) -> Result<SExpr<'a>, St
                                 "Index format not accepted, expect u64\
                                  Found {:?}",
                                 params
-                            ));
+                            )
+                            .into());
                         }
                     }
                 }
                 _ => {
-                    return Err(format!("value {:?} cannot be used as a function", v));
+                    return Err(format!("value {:?} cannot be used as a function", v).into());
                 }
             }
         }
@@ -168,7 +174,8 @@ fn eval_value<'a>(v: &Value<'a>, params: Vec<SExpr<'a>>) -> Result<SExpr<'a>, St
                         return Err(format!(
                             "get from map can only take one parameter, found {}",
                             params.len()
-                        ));
+                        )
+                        .into());
                     }
                     if let Some(Some(SharedValue::Map(ref m))) =
                         params.get(0).map(|expr| expr.val())
@@ -179,7 +186,8 @@ fn eval_value<'a>(v: &Value<'a>, params: Vec<SExpr<'a>>) -> Result<SExpr<'a>, St
                             "When use string value as function, \
                         only one map parameter is accepted, found {:?}",
                             params
-                        ));
+                        )
+                        .into());
                     }
                 }
                 OwnedValue::U64(index) => {
@@ -188,22 +196,22 @@ fn eval_value<'a>(v: &Value<'a>, params: Vec<SExpr<'a>>) -> Result<SExpr<'a>, St
                         return Err(format!(
                             "get by index/id can only take one parameter, found {}",
                             params.len()
-                        ));
+                        )
+                        .into());
                     }
                     match params.get(0).map(|expr| expr.val()) {
                         Some(Some(SharedValue::Map(ref m))) => {
-                            let val = m.get_by_key_id(*index).clone();
+                            let val = m.get_by_key_id(*index);
                             return Ok(SExpr::owned_value(val.owned()));
                         }
                         Some(Some(SharedValue::Array(ref arr))) => {
                             return Ok(SExpr::owned_value(
                                 arr.get(*index as usize)
-                                    .cloned()
                                     .unwrap_or(SharedValue::Null)
                                     .owned(),
                             ))
                         }
-                        _ => return Err(format!("Data type not accepted for {:?}", params)),
+                        _ => return Err(format!("Data type not accepted for {:?}", params).into()),
                     }
                 }
                 OwnedValue::Map(ref m) => {
@@ -211,7 +219,8 @@ fn eval_value<'a>(v: &Value<'a>, params: Vec<SExpr<'a>>) -> Result<SExpr<'a>, St
                         return Err(format!(
                             "get map can only take one parameter, found {}",
                             params.len()
-                        ));
+                        )
+                        .into());
                     }
                     match params.get(0).map(|v| v.val()) {
                         Some(Some(SharedValue::String(str_key))) => {
@@ -225,7 +234,8 @@ fn eval_value<'a>(v: &Value<'a>, params: Vec<SExpr<'a>>) -> Result<SExpr<'a>, St
                                 "Key format not accepted, expect one string or u64\
                                  Found {:?}",
                                 params
-                            ));
+                            )
+                            .into());
                         }
                     }
                 }
@@ -234,7 +244,8 @@ fn eval_value<'a>(v: &Value<'a>, params: Vec<SExpr<'a>>) -> Result<SExpr<'a>, St
                         return Err(format!(
                             "get map can only take one parameter, found {}",
                             params.len()
-                        ));
+                        )
+                        .into());
                     }
                     match params.get(0).map(|v| v.val()) {
                         Some(Some(SharedValue::U64(key_id))) => {
@@ -250,30 +261,31 @@ fn eval_value<'a>(v: &Value<'a>, params: Vec<SExpr<'a>>) -> Result<SExpr<'a>, St
                                 "Index format not accepted, expect u64\
                                  Found {:?}",
                                 params
-                            ));
+                            )
+                            .into());
                         }
                     }
                 }
                 _ => {
-                    return Err(format!("value {:?} cannot be used as a function", v));
+                    return Err(format!("value {:?} cannot be used as a function", v).into());
                 }
             }
         }
     }
 }
 
-pub fn defn<'a>(env: &mut Envorinment<'a>, mut exprs: Vec<SExpr<'a>>) -> Result<SExpr<'a>, String> {
+pub fn defn<'a>(
+    env: &mut Envorinment<'a>,
+    mut exprs: Vec<SExpr<'a>>,
+) -> Result<SExpr<'a>, DovahkiinError> {
     let name = exprs.remove(0);
-    let lambda = lambda_placeholder(exprs)?;
+    let lambda = lambda_placeholder(exprs, env)?;
     if let SExpr::Symbol(name) = name {
-        bind_by_name(env, &name, lambda);
+        bind_global_by_name(env, &name, lambda);
     } else if let SExpr::ISymbol(id, _) = name {
-        bind(env, id, lambda);
+        bind_global(env, id, lambda);
     } else {
-        return Err(format!(
-            "Function name should be a symbol, found {:?}",
-            name
-        ));
+        return Err(format!("Function name should be a symbol, found {:?}", name).into());
     }
     return Ok(SExpr::Value(Value::null()));
 }
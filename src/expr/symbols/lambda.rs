@@ -1,6 +1,12 @@
 use super::bindings::*;
 use super::*;
-pub fn lambda_placeholder(mut exprs: Vec<SExpr>) -> Result<SExpr, String> {
+use crate::error::DovahkiinError;
+use std::collections::HashSet;
+
+pub fn lambda_placeholder<'a>(
+    mut exprs: Vec<SExpr<'a>>,
+    env: &mut Envorinment<'a>,
+) -> Result<SExpr<'a>, DovahkiinError> {
     let params = exprs.remove(0);
     let params_list = if let SExpr::Vec(symbols) = params {
         let mut list = Vec::new();
@@ -9,55 +15,97 @@ pub fn lambda_placeholder(mut exprs: Vec<SExpr>) -> Result<SExpr, String> {
                 SExpr::Symbol(name) => SExpr::ISymbol(hash_str(&name), name),
                 SExpr::ISymbol(id, name) => SExpr::ISymbol(id, name),
                 _ => {
-                    return Err(format!(
-                        "lambda can only bind to symbols, found {:?}",
-                        symbol
-                    ))
+                    return Err(
+                        format!("lambda can only bind to symbols, found {:?}", symbol).into(),
+                    )
                 }
             });
         }
         list
     } else {
-        return Err(format!("lambda form should be vector, found {:?}", params));
+        return Err(format!("lambda form should be vector, found {:?}", params).into());
+    };
+    let captured = if env.lexical {
+        capture(&params_list, &exprs, env)
+    } else {
+        vec![]
     };
-    Ok(SExpr::LAMBDA(params_list, exprs))
+    Ok(SExpr::LAMBDA(params_list, exprs, captured))
 }
 
-pub fn eval_lambda<'a>(
-    lambda_expr: Rc<SExpr<'a>>,
-    params: Vec<SExpr<'a>>,
-    env: &mut Envorinment<'a>,
-) -> Result<SExpr<'a>, String> {
-    if let SExpr::LAMBDA(ref params_list, ref body) = &*lambda_expr {
-        {
-            // bind parameters
-            let mut param_pos = 0;
-            for param in params {
-                let lambda_param = params_list.get(param_pos).unwrap();
-                if let &SExpr::ISymbol(id, _) = lambda_param {
-                    bind(env, id, param);
-                } else {
-                    return Err(format!(
-                        "Expect ISymbol for lambda form, found {:?}",
-                        lambda_param
-                    ));
-                }
-                param_pos += 1;
+fn symbols_in<'a>(exprs: &[SExpr<'a>], symbols: &mut Vec<(u64, String)>) {
+    for expr in exprs {
+        match expr {
+            SExpr::ISymbol(id, name) => symbols.push((*id, name.clone())),
+            SExpr::Symbol(name) => symbols.push((hash_str(name), name.clone())),
+            SExpr::List(exprs, _) | SExpr::Vec(exprs) | SExpr::META(exprs) => {
+                symbols_in(exprs, symbols)
             }
+            SExpr::LAMBDA(_, body, _) => symbols_in(body, symbols),
+            _ => {}
         }
-        let mut last_result = SExpr::Value(Value::null());
-        for body_line in body {
-            // eval function body by cloning expression
-            last_result = body_line.clone().eval(env)?;
+    }
+}
+
+// Local bindings the body refers to, globals are left to be resolved when called so
+// functions can refer to themselves and to later definitions
+fn capture<'a>(params: &[SExpr<'a>], body: &[SExpr<'a>], env: &Envorinment<'a>) -> Vec<SExpr<'a>> {
+    let params: HashSet<u64> = params
+        .iter()
+        .filter_map(|p| match p {
+            SExpr::ISymbol(id, _) => Some(*id),
+            _ => None,
+        })
+        .collect();
+    let mut symbols = vec![];
+    symbols_in(body, &mut symbols);
+    let mut seen = HashSet::new();
+    let mut captured = vec![];
+    for (id, name) in symbols {
+        if params.contains(&id) || !seen.insert(id) {
+            continue;
         }
-        for lambda_param in params_list {
-            // unbind parameters
-            if let &SExpr::ISymbol(id, _) = lambda_param {
-                unbind(env, id);
-            }
+        if let Some(val) = env.bindings.get(&id).and_then(|list| list.front()) {
+            captured.push(SExpr::ISymbol(id, name));
+            captured.push((**val).clone());
         }
-        Ok(last_result)
-    } else {
-        return Err(format!("Expect lambda expression, found {:?}", lambda_expr));
     }
+    captured
+}
+
+// Checked before binding anything so nothing is left bound on errors
+pub fn bind_all<'a>(
+    params_list: &[SExpr<'a>],
+    params: Vec<SExpr<'a>>,
+    captured: &[SExpr<'a>],
+    env: &mut Envorinment<'a>,
+) -> Result<Vec<u64>, DovahkiinError> {
+    if params.len() > params_list.len() {
+        return Err(DovahkiinError::Arity {
+            expected: Arity::AtMost(params_list.len()),
+            found: params.len(),
+            span: None,
+        });
+    }
+    let mut bindings = Vec::new();
+    for pair in captured.chunks(2) {
+        if let [SExpr::ISymbol(id, _), val] = pair {
+            bindings.push((*id, val.clone()));
+        } else {
+            return Err(format!("Invalid captured binding {:?}", pair).into());
+        }
+    }
+    for (lambda_param, param) in params_list.iter().zip(params) {
+        if let &SExpr::ISymbol(id, _) = lambda_param {
+            bindings.push((id, param));
+        } else {
+            return Err(format!("Expect ISymbol for lambda form, found {:?}", lambda_param).into());
+        }
+    }
+    let mut ids = Vec::with_capacity(bindings.len());
+    for (id, val) in bindings {
+        bind(env, id, val);
+        ids.push(id);
+    }
+    Ok(ids)
 }
@@ -1,7 +1,11 @@
 use super::utils::is_true;
 use super::*;
+use crate::error::DovahkiinError;
 
-pub fn or<'a>(exprs: Vec<SExpr<'a>>, env: &mut Envorinment<'a>) -> Result<SExpr<'a>, String> {
+pub fn or<'a>(
+    exprs: Vec<SExpr<'a>>,
+    env: &mut Envorinment<'a>,
+) -> Result<SExpr<'a>, DovahkiinError> {
     for expr in exprs {
         if is_true(&expr.eval(env)?) {
             return Ok(SExpr::owned_value(OwnedValue::Bool(true)));
@@ -10,7 +14,10 @@ pub fn or<'a>(exprs: Vec<SExpr<'a>>, env: &mut Envorinment<'a>) -> Result<SExpr<
     return Ok(SExpr::owned_value(OwnedValue::Bool(false)));
 }
 
-pub fn and<'a>(exprs: Vec<SExpr<'a>>, env: &mut Envorinment<'a>) -> Result<SExpr<'a>, String> {
+pub fn and<'a>(
+    exprs: Vec<SExpr<'a>>,
+    env: &mut Envorinment<'a>,
+) -> Result<SExpr<'a>, DovahkiinError> {
     for expr in exprs {
         if !is_true(&expr.eval(env)?) {
             return Ok(SExpr::owned_value(OwnedValue::Bool(false)));
@@ -19,12 +26,12 @@ pub fn and<'a>(exprs: Vec<SExpr<'a>>, env: &mut Envorinment<'a>) -> Result<SExpr
     return Ok(SExpr::owned_value(OwnedValue::Bool(true)));
 }
 
-pub fn cond<'a>(exprs: Vec<SExpr<'a>>, env: &mut Envorinment<'a>) -> Result<SExpr<'a>, String> {
+pub fn cond<'a>(
+    exprs: Vec<SExpr<'a>>,
+    env: &mut Envorinment<'a>,
+) -> Result<SExpr<'a>, DovahkiinError> {
     if exprs.len() % 2 == 1 {
-        return Err(format!(
-            "cond need even number of parameters, found {}",
-            exprs.len()
-        ));
+        return Err(format!("cond need even number of parameters, found {}", exprs.len()).into());
     }
     let mut exprs = exprs.into_iter();
     while let (Some(condition), Some(expr)) = (exprs.next(), exprs.next()) {
@@ -34,3 +41,14 @@ pub fn cond<'a>(exprs: Vec<SExpr<'a>>, env: &mut Envorinment<'a>) -> Result<SExp
     }
     return Ok(SExpr::Value(Value::null()));
 }
+
+pub fn not<'a>(mut exprs: Vec<SExpr<'a>>) -> Result<SExpr<'a>, DovahkiinError> {
+    let expr = exprs.pop().unwrap();
+    Ok(SExpr::owned_value(OwnedValue::Bool(!is_true(&expr))))
+}
+
+pub fn is_nil<'a>(mut exprs: Vec<SExpr<'a>>) -> Result<SExpr<'a>, DovahkiinError> {
+    let expr = exprs.pop().unwrap();
+    let nil = matches!(expr.val(), Some(SharedValue::Null));
+    Ok(SExpr::owned_value(OwnedValue::Bool(nil)))
+}
@@ -1,9 +1,50 @@
 use super::*;
+use crate::error::DovahkiinError;
+use crate::types::Id;
 
-pub fn do_<'a>(exprs: Vec<SExpr<'a>>, env: &mut Envorinment<'a>) -> Result<SExpr<'a>, String> {
+pub fn do_<'a>(
+    exprs: Vec<SExpr<'a>>,
+    env: &mut Envorinment<'a>,
+) -> Result<SExpr<'a>, DovahkiinError> {
     let mut result = SExpr::Value(Value::null());
     for expr in exprs {
         result = expr.eval(env)?;
     }
     return Ok(result);
 }
+
+// Content addressed id of a value, equal values always have the same id
+pub fn id_of(expr: SExpr) -> Result<SExpr, DovahkiinError> {
+    match expr.owned_val() {
+        Some(val) => Ok(SExpr::owned_value(OwnedValue::Id(Id::from_obj(&val)))),
+        None => Err("Only values can have ids".into()),
+    }
+}
+
+pub fn new_id<'a>() -> Result<SExpr<'a>, DovahkiinError> {
+    Ok(SExpr::owned_value(OwnedValue::Id(Id::generate())))
+}
+
+// Joins strings, chars and numbers into one string, nulls are left out
+pub fn str_(exprs: Vec<SExpr>) -> Result<SExpr, DovahkiinError> {
+    let mut res = String::new();
+    for expr in exprs {
+        match expr.owned_val() {
+            Some(OwnedValue::String(s)) => res.push_str(&s),
+            Some(OwnedValue::Char(c)) => res.push(c),
+            Some(OwnedValue::Null) => {}
+            Some(OwnedValue::U8(n)) => res.push_str(&n.to_string()),
+            Some(OwnedValue::U16(n)) => res.push_str(&n.to_string()),
+            Some(OwnedValue::U32(n)) => res.push_str(&n.to_string()),
+            Some(OwnedValue::U64(n)) => res.push_str(&n.to_string()),
+            Some(OwnedValue::I8(n)) => res.push_str(&n.to_string()),
+            Some(OwnedValue::I16(n)) => res.push_str(&n.to_string()),
+            Some(OwnedValue::I32(n)) => res.push_str(&n.to_string()),
+            Some(OwnedValue::I64(n)) => res.push_str(&n.to_string()),
+            Some(OwnedValue::F32(n)) => res.push_str(&n.to_string()),
+            Some(OwnedValue::F64(n)) => res.push_str(&n.to_string()),
+            other => return Err(format!("Cannot make string from {:?}", other).into()),
+        }
+    }
+    Ok(SExpr::owned_value(OwnedValue::String(res)))
+}
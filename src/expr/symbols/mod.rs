@@ -1,6 +1,8 @@
 use bifrost_hasher::hash_str;
 use bifrost_plugins::hash_ident;
+use crate::error::{Arity, DovahkiinError};
 use crate::expr::SExpr;
+use crate::types::{TensorOp, TensorReduce};
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::fmt::Debug;
@@ -18,7 +20,14 @@ mod lambda;
 mod logic;
 pub mod misc;
 mod num_types;
+mod persistent;
+mod records;
+mod similarity;
+mod sketch;
 mod stream;
+mod tail;
+mod tensor;
+mod vectorized;
 pub mod utils;
 
 pub trait Symbol: Sync + Debug {
@@ -26,7 +35,7 @@ pub trait Symbol: Sync + Debug {
         &self,
         exprs: Vec<SExpr<'a>>,
         env: &mut Envorinment<'a>,
-    ) -> Result<SExpr<'a>, String>;
+    ) -> Result<SExpr<'a>, DovahkiinError>;
     fn is_macro(&self) -> bool;
 }
 
@@ -61,7 +70,7 @@ macro_rules! defsymbols {
             #[derive(Debug)]
             pub struct $name;
             impl Symbol for $name {
-                fn eval<'a>(&self, exprs: Vec<SExpr<'a>>, env: &mut Envorinment<'a>) -> Result<SExpr<'a>, String> where Self: Sized {
+                fn eval<'a>(&self, exprs: Vec<SExpr<'a>>, env: &mut Envorinment<'a>) -> Result<SExpr<'a>, DovahkiinError> where Self: Sized {
                     $eval(exprs, env)
                 }
                 fn is_macro(&self) -> bool {
@@ -104,50 +113,38 @@ where
     ISYMBOL_MAP.insert(symbol_name, symbol_impl)
 }
 
-fn check_num_params(num: usize, params: &Vec<SExpr>) -> Result<(), String> {
-    if num != params.len() {
-        Err(format!(
-            "Parameter number not match. Except {} but found {}",
-            num,
-            params.len()
-        ))
-    } else {
+fn check_arity(expected: Arity, params: &Vec<SExpr>) -> Result<(), DovahkiinError> {
+    let found = params.len();
+    let matched = match expected {
+        Arity::Exactly(num) => found == num,
+        Arity::AtLeast(num) => found >= num,
+        Arity::AtMost(num) => found <= num,
+    };
+    if matched {
         Ok(())
+    } else {
+        Err(DovahkiinError::Arity {
+            expected,
+            found,
+            span: None,
+        })
     }
 }
 
-fn check_params_not_empty(params: &Vec<SExpr>) -> Result<(), String> {
-    if params.len() == 0 {
-        Err(format!(
-            "Parameter number not match. Expected some but found empty"
-        ))
-    } else {
-        Ok(())
-    }
+fn check_num_params(num: usize, params: &Vec<SExpr>) -> Result<(), DovahkiinError> {
+    check_arity(Arity::Exactly(num), params)
 }
 
-fn check_params_not_least_than(num: usize, params: &Vec<SExpr>) -> Result<(), String> {
-    if params.len() < num {
-        Err(format!(
-            "Parameter number not match, Expected at least {} but found {}",
-            num,
-            params.len()
-        ))
-    } else {
-        Ok(())
-    }
+fn check_params_not_empty(params: &Vec<SExpr>) -> Result<(), DovahkiinError> {
+    check_arity(Arity::AtLeast(1), params)
 }
 
-fn check_params_not_greater_than(num: usize, params: &Vec<SExpr>) -> Result<(), String> {
-    if params.len() > num {
-        Err(format!(
-            "Parameter number not match, Expected at most {} but found {}",
-            num,
-            params.len()
-        ))
-    } else {
-        Ok(())
-    }
+fn check_params_not_least_than(num: usize, params: &Vec<SExpr>) -> Result<(), DovahkiinError> {
+    check_arity(Arity::AtLeast(num), params)
+}
+
+fn check_params_not_greater_than(num: usize, params: &Vec<SExpr>) -> Result<(), DovahkiinError> {
+    check_arity(Arity::AtMost(num), params)
 }
 
 fn split_pair(mut exprs: Vec<SExpr>) -> (SExpr, SExpr) {
@@ -220,7 +217,7 @@ defsymbols! {
     };
     "lambda" => Lambda, true, |exprs, env| {
         check_params_not_least_than(2, &exprs)?;
-        lambda::lambda_placeholder(exprs)
+        lambda::lambda_placeholder(exprs, env)
     };
     "defunc" => DefineFunc, true, |exprs, env| {
         check_params_not_least_than(3, &exprs)?;
@@ -243,6 +240,13 @@ defsymbols! {
     "do" => Do, false, |exprs, env| {
         misc::do_(exprs, env)
     };
+    "loop" => Loop, true, |exprs, env| {
+        check_params_not_least_than(2, &exprs)?;
+        tail::loop_(exprs, env)
+    };
+    "recur" => Recur, true, |exprs, env| {
+        tail::recur()
+    };
     "to_vec" => ToVec, false, |mut exprs, env| {
         check_num_params(1, &exprs)?;
         stream::to_vec(exprs.pop().unwrap())
@@ -258,6 +262,9 @@ defsymbols! {
     "concat" => Concat, false, |exprs, env| {
         collections::concat(exprs)
     };
+    "str" => Str, false, |exprs, env| {
+        misc::str_(exprs)
+    };
     "size" => Size, false, |exprs, env| {
         collections::size(exprs)
     };
@@ -270,6 +277,23 @@ defsymbols! {
     "conj" => Conjuction, false, |exprs, env| {
         collections::conj(exprs)
     };
+    "persistent" => Persistent, false, |mut exprs, env| {
+        check_num_params(1, &exprs)?;
+        persistent::persistent(exprs.pop().unwrap())
+    };
+    "assoc" => Assoc, false, |exprs, env| {
+        check_params_not_least_than(3, &exprs)?;
+        persistent::assoc(exprs)
+    };
+    "dissoc" => Dissoc, false, |exprs, env| {
+        check_params_not_empty(&exprs)?;
+        persistent::dissoc(exprs)
+    };
+    "get" => Get, false, |exprs, env| {
+        check_num_params(2, &exprs)?;
+        let (coll, key) = split_pair(exprs);
+        persistent::get(coll, key)
+    };
     "or" => Or, true, |exprs, env| {
         logic::or(exprs, env)
     };
@@ -279,6 +303,14 @@ defsymbols! {
     "cond" => Conditional, true, |exprs, env| {
         logic::cond(exprs, env)
     };
+    "not" => Not, false, |exprs, env| {
+        check_num_params(1, &exprs)?;
+        logic::not(exprs)
+    };
+    "nil?" => IsNil, false, |exprs, env| {
+        check_num_params(1, &exprs)?;
+        logic::is_nil(exprs)
+    };
     "u8" => U8, false, |exprs, env| {
         check_num_params(1, &exprs)?;
         num_types::u8(exprs.get(0).cloned().unwrap())
@@ -318,5 +350,164 @@ defsymbols! {
     "f64" => F64, false, |exprs, env| {
         check_num_params(1, &exprs)?;
         num_types::f64(exprs.get(0).cloned().unwrap())
+    };
+    "tensor" => Tensor, false, |exprs, env| {
+        check_num_params(2, &exprs)?;
+        tensor::tensor(exprs)
+    };
+    "shape" => Shape, false, |mut exprs, env| {
+        check_num_params(1, &exprs)?;
+        tensor::shape(exprs.pop().unwrap())
+    };
+    "reshape" => Reshape, false, |exprs, env| {
+        check_num_params(2, &exprs)?;
+        tensor::reshape(exprs)
+    };
+    "transpose" => Transpose, false, |exprs, env| {
+        check_params_not_empty(&exprs)?;
+        check_params_not_greater_than(2, &exprs)?;
+        tensor::transpose(exprs)
+    };
+    "tensor-slice" => TensorSlice, false, |exprs, env| {
+        check_num_params(4, &exprs)?;
+        tensor::slice(exprs)
+    };
+    "tensor-get" => TensorGet, false, |exprs, env| {
+        check_num_params(2, &exprs)?;
+        tensor::get(exprs)
+    };
+    "tensor-add" => TensorAdd, false, |exprs, env| {
+        check_num_params(2, &exprs)?;
+        tensor::binary(exprs, TensorOp::Add)
+    };
+    "tensor-sub" => TensorSub, false, |exprs, env| {
+        check_num_params(2, &exprs)?;
+        tensor::binary(exprs, TensorOp::Sub)
+    };
+    "tensor-mul" => TensorMul, false, |exprs, env| {
+        check_num_params(2, &exprs)?;
+        tensor::binary(exprs, TensorOp::Mul)
+    };
+    "tensor-div" => TensorDiv, false, |exprs, env| {
+        check_num_params(2, &exprs)?;
+        tensor::binary(exprs, TensorOp::Div)
+    };
+    "tensor-sum" => TensorSum, false, |exprs, env| {
+        check_params_not_empty(&exprs)?;
+        check_params_not_greater_than(2, &exprs)?;
+        tensor::reduce(exprs, TensorReduce::Sum)
+    };
+    "tensor-min" => TensorMin, false, |exprs, env| {
+        check_params_not_empty(&exprs)?;
+        check_params_not_greater_than(2, &exprs)?;
+        tensor::reduce(exprs, TensorReduce::Min)
+    };
+    "tensor-max" => TensorMax, false, |exprs, env| {
+        check_params_not_empty(&exprs)?;
+        check_params_not_greater_than(2, &exprs)?;
+        tensor::reduce(exprs, TensorReduce::Max)
+    };
+    "tensor-mean" => TensorMean, false, |exprs, env| {
+        check_params_not_empty(&exprs)?;
+        check_params_not_greater_than(2, &exprs)?;
+        tensor::reduce(exprs, TensorReduce::Mean)
+    };
+    "dot" => Dot, false, |exprs, env| {
+        check_num_params(2, &exprs)?;
+        similarity::dot(exprs)
+    };
+    "cosine" => Cosine, false, |exprs, env| {
+        check_num_params(2, &exprs)?;
+        similarity::cosine(exprs)
+    };
+    "l2-distance" => L2Distance, false, |exprs, env| {
+        check_num_params(2, &exprs)?;
+        similarity::l2_distance(exprs)
+    };
+    "normalize" => Normalize, false, |mut exprs, env| {
+        check_num_params(1, &exprs)?;
+        similarity::normalize(exprs.pop().unwrap())
+    };
+    "top-k" => TopK, false, |exprs, env| {
+        check_params_not_least_than(4, &exprs)?;
+        check_params_not_greater_than(5, &exprs)?;
+        similarity::top_k(exprs)
+    };
+    "sum" => Sum, false, |exprs, env| {
+        check_params_not_empty(&exprs)?;
+        vectorized::reduce(exprs, TensorReduce::Sum)
+    };
+    "min" => Min, false, |exprs, env| {
+        check_params_not_empty(&exprs)?;
+        vectorized::reduce(exprs, TensorReduce::Min)
+    };
+    "max" => Max, false, |exprs, env| {
+        check_params_not_empty(&exprs)?;
+        vectorized::reduce(exprs, TensorReduce::Max)
+    };
+    "mean" => Mean, false, |exprs, env| {
+        check_params_not_empty(&exprs)?;
+        vectorized::reduce(exprs, TensorReduce::Mean)
+    };
+    "to_prim_array" => ToPrimArray, false, |mut exprs, env| {
+        check_num_params(1, &exprs)?;
+        vectorized::into_prim_array(exprs.pop().unwrap())
+    };
+    "hll" => HyperLogLog, false, |exprs, env| {
+        check_params_not_greater_than(1, &exprs)?;
+        sketch::hll(exprs)
+    };
+    "count-min" => CountMin, false, |exprs, env| {
+        check_num_params(2, &exprs)?;
+        sketch::count_min(exprs)
+    };
+    "bloom" => Bloom, false, |exprs, env| {
+        check_num_params(2, &exprs)?;
+        sketch::bloom(exprs)
+    };
+    "hll-add" => HyperLogLogAdd, false, |exprs, env| {
+        check_params_not_empty(&exprs)?;
+        sketch::add(exprs, "hyperloglog")
+    };
+    "hll-count" => HyperLogLogCount, false, |mut exprs, env| {
+        check_num_params(1, &exprs)?;
+        sketch::hll_count(exprs.pop().unwrap())
+    };
+    "cms-add" => CountMinAdd, false, |exprs, env| {
+        check_params_not_empty(&exprs)?;
+        sketch::add(exprs, "count-min")
+    };
+    "cms-count" => CountMinCount, false, |exprs, env| {
+        check_num_params(2, &exprs)?;
+        sketch::cms_count(exprs)
+    };
+    "bloom-add" => BloomAdd, false, |exprs, env| {
+        check_params_not_empty(&exprs)?;
+        sketch::add(exprs, "bloom")
+    };
+    "bloom-contains?" => BloomContains, false, |exprs, env| {
+        check_num_params(2, &exprs)?;
+        sketch::bloom_contains(exprs)
+    };
+    "id-of" => IdOf, false, |mut exprs, env| {
+        check_num_params(1, &exprs)?;
+        misc::id_of(exprs.pop().unwrap())
+    };
+    "new-id" => NewId, false, |exprs, env| {
+        check_num_params(0, &exprs)?;
+        misc::new_id()
+    };
+    "deref" => Deref, false, |mut exprs, env| {
+        check_num_params(1, &exprs)?;
+        records::deref(exprs.pop().unwrap(), env)
+    };
+    "get-in-linked" => GetInLinked, false, |exprs, env| {
+        check_num_params(2, &exprs)?;
+        let (rec, path) = split_pair(exprs);
+        records::get_in_linked(rec, path, env)
+    };
+    "traverse" => Traverse, false, |exprs, env| {
+        check_num_params(3, &exprs)?;
+        records::traverse(exprs, env)
     }
 }
@@ -1,6 +1,7 @@
 use super::*;
+use crate::error::DovahkiinError;
 
-pub fn u64(value: SExpr) -> Result<SExpr, String> {
+pub fn u64(value: SExpr) -> Result<SExpr, DovahkiinError> {
     match value.val() {
         Some(SharedValue::U8(num)) => Ok(SExpr::owned_value(OwnedValue::U64(*num as u64))),
         Some(SharedValue::U16(num)) => Ok(SExpr::owned_value(OwnedValue::U64(*num as u64))),
@@ -12,11 +13,11 @@ pub fn u64(value: SExpr) -> Result<SExpr, String> {
         Some(SharedValue::I64(num)) => Ok(SExpr::owned_value(OwnedValue::U64(*num as u64))),
         Some(SharedValue::F32(num)) => Ok(SExpr::owned_value(OwnedValue::U64(*num as u64))),
         Some(SharedValue::F64(num)) => Ok(SExpr::owned_value(OwnedValue::U64(*num as u64))),
-        _ => Err("The value cannot be convert into u64".to_string()),
+        _ => Err("The value cannot be convert into u64".into()),
     }
 }
 
-pub fn u32(value: SExpr) -> Result<SExpr, String> {
+pub fn u32(value: SExpr) -> Result<SExpr, DovahkiinError> {
     match value.val() {
         Some(SharedValue::U8(num)) => Ok(SExpr::owned_value(OwnedValue::U32(*num as u32))),
         Some(SharedValue::U16(num)) => Ok(SExpr::owned_value(OwnedValue::U32(*num as u32))),
@@ -28,11 +29,11 @@ pub fn u32(value: SExpr) -> Result<SExpr, String> {
         Some(SharedValue::I64(num)) => Ok(SExpr::owned_value(OwnedValue::U32(*num as u32))),
         Some(SharedValue::F32(num)) => Ok(SExpr::owned_value(OwnedValue::U32(*num as u32))),
         Some(SharedValue::F64(num)) => Ok(SExpr::owned_value(OwnedValue::U32(*num as u32))),
-        _ => Err("The value cannot be convert into u32".to_string()),
+        _ => Err("The value cannot be convert into u32".into()),
     }
 }
 
-pub fn u16(value: SExpr) -> Result<SExpr, String> {
+pub fn u16(value: SExpr) -> Result<SExpr, DovahkiinError> {
     match value.val() {
         Some(SharedValue::U8(num)) => Ok(SExpr::owned_value(OwnedValue::U16(*num as u16))),
         Some(SharedValue::U16(num)) => Ok(SExpr::owned_value(OwnedValue::U16(*num as u16))),
@@ -44,11 +45,11 @@ pub fn u16(value: SExpr) -> Result<SExpr, String> {
         Some(SharedValue::I64(num)) => Ok(SExpr::owned_value(OwnedValue::U16(*num as u16))),
         Some(SharedValue::F32(num)) => Ok(SExpr::owned_value(OwnedValue::U16(*num as u16))),
         Some(SharedValue::F64(num)) => Ok(SExpr::owned_value(OwnedValue::U16(*num as u16))),
-        _ => Err("The value cannot be convert into u16".to_string()),
+        _ => Err("The value cannot be convert into u16".into()),
     }
 }
 
-pub fn u8(value: SExpr) -> Result<SExpr, String> {
+pub fn u8(value: SExpr) -> Result<SExpr, DovahkiinError> {
     match value.val() {
         Some(SharedValue::U8(num)) => Ok(SExpr::owned_value(OwnedValue::U8(*num as u8))),
         Some(SharedValue::U16(num)) => Ok(SExpr::owned_value(OwnedValue::U8(*num as u8))),
@@ -60,11 +61,11 @@ pub fn u8(value: SExpr) -> Result<SExpr, String> {
         Some(SharedValue::I64(num)) => Ok(SExpr::owned_value(OwnedValue::U8(*num as u8))),
         Some(SharedValue::F32(num)) => Ok(SExpr::owned_value(OwnedValue::U8(*num as u8))),
         Some(SharedValue::F64(num)) => Ok(SExpr::owned_value(OwnedValue::U8(*num as u8))),
-        _ => Err("The value cannot be convert into u8".to_string()),
+        _ => Err("The value cannot be convert into u8".into()),
     }
 }
 
-pub fn i64(value: SExpr) -> Result<SExpr, String> {
+pub fn i64(value: SExpr) -> Result<SExpr, DovahkiinError> {
     match value.val() {
         Some(SharedValue::U8(num)) => Ok(SExpr::owned_value(OwnedValue::I64(*num as i64))),
         Some(SharedValue::U16(num)) => Ok(SExpr::owned_value(OwnedValue::I64(*num as i64))),
@@ -76,11 +77,11 @@ pub fn i64(value: SExpr) -> Result<SExpr, String> {
         Some(SharedValue::I64(num)) => Ok(SExpr::owned_value(OwnedValue::I64(*num as i64))),
         Some(SharedValue::F32(num)) => Ok(SExpr::owned_value(OwnedValue::I64(*num as i64))),
         Some(SharedValue::F64(num)) => Ok(SExpr::owned_value(OwnedValue::I64(*num as i64))),
-        _ => Err("The value cannot be convert into i64".to_string()),
+        _ => Err("The value cannot be convert into i64".into()),
     }
 }
 
-pub fn i32(value: SExpr) -> Result<SExpr, String> {
+pub fn i32(value: SExpr) -> Result<SExpr, DovahkiinError> {
     match value.val() {
         Some(SharedValue::U8(num)) => Ok(SExpr::owned_value(OwnedValue::I32(*num as i32))),
         Some(SharedValue::U16(num)) => Ok(SExpr::owned_value(OwnedValue::I32(*num as i32))),
@@ -92,11 +93,11 @@ pub fn i32(value: SExpr) -> Result<SExpr, String> {
         Some(SharedValue::I64(num)) => Ok(SExpr::owned_value(OwnedValue::I32(*num as i32))),
         Some(SharedValue::F32(num)) => Ok(SExpr::owned_value(OwnedValue::I32(*num as i32))),
         Some(SharedValue::F64(num)) => Ok(SExpr::owned_value(OwnedValue::I32(*num as i32))),
-        _ => Err("The value cannot be convert into i32".to_string()),
+        _ => Err("The value cannot be convert into i32".into()),
     }
 }
 
-pub fn i16(value: SExpr) -> Result<SExpr, String> {
+pub fn i16(value: SExpr) -> Result<SExpr, DovahkiinError> {
     match value.val() {
         Some(SharedValue::U8(num)) => Ok(SExpr::owned_value(OwnedValue::I16(*num as i16))),
         Some(SharedValue::U16(num)) => Ok(SExpr::owned_value(OwnedValue::I16(*num as i16))),
@@ -108,11 +109,11 @@ pub fn i16(value: SExpr) -> Result<SExpr, String> {
         Some(SharedValue::I64(num)) => Ok(SExpr::owned_value(OwnedValue::I16(*num as i16))),
         Some(SharedValue::F32(num)) => Ok(SExpr::owned_value(OwnedValue::I16(*num as i16))),
         Some(SharedValue::F64(num)) => Ok(SExpr::owned_value(OwnedValue::I16(*num as i16))),
-        _ => Err("The value cannot be convert into i16".to_string()),
+        _ => Err("The value cannot be convert into i16".into()),
     }
 }
 
-pub fn i8(value: SExpr) -> Result<SExpr, String> {
+pub fn i8(value: SExpr) -> Result<SExpr, DovahkiinError> {
     match value.val() {
         Some(SharedValue::U8(num)) => Ok(SExpr::owned_value(OwnedValue::I8(*num as i8))),
         Some(SharedValue::U16(num)) => Ok(SExpr::owned_value(OwnedValue::I8(*num as i8))),
@@ -124,11 +125,11 @@ pub fn i8(value: SExpr) -> Result<SExpr, String> {
         Some(SharedValue::I64(num)) => Ok(SExpr::owned_value(OwnedValue::I8(*num as i8))),
         Some(SharedValue::F32(num)) => Ok(SExpr::owned_value(OwnedValue::I8(*num as i8))),
         Some(SharedValue::F64(num)) => Ok(SExpr::owned_value(OwnedValue::I8(*num as i8))),
-        _ => Err("The value cannot be convert into i8".to_string()),
+        _ => Err("The value cannot be convert into i8".into()),
     }
 }
 
-pub fn f32(value: SExpr) -> Result<SExpr, String> {
+pub fn f32(value: SExpr) -> Result<SExpr, DovahkiinError> {
     match value.val() {
         Some(SharedValue::U8(num)) => Ok(SExpr::owned_value(OwnedValue::F32(*num as f32))),
         Some(SharedValue::U16(num)) => Ok(SExpr::owned_value(OwnedValue::F32(*num as f32))),
@@ -140,11 +141,11 @@ pub fn f32(value: SExpr) -> Result<SExpr, String> {
         Some(SharedValue::I64(num)) => Ok(SExpr::owned_value(OwnedValue::F32(*num as f32))),
         Some(SharedValue::F32(num)) => Ok(SExpr::owned_value(OwnedValue::F32(*num as f32))),
         Some(SharedValue::F64(num)) => Ok(SExpr::owned_value(OwnedValue::F32(*num as f32))),
-        _ => Err("The value cannot be convert into f32".to_string()),
+        _ => Err("The value cannot be convert into f32".into()),
     }
 }
 
-pub fn f64(value: SExpr) -> Result<SExpr, String> {
+pub fn f64(value: SExpr) -> Result<SExpr, DovahkiinError> {
     match value.val() {
         Some(SharedValue::U8(num)) => Ok(SExpr::owned_value(OwnedValue::F64(*num as f64))),
         Some(SharedValue::U16(num)) => Ok(SExpr::owned_value(OwnedValue::F64(*num as f64))),
@@ -156,6 +157,6 @@ pub fn f64(value: SExpr) -> Result<SExpr, String> {
         Some(SharedValue::I64(num)) => Ok(SExpr::owned_value(OwnedValue::F64(*num as f64))),
         Some(SharedValue::F32(num)) => Ok(SExpr::owned_value(OwnedValue::F64(*num as f64))),
         Some(SharedValue::F64(num)) => Ok(SExpr::owned_value(OwnedValue::F64(*num as f64))),
-        _ => Err("The value cannot be convert into f64".to_string()),
+        _ => Err("The value cannot be convert into f64".into()),
     }
 }
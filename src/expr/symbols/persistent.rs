@@ -0,0 +1,158 @@
+use super::utils::to_usize;
+use super::*;
+use crate::error::DovahkiinError;
+use crate::types::{key_name, Map, PersistentMap, PersistentVec};
+
+fn owned_values(exprs: Vec<SExpr>) -> Result<Vec<OwnedValue>, DovahkiinError> {
+    let mut values = Vec::with_capacity(exprs.len());
+    for expr in exprs {
+        match expr {
+            SExpr::Value(val) => values.push(val.into_owned_val()),
+            _ => return Err(format!("Only values can be stored, found {:?}", expr).into()),
+        }
+    }
+    Ok(values)
+}
+
+fn owned_value(expr: SExpr) -> Result<OwnedValue, DovahkiinError> {
+    Ok(owned_values(vec![expr])?.pop().unwrap())
+}
+
+pub fn is_pmap(expr: &SExpr) -> bool {
+    match expr.val() {
+        Some(SharedValue::PMap(_)) => true,
+        _ => false,
+    }
+}
+
+pub fn is_pvec(expr: &SExpr) -> bool {
+    match expr.val() {
+        Some(SharedValue::PVec(_)) => true,
+        _ => false,
+    }
+}
+
+// Converts maps, arrays and vectors into their persistent counterparts
+pub fn persistent(expr: SExpr) -> Result<SExpr, DovahkiinError> {
+    let value = match expr {
+        SExpr::Vec(exprs) => OwnedValue::PVec(PersistentVec::from(owned_values(exprs)?)),
+        SExpr::Value(val) => match val.into_owned_val() {
+            OwnedValue::Map(map) => OwnedValue::PMap(PersistentMap::from(map)),
+            OwnedValue::Array(array) => OwnedValue::PVec(PersistentVec::from(array)),
+            array @ OwnedValue::PrimArray(_) => OwnedValue::PVec(PersistentVec::from(
+                array.cloned_iter_value().unwrap().collect::<Vec<_>>(),
+            )),
+            val @ OwnedValue::PMap(_) | val @ OwnedValue::PVec(_) => val,
+            val => return Err(format!("Cannot make {:?} persistent", val).into()),
+        },
+        _ => return Err(format!("Cannot make {:?} persistent", expr).into()),
+    };
+    Ok(SExpr::owned_value(value))
+}
+
+pub fn assoc(exprs: Vec<SExpr>) -> Result<SExpr, DovahkiinError> {
+    if exprs.len() % 2 == 0 {
+        return Err(format!(
+            "Assoc require a collection with key value pairs. Found {} parameters",
+            exprs.len()
+        )
+        .into());
+    }
+    let mut iter = exprs.into_iter();
+    let value = match iter.next().unwrap().owned_val() {
+        Some(OwnedValue::PMap(mut map)) => {
+            while let (Some(k), Some(v)) = (iter.next(), iter.next()) {
+                map = map.assoc(&key_name(&k)?, owned_value(v)?)?;
+            }
+            OwnedValue::PMap(map)
+        }
+        Some(OwnedValue::PVec(mut vec)) => {
+            while let (Some(k), Some(v)) = (iter.next(), iter.next()) {
+                vec = vec.assoc(to_usize(&k)?, owned_value(v)?)?;
+            }
+            OwnedValue::PVec(vec)
+        }
+        other => {
+            return Err(format!(
+                "Assoc only works on persistent collections, found {:?}",
+                other
+            )
+            .into())
+        }
+    };
+    Ok(SExpr::owned_value(value))
+}
+
+pub fn dissoc(exprs: Vec<SExpr>) -> Result<SExpr, DovahkiinError> {
+    let mut iter = exprs.into_iter();
+    let mut map = match iter.next().unwrap().owned_val() {
+        Some(OwnedValue::PMap(map)) => map,
+        other => {
+            return Err(format!("Dissoc only works on persistent maps, found {:?}", other).into())
+        }
+    };
+    for key in iter {
+        map = map.dissoc(&key_name(&key)?);
+    }
+    Ok(SExpr::owned_value(OwnedValue::PMap(map)))
+}
+
+// Missing keys and indices give null
+pub fn get<'a>(coll: SExpr<'a>, key: SExpr<'a>) -> Result<SExpr<'a>, DovahkiinError> {
+    let value = match coll.val() {
+        Some(SharedValue::PMap(map)) => map.get(&key_name(&key)?).clone(),
+        Some(SharedValue::PVec(vec)) => vec
+            .get(to_usize(&key)?)
+            .cloned()
+            .unwrap_or(OwnedValue::Null),
+        Some(SharedValue::Map(map)) => map.get(&key_name(&key)?).owned(),
+        Some(SharedValue::Array(array)) => array
+            .get(to_usize(&key)?)
+            .map(|v| v.owned())
+            .unwrap_or(OwnedValue::Null),
+        _ => return Err(format!("Cannot get from {:?}", coll).into()),
+    };
+    Ok(SExpr::owned_value(value))
+}
+
+pub fn conj(exprs: Vec<SExpr>) -> Result<SExpr, DovahkiinError> {
+    let mut iter = exprs.into_iter();
+    let mut vec = match iter.next().unwrap().owned_val() {
+        Some(OwnedValue::PVec(vec)) => vec,
+        other => return Err(format!("Expect persistent vector, found {:?}", other).into()),
+    };
+    for expr in iter {
+        vec = vec.conj(owned_value(expr)?);
+    }
+    Ok(SExpr::owned_value(OwnedValue::PVec(vec)))
+}
+
+pub fn concat(exprs: Vec<SExpr>) -> Result<SExpr, DovahkiinError> {
+    let mut vec = PersistentVec::new();
+    for expr in exprs {
+        vec = match expr.val() {
+            Some(SharedValue::PVec(other)) => vec.concat(other),
+            _ => match stream::to_vec(expr)? {
+                SExpr::Vec(exprs) => vec.concat(&PersistentVec::from(owned_values(exprs)?)),
+                _ => unreachable!(),
+            },
+        };
+    }
+    Ok(SExpr::owned_value(OwnedValue::PVec(vec)))
+}
+
+pub fn merge(exprs: Vec<SExpr>) -> Result<SExpr, DovahkiinError> {
+    let mut iter = exprs.into_iter();
+    let mut map = match iter.next().unwrap().owned_val() {
+        Some(OwnedValue::PMap(map)) => map,
+        other => return Err(format!("Expect persistent map, found {:?}", other).into()),
+    };
+    for expr in iter {
+        map = match expr.val() {
+            Some(SharedValue::PMap(other)) => map.merge(other),
+            Some(SharedValue::Map(other)) => map.merge(&PersistentMap::from(other.owned())),
+            _ => return Err(format!("Only map value can be merged. Found {:?}", expr).into()),
+        };
+    }
+    Ok(SExpr::owned_value(OwnedValue::PMap(map)))
+}
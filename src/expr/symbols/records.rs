@@ -0,0 +1,129 @@
+use super::utils::{to_usize, type_of};
+use super::*;
+use crate::error::DovahkiinError;
+use crate::expr::record_source::RecordSource;
+use crate::types::{key_name, Id, Map, OwnedPrimArray, Type};
+use std::collections::HashSet;
+use std::rc::Rc;
+
+fn source(env: &Envorinment) -> Result<Rc<dyn RecordSource>, DovahkiinError> {
+    env.record_source
+        .clone()
+        .ok_or_else(|| "No record source is attached to the interpreter".into())
+}
+
+fn to_id(expr: &SExpr) -> Result<Id, DovahkiinError> {
+    match expr.val() {
+        Some(SharedValue::Id(id)) => Ok(*id),
+        _ => Err(DovahkiinError::Type {
+            expected: Type::Id.into(),
+            found: type_of(expr),
+            span: None,
+        }),
+    }
+}
+
+fn field(value: &OwnedValue, name: &str) -> OwnedValue {
+    match value {
+        OwnedValue::Map(map) => map.get(name).clone(),
+        OwnedValue::PMap(map) => map.get(name).clone(),
+        _ => OwnedValue::Null,
+    }
+}
+
+// Ids held by an edge field, either a single id or a collection of them
+fn linked_ids(value: &OwnedValue) -> Vec<Id> {
+    match value {
+        OwnedValue::Id(id) => vec![*id],
+        OwnedValue::PrimArray(OwnedPrimArray::Id(ids)) => ids.clone(),
+        OwnedValue::Array(values) => values.iter().filter_map(|v| v.id().cloned()).collect(),
+        OwnedValue::PVec(values) => values.iter().filter_map(|v| v.id().cloned()).collect(),
+        _ => vec![],
+    }
+}
+
+fn or_null(record: Option<OwnedValue>) -> SExpr<'static> {
+    SExpr::owned_value(record.unwrap_or(OwnedValue::Null))
+}
+
+// Dereferences one id, or a vector of ids in one batch
+pub fn deref<'a>(expr: SExpr<'a>, env: &mut Envorinment<'a>) -> Result<SExpr<'a>, DovahkiinError> {
+    let source = source(env)?;
+    if let SExpr::Vec(exprs) = &expr {
+        let ids = exprs.iter().map(to_id).collect::<Result<Vec<_>, _>>()?;
+        return Ok(SExpr::Vec(
+            source.get_batch(&ids).into_iter().map(or_null).collect(),
+        ));
+    }
+    if let Some(SharedValue::Array(_)) | Some(SharedValue::PrimArray(_)) = expr.val() {
+        let ids = linked_ids(&expr.owned_val().unwrap());
+        return Ok(SExpr::Vec(
+            source.get_batch(&ids).into_iter().map(or_null).collect(),
+        ));
+    }
+    Ok(or_null(source.get(&to_id(&expr)?)))
+}
+
+// Follows the path from a record, dereferencing every id met on the way
+pub fn get_in_linked<'a>(
+    rec: SExpr<'a>,
+    path: SExpr<'a>,
+    env: &mut Envorinment<'a>,
+) -> Result<SExpr<'a>, DovahkiinError> {
+    let source = source(env)?;
+    let keys = match &path {
+        SExpr::Vec(keys) | SExpr::List(keys, _) => {
+            keys.iter().map(key_name).collect::<Result<Vec<_>, _>>()?
+        }
+        _ => vec![key_name(&path)?],
+    };
+    let mut current = match rec.owned_val() {
+        Some(val) => val,
+        None => return Err("Expect a record or an id to start from".into()),
+    };
+    for key in keys {
+        if let OwnedValue::Id(id) = current {
+            current = source.get(&id).unwrap_or(OwnedValue::Null);
+        }
+        current = field(&current, &key);
+        if current == OwnedValue::Null {
+            break;
+        }
+    }
+    Ok(SExpr::owned_value(current))
+}
+
+// Records reachable from the start by following the edge field at most `depth` times,
+// in breadth first order and starting with the start record itself. Every level is
+// fetched in one batch.
+pub fn traverse<'a>(
+    exprs: Vec<SExpr<'a>>,
+    env: &mut Envorinment<'a>,
+) -> Result<SExpr<'a>, DovahkiinError> {
+    let source = source(env)?;
+    let start = to_id(&exprs[0])?;
+    let edge = key_name(&exprs[1])?;
+    let depth = to_usize(&exprs[2])?;
+    let mut visited = HashSet::new();
+    let mut records = vec![];
+    let mut frontier = vec![start];
+    visited.insert(start);
+    for level in 0..=depth {
+        if frontier.is_empty() {
+            break;
+        }
+        let mut next = vec![];
+        for record in source.get_batch(&frontier).into_iter().flatten() {
+            if level < depth {
+                for id in linked_ids(&field(&record, &edge)) {
+                    if visited.insert(id) {
+                        next.push(id);
+                    }
+                }
+            }
+            records.push(SExpr::owned_value(record));
+        }
+        frontier = next;
+    }
+    Ok(SExpr::Vec(records))
+}
@@ -0,0 +1,95 @@
+use super::utils::{to_prim_array, to_usize};
+use super::*;
+use crate::error::DovahkiinError;
+use crate::types::similarity::{self, Metric};
+use crate::types::{key_hash, Map, OwnedPrimArray};
+
+fn pair(mut exprs: Vec<SExpr>) -> Result<(OwnedPrimArray, OwnedPrimArray), DovahkiinError> {
+    let b = to_prim_array(exprs.pop().unwrap())?;
+    let a = to_prim_array(exprs.pop().unwrap())?;
+    Ok((a, b))
+}
+
+fn f64_value<'a>(val: f64) -> SExpr<'a> {
+    SExpr::owned_value(OwnedValue::F64(val))
+}
+
+pub fn dot<'a>(exprs: Vec<SExpr<'a>>) -> Result<SExpr<'a>, DovahkiinError> {
+    let (a, b) = pair(exprs)?;
+    Ok(f64_value(similarity::dot(&a.shared(), &b.shared())?))
+}
+
+pub fn cosine<'a>(exprs: Vec<SExpr<'a>>) -> Result<SExpr<'a>, DovahkiinError> {
+    let (a, b) = pair(exprs)?;
+    Ok(f64_value(similarity::cosine_similarity(
+        &a.shared(),
+        &b.shared(),
+    )?))
+}
+
+pub fn l2_distance<'a>(exprs: Vec<SExpr<'a>>) -> Result<SExpr<'a>, DovahkiinError> {
+    let (a, b) = pair(exprs)?;
+    Ok(f64_value(similarity::l2_distance(
+        &a.shared(),
+        &b.shared(),
+    )?))
+}
+
+pub fn normalize<'a>(expr: SExpr<'a>) -> Result<SExpr<'a>, DovahkiinError> {
+    let vector = to_prim_array(expr)?;
+    Ok(SExpr::owned_value(OwnedValue::PrimArray(
+        similarity::normalize(&vector.shared())?,
+    )))
+}
+
+fn field_id(expr: &SExpr) -> Result<u64, DovahkiinError> {
+    match expr {
+        SExpr::Keyword(id, _) => Ok(*id),
+        _ => match expr.val() {
+            Some(SharedValue::String(name)) => Ok(key_hash(name)),
+            Some(SharedValue::U64(id)) => Ok(*id),
+            _ => Err(format!("Expect keyword, string or u64 as field, found {:?}", expr).into()),
+        },
+    }
+}
+
+// (top-k k query records :field [:cosine|:dot|:l2])
+pub fn top_k<'a>(exprs: Vec<SExpr<'a>>) -> Result<SExpr<'a>, DovahkiinError> {
+    let mut iter = exprs.into_iter();
+    let k = to_usize(&iter.next().unwrap())?;
+    let query = to_prim_array(iter.next().unwrap())?;
+    let records = match stream::to_vec(iter.next().unwrap())? {
+        SExpr::Vec(records) => records,
+        other => return Err(format!("Cannot rank {:?}", other).into()),
+    };
+    let field = field_id(&iter.next().unwrap())?;
+    let metric = match iter.next() {
+        None => Metric::Cosine,
+        Some(SExpr::Keyword(_, name)) => Metric::from_name(&name)?,
+        Some(other) => return Err(format!("Expect metric keyword, found {:?}", other).into()),
+    };
+    let ranked = {
+        let values: Vec<_> = records.iter().map(|record| record.val()).collect();
+        let mut vectors = Vec::with_capacity(values.len());
+        for value in &values {
+            vectors.push(match value {
+                Some(SharedValue::Map(map)) => match map.get_by_key_id(field) {
+                    SharedValue::PrimArray(vector) => Some(vector.clone()),
+                    SharedValue::Null => None,
+                    other => return Err(format!("Field is not a vector, found {:?}", other).into()),
+                },
+                _ => {
+                    return Err(format!("Only map records can be ranked, found {:?}", value).into())
+                }
+            });
+        }
+        similarity::top_k(&query.shared(), vectors, k, metric)?
+    };
+    let mut records: Vec<_> = records.into_iter().map(Some).collect();
+    Ok(SExpr::Vec(
+        ranked
+            .into_iter()
+            .map(|(index, _)| records[index].take().unwrap())
+            .collect(),
+    ))
+}
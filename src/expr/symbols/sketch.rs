@@ -0,0 +1,147 @@
+use super::utils::{to_usize, type_of};
+use super::*;
+use crate::error::{DovahkiinError, Expected};
+use crate::types::{
+    sketch_hash, BloomFilter, CountMin, HyperLogLog, Sketch, Type, DEFAULT_HLL_PRECISION,
+};
+use std::convert::TryFrom;
+
+fn sketch_expr<'a>(sketch: Sketch) -> SExpr<'a> {
+    SExpr::owned_value(OwnedValue::Sketch(sketch))
+}
+
+fn to_f64(expr: &SExpr) -> Result<f64, DovahkiinError> {
+    match expr.val() {
+        Some(SharedValue::F64(n)) => Ok(*n),
+        Some(SharedValue::F32(n)) => Ok(*n as f64),
+        _ => Err(DovahkiinError::Type {
+            expected: Type::F64.into(),
+            found: type_of(expr),
+            span: None,
+        }),
+    }
+}
+
+fn to_u32(expr: &SExpr, name: &str) -> Result<u32, DovahkiinError> {
+    let n = to_usize(expr)?;
+    u32::try_from(n).map_err(|_| format!("Count-Min {} {} is out of range", name, n).into())
+}
+
+fn not_sketch(kind: &'static str, expr: &SExpr) -> DovahkiinError {
+    DovahkiinError::Type {
+        expected: Expected::Sketch(kind),
+        found: type_of(expr),
+        span: None,
+    }
+}
+
+fn owned_sketch(expr: SExpr, kind: &'static str) -> Result<Sketch, DovahkiinError> {
+    let err = not_sketch(kind, &expr);
+    match expr.owned_val() {
+        Some(OwnedValue::Sketch(sketch)) if sketch.kind() == kind => Ok(sketch),
+        _ => Err(err),
+    }
+}
+
+fn with_sketch<R, F>(expr: &SExpr, kind: &'static str, f: F) -> Result<R, DovahkiinError>
+where
+    F: FnOnce(&Sketch) -> R,
+{
+    match expr.val() {
+        Some(SharedValue::Sketch(sketch)) if sketch.kind() == kind => Ok(f(sketch)),
+        _ => Err(not_sketch(kind, expr)),
+    }
+}
+
+fn value_hash(expr: &SExpr) -> Result<u64, DovahkiinError> {
+    match expr.val() {
+        Some(val) => Ok(sketch_hash(&val)),
+        None => Err(format!("Only values can be added to sketches, found {:?}", expr).into()),
+    }
+}
+
+pub fn hll(exprs: Vec<SExpr>) -> Result<SExpr, DovahkiinError> {
+    let precision = match exprs.first() {
+        Some(expr) => {
+            let precision = to_usize(expr)?;
+            u8::try_from(precision)
+                .map_err(|_| format!("HyperLogLog precision {} is out of range", precision))?
+        }
+        None => DEFAULT_HLL_PRECISION,
+    };
+    Ok(sketch_expr(Sketch::HyperLogLog(HyperLogLog::new(
+        precision,
+    )?)))
+}
+
+pub fn count_min(exprs: Vec<SExpr>) -> Result<SExpr, DovahkiinError> {
+    let width = to_u32(&exprs[0], "width")?;
+    let depth = to_u32(&exprs[1], "depth")?;
+    Ok(sketch_expr(Sketch::CountMin(CountMin::new(width, depth)?)))
+}
+
+pub fn bloom(exprs: Vec<SExpr>) -> Result<SExpr, DovahkiinError> {
+    let expected = to_usize(&exprs[0])? as u64;
+    let fpp = to_f64(&exprs[1])?;
+    Ok(sketch_expr(Sketch::Bloom(BloomFilter::with_rate(
+        expected, fpp,
+    )?)))
+}
+
+// Returns a new sketch with all the following values added
+pub fn add<'a>(exprs: Vec<SExpr<'a>>, kind: &'static str) -> Result<SExpr<'a>, DovahkiinError> {
+    let mut iter = exprs.into_iter();
+    let mut sketch = owned_sketch(iter.next().unwrap(), kind)?;
+    for expr in iter {
+        sketch.add_hash(value_hash(&expr)?);
+    }
+    Ok(sketch_expr(sketch))
+}
+
+pub fn hll_count(expr: SExpr) -> Result<SExpr, DovahkiinError> {
+    with_sketch(&expr, "hyperloglog", |sketch| match sketch {
+        Sketch::HyperLogLog(hll) => OwnedValue::U64(hll.count()),
+        _ => unreachable!(),
+    })
+    .map(SExpr::owned_value)
+}
+
+pub fn cms_count(exprs: Vec<SExpr>) -> Result<SExpr, DovahkiinError> {
+    let hash = value_hash(&exprs[1])?;
+    with_sketch(&exprs[0], "count-min", |sketch| match sketch {
+        Sketch::CountMin(cms) => OwnedValue::U64(cms.estimate(hash)),
+        _ => unreachable!(),
+    })
+    .map(SExpr::owned_value)
+}
+
+pub fn bloom_contains(exprs: Vec<SExpr>) -> Result<SExpr, DovahkiinError> {
+    let hash = value_hash(&exprs[1])?;
+    with_sketch(&exprs[0], "bloom", |sketch| match sketch {
+        Sketch::Bloom(bloom) => OwnedValue::Bool(bloom.contains_hash(hash)),
+        _ => unreachable!(),
+    })
+    .map(SExpr::owned_value)
+}
+
+pub fn is_sketch(expr: &SExpr) -> bool {
+    match expr.val() {
+        Some(SharedValue::Sketch(_)) => true,
+        _ => false,
+    }
+}
+
+pub fn merge(exprs: Vec<SExpr>) -> Result<SExpr, DovahkiinError> {
+    let mut iter = exprs.into_iter();
+    let mut sketch = match iter.next().and_then(|e| e.owned_val()) {
+        Some(OwnedValue::Sketch(sketch)) => sketch,
+        other => return Err(format!("Expect sketch, found {:?}", other).into()),
+    };
+    for expr in iter {
+        match expr.val() {
+            Some(SharedValue::Sketch(other)) => sketch.merge(other)?,
+            _ => return Err(format!("Cannot merge sketch with {:?}", expr).into()),
+        }
+    }
+    Ok(sketch_expr(sketch))
+}
@@ -1,8 +1,9 @@
 use super::functions::eval_function;
 use super::utils::is_true;
 use super::*;
+use crate::error::DovahkiinError;
 
-pub fn to_array<'a>(expr: SExpr<'a>) -> Result<SExpr<'a>, String> {
+pub fn to_array<'a>(expr: SExpr<'a>) -> Result<SExpr<'a>, DovahkiinError> {
     match expr {
         SExpr::Vec(vec) => {
             let mut array = Vec::new();
@@ -10,7 +11,7 @@ pub fn to_array<'a>(expr: SExpr<'a>) -> Result<SExpr<'a>, String> {
                 if let SExpr::Value(val) = expr {
                     array.push(val.into_owned_val())
                 } else {
-                    return Err(format!("Data {:?} cannot be value", expr));
+                    return Err(format!("Data {:?} cannot be value", expr).into());
                 }
             }
             return Ok(SExpr::owned_value(OwnedValue::Array(array)));
@@ -21,12 +22,12 @@ pub fn to_array<'a>(expr: SExpr<'a>) -> Result<SExpr<'a>, String> {
             return Err(format!(
                 "Only Vector can convert into array, found {:?}",
                 expr
-            ))
+            ).into())
         }
     }
 }
 
-pub fn to_vec(expr: SExpr) -> Result<SExpr, String> {
+pub fn to_vec(expr: SExpr) -> Result<SExpr, DovahkiinError> {
     match expr {
         SExpr::Value(Value::Owned(OwnedValue::Array(array))) => {
             return Ok(SExpr::Vec(
@@ -44,12 +45,30 @@ pub fn to_vec(expr: SExpr) -> Result<SExpr, String> {
                     .collect(),
             ));
         }
+        SExpr::Value(Value::Owned(array @ OwnedValue::PrimArray(_))) => {
+            return Ok(SExpr::Vec(
+                array
+                    .cloned_iter_value()
+                    .unwrap()
+                    .map(|val| SExpr::Value(Value::Owned(val)))
+                    .collect(),
+            ));
+        }
+        SExpr::Value(Value::Shared(SharedValue::PrimArray(array))) => {
+            return to_vec(SExpr::owned_value(OwnedValue::PrimArray(array.owned())));
+        }
+        SExpr::Value(Value::Owned(OwnedValue::PVec(vec))) => {
+            return Ok(SExpr::Vec(vec.iter().cloned().map(SExpr::owned_value).collect()));
+        }
+        SExpr::Value(Value::Shared(SharedValue::PVec(vec))) => {
+            return Ok(SExpr::Vec(vec.iter().map(|val| SExpr::shared_value(val.shared())).collect()));
+        }
         SExpr::Vec(_) => Ok(expr),
         _ => {
             return Err(format!(
                 "Only array value can convert into vector, found {:?}",
                 expr
-            ))
+            ).into())
         }
     }
 }
@@ -58,10 +77,12 @@ pub fn map<'a>(
     func: SExpr<'a>,
     data: SExpr<'a>,
     env: &mut Envorinment<'a>,
-) -> Result<SExpr<'a>, String> {
+) -> Result<SExpr<'a>, DovahkiinError> {
     match data {
         SExpr::Value(Value::Owned(OwnedValue::Array(_)))
-        | SExpr::Value(Value::Shared(SharedValue::Array(_))) => {
+        | SExpr::Value(Value::Shared(SharedValue::Array(_)))
+        | SExpr::Value(Value::Owned(OwnedValue::PrimArray(_)))
+        | SExpr::Value(Value::Shared(SharedValue::PrimArray(_))) => {
             return map(func, to_vec(data)?, env)
         }
         SExpr::Vec(expr_list) => {
@@ -71,7 +92,7 @@ pub fn map<'a>(
             }
             return Ok(SExpr::Vec(result));
         }
-        _ => return Err(format!("Cannot map function on {:?}", data)),
+        _ => return Err(format!("Cannot map function on {:?}", data).into()),
     }
 }
 
@@ -79,10 +100,12 @@ pub fn filter<'a>(
     func: SExpr<'a>,
     data: SExpr<'a>,
     env: &mut Envorinment<'a>,
-) -> Result<SExpr<'a>, String> {
+) -> Result<SExpr<'a>, DovahkiinError> {
     match data {
         SExpr::Value(Value::Owned(OwnedValue::Array(_)))
-        | SExpr::Value(Value::Shared(SharedValue::Array(_))) => {
+        | SExpr::Value(Value::Shared(SharedValue::Array(_)))
+        | SExpr::Value(Value::Owned(OwnedValue::PrimArray(_)))
+        | SExpr::Value(Value::Shared(SharedValue::PrimArray(_))) => {
             return filter(func, to_vec(data)?, env)
         }
         SExpr::Vec(expr_list) => {
@@ -95,6 +118,6 @@ pub fn filter<'a>(
             }
             return Ok(SExpr::Vec(result));
         }
-        _ => return Err(format!("Cannot map function on {:?}", data)),
+        _ => return Err(format!("Cannot map function on {:?}", data).into()),
     }
 }
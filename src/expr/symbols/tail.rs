@@ -0,0 +1,294 @@
+use super::bindings::{bind, bind_form, unbind};
+use super::functions::eval_function;
+use super::lambda::bind_all;
+use super::utils::is_true;
+use super::*;
+use crate::error::DovahkiinError;
+use crate::expr::interpreter::eval_all;
+use crate::lexer::Span;
+
+// Tail calls and loop/recur. Expressions in tail position are evaluated by `eval_tail`, which
+// follows branches in place and hands calls back to the trampoline in `eval_lambda` instead
+// of calling them on the Rust stack.
+
+pub enum Step<'a> {
+    Done(SExpr<'a>),
+    Call(Rc<SExpr<'a>>, Vec<SExpr<'a>>),
+    Recur(Vec<SExpr<'a>>),
+}
+
+// Bindings made in tail position are left in `bound` for the trampoline to remove, under
+// dynamic scoping the callee can still see them.
+pub fn eval_tail<'a>(
+    expr: SExpr<'a>,
+    env: &mut Envorinment<'a>,
+    bound: &mut Vec<u64>,
+) -> Result<Step<'a>, DovahkiinError> {
+    let mut span = None;
+    eval_tail_form(expr, env, bound, &mut span).map_err(|e| e.at(span))
+}
+
+// `span` is of the form being evaluated, forms are replaced by their branches in place
+fn eval_tail_form<'a>(
+    mut expr: SExpr<'a>,
+    env: &mut Envorinment<'a>,
+    bound: &mut Vec<u64>,
+    span: &mut Option<Span>,
+) -> Result<Step<'a>, DovahkiinError> {
+    loop {
+        let mut exprs = match expr {
+            SExpr::List(exprs, form) if !exprs.is_empty() => {
+                *span = form;
+                exprs
+            }
+            expr => return Ok(Step::Done(expr.eval(env)?)),
+        };
+        let func = exprs.remove(0).eval(env)?;
+        let form = match &func {
+            SExpr::ISymbol(id, _) => *id,
+            SExpr::LAMBDA(_, _, _) => {
+                return Ok(Step::Call(Rc::new(func), eval_all(exprs, env)?));
+            }
+            _ => 0,
+        };
+        expr = match form {
+            hash_ident!("if") | hash_ident!("if-not") => {
+                check_params_not_least_than(2, &exprs)?;
+                check_params_not_greater_than(3, &exprs)?;
+                let mut iter = exprs.into_iter();
+                let test = is_true(&iter.next().unwrap().eval(env)?);
+                let then_expr = iter.next().unwrap();
+                if test == (form == hash_ident!("if")) {
+                    then_expr
+                } else {
+                    iter.next().unwrap_or(SExpr::Value(Value::null()))
+                }
+            }
+            hash_ident!("when") | hash_ident!("when-not") => {
+                check_num_params(2, &exprs)?;
+                let then_expr = exprs.pop().unwrap();
+                let test = is_true(&exprs.pop().unwrap().eval(env)?);
+                if test == (form == hash_ident!("when")) {
+                    then_expr
+                } else {
+                    SExpr::Value(Value::null())
+                }
+            }
+            hash_ident!("cond") => {
+                if exprs.len() % 2 == 1 {
+                    return Err(format!(
+                        "cond need even number of parameters, found {}",
+                        exprs.len()
+                    )
+                    .into());
+                }
+                let mut iter = exprs.into_iter();
+                let mut branch = SExpr::Value(Value::null());
+                while let (Some(condition), Some(expr)) = (iter.next(), iter.next()) {
+                    if is_true(&condition.eval(env)?) {
+                        branch = expr;
+                        break;
+                    }
+                }
+                branch
+            }
+            hash_ident!("do") => match exprs.pop() {
+                Some(last) => {
+                    eval_all(exprs, env)?;
+                    last
+                }
+                None => SExpr::Value(Value::null()),
+            },
+            hash_ident!("let") => {
+                check_params_not_least_than(2, &exprs)?;
+                let mut iter = exprs.into_iter();
+                bound.extend(bind_form(env, iter.next().unwrap())?);
+                let body: Vec<_> = iter.collect();
+                match body_tail(body, env)? {
+                    Some(last) => last,
+                    None => SExpr::Value(Value::null()),
+                }
+            }
+            hash_ident!("loop") => return loop_step(exprs, env, bound),
+            hash_ident!("recur") => return Ok(Step::Recur(eval_all(exprs, env)?)),
+            _ => {
+                env.enter()?;
+                let res = eval_function(&func, exprs, env);
+                env.leave();
+                return Ok(Step::Done(res?));
+            }
+        };
+    }
+}
+
+// Evaluates all but the last expression, which is left for the tail position
+fn body_tail<'a>(
+    mut body: Vec<SExpr<'a>>,
+    env: &mut Envorinment<'a>,
+) -> Result<Option<SExpr<'a>>, DovahkiinError> {
+    let last = body.pop();
+    for expr in body {
+        expr.eval(env)?;
+    }
+    Ok(last)
+}
+
+// Runs a loop until it finishes or makes a call in tail position
+fn loop_step<'a>(
+    mut exprs: Vec<SExpr<'a>>,
+    env: &mut Envorinment<'a>,
+    bound: &mut Vec<u64>,
+) -> Result<Step<'a>, DovahkiinError> {
+    if exprs.is_empty() {
+        return Err("loop need a binding form".into());
+    }
+    let frame = bound.len();
+    let form = exprs.remove(0);
+    let ids: Vec<u64> = match &form {
+        SExpr::Vec(form) => form
+            .iter()
+            .step_by(2)
+            .filter_map(|symbol| match symbol {
+                SExpr::ISymbol(id, _) => Some(*id),
+                SExpr::Symbol(name) => Some(hash_str(name)),
+                _ => None,
+            })
+            .collect(),
+        _ => vec![],
+    };
+    bound.extend(bind_form(env, form)?);
+    loop {
+        env.step()?;
+        let last = match body_tail(exprs.clone(), env)? {
+            Some(last) => last,
+            None => return Ok(Step::Done(SExpr::Value(Value::null()))),
+        };
+        let values = match eval_tail(last, env, bound)? {
+            Step::Recur(values) => values,
+            step => return Ok(step),
+        };
+        if values.len() != ids.len() {
+            return Err(format!(
+                "recur expect {} values for loop, found {}",
+                ids.len(),
+                values.len()
+            )
+            .into());
+        }
+        // The values of the loop are replaced, bindings made in its body are dropped
+        unbind_from(env, bound, frame);
+        for (id, value) in ids.iter().zip(values) {
+            bind(env, *id, value);
+            bound.push(*id);
+        }
+    }
+}
+
+fn unbind_from<'a>(env: &mut Envorinment<'a>, bound: &mut Vec<u64>, frame: usize) {
+    for id in bound.drain(frame..).rev() {
+        unbind(env, id);
+    }
+}
+
+// Bindings of the frame that the parameters of a tail callee shadow. The caller never
+// sees them again, so they are dropped and the rest stay visible to the callee.
+fn unbind_shadowed<'a>(
+    env: &mut Envorinment<'a>,
+    bound: &mut Vec<u64>,
+    frame: usize,
+    lambda: &SExpr<'a>,
+    params: usize,
+) {
+    let shadowed: Vec<u64> = match lambda {
+        SExpr::LAMBDA(params_list, _, _) => params_list
+            .iter()
+            .take(params)
+            .filter_map(|param| match param {
+                SExpr::ISymbol(id, _) => Some(*id),
+                _ => None,
+            })
+            .collect(),
+        _ => return,
+    };
+    let mut index = frame;
+    while index < bound.len() {
+        if shadowed.contains(&bound[index]) {
+            unbind(env, bound.remove(index));
+        } else {
+            index += 1;
+        }
+    }
+}
+
+pub fn loop_<'a>(
+    exprs: Vec<SExpr<'a>>,
+    env: &mut Envorinment<'a>,
+) -> Result<SExpr<'a>, DovahkiinError> {
+    let mut bound = vec![];
+    let res = loop_step(exprs, env, &mut bound).and_then(|step| match step {
+        Step::Done(res) => Ok(res),
+        Step::Call(lambda, params) => eval_lambda(lambda, params, env),
+        Step::Recur(_) => unreachable!(),
+    });
+    unbind_from(env, &mut bound, 0);
+    res
+}
+
+pub fn recur<'a>() -> Result<SExpr<'a>, DovahkiinError> {
+    Err("recur can only be used in tail position of loop or lambda".into())
+}
+
+// Trampoline of lambda calls, tail calls replace the running call and `recur` restarts it
+pub fn eval_lambda<'a>(
+    mut lambda: Rc<SExpr<'a>>,
+    mut params: Vec<SExpr<'a>>,
+    env: &mut Envorinment<'a>,
+) -> Result<SExpr<'a>, DovahkiinError> {
+    let caller_bindings = if env.lexical {
+        Some(std::mem::take(&mut env.bindings))
+    } else {
+        None
+    };
+    let mut bound = vec![];
+    let res = loop {
+        if let Err(e) = env.step() {
+            break Err(e);
+        }
+        let (params_list, body, captured) = match &*lambda {
+            SExpr::LAMBDA(params_list, body, captured) => (params_list, body, captured),
+            other => break Err(format!("Expect lambda expression, found {:?}", other).into()),
+        };
+        if env.lexical {
+            // The body only sees its own bindings
+            env.bindings = Default::default();
+            bound.clear();
+        }
+        let frame = bound.len();
+        match bind_all(params_list, params, captured, env) {
+            Ok(ids) => bound.extend(ids),
+            Err(e) => break Err(e),
+        }
+        let step = body_tail(body.clone(), env).and_then(|last| match last {
+            Some(last) => eval_tail(last, env, &mut bound),
+            None => Ok(Step::Done(SExpr::Value(Value::null()))),
+        });
+        match step {
+            Ok(Step::Done(res)) => break Ok(res),
+            Ok(Step::Call(next, next_params)) => {
+                unbind_shadowed(env, &mut bound, frame, &next, next_params.len());
+                lambda = next;
+                params = next_params;
+            }
+            Ok(Step::Recur(next_params)) => {
+                unbind_from(env, &mut bound, frame);
+                params = next_params;
+            }
+            Err(e) => break Err(e),
+        }
+    };
+    match caller_bindings {
+        Some(bindings) => env.bindings = bindings,
+        None => unbind_from(env, &mut bound, 0),
+    }
+    res
+}
@@ -0,0 +1,111 @@
+use super::utils::{to_prim_array, to_usize, to_usizes, type_of};
+use super::*;
+use crate::error::{DovahkiinError, Expected};
+use crate::types::{OwnedTensor, SharedTensor, TensorOp, TensorReduce};
+
+fn tensor_of<'b>(expr: &'b SExpr) -> Result<SharedTensor<'b>, DovahkiinError> {
+    match expr.val() {
+        Some(SharedValue::Tensor(tensor)) => Ok(tensor),
+        Some(SharedValue::PrimArray(array)) => Ok(SharedTensor::from_prim_array(array)?),
+        _ => Err(DovahkiinError::Type {
+            expected: Expected::Tensor,
+            found: type_of(expr),
+            span: None,
+        }),
+    }
+}
+
+fn into_tensor(expr: SExpr) -> Result<OwnedTensor, DovahkiinError> {
+    match expr {
+        SExpr::Value(Value::Owned(OwnedValue::Tensor(tensor))) => Ok(tensor),
+        SExpr::Vec(_) => {
+            let data = to_prim_array(expr)?;
+            let len = data.len();
+            Ok(OwnedTensor::new(data, vec![len])?)
+        }
+        _ => Ok(tensor_of(&expr)?.owned()),
+    }
+}
+
+fn tensor_value<'a>(tensor: OwnedTensor) -> SExpr<'a> {
+    SExpr::owned_value(OwnedValue::Tensor(tensor))
+}
+
+pub fn tensor<'a>(mut exprs: Vec<SExpr<'a>>) -> Result<SExpr<'a>, DovahkiinError> {
+    let shape = to_usizes(&exprs.pop().unwrap())?;
+    let data = to_prim_array(exprs.pop().unwrap())?;
+    Ok(tensor_value(OwnedTensor::new(data, shape)?))
+}
+
+pub fn shape<'a>(expr: SExpr<'a>) -> Result<SExpr<'a>, DovahkiinError> {
+    let tensor = tensor_of(&expr)?;
+    Ok(SExpr::Vec(
+        tensor
+            .shape()
+            .iter()
+            .map(|dim| SExpr::owned_value(OwnedValue::U64(*dim as u64)))
+            .collect(),
+    ))
+}
+
+pub fn reshape<'a>(mut exprs: Vec<SExpr<'a>>) -> Result<SExpr<'a>, DovahkiinError> {
+    let shape = to_usizes(&exprs.pop().unwrap())?;
+    let tensor = into_tensor(exprs.pop().unwrap())?;
+    Ok(tensor_value(tensor.reshape(shape)?))
+}
+
+pub fn transpose<'a>(mut exprs: Vec<SExpr<'a>>) -> Result<SExpr<'a>, DovahkiinError> {
+    let perm = if exprs.len() > 1 {
+        Some(to_usizes(&exprs.pop().unwrap())?)
+    } else {
+        None
+    };
+    let tensor = into_tensor(exprs.pop().unwrap())?;
+    Ok(tensor_value(tensor.transpose(perm.as_deref())?))
+}
+
+pub fn slice<'a>(exprs: Vec<SExpr<'a>>) -> Result<SExpr<'a>, DovahkiinError> {
+    let mut iter = exprs.into_iter();
+    let tensor = into_tensor(iter.next().unwrap())?;
+    let axis = to_usize(&iter.next().unwrap())?;
+    let start = to_usize(&iter.next().unwrap())?;
+    let end = to_usize(&iter.next().unwrap())?;
+    Ok(tensor_value(tensor.slice(axis, start, end)?))
+}
+
+pub fn get<'a>(mut exprs: Vec<SExpr<'a>>) -> Result<SExpr<'a>, DovahkiinError> {
+    let index = to_usizes(&exprs.pop().unwrap())?;
+    let tensor_expr = exprs.pop().unwrap();
+    let tensor = tensor_of(&tensor_expr)?;
+    Ok(SExpr::owned_value(
+        tensor.get(&index).unwrap_or(OwnedValue::Null),
+    ))
+}
+
+pub fn binary<'a>(mut exprs: Vec<SExpr<'a>>, op: TensorOp) -> Result<SExpr<'a>, DovahkiinError> {
+    let rhs = exprs.pop().unwrap();
+    let lhs = exprs.pop().unwrap();
+    let lhs = tensor_of(&lhs)?;
+    let res = match tensor_of(&rhs) {
+        Ok(rhs) => lhs.binary(&rhs, op)?,
+        Err(_) => match rhs.val() {
+            Some(scalar) => lhs.scalar(&scalar, op)?,
+            None => return Err(format!("Expect tensor or scalar, found {:?}", rhs).into()),
+        },
+    };
+    Ok(tensor_value(res))
+}
+
+pub fn reduce<'a>(
+    mut exprs: Vec<SExpr<'a>>,
+    op: TensorReduce,
+) -> Result<SExpr<'a>, DovahkiinError> {
+    let axis = if exprs.len() > 1 {
+        Some(to_usize(&exprs.pop().unwrap())?)
+    } else {
+        None
+    };
+    let tensor_expr = exprs.pop().unwrap();
+    let tensor = tensor_of(&tensor_expr)?;
+    Ok(SExpr::owned_value(tensor.reduce(op, axis)?))
+}
@@ -1,4 +1,6 @@
 use super::*;
+use crate::error::DovahkiinError;
+use crate::types::{OwnedPrimArray, Type};
 
 pub fn is_true(expr: &SExpr) -> bool {
     match expr.val() {
@@ -6,3 +8,71 @@ pub fn is_true(expr: &SExpr) -> bool {
         _ => true, // anything else than false and null value will be considered as yes
     }
 }
+
+// Type of the value in type errors, NA for everything else
+pub fn type_of(expr: &SExpr) -> Type {
+    match expr.val() {
+        Some(SharedValue::Array(array)) if array.is_empty() => Type::NA,
+        Some(val) => val.base_type(),
+        None => Type::NA,
+    }
+}
+
+pub fn value_to_usize(val: &SharedValue) -> Option<usize> {
+    match val {
+        SharedValue::U8(n) => Some(**n as usize),
+        SharedValue::U16(n) => Some(**n as usize),
+        SharedValue::U32(n) => Some(**n as usize),
+        SharedValue::U64(n) => Some(**n as usize),
+        SharedValue::I8(n) if **n >= 0 => Some(**n as usize),
+        SharedValue::I16(n) if **n >= 0 => Some(**n as usize),
+        SharedValue::I32(n) if **n >= 0 => Some(**n as usize),
+        SharedValue::I64(n) if **n >= 0 => Some(**n as usize),
+        _ => None,
+    }
+}
+
+pub fn to_usize(expr: &SExpr) -> Result<usize, DovahkiinError> {
+    expr.val()
+        .as_ref()
+        .and_then(value_to_usize)
+        .ok_or_else(|| format!("Expect a non-negative integer, found {:?}", expr).into())
+}
+
+pub fn to_usizes(expr: &SExpr) -> Result<Vec<usize>, DovahkiinError> {
+    match expr {
+        SExpr::Vec(exprs) => exprs.iter().map(to_usize).collect(),
+        _ => match expr.val() {
+            Some(SharedValue::Array(array)) => array
+                .iter()
+                .map(|v| {
+                    value_to_usize(&v).ok_or_else(|| {
+                        format!("Expect a non-negative integer, found {:?}", v).into()
+                    })
+                })
+                .collect(),
+            _ => Err(format!("Expect a vector of integers, found {:?}", expr).into()),
+        },
+    }
+}
+
+pub fn to_prim_array(expr: SExpr) -> Result<OwnedPrimArray, DovahkiinError> {
+    match expr {
+        SExpr::Vec(exprs) => {
+            let mut values = Vec::with_capacity(exprs.len());
+            for expr in exprs {
+                match expr.owned_val() {
+                    Some(val) => values.push(val),
+                    None => return Err("Only values can be packed into primitive array".into()),
+                }
+            }
+            Ok(OwnedPrimArray::from_values(values)?)
+        }
+        SExpr::Value(val) => match val.into_owned_val() {
+            OwnedValue::PrimArray(array) => Ok(array),
+            OwnedValue::Array(values) => Ok(OwnedPrimArray::from_values(values)?),
+            other => Err(format!("Cannot convert {:?} into primitive array", other).into()),
+        },
+        _ => Err(format!("Cannot convert {:?} into primitive array", expr).into()),
+    }
+}
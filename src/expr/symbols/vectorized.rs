@@ -0,0 +1,77 @@
+use super::utils::to_prim_array;
+use super::*;
+use crate::error::DovahkiinError;
+use crate::expr::Value;
+use crate::types::vectorized::{self, CompareOp};
+use crate::types::OwnedPrimArray;
+use crate::types::{TensorOp, TensorReduce};
+
+pub fn is_array(expr: &SExpr) -> bool {
+    match expr {
+        SExpr::Vec(_)
+        | SExpr::Value(Value::Owned(OwnedValue::PrimArray(_)))
+        | SExpr::Value(Value::Owned(OwnedValue::Array(_)))
+        | SExpr::Value(Value::Shared(SharedValue::PrimArray(_)))
+        | SExpr::Value(Value::Shared(SharedValue::Array(_))) => true,
+        _ => false,
+    }
+}
+
+// Scalars are treated as single element arrays so they broadcast against the others
+fn operand(expr: SExpr) -> Result<OwnedPrimArray, DovahkiinError> {
+    if is_array(&expr) {
+        return to_prim_array(expr);
+    }
+    match expr {
+        SExpr::Value(val) => Ok(OwnedPrimArray::from_values(vec![val.into_owned_val()])?),
+        _ => Err(format!("Expect array or scalar value, found {:?}", expr).into()),
+    }
+}
+
+fn prim_array_expr<'a>(array: OwnedPrimArray) -> SExpr<'a> {
+    SExpr::owned_value(OwnedValue::PrimArray(array))
+}
+
+pub fn arith(exprs: Vec<SExpr>, op: TensorOp) -> Result<SExpr, DovahkiinError> {
+    let mut iter = exprs.into_iter();
+    let mut acc = match iter.next() {
+        Some(expr) => operand(expr)?,
+        None => return Err("Cannot do arithmetic on empty parameters".into()),
+    };
+    for expr in iter {
+        acc = vectorized::arith(&acc.shared(), &operand(expr)?.shared(), op)?;
+    }
+    Ok(prim_array_expr(acc))
+}
+
+pub fn compare(exprs: Vec<SExpr>, op: CompareOp) -> Result<SExpr, DovahkiinError> {
+    if exprs.len() != 2 {
+        return Err(format!(
+            "Array comparison takes exactly 2 parameters, found {}",
+            exprs.len()
+        )
+        .into());
+    }
+    let mut iter = exprs.into_iter();
+    let a = operand(iter.next().unwrap())?;
+    let b = operand(iter.next().unwrap())?;
+    Ok(prim_array_expr(vectorized::compare(
+        &a.shared(),
+        &b.shared(),
+        op,
+    )?))
+}
+
+// Reduce a single array, or all the scalar parameters as a whole
+pub fn reduce(mut exprs: Vec<SExpr>, op: TensorReduce) -> Result<SExpr, DovahkiinError> {
+    let array = if exprs.len() == 1 && is_array(&exprs[0]) {
+        to_prim_array(exprs.pop().unwrap())?
+    } else {
+        to_prim_array(SExpr::Vec(exprs))?
+    };
+    Ok(SExpr::owned_value(vectorized::reduce(&array.shared(), op)?))
+}
+
+pub fn into_prim_array(expr: SExpr) -> Result<SExpr, DovahkiinError> {
+    Ok(prim_array_expr(to_prim_array(expr)?))
+}
@@ -0,0 +1,722 @@
+use super::DEFAULT_MAX_DEPTH;
+use crate::expr::serde::Expr;
+use crate::types::visitor::ordered_keys;
+use crate::types::*;
+use bifrost_hasher::hash_str;
+use std::convert::TryFrom;
+
+// Values and expressions over the data model shared by CBOR and MessagePack. Types the
+// formats have no words for are wrapped in tags, CBOR tags from `TAG_BASE` on or MessagePack
+// extensions numbered by the offset from it.
+//
+// Tags by offset:
+// * type id: scalar of that type, integers of other widths than `i64`, chars, ids as 16 big
+//   endian bytes, positions as arrays of floats and small bytes
+// * 32 + type id: primitive array of that type, an array of the untagged elements
+// * NA, persistent maps and vectors, tensors as `[shape, data]`
+// * symbols, keywords, lists, vectors, meta and lambdas of expressions
+
+pub const TAG_BASE: u64 = 0x4456_0000;
+
+const PRIM_ARRAY: u64 = 32;
+const NA: u64 = 64;
+const PMAP: u64 = 65;
+const PVEC: u64 = 66;
+const TENSOR: u64 = 67;
+const SYMBOL: u64 = 96;
+const KEYWORD: u64 = 97;
+const LIST: u64 = 98;
+const VEC: u64 = 99;
+const META: u64 = 100;
+const LAMBDA: u64 = 101;
+
+#[derive(Debug)]
+pub enum Item {
+    Null,
+    Undefined,
+    Bool(bool),
+    Int(i128),
+    F32(f32),
+    F64(f64),
+    Text(String),
+    Bytes(Vec<u8>),
+    // Lengths are unknown for indefinite CBOR containers, which end with `Break`
+    Array(Option<usize>),
+    Map(Option<usize>),
+    Break,
+    Tag(u64),
+}
+
+pub trait ItemReader {
+    // None at the end of input
+    fn next_item(&mut self) -> Result<Option<Item>, String>;
+
+    // Arrays, maps and tags nested deeper are errors
+    fn max_depth(&self) -> usize {
+        DEFAULT_MAX_DEPTH
+    }
+}
+
+pub trait ItemWriter {
+    fn null(&mut self) -> Result<(), String>;
+    fn bool(&mut self, b: bool) -> Result<(), String>;
+    fn int(&mut self, n: i128) -> Result<(), String>;
+    fn f32(&mut self, n: f32) -> Result<(), String>;
+    fn f64(&mut self, n: f64) -> Result<(), String>;
+    fn text(&mut self, s: &str) -> Result<(), String>;
+    fn bytes(&mut self, b: &[u8]) -> Result<(), String>;
+    fn array(&mut self, len: usize) -> Result<(), String>;
+    fn map(&mut self, len: usize) -> Result<(), String>;
+    // Tags the single item written by `content`
+    fn tagged<F>(&mut self, tag: u64, content: F) -> Result<(), String>
+    where
+        F: FnOnce(&mut Self) -> Result<(), String>,
+        Self: Sized;
+}
+
+fn item<R: ItemReader>(reader: &mut R) -> Result<Item, String> {
+    reader
+        .next_item()?
+        .ok_or_else(|| "Unexpected end of input".to_string())
+}
+
+// Calls `f` on every item in a container until its length is reached or it breaks
+fn elements<R, F>(len: Option<usize>, reader: &mut R, mut f: F) -> Result<(), String>
+where
+    R: ItemReader,
+    F: FnMut(Item, &mut R) -> Result<(), String>,
+{
+    match len {
+        Some(len) => {
+            for _ in 0..len {
+                let next = item(reader)?;
+                f(next, reader)?;
+            }
+        }
+        None => loop {
+            match item(reader)? {
+                Item::Break => break,
+                next => f(next, reader)?,
+            }
+        },
+    }
+    Ok(())
+}
+
+// Depth inside one more container or tag
+fn nest<R: ItemReader>(depth: usize, reader: &R) -> Result<usize, String> {
+    if depth >= reader.max_depth() {
+        return Err(format!(
+            "Input nested deeper than {} levels",
+            reader.max_depth()
+        ));
+    }
+    Ok(depth + 1)
+}
+
+// Scalars and NA are small enough for MessagePack to buffer as extension payloads
+pub fn is_scalar_tag(tag: u64) -> bool {
+    matches!(tag.checked_sub(TAG_BASE), Some(offset) if offset < PRIM_ARRAY || offset == NA)
+}
+
+fn capacity(len: Option<usize>) -> usize {
+    // Lengths come from the input, do not trust them with large allocations
+    len.unwrap_or(0).min(4096)
+}
+
+pub fn write_value<W: ItemWriter>(value: &OwnedValue, writer: &mut W) -> Result<(), String> {
+    match value {
+        OwnedValue::Null => writer.null(),
+        OwnedValue::NA => writer.tagged(TAG_BASE + NA, |w| w.null()),
+        OwnedValue::Bool(b) => writer.bool(*b),
+        OwnedValue::I64(n) => writer.int(*n as i128),
+        OwnedValue::F32(n) => writer.f32(*n),
+        OwnedValue::F64(n) => writer.f64(*n),
+        OwnedValue::String(s) => writer.text(s),
+        OwnedValue::Bytes(b) => writer.bytes(&b.data),
+        OwnedValue::Map(map) => {
+            let keys = ordered_keys(map.fields.iter(), map.map.keys().cloned());
+            writer.map(keys.len())?;
+            for (key, name) in keys {
+                write_key(key, name, writer)?;
+                write_value(&map.map[&key], writer)?;
+            }
+            Ok(())
+        }
+        OwnedValue::Array(array) => {
+            writer.array(array.len())?;
+            for value in array {
+                write_value(value, writer)?;
+            }
+            Ok(())
+        }
+        OwnedValue::PrimArray(array) => {
+            let tag = TAG_BASE + PRIM_ARRAY + array.base_type().id() as u64;
+            writer.tagged(tag, |w| {
+                w.array(array.len())?;
+                for i in 0..array.len() {
+                    write_scalar(&array.get_owned(i).unwrap(), w)?;
+                }
+                Ok(())
+            })
+        }
+        OwnedValue::PMap(map) => writer.tagged(TAG_BASE + PMAP, |w| {
+            let keys = ordered_keys(map.fields(), map.keys().cloned());
+            w.map(keys.len())?;
+            for (key, name) in keys {
+                write_key(key, name, w)?;
+                write_value(map.get_by_key_id(key), w)?;
+            }
+            Ok(())
+        }),
+        OwnedValue::PVec(vec) => writer.tagged(TAG_BASE + PVEC, |w| {
+            w.array(vec.len())?;
+            for value in vec.iter() {
+                write_value(value, w)?;
+            }
+            Ok(())
+        }),
+        OwnedValue::Tensor(tensor) => {
+            let compact = tensor.shared().owned();
+            writer.tagged(TAG_BASE + TENSOR, |w| {
+                w.array(2)?;
+                w.array(compact.shape().len())?;
+                for dim in compact.shape() {
+                    w.int(*dim as i128)?;
+                }
+                write_value(&OwnedValue::PrimArray(compact.data().clone()), w)
+            })
+        }
+        OwnedValue::Sketch(sketch) => Err(format!(
+            "{} sketch has no binary interchange form",
+            sketch.kind()
+        )),
+        _ => {
+            let tag = TAG_BASE + value.base_type().id() as u64;
+            writer.tagged(tag, |w| write_scalar(value, w))
+        }
+    }
+}
+
+fn write_key<W: ItemWriter>(key: u64, name: Option<&String>, writer: &mut W) -> Result<(), String> {
+    match name {
+        Some(name) => writer.text(name),
+        None => writer.int(key as i128),
+    }
+}
+
+// Scalars without their tag, as in primitive arrays
+fn write_scalar<W: ItemWriter>(value: &OwnedValue, writer: &mut W) -> Result<(), String> {
+    match value {
+        OwnedValue::Bool(b) => writer.bool(*b),
+        OwnedValue::Char(c) => writer.text(&c.to_string()),
+        OwnedValue::I8(n) => writer.int(*n as i128),
+        OwnedValue::I16(n) => writer.int(*n as i128),
+        OwnedValue::I32(n) => writer.int(*n as i128),
+        OwnedValue::I64(n) => writer.int(*n as i128),
+        OwnedValue::U8(n) => writer.int(*n as i128),
+        OwnedValue::U16(n) => writer.int(*n as i128),
+        OwnedValue::U32(n) => writer.int(*n as i128),
+        OwnedValue::U64(n) => writer.int(*n as i128),
+        OwnedValue::F32(n) => writer.f32(*n),
+        OwnedValue::F64(n) => writer.f64(*n),
+        OwnedValue::Pos2d32(p) => {
+            writer.array(2)?;
+            writer.f32(p.x)?;
+            writer.f32(p.y)
+        }
+        OwnedValue::Pos2d64(p) => {
+            writer.array(2)?;
+            writer.f64(p.x)?;
+            writer.f64(p.y)
+        }
+        OwnedValue::Pos3d32(p) => {
+            writer.array(3)?;
+            writer.f32(p.x)?;
+            writer.f32(p.y)?;
+            writer.f32(p.z)
+        }
+        OwnedValue::Pos3d64(p) => {
+            writer.array(3)?;
+            writer.f64(p.x)?;
+            writer.f64(p.y)?;
+            writer.f64(p.z)
+        }
+        OwnedValue::Id(id) => {
+            let mut bytes = [0u8; 16];
+            bytes[..8].copy_from_slice(&id.higher.to_be_bytes());
+            bytes[8..].copy_from_slice(&id.lower.to_be_bytes());
+            writer.bytes(&bytes)
+        }
+        OwnedValue::String(s) => writer.text(s),
+        OwnedValue::Bytes(b) => writer.bytes(&b.data),
+        OwnedValue::SmallBytes(b) => writer.bytes(&b.data),
+        _ => Err(format!("{:?} is not a scalar", value)),
+    }
+}
+
+pub fn write_expr<W: ItemWriter>(expr: &Expr, writer: &mut W) -> Result<(), String> {
+    let (tag, exprs) = match expr {
+        Expr::Value(value) => return write_value(value, writer),
+        Expr::Symbol(_, name) => return writer.tagged(TAG_BASE + SYMBOL, |w| w.text(name)),
+        Expr::Keyword(_, name) => return writer.tagged(TAG_BASE + KEYWORD, |w| w.text(name)),
+        Expr::LAMBDA(params, body, captured) => {
+            return writer.tagged(TAG_BASE + LAMBDA, |w| {
+                w.array(3)?;
+                write_exprs(params, w)?;
+                write_exprs(body, w)?;
+                write_exprs(captured, w)
+            })
+        }
+        Expr::List(exprs, _) => (LIST, exprs),
+        Expr::Vec(exprs) => (VEC, exprs),
+        Expr::META(exprs) => (META, exprs),
+    };
+    writer.tagged(TAG_BASE + tag, |w| write_exprs(exprs, w))
+}
+
+fn write_exprs<W: ItemWriter>(exprs: &[Expr], writer: &mut W) -> Result<(), String> {
+    writer.array(exprs.len())?;
+    for expr in exprs {
+        write_expr(expr, writer)?;
+    }
+    Ok(())
+}
+
+pub fn read_value<R: ItemReader>(reader: &mut R) -> Result<Option<OwnedValue>, String> {
+    match reader.next_item()? {
+        Some(item) => value_of(item, reader, 0).map(Some),
+        None => Ok(None),
+    }
+}
+
+fn value_of<R: ItemReader>(item: Item, reader: &mut R, depth: usize) -> Result<OwnedValue, String> {
+    match item {
+        Item::Null => Ok(OwnedValue::Null),
+        Item::Undefined => Ok(OwnedValue::NA),
+        Item::Bool(b) => Ok(OwnedValue::Bool(b)),
+        Item::Int(n) => match i64::try_from(n) {
+            Ok(n) => Ok(OwnedValue::I64(n)),
+            Err(_) => u64::try_from(n)
+                .map(OwnedValue::U64)
+                .map_err(|_| format!("Integer {} is out of range", n)),
+        },
+        Item::F32(n) => Ok(OwnedValue::F32(n)),
+        Item::F64(n) => Ok(OwnedValue::F64(n)),
+        Item::Text(s) => Ok(OwnedValue::String(s)),
+        Item::Bytes(b) => Ok(OwnedValue::Bytes(Bytes::from_vec(b))),
+        Item::Array(len) => {
+            let depth = nest(depth, reader)?;
+            let mut array = Vec::with_capacity(capacity(len));
+            elements(len, reader, |item, r| {
+                array.push(value_of(item, r, depth)?);
+                Ok(())
+            })?;
+            Ok(OwnedValue::Array(array))
+        }
+        Item::Map(len) => {
+            let depth = nest(depth, reader)?;
+            Ok(OwnedValue::Map(map_of(len, reader, depth)?))
+        }
+        Item::Break => Err("Unexpected break".to_string()),
+        Item::Tag(tag) => {
+            let depth = nest(depth, reader)?;
+            tagged(tag, reader, depth)
+        }
+    }
+}
+
+// Entries of a map at `depth`
+fn map_of<R: ItemReader>(
+    len: Option<usize>,
+    reader: &mut R,
+    depth: usize,
+) -> Result<OwnedMap, String> {
+    let mut map = OwnedMap::new();
+    let mut key = None;
+    elements(len.map(|l| l.saturating_mul(2)), reader, |item, r| {
+        match key.take() {
+            None => key = Some(item),
+            Some(Item::Text(name)) => {
+                map.try_insert(&name, value_of(item, r, depth)?)?;
+            }
+            Some(Item::Int(id)) => {
+                let id = u64::try_from(id).map_err(|_| format!("Invalid key id {}", id))?;
+                map.insert_key_id(id, value_of(item, r, depth)?);
+            }
+            Some(other) => {
+                return Err(format!(
+                    "Expect string or key id as map key, found {:?}",
+                    other
+                ))
+            }
+        }
+        Ok(())
+    })?;
+    if key.is_some() {
+        return Err("Map key has no value".to_string());
+    }
+    Ok(map)
+}
+
+// Content of a tag at `depth`
+fn tagged<R: ItemReader>(tag: u64, reader: &mut R, depth: usize) -> Result<OwnedValue, String> {
+    let content = item(reader)?;
+    // Tags of other conventions are ignored
+    let offset = match tag.checked_sub(TAG_BASE) {
+        Some(offset) => offset,
+        None => return value_of(content, reader, depth),
+    };
+    match offset {
+        NA => Ok(OwnedValue::NA),
+        PMAP => match content {
+            Item::Map(len) => Ok(OwnedValue::PMap(PersistentMap::from(map_of(
+                len, reader, depth,
+            )?))),
+            other => Err(format!("Expect map for persistent map, found {:?}", other)),
+        },
+        PVEC => match value_of(content, reader, depth)? {
+            OwnedValue::Array(array) => Ok(OwnedValue::PVec(PersistentVec::from(array))),
+            other => Err(format!(
+                "Expect array for persistent vector, found {:?}",
+                other
+            )),
+        },
+        TENSOR => {
+            if !matches!(content, Item::Array(Some(2))) {
+                return Err(format!(
+                    "Expect [shape, data] for tensor, found {:?}",
+                    content
+                ));
+            }
+            let shape = match value_of(item(reader)?, reader, depth)? {
+                OwnedValue::Array(dims) => dims
+                    .iter()
+                    .map(|d| match d {
+                        OwnedValue::I64(d) => usize::try_from(*d).map_err(|e| e.to_string()),
+                        _ => Err(format!("Invalid tensor dimension {:?}", d)),
+                    })
+                    .collect::<Result<Vec<_>, _>>()?,
+                other => return Err(format!("Expect tensor shape, found {:?}", other)),
+            };
+            match value_of(item(reader)?, reader, depth)? {
+                OwnedValue::PrimArray(data) => {
+                    Ok(OwnedValue::Tensor(OwnedTensor::new(data, shape)?))
+                }
+                other => Err(format!(
+                    "Expect primitive array for tensor, found {:?}",
+                    other
+                )),
+            }
+        }
+        offset if (PRIM_ARRAY..NA).contains(&offset) => {
+            let t = type_of(offset - PRIM_ARRAY)?;
+            let len = match content {
+                Item::Array(len) => len,
+                other => {
+                    return Err(format!(
+                        "Expect array for {} array, found {:?}",
+                        get_type(t),
+                        other
+                    ))
+                }
+            };
+            let mut values = Vec::with_capacity(capacity(len));
+            elements(len, reader, |item, r| {
+                values.push(scalar(t, item, r)?);
+                Ok(())
+            })?;
+            Ok(OwnedValue::PrimArray(OwnedPrimArray::from_typed_values(
+                t, values,
+            )?))
+        }
+        offset if offset < PRIM_ARRAY => scalar(type_of(offset)?, content, reader),
+        _ => Err(format!("Unknown tag {:#x}", tag)),
+    }
+}
+
+fn type_of(id: u64) -> Result<Type, String> {
+    match Type::from_id(id as u8) {
+        Type::NA | Type::Null | Type::Map => Err(format!("Unknown type id {}", id)),
+        t => Ok(t),
+    }
+}
+
+macro_rules! int_value {
+    ($e: ident, $t: ty, $item: expr) => {
+        match $item {
+            Item::Int(n) => <$t>::try_from(n)
+                .map(OwnedValue::$e)
+                .map_err(|_| format!("{} is out of range for {}", n, stringify!($t))),
+            other => Err(format!("Expect integer, found {:?}", other)),
+        }
+    };
+}
+
+fn float(item: Item) -> Result<f64, String> {
+    match item {
+        Item::F32(n) => Ok(n as f64),
+        Item::F64(n) => Ok(n),
+        Item::Int(n) => Ok(n as f64),
+        other => Err(format!("Expect number, found {:?}", other)),
+    }
+}
+
+fn floats<R: ItemReader>(item: Item, reader: &mut R, len: usize) -> Result<Vec<f64>, String> {
+    match item {
+        Item::Array(Some(l)) if l == len => (0..len).map(|_| float(self::item(reader)?)).collect(),
+        other => Err(format!("Expect {} numbers, found {:?}", len, other)),
+    }
+}
+
+fn scalar<R: ItemReader>(t: Type, item: Item, reader: &mut R) -> Result<OwnedValue, String> {
+    match t {
+        Type::Bool => match item {
+            Item::Bool(b) => Ok(OwnedValue::Bool(b)),
+            other => Err(format!("Expect boolean, found {:?}", other)),
+        },
+        Type::Char => match item {
+            Item::Text(s) if s.chars().count() == 1 => {
+                Ok(OwnedValue::Char(s.chars().next().unwrap()))
+            }
+            other => Err(format!("Expect one character, found {:?}", other)),
+        },
+        Type::I8 => int_value!(I8, i8, item),
+        Type::I16 => int_value!(I16, i16, item),
+        Type::I32 => int_value!(I32, i32, item),
+        Type::I64 => int_value!(I64, i64, item),
+        Type::U8 => int_value!(U8, u8, item),
+        Type::U16 => int_value!(U16, u16, item),
+        Type::U32 => int_value!(U32, u32, item),
+        Type::U64 => int_value!(U64, u64, item),
+        Type::F32 => float(item).map(|n| OwnedValue::F32(n as f32)),
+        Type::F64 => float(item).map(OwnedValue::F64),
+        Type::Pos2d32 => floats(item, reader, 2).map(|p| {
+            OwnedValue::Pos2d32(Pos2d32 {
+                x: p[0] as f32,
+                y: p[1] as f32,
+            })
+        }),
+        Type::Pos2d64 => {
+            floats(item, reader, 2).map(|p| OwnedValue::Pos2d64(Pos2d64 { x: p[0], y: p[1] }))
+        }
+        Type::Pos3d32 => floats(item, reader, 3).map(|p| {
+            OwnedValue::Pos3d32(Pos3d32 {
+                x: p[0] as f32,
+                y: p[1] as f32,
+                z: p[2] as f32,
+            })
+        }),
+        Type::Pos3d64 => floats(item, reader, 3).map(|p| {
+            OwnedValue::Pos3d64(Pos3d64 {
+                x: p[0],
+                y: p[1],
+                z: p[2],
+            })
+        }),
+        Type::Id => match item {
+            Item::Bytes(b) if b.len() == 16 => {
+                let mut higher = [0u8; 8];
+                let mut lower = [0u8; 8];
+                higher.copy_from_slice(&b[..8]);
+                lower.copy_from_slice(&b[8..]);
+                Ok(OwnedValue::Id(Id::new(
+                    u64::from_be_bytes(higher),
+                    u64::from_be_bytes(lower),
+                )))
+            }
+            other => Err(format!("Expect 16 bytes for id, found {:?}", other)),
+        },
+        Type::String => match item {
+            Item::Text(s) => Ok(OwnedValue::String(s)),
+            other => Err(format!("Expect string, found {:?}", other)),
+        },
+        Type::Bytes => match item {
+            Item::Bytes(b) => Ok(OwnedValue::Bytes(Bytes::from_vec(b))),
+            other => Err(format!("Expect bytes, found {:?}", other)),
+        },
+        Type::SmallBytes => match item {
+            Item::Bytes(b) => Ok(OwnedValue::SmallBytes(SmallBytes::from_vec(b))),
+            other => Err(format!("Expect bytes, found {:?}", other)),
+        },
+        _ => Err(format!("Type {:?} is not a scalar", t)),
+    }
+}
+
+pub fn read_expr<R: ItemReader>(reader: &mut R) -> Result<Option<Expr>, String> {
+    match reader.next_item()? {
+        Some(item) => expr_of(item, reader, 0).map(Some),
+        None => Ok(None),
+    }
+}
+
+fn expr_of<R: ItemReader>(item: Item, reader: &mut R, depth: usize) -> Result<Expr, String> {
+    let offset = match item {
+        Item::Tag(tag) if (TAG_BASE + SYMBOL..=TAG_BASE + LAMBDA).contains(&tag) => tag - TAG_BASE,
+        item => return value_of(item, reader, depth).map(Expr::Value),
+    };
+    let depth = nest(depth, reader)?;
+    let content = self::item(reader)?;
+    match (offset, content) {
+        (SYMBOL, Item::Text(name)) => Ok(Expr::Symbol(hash_str(&name), name)),
+        (KEYWORD, Item::Text(name)) => Ok(Expr::Keyword(hash_str(&name), name)),
+        (LIST, Item::Array(len)) => Ok(Expr::List(exprs_of(len, reader, depth)?, None)),
+        (VEC, Item::Array(len)) => Ok(Expr::Vec(exprs_of(len, reader, depth)?)),
+        (META, Item::Array(len)) => Ok(Expr::META(exprs_of(len, reader, depth)?)),
+        // Lambdas written before captured bindings were kept have only two parts
+        (LAMBDA, Item::Array(Some(parts @ 2..=3))) => {
+            let mut parts = (0..parts)
+                .map(|_| match self::item(reader)? {
+                    Item::Array(len) => exprs_of(len, reader, depth),
+                    other => Err(format!("Expect lambda part, found {:?}", other)),
+                })
+                .collect::<Result<Vec<_>, _>>()?
+                .into_iter();
+            let params = parts.next().unwrap();
+            let body = parts.next().unwrap();
+            Ok(Expr::LAMBDA(params, body, parts.next().unwrap_or_default()))
+        }
+        (_, content) => Err(format!("Invalid expression content {:?}", content)),
+    }
+}
+
+// Expressions of a list, vector or lambda part at `depth`
+fn exprs_of<R: ItemReader>(
+    len: Option<usize>,
+    reader: &mut R,
+    depth: usize,
+) -> Result<Vec<Expr>, String> {
+    let mut exprs = Vec::with_capacity(capacity(len));
+    elements(len, reader, |item, r| {
+        exprs.push(expr_of(item, r, depth)?);
+        Ok(())
+    })?;
+    Ok(exprs)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::expr::serde::Expr;
+    use crate::expr::SExpr;
+    use crate::formats::cbor::*;
+    use crate::formats::msgpack::*;
+    use crate::integrated::lisp::{eval_string, get_lexical_interpreter, parse_to_serde_expr};
+    use crate::types::*;
+
+    fn sample() -> OwnedValue {
+        let mut map = OwnedMap::new();
+        map.insert_value("name", "Dovahkiin".to_string());
+        map.insert_value("age", 30u8);
+        map.insert_value("offset", -3i16);
+        map.insert_value("big", u64::max_value());
+        map.insert_value("ratio", 0.5f32);
+        map.insert_value("id", Id::new(1, 2));
+        map.insert_value(
+            "pos",
+            Pos3d64 {
+                x: 1.0,
+                y: 2.0,
+                z: 3.0,
+            },
+        );
+        map.insert_value("blob", Bytes::from_vec(vec![1, 2, 3]));
+        map.insert_value("small", SmallBytes::from_vec(vec![4]));
+        map.insert_value("tags", vec![1u16, 2, 3]);
+        map.insert_value("ids", vec![Id::new(3, 4)]);
+        map.insert("na", OwnedValue::NA);
+        map.insert(
+            "items",
+            OwnedValue::Array(vec![OwnedValue::Char('x'), OwnedValue::Null]),
+        );
+        map.insert(
+            "matrix",
+            OwnedValue::Tensor(
+                OwnedTensor::new(OwnedPrimArray::F64(vec![1.0, 2.0, 3.0, 4.0]), vec![2, 2])
+                    .unwrap(),
+            ),
+        );
+        map.insert(
+            "pvec",
+            OwnedValue::PVec(PersistentVec::from(vec![
+                OwnedValue::I64(1),
+                OwnedValue::String("a".to_string()),
+            ])),
+        );
+        map.insert_key_id(42, OwnedValue::Bool(true));
+        let mut inner = OwnedMap::new();
+        inner.insert_value("x", 1u32);
+        map.insert("pmap", OwnedValue::PMap(PersistentMap::from(inner)));
+        OwnedValue::Map(map)
+    }
+
+    #[test]
+    fn round_trip() {
+        let value = sample();
+        assert_eq!(from_cbor(&to_cbor(&value).unwrap()).unwrap(), value);
+        assert_eq!(from_msgpack(&to_msgpack(&value).unwrap()).unwrap(), value);
+
+        let expr = parse_to_serde_expr("(lambda [x] (+ x 1u8 :k [1u32 \"s\"]))").unwrap();
+        // Spans are not encoded, restored expressions encode the same without them
+        let cbor = expr_to_cbor(&expr[0]).unwrap();
+        let restored = expr_from_cbor(&cbor).unwrap();
+        assert!(matches!(restored, Expr::List(_, None)));
+        assert_eq!(expr_to_cbor(&restored).unwrap(), cbor);
+        let msgpack = expr_to_msgpack(&expr[0]).unwrap();
+        let restored = expr_from_msgpack(&msgpack).unwrap();
+        assert_eq!(expr_to_msgpack(&restored).unwrap(), msgpack);
+
+        // Closures keep their captured bindings
+        let mut interpreter = get_lexical_interpreter();
+        let closure = eval_string(&mut interpreter, "(let [y 2u8] (lambda [x] (+ x y)))").unwrap();
+        let closure = Expr::from_sexpr(closure);
+        let cbor = expr_to_cbor(&closure).unwrap();
+        assert_eq!(expr_to_cbor(&expr_from_cbor(&cbor).unwrap()).unwrap(), cbor);
+        let restored = expr_from_msgpack(&expr_to_msgpack(&closure).unwrap()).unwrap();
+        let mut interpreter = get_lexical_interpreter();
+        interpreter.bind("f", restored.to_sexpr());
+        assert_eq!(
+            eval_string(&mut interpreter, "(f 1u8)").unwrap(),
+            SExpr::owned_value(OwnedValue::U8(3))
+        );
+    }
+
+    #[test]
+    fn streaming() {
+        let values = vec![sample(), OwnedValue::I64(1), OwnedValue::Array(vec![])];
+        let mut cbor = CborEncoder::new(vec![]);
+        let mut msgpack = MsgPackEncoder::new(vec![]);
+        for value in &values {
+            cbor.write_value(value).unwrap();
+            msgpack.write_value(value).unwrap();
+        }
+        let cbor = cbor.into_inner();
+        let msgpack = msgpack.into_inner();
+        let mut cbor_decoder = CborDecoder::new(std::io::Cursor::new(cbor));
+        let mut msgpack_decoder = MsgPackDecoder::new(std::io::Cursor::new(msgpack));
+        for value in &values {
+            assert_eq!(cbor_decoder.read_value().unwrap().as_ref(), Some(value));
+            assert_eq!(msgpack_decoder.read_value().unwrap().as_ref(), Some(value));
+        }
+        assert_eq!(cbor_decoder.read_value().unwrap(), None);
+        assert_eq!(msgpack_decoder.read_value().unwrap(), None);
+        assert!(to_cbor(&OwnedValue::Sketch(Sketch::HyperLogLog(
+            HyperLogLog::new(10).unwrap()
+        )))
+        .is_err());
+    }
+
+    #[test]
+    fn depth_limit() {
+        let nested = vec![0x81; 100_000];
+        assert!(from_cbor(&nested).is_err());
+        assert!(from_msgpack(&nested).is_err());
+        assert!(expr_from_cbor(&nested).is_err());
+        let mut shallow = vec![0x81; 3];
+        shallow.push(0x01);
+        assert!(from_cbor(&shallow).is_ok());
+        let mut decoder = CborDecoder::new(&shallow[..]).with_max_depth(2);
+        assert!(decoder.read_value().is_err());
+        let mut decoder = MsgPackDecoder::new(&[0x91, 0x91, 0x01][..]).with_max_depth(1);
+        assert!(decoder.read_value().is_err());
+    }
+}
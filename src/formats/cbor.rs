@@ -0,0 +1,336 @@
+use super::binary::{self, Item, ItemReader, ItemWriter};
+use super::DEFAULT_MAX_DEPTH;
+use crate::expr::serde::Expr;
+use crate::types::OwnedValue;
+use std::io::{Read, Write};
+
+// CBOR (RFC 8949) for values and expressions. Decoding pulls items from the reader one at a
+// time, so arrays are built element by element without buffering the input. Indefinite
+// lengths and half precision floats from other encoders are understood.
+
+pub struct CborEncoder<W: Write> {
+    writer: W,
+}
+
+pub struct CborDecoder<R: Read> {
+    reader: R,
+    max_depth: usize,
+}
+
+impl<W: Write> CborEncoder<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    pub fn write_value(&mut self, value: &OwnedValue) -> Result<(), String> {
+        binary::write_value(value, self)
+    }
+
+    pub fn write_expr(&mut self, expr: &Expr) -> Result<(), String> {
+        binary::write_expr(expr, self)
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn put(&mut self, data: &[u8]) -> Result<(), String> {
+        self.writer.write_all(data).map_err(|e| e.to_string())
+    }
+
+    fn header(&mut self, major: u8, arg: u64) -> Result<(), String> {
+        let major = major << 5;
+        if arg < 24 {
+            self.put(&[major | arg as u8])
+        } else if arg <= u8::MAX as u64 {
+            self.put(&[major | 24, arg as u8])
+        } else if arg <= u16::MAX as u64 {
+            self.put(&[major | 25])?;
+            self.put(&(arg as u16).to_be_bytes())
+        } else if arg <= u32::MAX as u64 {
+            self.put(&[major | 26])?;
+            self.put(&(arg as u32).to_be_bytes())
+        } else {
+            self.put(&[major | 27])?;
+            self.put(&arg.to_be_bytes())
+        }
+    }
+}
+
+impl<W: Write> ItemWriter for CborEncoder<W> {
+    fn null(&mut self) -> Result<(), String> {
+        self.put(&[0xf6])
+    }
+
+    fn bool(&mut self, b: bool) -> Result<(), String> {
+        self.put(&[if b { 0xf5 } else { 0xf4 }])
+    }
+
+    fn int(&mut self, n: i128) -> Result<(), String> {
+        if n >= 0 {
+            self.header(0, n as u64)
+        } else {
+            self.header(1, (-1 - n) as u64)
+        }
+    }
+
+    fn f32(&mut self, n: f32) -> Result<(), String> {
+        self.put(&[0xfa])?;
+        self.put(&n.to_be_bytes())
+    }
+
+    fn f64(&mut self, n: f64) -> Result<(), String> {
+        self.put(&[0xfb])?;
+        self.put(&n.to_be_bytes())
+    }
+
+    fn text(&mut self, s: &str) -> Result<(), String> {
+        self.header(3, s.len() as u64)?;
+        self.put(s.as_bytes())
+    }
+
+    fn bytes(&mut self, b: &[u8]) -> Result<(), String> {
+        self.header(2, b.len() as u64)?;
+        self.put(b)
+    }
+
+    fn array(&mut self, len: usize) -> Result<(), String> {
+        self.header(4, len as u64)
+    }
+
+    fn map(&mut self, len: usize) -> Result<(), String> {
+        self.header(5, len as u64)
+    }
+
+    fn tagged<F>(&mut self, tag: u64, content: F) -> Result<(), String>
+    where
+        F: FnOnce(&mut Self) -> Result<(), String>,
+    {
+        self.header(6, tag)?;
+        content(self)
+    }
+}
+
+impl<R: Read> CborDecoder<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            max_depth: DEFAULT_MAX_DEPTH,
+        }
+    }
+
+    // Arrays, maps and tags nested deeper than `max_depth` are errors
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    // Next value in the stream, None at its end
+    pub fn read_value(&mut self) -> Result<Option<OwnedValue>, String> {
+        binary::read_value(self)
+    }
+
+    pub fn read_expr(&mut self) -> Result<Option<Expr>, String> {
+        binary::read_expr(self)
+    }
+
+    fn take(&mut self, len: u64) -> Result<Vec<u8>, String> {
+        let mut data = vec![];
+        (&mut self.reader)
+            .take(len)
+            .read_to_end(&mut data)
+            .map_err(|e| e.to_string())?;
+        if (data.len() as u64) < len {
+            return Err("Unexpected end of input".to_string());
+        }
+        Ok(data)
+    }
+
+    fn arg(&mut self, info: u8) -> Result<Option<u64>, String> {
+        let size = match info {
+            0..=23 => return Ok(Some(info as u64)),
+            24 => 1,
+            25 => 2,
+            26 => 4,
+            27 => 8,
+            31 => return Ok(None),
+            _ => return Err(format!("Invalid additional information {}", info)),
+        };
+        let data = self.take(size)?;
+        Ok(Some(data.iter().fold(0u64, |acc, b| acc << 8 | *b as u64)))
+    }
+
+    // Indefinite strings are sent in chunks of the same major type
+    fn chunks(&mut self, major: u8, len: Option<u64>) -> Result<Vec<u8>, String> {
+        if let Some(len) = len {
+            return self.take(len);
+        }
+        let mut data = vec![];
+        loop {
+            let initial = self.take(1)?[0];
+            if initial == 0xff {
+                return Ok(data);
+            }
+            if initial >> 5 != major {
+                return Err(format!("Invalid chunk {:#x} in indefinite string", initial));
+            }
+            match self.arg(initial & 0x1f)? {
+                Some(len) => data.extend(self.take(len)?),
+                None => return Err("Nested indefinite string".to_string()),
+            }
+        }
+    }
+}
+
+impl<R: Read> ItemReader for CborDecoder<R> {
+    fn max_depth(&self) -> usize {
+        self.max_depth
+    }
+
+    fn next_item(&mut self) -> Result<Option<Item>, String> {
+        let mut initial = [0u8];
+        if self.reader.read(&mut initial).map_err(|e| e.to_string())? == 0 {
+            return Ok(None);
+        }
+        let major = initial[0] >> 5;
+        let info = initial[0] & 0x1f;
+        if major == 7 {
+            let item = match info {
+                20 => Item::Bool(false),
+                21 => Item::Bool(true),
+                22 => Item::Null,
+                23 => Item::Undefined,
+                25 => {
+                    let data = self.take(2)?;
+                    Item::F32(half_to_f32(u16::from_be_bytes([data[0], data[1]])))
+                }
+                26 => {
+                    let data = self.take(4)?;
+                    Item::F32(f32::from_be_bytes([data[0], data[1], data[2], data[3]]))
+                }
+                27 => {
+                    let mut bytes = [0u8; 8];
+                    bytes.copy_from_slice(&self.take(8)?);
+                    Item::F64(f64::from_be_bytes(bytes))
+                }
+                31 => Item::Break,
+                _ => return Err(format!("Unsupported simple value {}", info)),
+            };
+            return Ok(Some(item));
+        }
+        let arg = self.arg(info)?;
+        let definite = |arg: Option<u64>| {
+            arg.ok_or_else(|| format!("Major type {} cannot be indefinite", major))
+        };
+        let item = match major {
+            0 => Item::Int(definite(arg)? as i128),
+            1 => Item::Int(-1 - definite(arg)? as i128),
+            2 => Item::Bytes(self.chunks(major, arg)?),
+            3 => {
+                Item::Text(String::from_utf8(self.chunks(major, arg)?).map_err(|e| e.to_string())?)
+            }
+            4 => Item::Array(arg.map(|len| len as usize)),
+            5 => Item::Map(arg.map(|len| len as usize)),
+            _ => Item::Tag(definite(arg)?),
+        };
+        Ok(Some(item))
+    }
+}
+
+fn half_to_f32(half: u16) -> f32 {
+    let sign = if half & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exp = (half >> 10) & 0x1f;
+    let mant = (half & 0x3ff) as f32;
+    sign * match exp {
+        0 => mant * 2f32.powi(-24),
+        31 if mant == 0.0 => f32::INFINITY,
+        31 => f32::NAN,
+        _ => (1.0 + mant / 1024.0) * 2f32.powi(exp as i32 - 15),
+    }
+}
+
+pub fn to_cbor(value: &OwnedValue) -> Result<Vec<u8>, String> {
+    let mut encoder = CborEncoder::new(vec![]);
+    encoder.write_value(value)?;
+    Ok(encoder.into_inner())
+}
+
+pub fn from_cbor(data: &[u8]) -> Result<OwnedValue, String> {
+    let mut decoder = CborDecoder::new(data);
+    let value = decoder
+        .read_value()?
+        .ok_or_else(|| "Empty input".to_string())?;
+    if !decoder.reader.is_empty() {
+        return Err(format!(
+            "{} bytes left after the value",
+            decoder.reader.len()
+        ));
+    }
+    Ok(value)
+}
+
+pub fn expr_to_cbor(expr: &Expr) -> Result<Vec<u8>, String> {
+    let mut encoder = CborEncoder::new(vec![]);
+    encoder.write_expr(expr)?;
+    Ok(encoder.into_inner())
+}
+
+pub fn expr_from_cbor(data: &[u8]) -> Result<Expr, String> {
+    let mut decoder = CborDecoder::new(data);
+    let expr = decoder
+        .read_expr()?
+        .ok_or_else(|| "Empty input".to_string())?;
+    if !decoder.reader.is_empty() {
+        return Err(format!(
+            "{} bytes left after the expression",
+            decoder.reader.len()
+        ));
+    }
+    Ok(expr)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::types::*;
+
+    #[test]
+    fn encoding() {
+        assert_eq!(
+            to_cbor(&OwnedValue::I64(500)).unwrap(),
+            vec![0x19, 0x01, 0xf4]
+        );
+        assert_eq!(to_cbor(&OwnedValue::I64(-1)).unwrap(), vec![0x20]);
+        assert_eq!(
+            to_cbor(&OwnedValue::String("a".to_string())).unwrap(),
+            vec![0x61, b'a']
+        );
+        // u8 1 under the tag of its type
+        assert_eq!(
+            to_cbor(&OwnedValue::U8(1)).unwrap(),
+            vec![0xda, 0x44, 0x56, 0x00, Type::U8.id(), 0x01]
+        );
+    }
+
+    #[test]
+    fn foreign_input() {
+        // Indefinite array of a half float and chunked text, under an unknown tag
+        let data = [
+            0xc1, 0x9f, 0xf9, 0x3c, 0x00, 0x7f, 0x61, b'a', 0x61, b'b', 0xff, 0xff,
+        ];
+        assert_eq!(
+            from_cbor(&data).unwrap(),
+            OwnedValue::Array(vec![
+                OwnedValue::F32(1.0),
+                OwnedValue::String("ab".to_string())
+            ])
+        );
+        let data = [0xa1, 0x61, b'a', 0x01];
+        assert_eq!(
+            from_cbor(&data).unwrap().Map().unwrap().get("a"),
+            &OwnedValue::I64(1)
+        );
+        assert!(from_cbor(&[0x82, 0x01]).is_err());
+        assert!(from_cbor(&[0x01, 0x01]).is_err());
+    }
+}
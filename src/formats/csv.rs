@@ -0,0 +1,435 @@
+use crate::types::*;
+use std::collections::HashMap;
+use std::fmt;
+
+// CSV with a header row. Column types are inferred from a sample of rows, picking the
+// narrowest of bool, integers from `u8` to `i64`, `f64` and id, falling back to string.
+// Cells that do not convert make their row an error and the row is skipped, empty cells
+// are left out of the row.
+
+// Cells of a record with the line it starts at
+type Record = (usize, Vec<String>);
+
+#[derive(Debug, Clone)]
+pub struct Csv {
+    delimiter: char,
+    sample_size: usize,
+    types: HashMap<String, Type>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CsvError {
+    pub line: usize,
+    pub column: Option<String>,
+    pub message: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct CsvOutput<T> {
+    pub data: T,
+    pub schema: Vec<(String, Type)>,
+    pub errors: Vec<CsvError>,
+}
+
+impl fmt::Display for CsvError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.column {
+            Some(column) => write!(f, "line {}, column {}: {}", self.line, column, self.message),
+            None => write!(f, "line {}: {}", self.line, self.message),
+        }
+    }
+}
+
+impl Default for Csv {
+    fn default() -> Self {
+        Self {
+            delimiter: ',',
+            sample_size: 100,
+            types: HashMap::new(),
+        }
+    }
+}
+
+impl Csv {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn delimiter(mut self, delimiter: char) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    // Number of rows looked at to infer column types
+    pub fn sample_size(mut self, rows: usize) -> Self {
+        self.sample_size = rows;
+        self
+    }
+
+    // Explicit type of a column, skipping inference
+    pub fn column_type(mut self, column: &str, t: Type) -> Self {
+        self.types.insert(column.to_string(), t);
+        self
+    }
+
+    pub fn read_rows(&self, text: &str) -> Result<CsvOutput<Vec<OwnedMap>>, String> {
+        let (mut records, mut errors) = self.records(text)?;
+        let (_, header) = records.remove(0);
+        let schema = self.schema(&header, &records);
+        let mut rows = Vec::with_capacity(records.len());
+        for (line, cells) in records {
+            if cells.len() != schema.len() {
+                errors.push(CsvError {
+                    line,
+                    column: None,
+                    message: format!("Expect {} cells, found {}", schema.len(), cells.len()),
+                });
+                continue;
+            }
+            let mut row = OwnedMap::new();
+            let mut failed = false;
+            for ((name, t), cell) in schema.iter().zip(cells.iter()) {
+                if cell.is_empty() {
+                    continue;
+                }
+                match parse_cell(*t, cell).and_then(|value| row.try_insert(name, value)) {
+                    Ok(_) => {}
+                    Err(message) => {
+                        errors.push(CsvError {
+                            line,
+                            column: Some(name.clone()),
+                            message,
+                        });
+                        failed = true;
+                    }
+                }
+            }
+            if !failed {
+                rows.push(row);
+            }
+        }
+        errors.sort_by_key(|e| e.line);
+        Ok(CsvOutput {
+            data: rows,
+            schema,
+            errors,
+        })
+    }
+
+    pub fn read_batch(&self, text: &str) -> Result<CsvOutput<RecordBatch>, String> {
+        let output = self.read_rows(text)?;
+        Ok(CsvOutput {
+            data: RecordBatch::from_rows(&output.data),
+            schema: output.schema,
+            errors: output.errors,
+        })
+    }
+
+    // Columns follow the order of the first row they appear in
+    pub fn write_rows(&self, rows: &[OwnedMap]) -> Result<String, String> {
+        self.write_batch(&RecordBatch::from_rows(rows))
+    }
+
+    pub fn write_batch(&self, batch: &RecordBatch) -> Result<String, String> {
+        let mut out = String::new();
+        let header: Vec<String> = batch
+            .columns
+            .iter()
+            .map(|c| match &c.name {
+                Some(name) => name.clone(),
+                None => field_name(c.key).unwrap_or_else(|| format!("#{}", c.key)),
+            })
+            .collect();
+        self.write_line(&mut out, &header);
+        for row in 0..batch.num_rows {
+            let cells = batch
+                .columns
+                .iter()
+                .map(|c| format_cell(&c.owned_value(row)))
+                .collect::<Result<Vec<_>, _>>()?;
+            self.write_line(&mut out, &cells);
+        }
+        Ok(out)
+    }
+
+    fn write_line(&self, out: &mut String, cells: &[String]) {
+        for (i, cell) in cells.iter().enumerate() {
+            if i > 0 {
+                out.push(self.delimiter);
+            }
+            if cell.contains([self.delimiter, '"', '\n', '\r']) {
+                out.push('"');
+                out.push_str(&cell.replace('"', "\"\""));
+                out.push('"');
+            } else {
+                out.push_str(cell);
+            }
+        }
+        out.push('\n');
+    }
+
+    // Splits the text into records, the first is the header. Quoted cells may span lines.
+    fn records(&self, text: &str) -> Result<(Vec<Record>, Vec<CsvError>), String> {
+        let mut records = vec![];
+        let mut errors = vec![];
+        let mut cells = vec![];
+        let mut cell = String::new();
+        let mut line = 1;
+        let mut start = 1;
+        let mut quoted = false;
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            if quoted {
+                match c {
+                    '"' if chars.peek() == Some(&'"') => {
+                        chars.next();
+                        cell.push('"');
+                    }
+                    '"' => quoted = false,
+                    c => {
+                        if c == '\n' {
+                            line += 1;
+                        }
+                        cell.push(c)
+                    }
+                }
+                continue;
+            }
+            match c {
+                '"' if cell.is_empty() => quoted = true,
+                '\r' if chars.peek() == Some(&'\n') => {}
+                '\n' | '\r' => {
+                    cells.push(std::mem::take(&mut cell));
+                    // Blank lines are skipped
+                    if cells.len() > 1 || !cells[0].is_empty() {
+                        records.push((start, std::mem::take(&mut cells)));
+                    }
+                    cells.clear();
+                    line += 1;
+                    start = line;
+                }
+                c if c == self.delimiter => cells.push(std::mem::take(&mut cell)),
+                c => cell.push(c),
+            }
+        }
+        if quoted {
+            errors.push(CsvError {
+                line: start,
+                column: None,
+                message: "Quoted cell is not closed".to_string(),
+            });
+        } else if !cell.is_empty() || !cells.is_empty() {
+            cells.push(cell);
+            records.push((start, cells));
+        }
+        if records.is_empty() {
+            return Err("CSV has no header".to_string());
+        }
+        Ok((records, errors))
+    }
+
+    fn schema(&self, header: &[String], records: &[Record]) -> Vec<(String, Type)> {
+        header
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                let t = match self.types.get(name) {
+                    Some(t) => *t,
+                    None => infer_type(
+                        records
+                            .iter()
+                            .take(self.sample_size)
+                            .filter_map(|(_, cells)| cells.get(i))
+                            .filter(|cell| !cell.is_empty()),
+                    ),
+                };
+                (name.clone(), t)
+            })
+            .collect()
+    }
+}
+
+fn parse_bool(cell: &str) -> Option<bool> {
+    match cell.to_lowercase().as_str() {
+        "true" => Some(true),
+        "false" => Some(false),
+        _ => None,
+    }
+}
+
+// Narrowest type every sampled cell converts to
+fn infer_type<'a, I: Iterator<Item = &'a String>>(cells: I) -> Type {
+    let mut bool = true;
+    let mut int = true;
+    let mut float = true;
+    let mut id = true;
+    let mut min = 0i128;
+    let mut max = 0i128;
+    let mut sampled = false;
+    for cell in cells {
+        sampled = true;
+        bool &= parse_bool(cell).is_some();
+        match cell.parse::<i128>() {
+            Ok(n) => {
+                min = min.min(n);
+                max = max.max(n);
+            }
+            Err(_) => int = false,
+        }
+        float &= cell.parse::<f64>().is_ok();
+        id &= cell.parse::<Id>().is_ok();
+    }
+    if !sampled {
+        return Type::String;
+    }
+    if bool {
+        return Type::Bool;
+    }
+    if int {
+        if min >= 0 {
+            if max <= u8::MAX as i128 {
+                return Type::U8;
+            } else if max <= u16::MAX as i128 {
+                return Type::U16;
+            } else if max <= u32::MAX as i128 {
+                return Type::U32;
+            }
+        } else if min >= i8::MIN as i128 && max <= i8::MAX as i128 {
+            return Type::I8;
+        } else if min >= i16::MIN as i128 && max <= i16::MAX as i128 {
+            return Type::I16;
+        } else if min >= i32::MIN as i128 && max <= i32::MAX as i128 {
+            return Type::I32;
+        }
+        if min >= i64::MIN as i128 && max <= i64::MAX as i128 {
+            return Type::I64;
+        }
+    }
+    // Hex ids can look like numbers in exponent form
+    if id {
+        return Type::Id;
+    }
+    if float {
+        return Type::F64;
+    }
+    Type::String
+}
+
+fn parse_cell(t: Type, cell: &str) -> Result<OwnedValue, String> {
+    let invalid = || format!("'{}' is not a valid {}", cell, get_type(t));
+    match t {
+        Type::Bool => parse_bool(cell).map(OwnedValue::Bool).ok_or_else(invalid),
+        Type::Char => {
+            let mut chars = cell.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Ok(OwnedValue::Char(c)),
+                _ => Err(invalid()),
+            }
+        }
+        Type::I8 => cell.parse().map(OwnedValue::I8).map_err(|_| invalid()),
+        Type::I16 => cell.parse().map(OwnedValue::I16).map_err(|_| invalid()),
+        Type::I32 => cell.parse().map(OwnedValue::I32).map_err(|_| invalid()),
+        Type::I64 => cell.parse().map(OwnedValue::I64).map_err(|_| invalid()),
+        Type::U8 => cell.parse().map(OwnedValue::U8).map_err(|_| invalid()),
+        Type::U16 => cell.parse().map(OwnedValue::U16).map_err(|_| invalid()),
+        Type::U32 => cell.parse().map(OwnedValue::U32).map_err(|_| invalid()),
+        Type::U64 => cell.parse().map(OwnedValue::U64).map_err(|_| invalid()),
+        Type::F32 => cell.parse().map(OwnedValue::F32).map_err(|_| invalid()),
+        Type::F64 => cell.parse().map(OwnedValue::F64).map_err(|_| invalid()),
+        Type::Id => cell.parse().map(OwnedValue::Id).map_err(|_| invalid()),
+        Type::String => Ok(OwnedValue::String(cell.to_string())),
+        _ => Err(format!("Type {} cannot be read from CSV", get_type(t))),
+    }
+}
+
+fn format_cell(value: &OwnedValue) -> Result<String, String> {
+    Ok(match value {
+        OwnedValue::Null | OwnedValue::NA => String::new(),
+        OwnedValue::Bool(b) => b.to_string(),
+        OwnedValue::Char(c) => c.to_string(),
+        OwnedValue::I8(n) => n.to_string(),
+        OwnedValue::I16(n) => n.to_string(),
+        OwnedValue::I32(n) => n.to_string(),
+        OwnedValue::I64(n) => n.to_string(),
+        OwnedValue::U8(n) => n.to_string(),
+        OwnedValue::U16(n) => n.to_string(),
+        OwnedValue::U32(n) => n.to_string(),
+        OwnedValue::U64(n) => n.to_string(),
+        OwnedValue::F32(n) => n.to_string(),
+        OwnedValue::F64(n) => n.to_string(),
+        OwnedValue::Id(id) => id.to_string(),
+        OwnedValue::String(s) => s.clone(),
+        _ => return Err(format!("{:?} cannot be written as a CSV cell", value)),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const TEXT: &str = "name,age,score,active,id\n\
+                        a,1,1.5,true,0000000000000001000000000000000a\n\
+                        \"b, \"\"quoted\"\"\n\",300,2,false,\n\
+                        \n\
+                        c,-1,3,true,0000000000000001000000000000000b\n\
+                        d,4\n";
+
+    #[test]
+    fn reading() {
+        let output = Csv::new().read_rows(TEXT).unwrap();
+        assert_eq!(
+            output.schema,
+            vec![
+                ("name".to_string(), Type::String),
+                ("age".to_string(), Type::I16),
+                ("score".to_string(), Type::F64),
+                ("active".to_string(), Type::Bool),
+                ("id".to_string(), Type::Id),
+            ]
+        );
+        assert_eq!(output.data.len(), 3);
+        assert_eq!(
+            output.data[1].get("name"),
+            &OwnedValue::String("b, \"quoted\"\n".to_string())
+        );
+        assert_eq!(output.data[1].get("id"), &OwnedValue::Null);
+        assert_eq!(
+            output.errors.iter().map(|e| e.line).collect::<Vec<_>>(),
+            vec![7]
+        );
+
+        let output = Csv::new()
+            .sample_size(1)
+            .column_type("score", Type::F32)
+            .read_batch(TEXT)
+            .unwrap();
+        assert_eq!(output.schema[1].1, Type::U8);
+        assert_eq!(output.schema[2].1, Type::F32);
+        assert_eq!(output.data.num_rows, 1);
+        assert_eq!(
+            output.errors.iter().map(|e| e.line).collect::<Vec<_>>(),
+            vec![3, 6, 7]
+        );
+        assert_eq!(
+            output.errors[0],
+            CsvError {
+                line: 3,
+                column: Some("age".to_string()),
+                message: "'300' is not a valid u8".to_string()
+            }
+        );
+        assert_eq!(
+            output.data.column_by_name("age").unwrap().view(),
+            Some(OwnedPrimArray::U8(vec![1]).shared())
+        );
+    }
+
+    #[test]
+    fn writing() {
+        let csv = Csv::new().delimiter(';');
+        let output = csv.read_rows(&TEXT.replace(',', ";")).unwrap();
+        let text = csv.write_rows(&output.data).unwrap();
+        assert_eq!(csv.read_rows(&text).unwrap().data, output.data);
+        assert!(text.starts_with("name;age;score;active;id\na;1;1.5;true;"));
+    }
+}
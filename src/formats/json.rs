@@ -0,0 +1,944 @@
+use super::DEFAULT_MAX_DEPTH;
+use crate::types::visitor::ordered_keys;
+use crate::types::*;
+use std::convert::TryFrom;
+use std::fmt::Write;
+
+// JSON text for values. Maps become objects, arrays and primitive arrays become arrays,
+// bytes become base64 strings and ids become hex strings.
+//
+// Plain JSON cannot tell `u8` from `i64` or an id from a string, so the typed form wraps
+// such values as `{"$type": "u8", "value": 1}`, using the type names of the lisp dialect.
+// Primitive arrays are tagged with the element type followed by `[]`. Reading always
+// understands the wrapped form, numbers without one become `i64` (or `f64` with a fraction
+// or an exponent). Map keys without a field name are written as `#<key id>`, field names
+// starting with `#` are written with another `#` in front.
+
+const TYPE_KEY: &str = "$type";
+const VALUE_KEY: &str = "value";
+
+pub fn to_json(value: &OwnedValue) -> Result<String, String> {
+    let mut writer = Writer {
+        out: String::new(),
+        typed: false,
+    };
+    writer.value(value)?;
+    Ok(writer.out)
+}
+
+// Lossless form, reading it back gives an equal value
+pub fn to_typed_json(value: &OwnedValue) -> Result<String, String> {
+    let mut writer = Writer {
+        out: String::new(),
+        typed: true,
+    };
+    writer.value(value)?;
+    Ok(writer.out)
+}
+
+pub fn from_json(text: &str) -> Result<OwnedValue, String> {
+    from_json_with_max_depth(text, DEFAULT_MAX_DEPTH)
+}
+
+// Arrays and objects nested deeper than `max_depth` are errors
+pub fn from_json_with_max_depth(text: &str, max_depth: usize) -> Result<OwnedValue, String> {
+    let mut parser = Parser {
+        text,
+        bytes: text.as_bytes(),
+        pos: 0,
+        depth: 0,
+        max_depth,
+    };
+    let json = parser.parse()?;
+    to_value(&json)
+}
+
+struct Writer {
+    out: String,
+    typed: bool,
+}
+
+impl Writer {
+    fn value(&mut self, value: &OwnedValue) -> Result<(), String> {
+        match value {
+            OwnedValue::Null => self.out.push_str("null"),
+            OwnedValue::NA => {
+                if self.typed {
+                    self.out.push_str("{\"$type\":\"na\"}");
+                } else {
+                    self.out.push_str("null");
+                }
+            }
+            OwnedValue::Bool(b) => self.out.push_str(if *b { "true" } else { "false" }),
+            OwnedValue::I64(n) => write!(self.out, "{}", n).unwrap(),
+            OwnedValue::F64(n) if n.is_finite() => self.float(*n),
+            OwnedValue::String(s) => self.string(s),
+            OwnedValue::Map(map) => {
+                let keys = ordered_keys(map.fields.iter(), map.map.keys().cloned());
+                let wrap =
+                    self.typed && keys.iter().any(|(_, n)| *n == Some(&TYPE_KEY.to_string()));
+                if wrap {
+                    self.type_tag("map");
+                }
+                self.out.push('{');
+                for (i, (key, name)) in keys.into_iter().enumerate() {
+                    if i > 0 {
+                        self.out.push(',');
+                    }
+                    self.key(key, name.map(|n| n.as_str()));
+                    self.value(&map.map[&key])?;
+                }
+                self.out.push('}');
+                if wrap {
+                    self.out.push('}');
+                }
+            }
+            OwnedValue::Array(array) => self.array(array.iter())?,
+            OwnedValue::PrimArray(array) => {
+                let elements = (0..array.len()).map(|i| array.get_owned(i).unwrap());
+                if self.typed {
+                    self.type_tag(&format!("{}[]", get_type(array.base_type())));
+                    self.scalars(elements);
+                    self.out.push('}');
+                } else {
+                    self.scalars(elements);
+                }
+            }
+            OwnedValue::PMap(map) => {
+                if self.typed {
+                    self.type_tag("pmap");
+                }
+                self.value(&OwnedValue::Map(map.to_map()))?;
+                if self.typed {
+                    self.out.push('}');
+                }
+            }
+            OwnedValue::PVec(vec) => {
+                if self.typed {
+                    self.type_tag("pvec");
+                }
+                self.array(vec.iter())?;
+                if self.typed {
+                    self.out.push('}');
+                }
+            }
+            OwnedValue::Tensor(tensor) => {
+                let compact = tensor.shared().owned();
+                if self.typed {
+                    self.out.push_str("{\"$type\":\"tensor\",\"shape\":");
+                } else {
+                    self.out.push_str("{\"shape\":");
+                }
+                self.array(
+                    compact
+                        .shape()
+                        .iter()
+                        .map(|d| OwnedValue::U64(*d as u64))
+                        .collect::<Vec<_>>()
+                        .iter(),
+                )?;
+                self.out.push_str(if self.typed {
+                    ",\"value\":"
+                } else {
+                    ",\"data\":"
+                });
+                self.value(&OwnedValue::PrimArray(compact.into_data()))?;
+                self.out.push('}');
+            }
+            OwnedValue::Sketch(sketch) => {
+                return Err(format!(
+                    "{} sketch cannot be written as JSON",
+                    sketch.kind()
+                ))
+            }
+            _ => self.scalar(value),
+        }
+        Ok(())
+    }
+
+    fn array<'a, I: Iterator<Item = &'a OwnedValue>>(&mut self, values: I) -> Result<(), String> {
+        self.out.push('[');
+        for (i, value) in values.enumerate() {
+            if i > 0 {
+                self.out.push(',');
+            }
+            self.value(value)?;
+        }
+        self.out.push(']');
+        Ok(())
+    }
+
+    // Elements of primitive arrays, the array carries the type
+    fn scalars<I: Iterator<Item = OwnedValue>>(&mut self, values: I) {
+        let typed = self.typed;
+        self.typed = false;
+        self.out.push('[');
+        for (i, value) in values.enumerate() {
+            if i > 0 {
+                self.out.push(',');
+            }
+            self.scalar_content(&value);
+        }
+        self.out.push(']');
+        self.typed = typed;
+    }
+
+    fn scalar(&mut self, value: &OwnedValue) {
+        if self.typed {
+            self.type_tag(get_type(value.base_type()));
+            self.scalar_content(value);
+            self.out.push('}');
+        } else {
+            self.scalar_content(value);
+        }
+    }
+
+    fn scalar_content(&mut self, value: &OwnedValue) {
+        match value {
+            OwnedValue::Bool(b) => self.out.push_str(if *b { "true" } else { "false" }),
+            OwnedValue::Char(c) => self.string(&c.to_string()),
+            OwnedValue::I8(n) => write!(self.out, "{}", n).unwrap(),
+            OwnedValue::I16(n) => write!(self.out, "{}", n).unwrap(),
+            OwnedValue::I32(n) => write!(self.out, "{}", n).unwrap(),
+            OwnedValue::I64(n) => write!(self.out, "{}", n).unwrap(),
+            OwnedValue::U8(n) => write!(self.out, "{}", n).unwrap(),
+            OwnedValue::U16(n) => write!(self.out, "{}", n).unwrap(),
+            OwnedValue::U32(n) => write!(self.out, "{}", n).unwrap(),
+            OwnedValue::U64(n) => write!(self.out, "{}", n).unwrap(),
+            OwnedValue::F32(n) => self.float(*n as f64),
+            OwnedValue::F64(n) => self.float(*n),
+            OwnedValue::Pos2d32(p) => self.floats(&[p.x as f64, p.y as f64]),
+            OwnedValue::Pos2d64(p) => self.floats(&[p.x, p.y]),
+            OwnedValue::Pos3d32(p) => self.floats(&[p.x as f64, p.y as f64, p.z as f64]),
+            OwnedValue::Pos3d64(p) => self.floats(&[p.x, p.y, p.z]),
+            OwnedValue::Id(id) => self.string(&id.to_hex()),
+            OwnedValue::String(s) => self.string(s),
+            OwnedValue::Bytes(b) => self.string(&base64_encode(&b.data)),
+            OwnedValue::SmallBytes(b) => self.string(&base64_encode(&b.data)),
+            _ => self.out.push_str("null"),
+        }
+    }
+
+    // Non-finite numbers have no JSON form, they are kept as strings in the typed form
+    fn float(&mut self, n: f64) {
+        if !n.is_finite() {
+            if self.typed {
+                self.string(&n.to_string());
+            } else {
+                self.out.push_str("null");
+            }
+            return;
+        }
+        let text = n.to_string();
+        self.out.push_str(&text);
+        if !text.contains(['.', 'e', 'E']) {
+            self.out.push_str(".0");
+        }
+    }
+
+    fn floats(&mut self, values: &[f64]) {
+        self.out.push('[');
+        for (i, n) in values.iter().enumerate() {
+            if i > 0 {
+                self.out.push(',');
+            }
+            self.float(*n);
+        }
+        self.out.push(']');
+    }
+
+    fn type_tag(&mut self, name: &str) {
+        self.out.push_str("{\"$type\":");
+        self.string(name);
+        self.out.push_str(",\"value\":");
+    }
+
+    fn key(&mut self, key: u64, name: Option<&str>) {
+        match name {
+            Some(name) => self.field(name),
+            None => match field_name(key) {
+                Some(name) => self.field(&name),
+                None => self.string(&format!("#{}", key)),
+            },
+        }
+        self.out.push(':');
+    }
+
+    fn field(&mut self, name: &str) {
+        if name.starts_with('#') {
+            self.string(&format!("#{}", name))
+        } else {
+            self.string(name)
+        }
+    }
+
+    fn string(&mut self, s: &str) {
+        self.out.push('"');
+        for c in s.chars() {
+            match c {
+                '"' => self.out.push_str("\\\""),
+                '\\' => self.out.push_str("\\\\"),
+                '\n' => self.out.push_str("\\n"),
+                '\r' => self.out.push_str("\\r"),
+                '\t' => self.out.push_str("\\t"),
+                '\u{8}' => self.out.push_str("\\b"),
+                '\u{c}' => self.out.push_str("\\f"),
+                c if (c as u32) < 0x20 => write!(self.out, "\\u{:04x}", c as u32).unwrap(),
+                c => self.out.push(c),
+            }
+        }
+        self.out.push('"');
+    }
+}
+
+// Parsed JSON, numbers keep their text so they can be read into any width
+#[derive(Debug)]
+enum Json {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+// Positions in errors are byte offsets into the text
+struct Parser<'a> {
+    text: &'a str,
+    bytes: &'a [u8],
+    pos: usize,
+    depth: usize,
+    max_depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn parse(&mut self) -> Result<Json, String> {
+        let json = self.value()?;
+        self.whitespaces();
+        if self.pos < self.bytes.len() {
+            return Err(format!(
+                "Unexpected '{}' at {}",
+                self.char_at(self.pos),
+                self.pos
+            ));
+        }
+        Ok(json)
+    }
+
+    // Lead bytes are at character boundaries, the other bytes never get here
+    fn char_at(&self, pos: usize) -> char {
+        self.text[pos..].chars().next().unwrap_or(' ')
+    }
+
+    fn whitespaces(&mut self) {
+        while let Some(b' ') | Some(b'\t') | Some(b'\r') | Some(b'\n') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).cloned()
+    }
+
+    fn next(&mut self) -> Result<u8, String> {
+        let c = self
+            .peek()
+            .ok_or_else(|| "Unexpected end of JSON".to_string())?;
+        self.pos += 1;
+        Ok(c)
+    }
+
+    fn expect(&mut self, expected: u8) -> Result<(), String> {
+        self.whitespaces();
+        match self.next()? {
+            c if c == expected => Ok(()),
+            _ => Err(format!(
+                "Expect '{}' at {}, found '{}'",
+                expected as char,
+                self.pos - 1,
+                self.char_at(self.pos - 1)
+            )),
+        }
+    }
+
+    fn literal(&mut self, word: &str, json: Json) -> Result<Json, String> {
+        for expected in word.bytes() {
+            if self.next()? != expected {
+                return Err(format!(
+                    "Invalid literal at {}, expect {}",
+                    self.pos - 1,
+                    word
+                ));
+            }
+        }
+        Ok(json)
+    }
+
+    fn enter(&mut self) -> Result<(), String> {
+        self.pos += 1;
+        self.depth += 1;
+        if self.depth > self.max_depth {
+            return Err(format!(
+                "JSON nested deeper than {} levels at {}",
+                self.max_depth,
+                self.pos - 1
+            ));
+        }
+        Ok(())
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.whitespaces();
+        match self.peek() {
+            Some(b'n') => self.literal("null", Json::Null),
+            Some(b't') => self.literal("true", Json::Bool(true)),
+            Some(b'f') => self.literal("false", Json::Bool(false)),
+            Some(b'"') => Ok(Json::String(self.string()?)),
+            Some(b'[') => {
+                self.enter()?;
+                let items = self.array()?;
+                self.depth -= 1;
+                Ok(Json::Array(items))
+            }
+            Some(b'{') => {
+                self.enter()?;
+                let entries = self.object()?;
+                self.depth -= 1;
+                Ok(Json::Object(entries))
+            }
+            Some(c) if c == b'-' || c.is_ascii_digit() => self.number(),
+            Some(_) => Err(format!(
+                "Unexpected '{}' at {}",
+                self.char_at(self.pos),
+                self.pos
+            )),
+            None => Err("Unexpected end of JSON".to_string()),
+        }
+    }
+
+    fn array(&mut self) -> Result<Vec<Json>, String> {
+        let mut items = vec![];
+        self.whitespaces();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Ok(items);
+        }
+        loop {
+            items.push(self.value()?);
+            self.whitespaces();
+            match self.next()? {
+                b',' => continue,
+                b']' => return Ok(items),
+                _ => {
+                    return Err(format!(
+                        "Expect ',' or ']' at {}, found '{}'",
+                        self.pos - 1,
+                        self.char_at(self.pos - 1)
+                    ))
+                }
+            }
+        }
+    }
+
+    fn object(&mut self) -> Result<Vec<(String, Json)>, String> {
+        let mut entries = vec![];
+        self.whitespaces();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(entries);
+        }
+        loop {
+            self.whitespaces();
+            let key = self.string()?;
+            self.expect(b':')?;
+            entries.push((key, self.value()?));
+            self.whitespaces();
+            match self.next()? {
+                b',' => continue,
+                b'}' => return Ok(entries),
+                _ => {
+                    return Err(format!(
+                        "Expect ',' or '}}' at {}, found '{}'",
+                        self.pos - 1,
+                        self.char_at(self.pos - 1)
+                    ))
+                }
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Json, String> {
+        let start = self.pos;
+        while let Some(b'0'..=b'9' | b'-' | b'+' | b'.' | b'e' | b'E') = self.peek() {
+            self.pos += 1;
+        }
+        let text = &self.text[start..self.pos];
+        if text.parse::<f64>().is_err() {
+            return Err(format!("Invalid number '{}' at {}", text, start));
+        }
+        Ok(Json::Number(text.to_string()))
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let c = self.next()?;
+            let digit = (c as char).to_digit(16).ok_or_else(|| {
+                format!(
+                    "Invalid unicode escape '{}' at {}",
+                    self.char_at(self.pos - 1),
+                    self.pos - 1
+                )
+            })?;
+            code = code * 16 + digit;
+        }
+        Ok(code)
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        if self.next()? != b'"' {
+            return Err(format!("Expect string at {}", self.pos - 1));
+        }
+        let mut res = String::new();
+        loop {
+            // Runs without escapes are copied as they are, they end at ASCII characters so
+            // they are whole characters
+            let start = self.pos;
+            while let Some(c) = self.peek() {
+                if c == b'"' || c == b'\\' {
+                    break;
+                }
+                self.pos += 1;
+            }
+            res.push_str(&self.text[start..self.pos]);
+            match self.next()? {
+                b'"' => return Ok(res),
+                _ => match self.next()? {
+                    b'"' => res.push('"'),
+                    b'\\' => res.push('\\'),
+                    b'/' => res.push('/'),
+                    b'b' => res.push('\u{8}'),
+                    b'f' => res.push('\u{c}'),
+                    b'n' => res.push('\n'),
+                    b'r' => res.push('\r'),
+                    b't' => res.push('\t'),
+                    b'u' => {
+                        let mut code = self.hex4()?;
+                        // Characters out of the basic plane are escaped as surrogate pairs
+                        if (0xd800..0xdc00).contains(&code) {
+                            if self.next()? != b'\\' || self.next()? != b'u' {
+                                return Err(format!("Unpaired surrogate at {}", self.pos));
+                            }
+                            let low = self.hex4()?;
+                            if !(0xdc00..0xe000).contains(&low) {
+                                return Err(format!(
+                                    "Invalid low surrogate {:x} at {}",
+                                    low,
+                                    self.pos - 4
+                                ));
+                            }
+                            code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+                        }
+                        res.push(
+                            std::char::from_u32(code)
+                                .ok_or_else(|| format!("Invalid unicode escape {:x}", code))?,
+                        );
+                    }
+                    _ => {
+                        return Err(format!(
+                            "Unknown escape character '{}' at {}",
+                            self.char_at(self.pos - 1),
+                            self.pos - 1
+                        ))
+                    }
+                },
+            }
+        }
+    }
+}
+
+fn to_value(json: &Json) -> Result<OwnedValue, String> {
+    match json {
+        Json::Null => Ok(OwnedValue::Null),
+        Json::Bool(b) => Ok(OwnedValue::Bool(*b)),
+        Json::Number(text) => {
+            if text.contains(['.', 'e', 'E']) {
+                return Ok(OwnedValue::F64(text.parse().unwrap()));
+            }
+            match text.parse::<i64>() {
+                Ok(n) => Ok(OwnedValue::I64(n)),
+                Err(_) => Ok(OwnedValue::F64(text.parse().unwrap())),
+            }
+        }
+        Json::String(s) => Ok(OwnedValue::String(s.clone())),
+        Json::Array(items) => Ok(OwnedValue::Array(
+            items.iter().map(to_value).collect::<Result<_, _>>()?,
+        )),
+        Json::Object(entries) => match entries.iter().find(|(k, _)| k == TYPE_KEY) {
+            Some((_, Json::String(name))) => typed_value(name, entries),
+            Some((_, other)) => Err(format!(
+                "Type annotation should be a string, found {:?}",
+                other
+            )),
+            None => Ok(OwnedValue::Map(to_map(entries)?)),
+        },
+    }
+}
+
+fn to_map(entries: &[(String, Json)]) -> Result<OwnedMap, String> {
+    let mut map = OwnedMap::new();
+    for (key, value) in entries {
+        let value = to_value(value)?;
+        match key.strip_prefix('#') {
+            Some(escaped) if escaped.starts_with('#') => {
+                map.try_insert(escaped, value)?;
+            }
+            Some(id) if !id.is_empty() && id.bytes().all(|b| b.is_ascii_digit()) => {
+                let id = id
+                    .parse::<u64>()
+                    .map_err(|_| format!("Key id {} out of range", id))?;
+                map.insert_key_id(id, value);
+            }
+            _ => {
+                map.try_insert(key, value)?;
+            }
+        }
+    }
+    Ok(map)
+}
+
+fn entry<'a>(entries: &'a [(String, Json)], key: &str, name: &str) -> Result<&'a Json, String> {
+    entries
+        .iter()
+        .find(|(k, _)| k == key)
+        .map(|(_, v)| v)
+        .ok_or_else(|| format!("Value of type {} has no '{}'", name, key))
+}
+
+fn typed_value(name: &str, entries: &[(String, Json)]) -> Result<OwnedValue, String> {
+    if name == "na" {
+        return Ok(OwnedValue::NA);
+    }
+    let value = entry(entries, VALUE_KEY, name)?;
+    match name {
+        "map" => match value {
+            Json::Object(entries) => Ok(OwnedValue::Map(to_map(entries)?)),
+            _ => Err(format!("Expect object for map, found {:?}", value)),
+        },
+        "pmap" => match to_value(value)? {
+            OwnedValue::Map(map) => Ok(OwnedValue::PMap(PersistentMap::from(map))),
+            other => Err(format!("Expect map for pmap, found {:?}", other)),
+        },
+        "pvec" => match to_value(value)? {
+            OwnedValue::Array(array) => Ok(OwnedValue::PVec(PersistentVec::from(array))),
+            other => Err(format!("Expect array for pvec, found {:?}", other)),
+        },
+        "tensor" => {
+            let shape = match entry(entries, "shape", name)? {
+                Json::Array(dims) => dims
+                    .iter()
+                    .map(|d| integer(d).and_then(|n| usize::try_from(n).map_err(|e| e.to_string())))
+                    .collect::<Result<Vec<_>, _>>()?,
+                other => return Err(format!("Expect array for tensor shape, found {:?}", other)),
+            };
+            match to_value(value)? {
+                OwnedValue::PrimArray(data) => {
+                    Ok(OwnedValue::Tensor(OwnedTensor::new(data, shape)?))
+                }
+                other => Err(format!(
+                    "Expect primitive array for tensor, found {:?}",
+                    other
+                )),
+            }
+        }
+        _ => match name.strip_suffix("[]") {
+            Some(element) => {
+                let t = type_of(element)?;
+                let items = match value {
+                    Json::Array(items) => items,
+                    _ => return Err(format!("Expect array for {}, found {:?}", name, value)),
+                };
+                let values = items
+                    .iter()
+                    .map(|item| scalar(t, item))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(OwnedValue::PrimArray(OwnedPrimArray::from_typed_values(
+                    t, values,
+                )?))
+            }
+            None => scalar(type_of(name)?, value),
+        },
+    }
+}
+
+fn type_of(name: &str) -> Result<Type, String> {
+    match Type::from_id(get_type_id(name.to_string())) {
+        Type::NA | Type::Null | Type::Map => Err(format!("Unknown type '{}'", name)),
+        t => Ok(t),
+    }
+}
+
+fn integer(json: &Json) -> Result<i128, String> {
+    match json {
+        Json::Number(text) => text
+            .parse::<i128>()
+            .map_err(|_| format!("Expect integer, found {}", text)),
+        _ => Err(format!("Expect integer, found {:?}", json)),
+    }
+}
+
+fn float(json: &Json) -> Result<f64, String> {
+    match json {
+        Json::Number(text) => Ok(text.parse().unwrap()),
+        // Non-finite numbers are kept as strings
+        Json::String(text) => text
+            .parse()
+            .map_err(|_| format!("Expect number, found '{}'", text)),
+        _ => Err(format!("Expect number, found {:?}", json)),
+    }
+}
+
+fn floats(json: &Json, len: usize) -> Result<Vec<f64>, String> {
+    match json {
+        Json::Array(items) if items.len() == len => items.iter().map(float).collect(),
+        _ => Err(format!("Expect {} numbers, found {:?}", len, json)),
+    }
+}
+
+fn text(json: &Json) -> Result<&str, String> {
+    match json {
+        Json::String(s) => Ok(s),
+        _ => Err(format!("Expect string, found {:?}", json)),
+    }
+}
+
+macro_rules! int_value {
+    ($e: ident, $t: ty, $json: expr) => {{
+        let n = integer($json)?;
+        <$t>::try_from(n)
+            .map(OwnedValue::$e)
+            .map_err(|_| format!("{} is out of range for {}", n, stringify!($t)))
+    }};
+}
+
+// Coerces JSON into a value of the given type, integers are checked against the width
+fn scalar(t: Type, json: &Json) -> Result<OwnedValue, String> {
+    match t {
+        Type::Bool => match json {
+            Json::Bool(b) => Ok(OwnedValue::Bool(*b)),
+            _ => Err(format!("Expect boolean, found {:?}", json)),
+        },
+        Type::Char => {
+            let s = text(json)?;
+            let mut chars = s.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Ok(OwnedValue::Char(c)),
+                _ => Err(format!("Expect one character, found '{}'", s)),
+            }
+        }
+        Type::I8 => int_value!(I8, i8, json),
+        Type::I16 => int_value!(I16, i16, json),
+        Type::I32 => int_value!(I32, i32, json),
+        Type::I64 => int_value!(I64, i64, json),
+        Type::U8 => int_value!(U8, u8, json),
+        Type::U16 => int_value!(U16, u16, json),
+        Type::U32 => int_value!(U32, u32, json),
+        Type::U64 => int_value!(U64, u64, json),
+        Type::F32 => float(json).map(|n| OwnedValue::F32(n as f32)),
+        Type::F64 => float(json).map(OwnedValue::F64),
+        Type::Pos2d32 => floats(json, 2).map(|p| {
+            OwnedValue::Pos2d32(Pos2d32 {
+                x: p[0] as f32,
+                y: p[1] as f32,
+            })
+        }),
+        Type::Pos2d64 => floats(json, 2).map(|p| OwnedValue::Pos2d64(Pos2d64 { x: p[0], y: p[1] })),
+        Type::Pos3d32 => floats(json, 3).map(|p| {
+            OwnedValue::Pos3d32(Pos3d32 {
+                x: p[0] as f32,
+                y: p[1] as f32,
+                z: p[2] as f32,
+            })
+        }),
+        Type::Pos3d64 => floats(json, 3).map(|p| {
+            OwnedValue::Pos3d64(Pos3d64 {
+                x: p[0],
+                y: p[1],
+                z: p[2],
+            })
+        }),
+        Type::Id => text(json)?.parse::<Id>().map(OwnedValue::Id),
+        Type::String => text(json).map(|s| OwnedValue::String(s.to_string())),
+        Type::Bytes => {
+            base64_decode(text(json)?).map(|data| OwnedValue::Bytes(Bytes::from_vec(data)))
+        }
+        Type::SmallBytes => base64_decode(text(json)?)
+            .map(|data| OwnedValue::SmallBytes(SmallBytes::from_vec(data))),
+        _ => Err(format!("Type {:?} has no JSON form", t)),
+    }
+}
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64_encode(data: &[u8]) -> String {
+    let mut res = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bits =
+            chunk.iter().fold(0u32, |acc, b| acc << 8 | *b as u32) << (8 * (3 - chunk.len()));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                res.push(BASE64_ALPHABET[(bits >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                res.push('=');
+            }
+        }
+    }
+    res
+}
+
+// Only the padded form is read, with the unused bits of the last character zero
+fn base64_decode(text: &str) -> Result<Vec<u8>, String> {
+    if text.len() % 4 != 0 {
+        return Err(format!(
+            "Base64 text of {} characters is not padded to a multiple of 4",
+            text.len()
+        ));
+    }
+    let padded = text;
+    let text = text.trim_end_matches('=');
+    if padded.len() - text.len() > 2 {
+        return Err(format!("Base64 text '{}' has too much padding", padded));
+    }
+    let mut res = Vec::with_capacity(text.len() * 3 / 4);
+    let mut bits = 0u32;
+    let mut len = 0;
+    for c in text.chars() {
+        let digit = BASE64_ALPHABET
+            .iter()
+            .position(|d| *d as char == c)
+            .ok_or_else(|| format!("Invalid base64 character '{}'", c))?;
+        bits = bits << 6 | digit as u32;
+        len += 6;
+        if len >= 8 {
+            len -= 8;
+            res.push((bits >> len) as u8);
+        }
+    }
+    if bits & ((1 << len) - 1) != 0 {
+        return Err(format!("Base64 text '{}' has trailing bits", padded));
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut inner = OwnedMap::new();
+        inner.insert_value("$type", "not a tag".to_string());
+        let mut map = OwnedMap::new();
+        map.insert_value("name", "Dova\"kiin\n".to_string());
+        map.insert_value("age", 30u8);
+        map.insert_value("score", 1f64);
+        map.insert_value("id", Id::new(1, 2));
+        map.insert_value("pos", Pos2d32 { x: 1.5, y: -2.0 });
+        map.insert_value("blob", Bytes::from_vec(vec![0u8, 1, 2, 250, 251]));
+        map.insert_value("ratio", std::f32::NAN);
+        map.insert_value("weights", vec![1u16, 2, 3]);
+        map.insert("empty", OwnedValue::PrimArray(OwnedPrimArray::U32(vec![])));
+        map.insert("inner", OwnedValue::Map(inner));
+        map.insert(
+            "items",
+            OwnedValue::Array(vec![OwnedValue::I64(-1), OwnedValue::Null]),
+        );
+        map.insert_key_id(42, OwnedValue::Char('x'));
+        map.insert_value("#7", 7u8);
+        map.insert_value("##", 8u8);
+        let value = OwnedValue::Map(map);
+
+        let typed = from_json(&to_typed_json(&value).unwrap()).unwrap();
+        // NaN is not equal to itself
+        assert!(typed.Map().unwrap().get("ratio").f32().unwrap().is_nan());
+        let mut expected = value.clone();
+        let mut restored = typed.clone();
+        for v in [&mut expected, &mut restored] {
+            if let OwnedValue::Map(map) = v {
+                map.insert_value("ratio", 0f32);
+            }
+        }
+        assert_eq!(restored, expected);
+
+        // Without hints a `$type` field reads as an annotation
+        assert!(from_json(&to_json(&value).unwrap()).is_err());
+        let mut value = value;
+        if let OwnedValue::Map(map) = &mut value {
+            map.remove("inner");
+        }
+        let plain = from_json(&to_json(&value).unwrap()).unwrap();
+        let plain = plain.Map().unwrap();
+        assert_eq!(plain.get("age"), &OwnedValue::I64(30));
+        assert_eq!(plain.get("score"), &OwnedValue::F64(1.0));
+        assert_eq!(
+            plain.get("blob"),
+            &OwnedValue::String("AAEC+vs=".to_string())
+        );
+        assert_eq!(plain.get("ratio"), &OwnedValue::Null);
+    }
+
+    #[test]
+    fn reading() {
+        let value = from_json(r#" { "a" : [1, 2.5e1, "é😀", true, null], "b": {} } "#).unwrap();
+        let map = value.Map().unwrap();
+        assert_eq!(
+            map.get("a"),
+            &OwnedValue::Array(vec![
+                OwnedValue::I64(1),
+                OwnedValue::F64(25.0),
+                OwnedValue::String("é😀".to_string()),
+                OwnedValue::Bool(true),
+                OwnedValue::Null
+            ])
+        );
+        assert_eq!(map.get("b"), &OwnedValue::Map(OwnedMap::new()));
+        assert!(from_json(r#"{"$type": "u8", "value": 256}"#).is_err());
+        assert!(from_json(r#"{"$type": "nope", "value": 1}"#).is_err());
+        assert!(from_json("[1, 2").is_err());
+        assert!(from_json("[1] 2").is_err());
+        assert!(from_json("\"é\\é\"").is_err());
+        assert_eq!(
+            from_json(r#""\ud83d\ude00""#).unwrap(),
+            OwnedValue::String("😀".to_string())
+        );
+        assert!(from_json(r#""\ud83d\u0041""#).is_err());
+        assert!(from_json(r#""\ud83d\ue000""#).is_err());
+
+        let keys = from_json(r###"{"#12": 1, "##12": 2, "#a": 3}"###).unwrap();
+        let keys = keys.Map().unwrap();
+        assert_eq!(keys.get_by_key_id(12), &OwnedValue::I64(1));
+        assert_eq!(keys.get("#12"), &OwnedValue::I64(2));
+        assert_eq!(keys.get("#a"), &OwnedValue::I64(3));
+        assert!(from_json(r##"{"#99999999999999999999": 1}"##).is_err());
+
+        let bytes =
+            |text: &str| from_json(&format!(r#"{{"$type": "bytes", "value": "{}"}}"#, text));
+        assert_eq!(
+            bytes("AAEC+vs=").unwrap(),
+            OwnedValue::Bytes(Bytes::from_vec(vec![0u8, 1, 2, 250, 251]))
+        );
+        assert!(bytes("AAEC+vs").is_err());
+        assert!(bytes("A===").is_err());
+        assert!(bytes("AAE=AAE=").is_err());
+        assert!(bytes("AAF=").is_err());
+
+        let nested = format!("{}{}", "[".repeat(3), "]".repeat(3));
+        assert!(from_json_with_max_depth(&nested, 3).is_ok());
+        assert!(from_json_with_max_depth(&nested, 2).is_err());
+        let deep = "[".repeat(1 << 20);
+        assert!(from_json(&deep)
+            .unwrap_err()
+            .starts_with("JSON nested deeper than"));
+    }
+}
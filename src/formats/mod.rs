@@ -0,0 +1,11 @@
+mod binary;
+pub mod cbor;
+pub mod csv;
+pub mod json;
+pub mod msgpack;
+
+pub use binary::TAG_BASE;
+
+// Nesting of arrays, maps and tagged items readers accept unless told otherwise, every level
+// takes stack to read
+pub const DEFAULT_MAX_DEPTH: usize = 512;
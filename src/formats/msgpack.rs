@@ -0,0 +1,429 @@
+use super::binary::{self, Item, ItemReader, ItemWriter, TAG_BASE};
+use super::DEFAULT_MAX_DEPTH;
+use crate::expr::serde::Expr;
+use crate::types::OwnedValue;
+use std::io::{Cursor, Read, Write};
+
+// MessagePack for values and expressions. It has no tags, tagged items are written as
+// extensions numbered by the offset of the tag. Tagged scalars are extensions carrying the
+// MessagePack encoding of the scalar, which is buffered on both sides. Primitive arrays,
+// tensors, persistent collections and expressions are tagged by an empty extension followed
+// by the item itself, so they are written and read element by element. Readers of other
+// conventions see such an empty extension as an item of its own.
+
+pub struct MsgPackEncoder<W: Write> {
+    writer: W,
+    // Payloads of the extensions being written, innermost last
+    nested: Vec<Vec<u8>>,
+}
+
+pub struct MsgPackDecoder<R: Read> {
+    reader: R,
+    // Payloads of the extensions being read, innermost last
+    nested: Vec<Cursor<Vec<u8>>>,
+    max_depth: usize,
+}
+
+impl<W: Write> MsgPackEncoder<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            nested: vec![],
+        }
+    }
+
+    pub fn write_value(&mut self, value: &OwnedValue) -> Result<(), String> {
+        binary::write_value(value, self)
+    }
+
+    pub fn write_expr(&mut self, expr: &Expr) -> Result<(), String> {
+        binary::write_expr(expr, self)
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn put(&mut self, data: &[u8]) -> Result<(), String> {
+        match self.nested.last_mut() {
+            Some(payload) => {
+                payload.extend_from_slice(data);
+                Ok(())
+            }
+            None => self.writer.write_all(data).map_err(|e| e.to_string()),
+        }
+    }
+
+    // Lengths for the forms with 8, 16 and 32 bit length, the fixed form is taken by the caller
+    fn len(&mut self, codes: [u8; 3], len: usize) -> Result<(), String> {
+        if len <= u8::MAX as usize && codes[0] != 0 {
+            self.put(&[codes[0], len as u8])
+        } else if len <= u16::MAX as usize {
+            self.put(&[codes[1]])?;
+            self.put(&(len as u16).to_be_bytes())
+        } else if len <= u32::MAX as usize {
+            self.put(&[codes[2]])?;
+            self.put(&(len as u32).to_be_bytes())
+        } else {
+            Err(format!("Length {} is too large for MessagePack", len))
+        }
+    }
+}
+
+impl<W: Write> ItemWriter for MsgPackEncoder<W> {
+    fn null(&mut self) -> Result<(), String> {
+        self.put(&[0xc0])
+    }
+
+    fn bool(&mut self, b: bool) -> Result<(), String> {
+        self.put(&[if b { 0xc3 } else { 0xc2 }])
+    }
+
+    fn int(&mut self, n: i128) -> Result<(), String> {
+        if (0..128).contains(&n) || (-32..0).contains(&n) {
+            self.put(&[n as i8 as u8])
+        } else if n >= 0 {
+            if n <= u8::MAX as i128 {
+                self.put(&[0xcc, n as u8])
+            } else if n <= u16::MAX as i128 {
+                self.put(&[0xcd])?;
+                self.put(&(n as u16).to_be_bytes())
+            } else if n <= u32::MAX as i128 {
+                self.put(&[0xce])?;
+                self.put(&(n as u32).to_be_bytes())
+            } else if n <= u64::MAX as i128 {
+                self.put(&[0xcf])?;
+                self.put(&(n as u64).to_be_bytes())
+            } else {
+                Err(format!("Integer {} is too large for MessagePack", n))
+            }
+        } else if n >= i8::MIN as i128 {
+            self.put(&[0xd0, n as i8 as u8])
+        } else if n >= i16::MIN as i128 {
+            self.put(&[0xd1])?;
+            self.put(&(n as i16).to_be_bytes())
+        } else if n >= i32::MIN as i128 {
+            self.put(&[0xd2])?;
+            self.put(&(n as i32).to_be_bytes())
+        } else if n >= i64::MIN as i128 {
+            self.put(&[0xd3])?;
+            self.put(&(n as i64).to_be_bytes())
+        } else {
+            Err(format!("Integer {} is too small for MessagePack", n))
+        }
+    }
+
+    fn f32(&mut self, n: f32) -> Result<(), String> {
+        self.put(&[0xca])?;
+        self.put(&n.to_be_bytes())
+    }
+
+    fn f64(&mut self, n: f64) -> Result<(), String> {
+        self.put(&[0xcb])?;
+        self.put(&n.to_be_bytes())
+    }
+
+    fn text(&mut self, s: &str) -> Result<(), String> {
+        if s.len() < 32 {
+            self.put(&[0xa0 | s.len() as u8])?;
+        } else {
+            self.len([0xd9, 0xda, 0xdb], s.len())?;
+        }
+        self.put(s.as_bytes())
+    }
+
+    fn bytes(&mut self, b: &[u8]) -> Result<(), String> {
+        self.len([0xc4, 0xc5, 0xc6], b.len())?;
+        self.put(b)
+    }
+
+    fn array(&mut self, len: usize) -> Result<(), String> {
+        if len < 16 {
+            return self.put(&[0x90 | len as u8]);
+        }
+        self.len([0, 0xdc, 0xdd], len)
+    }
+
+    fn map(&mut self, len: usize) -> Result<(), String> {
+        if len < 16 {
+            return self.put(&[0x80 | len as u8]);
+        }
+        self.len([0, 0xde, 0xdf], len)
+    }
+
+    fn tagged<F>(&mut self, tag: u64, content: F) -> Result<(), String>
+    where
+        F: FnOnce(&mut Self) -> Result<(), String>,
+    {
+        let code = match tag.checked_sub(TAG_BASE) {
+            Some(code) if code < 128 => code as u8,
+            _ => return Err(format!("Tag {:#x} has no MessagePack extension", tag)),
+        };
+        if !binary::is_scalar_tag(tag) {
+            self.put(&[0xc7, 0, code])?;
+            return content(self);
+        }
+        self.nested.push(vec![]);
+        let res = content(self);
+        let payload = self.nested.pop().unwrap();
+        res?;
+        match payload.len() {
+            1 => self.put(&[0xd4, code])?,
+            2 => self.put(&[0xd5, code])?,
+            4 => self.put(&[0xd6, code])?,
+            8 => self.put(&[0xd7, code])?,
+            16 => self.put(&[0xd8, code])?,
+            len => {
+                self.len([0xc7, 0xc8, 0xc9], len)?;
+                self.put(&[code])?;
+            }
+        }
+        self.put(&payload)
+    }
+}
+
+impl<R: Read> MsgPackDecoder<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            nested: vec![],
+            max_depth: DEFAULT_MAX_DEPTH,
+        }
+    }
+
+    // Arrays, maps and extensions nested deeper than `max_depth` are errors
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    // Next value in the stream, None at its end
+    pub fn read_value(&mut self) -> Result<Option<OwnedValue>, String> {
+        binary::read_value(self)
+    }
+
+    pub fn read_expr(&mut self) -> Result<Option<Expr>, String> {
+        binary::read_expr(self)
+    }
+
+    fn source(&mut self) -> &mut dyn Read {
+        while let Some(payload) = self.nested.last() {
+            if (payload.position() as usize) < payload.get_ref().len() {
+                break;
+            }
+            self.nested.pop();
+        }
+        match self.nested.last_mut() {
+            Some(payload) => payload,
+            None => &mut self.reader,
+        }
+    }
+
+    fn take(&mut self, len: u64) -> Result<Vec<u8>, String> {
+        let mut data = vec![];
+        self.source()
+            .take(len)
+            .read_to_end(&mut data)
+            .map_err(|e| e.to_string())?;
+        if (data.len() as u64) < len {
+            return Err("Unexpected end of input".to_string());
+        }
+        Ok(data)
+    }
+
+    fn uint(&mut self, size: u64) -> Result<u64, String> {
+        Ok(self
+            .take(size)?
+            .iter()
+            .fold(0u64, |acc, b| acc << 8 | *b as u64))
+    }
+
+    fn int(&mut self, size: u64) -> Result<i128, String> {
+        let n = self.uint(size)?;
+        let shift = 64 - size * 8;
+        Ok(((n << shift) as i64 >> shift) as i128)
+    }
+
+    fn text(&mut self, len: u64) -> Result<Item, String> {
+        String::from_utf8(self.take(len)?)
+            .map(Item::Text)
+            .map_err(|e| e.to_string())
+    }
+
+    fn ext(&mut self, len: u64) -> Result<Item, String> {
+        let code = self.take(1)?[0] as i8;
+        if code < 0 {
+            return Err(format!("Unsupported extension type {}", code));
+        }
+        // Empty extensions tag the item after them
+        if len > 0 {
+            let payload = self.take(len)?;
+            self.nested.push(Cursor::new(payload));
+        }
+        Ok(Item::Tag(TAG_BASE + code as u64))
+    }
+}
+
+impl<R: Read> ItemReader for MsgPackDecoder<R> {
+    fn max_depth(&self) -> usize {
+        self.max_depth
+    }
+
+    fn next_item(&mut self) -> Result<Option<Item>, String> {
+        let mut initial = [0u8];
+        if self
+            .source()
+            .read(&mut initial)
+            .map_err(|e| e.to_string())?
+            == 0
+        {
+            return Ok(None);
+        }
+        let item = match initial[0] {
+            b @ 0x00..=0x7f => Item::Int(b as i128),
+            b @ 0x80..=0x8f => Item::Map(Some((b & 0x0f) as usize)),
+            b @ 0x90..=0x9f => Item::Array(Some((b & 0x0f) as usize)),
+            b @ 0xa0..=0xbf => self.text((b & 0x1f) as u64)?,
+            0xc0 => Item::Null,
+            0xc2 => Item::Bool(false),
+            0xc3 => Item::Bool(true),
+            0xc4 => {
+                let len = self.uint(1)?;
+                Item::Bytes(self.take(len)?)
+            }
+            0xc5 => {
+                let len = self.uint(2)?;
+                Item::Bytes(self.take(len)?)
+            }
+            0xc6 => {
+                let len = self.uint(4)?;
+                Item::Bytes(self.take(len)?)
+            }
+            0xc7 => {
+                let len = self.uint(1)?;
+                self.ext(len)?
+            }
+            0xc8 => {
+                let len = self.uint(2)?;
+                self.ext(len)?
+            }
+            0xc9 => {
+                let len = self.uint(4)?;
+                self.ext(len)?
+            }
+            0xca => {
+                let data = self.take(4)?;
+                Item::F32(f32::from_be_bytes([data[0], data[1], data[2], data[3]]))
+            }
+            0xcb => {
+                let mut bytes = [0u8; 8];
+                bytes.copy_from_slice(&self.take(8)?);
+                Item::F64(f64::from_be_bytes(bytes))
+            }
+            0xcc => Item::Int(self.uint(1)? as i128),
+            0xcd => Item::Int(self.uint(2)? as i128),
+            0xce => Item::Int(self.uint(4)? as i128),
+            0xcf => Item::Int(self.uint(8)? as i128),
+            0xd0 => Item::Int(self.int(1)?),
+            0xd1 => Item::Int(self.int(2)?),
+            0xd2 => Item::Int(self.int(4)?),
+            0xd3 => Item::Int(self.int(8)?),
+            0xd4 => self.ext(1)?,
+            0xd5 => self.ext(2)?,
+            0xd6 => self.ext(4)?,
+            0xd7 => self.ext(8)?,
+            0xd8 => self.ext(16)?,
+            0xd9 => {
+                let len = self.uint(1)?;
+                self.text(len)?
+            }
+            0xda => {
+                let len = self.uint(2)?;
+                self.text(len)?
+            }
+            0xdb => {
+                let len = self.uint(4)?;
+                self.text(len)?
+            }
+            0xdc => Item::Array(Some(self.uint(2)? as usize)),
+            0xdd => Item::Array(Some(self.uint(4)? as usize)),
+            0xde => Item::Map(Some(self.uint(2)? as usize)),
+            0xdf => Item::Map(Some(self.uint(4)? as usize)),
+            b @ 0xe0..=0xff => Item::Int(b as i8 as i128),
+            b => return Err(format!("Invalid MessagePack byte {:#x}", b)),
+        };
+        Ok(Some(item))
+    }
+}
+
+pub fn to_msgpack(value: &OwnedValue) -> Result<Vec<u8>, String> {
+    let mut encoder = MsgPackEncoder::new(vec![]);
+    encoder.write_value(value)?;
+    Ok(encoder.into_inner())
+}
+
+pub fn from_msgpack(data: &[u8]) -> Result<OwnedValue, String> {
+    let mut decoder = MsgPackDecoder::new(data);
+    let value = decoder
+        .read_value()?
+        .ok_or_else(|| "Empty input".to_string())?;
+    if !decoder.reader.is_empty() {
+        return Err(format!(
+            "{} bytes left after the value",
+            decoder.reader.len()
+        ));
+    }
+    Ok(value)
+}
+
+pub fn expr_to_msgpack(expr: &Expr) -> Result<Vec<u8>, String> {
+    let mut encoder = MsgPackEncoder::new(vec![]);
+    encoder.write_expr(expr)?;
+    Ok(encoder.into_inner())
+}
+
+pub fn expr_from_msgpack(data: &[u8]) -> Result<Expr, String> {
+    let mut decoder = MsgPackDecoder::new(data);
+    let expr = decoder
+        .read_expr()?
+        .ok_or_else(|| "Empty input".to_string())?;
+    if !decoder.reader.is_empty() {
+        return Err(format!(
+            "{} bytes left after the expression",
+            decoder.reader.len()
+        ));
+    }
+    Ok(expr)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::types::*;
+
+    #[test]
+    fn encoding() {
+        assert_eq!(to_msgpack(&OwnedValue::I64(-33)).unwrap(), vec![0xd0, 0xdf]);
+        assert_eq!(
+            to_msgpack(&OwnedValue::I64(300)).unwrap(),
+            vec![0xcd, 0x01, 0x2c]
+        );
+        // u8 1 as an extension holding a positive fixint
+        assert_eq!(
+            to_msgpack(&OwnedValue::U8(1)).unwrap(),
+            vec![0xd4, Type::U8.id(), 0x01]
+        );
+        let data = [0x82, 0xa1, b'a', 0xc3, 0x01, 0xc0];
+        let value = from_msgpack(&data).unwrap();
+        let map = value.Map().unwrap();
+        assert_eq!(map.get("a"), &OwnedValue::Bool(true));
+        assert_eq!(map.get_by_key_id(1), &OwnedValue::Null);
+        assert!(from_msgpack(&[0xc1]).is_err());
+
+        // Primitive arrays follow an empty extension instead of being its payload
+        let array = OwnedValue::PrimArray(OwnedPrimArray::U8(vec![1, 2]));
+        let data = to_msgpack(&array).unwrap();
+        assert_eq!(data, vec![0xc7, 0, 32 + Type::U8.id(), 0x92, 0x01, 0x02]);
+        assert_eq!(from_msgpack(&data).unwrap(), array);
+    }
+}
@@ -1,3 +1,4 @@
+use crate::error::DovahkiinError;
 use crate::expr::interpreter::Interpreter;
 use crate::expr::SExpr;
 use crate::lexer::lisp as lisp_lexer;
@@ -5,12 +6,12 @@ use crate::parser::lisp as lisp_parser;
 
 use crate::expr::serde::Expr;
 
-pub fn parse_to_sexpr<'a, 'b>(code: &'b str) -> Result<Vec<SExpr<'a>>, String> {
+pub fn parse_to_sexpr<'a, 'b>(code: &'b str) -> Result<Vec<SExpr<'a>>, DovahkiinError> {
     let tokens = lisp_lexer::tokenize_str(code)?;
     lisp_parser::SExprParser::parse_to_expr(tokens)
 }
 
-pub fn parse_to_serde_expr<'b>(code: &'b str) -> Result<Vec<Expr>, String> {
+pub fn parse_to_serde_expr<'b>(code: &'b str) -> Result<Vec<Expr>, DovahkiinError> {
     let tokens = lisp_lexer::tokenize_str(code)?;
     lisp_parser::SerdeExprParser::parse_to_expr(tokens)
 }
@@ -19,16 +20,22 @@ pub fn get_interpreter<'a>() -> Interpreter<'a> {
     Interpreter::new()
 }
 
+pub fn get_lexical_interpreter<'a>() -> Interpreter<'a> {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_lexical_scoping(true);
+    interpreter
+}
+
 pub fn eval<'a>(
     interpreter: &mut Interpreter<'a>,
     exprs: Vec<SExpr<'a>>,
-) -> Result<SExpr<'a>, String> {
+) -> Result<SExpr<'a>, DovahkiinError> {
     interpreter.eval(exprs)
 }
 
 pub fn eval_string<'a, 'b>(
     interpreter: &mut Interpreter<'a>,
     code: &'b str,
-) -> Result<SExpr<'a>, String> {
+) -> Result<SExpr<'a>, DovahkiinError> {
     eval(interpreter, parse_to_sexpr(code)?)
 }
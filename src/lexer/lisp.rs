@@ -1,4 +1,6 @@
 use std::collections::HashSet;
+use crate::error::DovahkiinError;
+use crate::lexer::Span;
 
 #[derive(Debug)]
 pub enum Token {
@@ -10,7 +12,8 @@ pub enum Token {
     String(String),
     LeftVecParentheses,
     RightVecParentheses,
-    Keyword(String)
+    Keyword(String),
+    Id(String)
     // Quote
 }
 
@@ -25,7 +28,8 @@ impl ToString for Token {
             &Token::String(ref s) => format!("\"{}\"", s),
             &Token::LeftVecParentheses => String::from("["),
             &Token::RightVecParentheses => String::from("]"),
-            &Token::Keyword(ref s) => format!(":{}", s)
+            &Token::Keyword(ref s) => format!(":{}", s),
+            &Token::Id(ref s) => format!("#id\"{}\"", s)
         }
     }
 }
@@ -33,6 +37,10 @@ impl ToString for Token {
 pub struct CharIter {
     chars: Vec<char>,
     current_pos: usize,
+    // Of the current char
+    byte: usize,
+    line: usize,
+    column: usize,
 }
 
 impl CharIter {
@@ -40,19 +48,55 @@ impl CharIter {
         CharIter {
             chars: data,
             current_pos: 0,
+            byte: 0,
+            line: 1,
+            column: 1,
         }
     }
     pub fn next(&mut self) -> Option<char> {
+        if let Some(&c) = self.chars.get(self.current_pos) {
+            self.byte += c.len_utf8();
+            if c == '\n' {
+                self.line += 1;
+                self.column = 1;
+            } else {
+                self.column += 1;
+            }
+        }
         self.current_pos += 1;
         self.chars.get(self.current_pos).cloned()
     }
     pub fn peek_next(&self) -> Option<char> {
         self.chars.get(self.current_pos + 1).cloned()
     }
+    // Whether the chars after the current one start with `text`
+    pub fn next_starts_with(&self, text: &str) -> bool {
+        let mut chars = self.chars.iter().skip(self.current_pos + 1);
+        text.chars().all(|c| chars.next() == Some(&c))
+    }
 
     pub fn current(&mut self) -> Option<char> {
         self.chars.get(self.current_pos).cloned()
     }
+
+    // Empty span at the current char
+    pub fn span(&self) -> Span {
+        Span {
+            start: self.byte,
+            end: self.byte,
+            line: self.line,
+            column: self.column,
+        }
+    }
+
+    pub fn error<S: Into<String>>(&self, message: S) -> DovahkiinError {
+        let mut span = self.span();
+        span.end += self.chars.get(self.current_pos).map_or(0, |c| c.len_utf8());
+        DovahkiinError::Lex {
+            message: message.into(),
+            span,
+        }
+    }
 }
 
 lazy_static! {
@@ -87,7 +131,7 @@ fn readout_whitespaces(iter: &mut CharIter) {
     }
 }
 
-fn read_number(first: char, iter: &mut CharIter) -> Result<Token, String> {
+fn read_number(first: char, iter: &mut CharIter) -> Result<Token, DovahkiinError> {
     let mut digit_chars = vec![first];
     let mut unit_chars = Vec::new();
     let mut is_float_number = false;
@@ -126,7 +170,7 @@ fn read_number(first: char, iter: &mut CharIter) -> Result<Token, String> {
                         digit_chars.push(c);
                     }
                     _ => {
-                        return Err(format!("Unexpected token '{}' for number unit", c))
+                        return Err(iter.error(format!("Unexpected token '{}' for number unit", c)))
                     }
                 }
             }
@@ -135,7 +179,7 @@ fn read_number(first: char, iter: &mut CharIter) -> Result<Token, String> {
                     is_float_number = true;
                     digit_chars.push(c);
                 } else {
-                    return Err("There is a floating point in the number already".to_string());
+                    return Err(iter.error("There is a floating point in the number already"));
                 }
             }
             'u' | 'i' | 'f' => {
@@ -144,7 +188,7 @@ fn read_number(first: char, iter: &mut CharIter) -> Result<Token, String> {
             ' ' | '\t' | '\r' | '\n' | ',' => {
                 break;
             }
-            _ => return Err(format!("Unexpected token '{}' for number", c)),
+            _ => return Err(iter.error(format!("Unexpected token '{}' for number", c))),
         }
     }
     let digit_part: String = digit_chars.into_iter().collect();
@@ -152,24 +196,24 @@ fn read_number(first: char, iter: &mut CharIter) -> Result<Token, String> {
     iter.next();
     if is_float_number {
         if !FLOAT_NUM_TYPES.contains(&unit_part) {
-            return Err(format!(
+            return Err(iter.error(format!(
                 "Invalid float number '{}{}'",
                 digit_part, unit_part
-            ));
+            )));
         }
         return Ok(Token::FloatNumber(digit_part, unit_part));
     } else {
         if !INT_NUM_TYPES.contains(&unit_part) {
-            return Err(format!(
+            return Err(iter.error(format!(
                 "Invalid integer number '{}{}'",
                 digit_part, unit_part
-            ));
+            )));
         }
         return Ok(Token::IntNumber(digit_part, unit_part));
     }
 }
 
-fn read_escaped_char(iter: &mut CharIter) -> Result<char, String> {
+fn read_escaped_char(iter: &mut CharIter) -> Result<char, DovahkiinError> {
     while let Some(c) = iter.next() {
         match c {
             'u' | 'U' => {
@@ -190,10 +234,12 @@ fn read_escaped_char(iter: &mut CharIter) -> Result<char, String> {
                 }
                 let unicode_hex: String = hex_chars.into_iter().collect();
                 let unicode = u32::from_str_radix(&unicode_hex, 16).map_err(|_| {
-                    format!("Cannot parse hex for escape character 0x{}", unicode_hex)
+                    iter.error(format!("Cannot parse hex for escape character 0x{}", unicode_hex))
                 })?;
                 return ::std::char::from_u32(unicode)
-                    .ok_or(format!("Cannot escape character \\u{}", unicode_hex));
+                    .ok_or_else(|| {
+                        iter.error(format!("Cannot escape character \\u{}", unicode_hex))
+                    });
             }
             't' => return Ok('\t'),
             'n' => return Ok('\n'),
@@ -201,13 +247,13 @@ fn read_escaped_char(iter: &mut CharIter) -> Result<char, String> {
             '\'' => return Ok('\''),
             '"' => return Ok('"'),
             '\\' => return Ok('\''),
-            _ => return Err(format!("Unknown escape character '{}'", c)),
+            _ => return Err(iter.error(format!("Unknown escape character '{}'", c))),
         }
     }
-    return Err("Unexpected EOF".to_string());
+    return Err(iter.error("Unexpected EOF"));
 }
 
-fn read_string(iter: &mut CharIter) -> Result<Token, String> {
+fn read_string_content(iter: &mut CharIter) -> Result<String, DovahkiinError> {
     let mut chars = Vec::new();
     while let Some(c) = iter.next() {
         match c {
@@ -216,14 +262,46 @@ fn read_string(iter: &mut CharIter) -> Result<Token, String> {
                 chars.push(read_escaped_char(iter)?);
             }
             '"' => {
-                break;
+                // step over the closing quote
+                iter.next();
+                return Ok(chars.into_iter().collect());
             }
             _ => {
                 chars.push(c);
             }
         }
     }
-    return Ok(Token::String(chars.into_iter().collect()));
+    return Err(iter.error("Unexpected EOF, expect '\"'"));
+}
+
+fn read_string(iter: &mut CharIter) -> Result<Token, DovahkiinError> {
+    return Ok(Token::String(read_string_content(iter)?));
+}
+
+const LITERAL_TAGS: &[&str] = &["id\""];
+
+// Anything else starting with '#' is a symbol
+fn is_tagged(iter: &CharIter) -> bool {
+    LITERAL_TAGS.iter().any(|tag| iter.next_starts_with(tag))
+}
+
+// Tagged literals in the form of #tag"content"
+fn read_tagged(iter: &mut CharIter) -> Result<Token, DovahkiinError> {
+    let mut tag = String::new();
+    while let Some(c) = iter.next() {
+        if !c.is_alphanumeric() {
+            break;
+        }
+        tag.push(c);
+    }
+    if iter.current() != Some('"') {
+        return Err(iter.error(format!("Expect string after tag '#{}'", tag)));
+    }
+    let content = read_string_content(iter)?;
+    match tag.as_ref() {
+        "id" => Ok(Token::Id(content)),
+        _ => Err(iter.error(format!("Unknown literal tag '#{}'", tag))),
+    }
 }
 
 fn read_ident_str(chars: &mut Vec<char>, iter: &mut CharIter) {
@@ -239,53 +317,53 @@ fn read_ident_str(chars: &mut Vec<char>, iter: &mut CharIter) {
     }
 }
 
-fn read_symbol(first: char, iter: &mut CharIter) -> Result<Token, String> {
+fn read_symbol(first: char, iter: &mut CharIter) -> Result<Token, DovahkiinError> {
     let mut chars = vec![first];
     read_ident_str(&mut chars, iter);
     return Ok(Token::Symbol(chars.into_iter().collect()));
 }
 
-fn read_keyword(iter: &mut CharIter) -> Result<Token, String> {
+fn read_keyword(iter: &mut CharIter) -> Result<Token, DovahkiinError> {
     let mut chars = vec![]; // Emit the colon part
     read_ident_str(&mut chars, iter);
     return Ok(Token::Keyword(chars.into_iter().collect()));
 }
 
-pub fn tokenize_chars_iter(iter: &mut CharIter) -> Result<Vec<Token>, String> {
+pub fn tokenize_chars_iter(iter: &mut CharIter) -> Result<Vec<(Token, Span)>, DovahkiinError> {
     let mut tokens = Vec::new();
     while let Some(c) = iter.current() {
-        match c {
+        let start = iter.span();
+        let token = match c {
             ' ' | '\t' | '\r' | '\n' | ',' => {
                 // whitespaces
                 // will do nothing
                 readout_whitespaces(iter);
+                continue;
             }
             '(' => {
-                tokens.push(Token::LeftParentheses);
                 iter.next();
+                Token::LeftParentheses
             }
             ')' => {
-                tokens.push(Token::RightParentheses);
                 iter.next();
+                Token::RightParentheses
             }
             '[' => {
-                tokens.push(Token::LeftVecParentheses);
                 iter.next();
+                Token::LeftVecParentheses
             }
             ']' => {
-                tokens.push(Token::RightVecParentheses);
                 iter.next();
+                Token::RightVecParentheses
             }
-            NUMBER_PATTERN!() => {
-                tokens.push(read_number(c, iter)?);
-            }
+            NUMBER_PATTERN!() => read_number(c, iter)?,
             // match negative number need next char to be a digit
             '-' if match iter.peek_next() {
                 Some(NUMBER_PATTERN!()) => true,
                 _ => false,
             } =>
             {
-                tokens.push(read_number(c, iter)?);
+                read_number(c, iter)?
             }
             // '\'' => { // quote
             //     tokens.push(Token::Quote);
@@ -293,21 +371,21 @@ pub fn tokenize_chars_iter(iter: &mut CharIter) -> Result<Vec<Token>, String> {
             // },
             '"' => {
                 // string
-                tokens.push(read_string(iter)?);
-            }
-            ':' => {
-                tokens.push(read_keyword(iter)?)
+                read_string(iter)?
             }
+            ':' => read_keyword(iter)?,
+            '#' if is_tagged(iter) => read_tagged(iter)?,
             _ => {
                 // symbol with utf8 chars including emojis
-                tokens.push(read_symbol(c, iter)?);
+                read_symbol(c, iter)?
             }
-        }
+        };
+        tokens.push((token, start.to(iter.span())));
     }
     return Ok(tokens);
 }
 
-pub fn tokenize_str<'a>(str: &'a str) -> Result<Vec<Token>, String> {
+pub fn tokenize_str<'a>(str: &'a str) -> Result<Vec<(Token, Span)>, DovahkiinError> {
     let mut iter = CharIter::new(str.chars().collect());
     tokenize_chars_iter(&mut iter)
 }
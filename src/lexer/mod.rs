@@ -1 +1,21 @@
 pub mod lisp;
+
+// Where a token or form was read from. `start` and `end` are byte offsets into the source,
+// line and column are of the start and count from 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    // From the start of this span to the end of the other one
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            end: other.end,
+            ..self
+        }
+    }
+}
@@ -13,7 +13,9 @@ extern crate lazy_static;
 
 #[macro_use]
 pub mod types;
+pub mod error;
 pub mod expr;
 pub mod integrated;
 pub mod lexer;
 pub mod parser;
+pub mod formats;
@@ -1,12 +1,16 @@
 use bifrost_hasher::hash_str;
+use crate::error::DovahkiinError;
 use crate::lexer::lisp::Token;
-use crate::types::OwnedValue as Value;
+use crate::lexer::Span;
+use crate::types::{Id, OwnedValue as Value};
 use std::{vec::IntoIter, marker::PhantomData};
 
 use crate::expr::{SExpr, serde::Expr};
 
+// Only lists are given the span they were read from, other forms leave errors in them to
+// the innermost list around them, if any
 pub trait ParserExpr: Sized {
-    fn list(data: Vec<Self>) -> Self;
+    fn list(data: Vec<Self>, span: Span) -> Self;
     fn vec(data: Vec<Self>) -> Self;
     fn symbol(name: String) -> Self;
     fn keyword(name: String) -> Self;
@@ -20,35 +24,38 @@ pub struct Parser<E: ParserExpr> {
 pub type SExprParser<'a> = Parser<SExpr<'a>>;
 pub type SerdeExprParser = Parser<Expr>;
 
+type Tokens = IntoIter<(Token, Span)>;
+
 impl <E: ParserExpr> Parser <E> {
-    fn parse_list<'a>(iter: &mut IntoIter<Token>) -> Result<E, String> {
+    // Unclosed lists and vectors are reported at where they start
+    fn parse_list<'a>(open: Span, iter: &mut Tokens) -> Result<E, DovahkiinError> {
         let mut contents = Vec::new();
-        while let Some(token) = iter.next() {
+        while let Some((token, span)) = iter.next() {
             match token {
                 Token::RightParentheses => {
-                    return Ok(E::list(contents));
+                    return Ok(E::list(contents, open.to(span)));
                 }
                 _ => {
-                    contents.push(Self::parse_token(token, iter)?);
+                    contents.push(Self::parse_token(token, span, iter)?);
                 }
             }
         }
-        Err(String::from("Unexpected EOF, expect ')'"))
+        Err(DovahkiinError::parse("Unexpected EOF, expect ')'", open))
     }
     
-    fn parse_vec<'a>(iter: &mut IntoIter<Token>) -> Result<E, String> {
+    fn parse_vec<'a>(open: Span, iter: &mut Tokens) -> Result<E, DovahkiinError> {
         let mut contents = Vec::new();
-        while let Some(token) = iter.next() {
+        while let Some((token, span)) = iter.next() {
             match token {
                 Token::RightVecParentheses => {
                     return Ok(E::vec(contents));
                 }
                 _ => {
-                    contents.push(Self::parse_token(token, iter)?);
+                    contents.push(Self::parse_token(token, span, iter)?);
                 }
             }
         }
-        Err(String::from("Unexpected EOF, expect ']'"))
+        Err(DovahkiinError::parse("Unexpected EOF, expect ']'", open))
     }
     
     fn parse_symbol<'a>(name: String) -> E {
@@ -59,7 +66,7 @@ impl <E: ParserExpr> Parser <E> {
         E::keyword(name)
     }
     
-    fn parse_int<'a>(num_str: String, unit: String) -> Result<E, String> {
+    fn parse_int<'a>(num_str: String, unit: String, span: Span) -> Result<E, DovahkiinError> {
         match unit.as_ref() {
             "u8" => num_str.parse::<u8>().map(Value::U8),
             "u16" => num_str.parse::<u16>().map(Value::U16),
@@ -69,27 +76,43 @@ impl <E: ParserExpr> Parser <E> {
             "i16" => num_str.parse::<i16>().map(Value::I16),
             "i32" => num_str.parse::<i32>().map(Value::I32),
             "i64" => num_str.parse::<i64>().map(Value::I64),
-            _ => return Err(format!("Unknown int number type {}", unit)),
+            _ => {
+                return Err(DovahkiinError::parse(
+                    format!("Unknown int number type {}", unit),
+                    span,
+                ))
+            }
         }
         .map_err(|e| {
-            format!(
-                "Cannot parse int {} with unit {}, reason: {:?}",
-                num_str, unit, e
+            DovahkiinError::parse(
+                format!(
+                    "Cannot parse int {} with unit {}, reason: {:?}",
+                    num_str, unit, e
+                ),
+                span,
             )
         })
         .map(E::owned_val)
     }
     
-    fn parse_float<'a>(num_str: String, unit: String) -> Result<E, String> {
+    fn parse_float<'a>(num_str: String, unit: String, span: Span) -> Result<E, DovahkiinError> {
         match unit.as_ref() {
             "f32" => num_str.parse::<f32>().map(Value::F32),
             "f64" => num_str.parse::<f64>().map(Value::F64),
-            _ => return Err(format!("Unknown float number type {}", unit)),
+            _ => {
+                return Err(DovahkiinError::parse(
+                    format!("Unknown float number type {}", unit),
+                    span,
+                ))
+            }
         }
         .map_err(|e| {
-            format!(
-                "Cannot parse float {} with unit {}, reason: {:?}",
-                num_str, unit, e
+            DovahkiinError::parse(
+                format!(
+                    "Cannot parse float {} with unit {}, reason: {:?}",
+                    num_str, unit, e
+                ),
+                span,
             )
         })
         .map(E::owned_val)
@@ -98,25 +121,35 @@ impl <E: ParserExpr> Parser <E> {
     fn parse_string<'a>(str: String) -> E {
         E::owned_val(Value::String(str))
     }
+
+    fn parse_id<'a>(str: String, span: Span) -> Result<E, DovahkiinError> {
+        str.parse::<Id>()
+            .map(|id| E::owned_val(Value::Id(id)))
+            .map_err(|e| DovahkiinError::parse(e, span))
+    }
     
-    fn parse_token<'a>(token: Token, iter: &mut IntoIter<Token>) -> Result<E, String> {
+    fn parse_token<'a>(token: Token, span: Span, iter: &mut Tokens) -> Result<E, DovahkiinError> {
         match token {
-            Token::LeftParentheses => Ok(Self::parse_list(iter)?), // list
+            Token::LeftParentheses => Ok(Self::parse_list(span, iter)?), // list
             Token::Symbol(name) => Ok(Self::parse_symbol(name)),
-            Token::IntNumber(num, unit) => Ok(Self::parse_int(num, unit)?),
-            Token::FloatNumber(num, unit) => Ok(Self::parse_float(num, unit)?),
+            Token::IntNumber(num, unit) => Ok(Self::parse_int(num, unit, span)?),
+            Token::FloatNumber(num, unit) => Ok(Self::parse_float(num, unit, span)?),
             Token::String(str) => Ok(Self::parse_string(str)),
-            Token::LeftVecParentheses => Ok(Self::parse_vec(iter)?),
+            Token::LeftVecParentheses => Ok(Self::parse_vec(span, iter)?),
             Token::Keyword(str) => Ok(Self::parse_keyword(str)),
-            _ => Err(format!("Unexpected start token {}", token.to_string())),
+            Token::Id(str) => Ok(Self::parse_id(str, span)?),
+            _ => Err(DovahkiinError::parse(
+                format!("Unexpected start token {}", token.to_string()),
+                span,
+            )),
         }
     }
     
-    pub fn parse_to_expr<'a>(tokens: Vec<Token>) -> Result<Vec<E>, String> {
+    pub fn parse_to_expr<'a>(tokens: Vec<(Token, Span)>) -> Result<Vec<E>, DovahkiinError> {
         let mut exprs: Vec<E> = Vec::new();
         let mut iter = tokens.into_iter();
-        while let Some(token) = iter.next() {
-            exprs.push(Self::parse_token(token, &mut iter)?)
+        while let Some((token, span)) = iter.next() {
+            exprs.push(Self::parse_token(token, span, &mut iter)?)
         }
         Ok(exprs)
     }    
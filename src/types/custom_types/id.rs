@@ -2,7 +2,28 @@ use bifrost::utils::serde::serialize;
 use bifrost_hasher::{hash_bytes, hash_bytes_secondary};
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 use serde;
+use std::fmt;
 use std::io::{Cursor, Error};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Crockford's alphabet, without the letters that can be confused with digits
+const BASE32_ALPHABET: &[u8; 32] = b"0123456789abcdefghjkmnpqrstvwxyz";
+const BASE32_LEN: usize = 26;
+const HEX_LEN: usize = 32;
+pub const DEFAULT_NODE_BITS: u8 = 16;
+const MAX_NODE_BITS: u8 = 32;
+
+lazy_static! {
+    static ref ID_GENERATOR: Mutex<IdGenerator> = Mutex::new(
+        IdGenerator::new(
+            DEFAULT_NODE_BITS,
+            rand::random::<u64>() >> (64 - DEFAULT_NODE_BITS)
+        )
+        .unwrap()
+    );
+}
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, Hash, Ord, PartialOrd, PartialEq, Eq)]
 pub struct Id {
@@ -25,8 +46,59 @@ impl Id {
             lower: hash_bytes_secondary(&bin),
         }
     }
+    // Same order as `Ord`, higher part first
     pub fn is_greater_than(&self, other: &Id) -> bool {
-        self.higher >= other.higher && self.lower > other.lower
+        self > other
+    }
+    // Time ordered id from the process wide generator
+    pub fn generate() -> Id {
+        ID_GENERATOR.lock().unwrap().next()
+    }
+    pub fn rand() -> Id {
+        Id::new(rand::random(), rand::random())
+    }
+    pub fn to_hex(&self) -> String {
+        format!("{:016x}{:016x}", self.higher, self.lower)
+    }
+    pub fn to_base32(&self) -> String {
+        let value = ((self.higher as u128) << 64) | self.lower as u128;
+        (0..BASE32_LEN)
+            .rev()
+            .map(|i| BASE32_ALPHABET[((value >> (i * 5)) & 31) as usize] as char)
+            .collect()
+    }
+    pub fn from_hex(str: &str) -> Result<Id, String> {
+        if str.len() != HEX_LEN {
+            return Err(format!("Hex id should have {} digits, found '{}'", HEX_LEN, str));
+        }
+        // `from_str_radix` would also take a sign
+        if let Some(c) = str.chars().find(|c| !c.is_ascii_hexdigit()) {
+            return Err(format!("Invalid hex digit '{}' in id '{}'", c, str));
+        }
+        let value = u128::from_str_radix(str, 16)
+            .map_err(|e| format!("Cannot parse hex id '{}', reason: {:?}", str, e))?;
+        Ok(Id::new((value >> 64) as u64, value as u64))
+    }
+    pub fn from_base32(str: &str) -> Result<Id, String> {
+        if str.len() != BASE32_LEN {
+            return Err(format!(
+                "Base32 id should have {} digits, found '{}'",
+                BASE32_LEN, str
+            ));
+        }
+        let mut value = 0u128;
+        for (i, c) in str.chars().enumerate() {
+            let digit = BASE32_ALPHABET
+                .iter()
+                .position(|d| *d as char == c.to_ascii_lowercase())
+                .ok_or_else(|| format!("Invalid base32 digit '{}' in id '{}'", c, str))?;
+            // 26 digits hold 130 bits, the first one can only carry 3 of them
+            if i == 0 && digit > 7 {
+                return Err(format!("Base32 id '{}' overflows 128 bits", str));
+            }
+            value = (value << 5) | digit as u128;
+        }
+        Ok(Id::new((value >> 64) as u64, value as u64))
     }
     pub const fn unit_id() -> Id {
         Id {
@@ -66,12 +138,88 @@ impl Default for Id {
     }
 }
 
+impl fmt::Display for Id {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_hex())
+    }
+}
+
+// Accepts both the hex and the base32 form, told apart by their length
+impl FromStr for Id {
+    type Err = String;
+
+    fn from_str(str: &str) -> Result<Id, String> {
+        match str.len() {
+            HEX_LEN => Id::from_hex(str),
+            BASE32_LEN => Id::from_base32(str),
+            _ => Err(format!("Cannot parse id '{}'", str)),
+        }
+    }
+}
+
+// Generates ids ordered by creation time. The higher part is the unix time in milliseconds,
+// the lower part holds the node in its top `node_bits` bits followed by a sequence number.
+// Ids from one generator are strictly increasing, ids from generators with distinct nodes
+// never collide.
+#[derive(Debug, Clone)]
+pub struct IdGenerator {
+    node: u64,
+    node_bits: u8,
+    last_millis: u64,
+    sequence: u64,
+}
+
+impl IdGenerator {
+    pub fn new(node_bits: u8, node: u64) -> Result<Self, String> {
+        if node_bits > MAX_NODE_BITS {
+            return Err(format!(
+                "Id generator supports at most {} node bits, found {}",
+                MAX_NODE_BITS, node_bits
+            ));
+        }
+        if node >= 1 << node_bits {
+            return Err(format!("Node {} does not fit in {} bits", node, node_bits));
+        }
+        Ok(Self {
+            node,
+            node_bits,
+            last_millis: 0,
+            sequence: 0,
+        })
+    }
+
+    pub fn next(&mut self) -> Id {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+        let sequence_bits = 64 - self.node_bits as u32;
+        let max_sequence = !0u64 >> self.node_bits;
+        if now > self.last_millis {
+            self.last_millis = now;
+            self.sequence = 0;
+        } else if self.sequence == max_sequence {
+            // Sequence exhausted or the clock went back, borrow the next millisecond
+            self.last_millis += 1;
+            self.sequence = 0;
+        } else {
+            self.sequence += 1;
+        }
+        let node = self.node.checked_shl(sequence_bits).unwrap_or(0);
+        Id::new(self.last_millis, node | self.sequence)
+    }
+}
+
+pub fn set_id_generator(generator: IdGenerator) {
+    *ID_GENERATOR.lock().unwrap() = generator;
+}
+
 #[cfg(test)]
 mod test {
     use std::cmp::Ordering;
     use std::collections::BTreeMap;
     use std::collections::HashMap;
-    use crate::types::custom_types::id::Id;
+    use crate::types::custom_types::id::{Id, IdGenerator};
 
     #[test]
     fn compare() {
@@ -83,6 +231,37 @@ mod test {
         assert!(id_1 < id_2);
         assert_eq!(id_1, id_3);
         assert!(id_4 > id_1);
+        assert!(id_2.is_greater_than(&id_4));
+        assert!(!id_4.is_greater_than(&id_2));
+        assert!(!id_1.is_greater_than(&id_3));
+    }
+
+    #[test]
+    fn textual() {
+        for id in [Id::new(1, 2), Id::max_id(), Id::unit_id(), Id::rand()] {
+            assert_eq!(id.to_string().parse::<Id>(), Ok(id));
+            assert_eq!(id.to_base32().parse::<Id>(), Ok(id));
+            assert_eq!(id.to_base32().to_uppercase().parse::<Id>(), Ok(id));
+        }
+        assert_eq!(
+            Id::new(1, 255).to_string(),
+            "000000000000000100000000000000ff"
+        );
+        assert!("zzzzzzzzzzzzzzzzzzzzzzzzzz".parse::<Id>().is_err());
+        assert!("123".parse::<Id>().is_err());
+        let hex = Id::new(1, 2).to_hex();
+        assert!(format!("+{}", &hex[1..]).parse::<Id>().is_err());
+    }
+
+    #[test]
+    fn generation() {
+        let mut generator = IdGenerator::new(8, 3).unwrap();
+        let ids: Vec<Id> = (0..1000).map(|_| generator.next()).collect();
+        assert!(ids.windows(2).all(|w| w[1] > w[0]));
+        assert!(ids.iter().all(|id| id.lower >> 56 == 3));
+        assert!(IdGenerator::new(8, 256).is_err());
+        assert!(IdGenerator::new(40, 0).is_err());
+        assert!(Id::generate() < Id::generate());
     }
 
     #[test]
@@ -1,22 +1,28 @@
-use std::{collections::HashMap, slice::Iter};
+use std::{collections::HashMap, slice::Iter, sync::RwLock};
 
-use crate::types::{Value, key_hash, SharedMap, OwnedMap};
+use crate::expr::SExpr;
+use crate::types::{Value, key_hash, SharedMap, SharedValue, OwnedMap};
 
 pub trait Map {
     type Value: Value;
+    // What getters return, references into owned maps and values read from shared views
+    type Ref<'a>
+    where
+        Self: 'a;
     fn new() -> Self;
     fn from_hash_map(map: HashMap<String, Self::Value>) -> Self;
     fn insert<'a>(&mut self, key: &'a str, value: Self::Value) -> Option<Self::Value>;
+    fn try_insert<'a>(&mut self, key: &'a str, value: Self::Value) -> Result<Option<Self::Value>, String>;
     fn insert_key_id(&mut self, key: u64, value: Self::Value) -> Option<Self::Value>;
-    fn get_by_key_id(&self, key: u64) -> &Self::Value;
+    fn get_by_key_id(&self, key: u64) -> Self::Ref<'_>;
     fn get_mut_by_key_id(&mut self, key: u64) -> &mut Self::Value;
-    fn get<'a>(&self, key: &'a str) -> &Self::Value;
+    fn get<'a>(&self, key: &'a str) -> Self::Ref<'_>;
     fn get_mut<'a>(&mut self, key: &'a str) -> &mut Self::Value;
     fn strs_to_ids<'a>(keys: &[&'a str]) -> Vec<u64> {
         keys.iter().map(|str| key_hash(str)).collect()
     }
-    fn get_in_by_ids<'a, I: Iterator<Item = &'a u64> + ExactSizeIterator>(&self, key_ids: I) -> &Self::Value;
-    fn get_in(&self, keys: &[&'static str]) -> &Self::Value;
+    fn get_in_by_ids<'a, I: Iterator<Item = &'a u64> + ExactSizeIterator>(&self, key_ids: I) -> Self::Ref<'_>;
+    fn get_in(&self, keys: &[&'static str]) -> Self::Ref<'_>;
     fn get_in_mut_by_key_ids(&mut self, keys_ids: Iter<u64>) -> Option<&mut Self::Value>;
     fn get_in_mut(&mut self, keys: &[&'static str]) -> Option<&mut Self::Value>;
     fn update_in_by_key_ids<U>(&mut self, keys: Iter<u64>, update: U) -> Option<()> where U: FnOnce(&mut Self::Value);
@@ -27,4 +33,112 @@ pub trait Map {
     fn len(&self) -> usize;
     fn shared<'a>(&'a self) -> SharedMap<'a>;
     fn owned(&self) -> OwnedMap;
-}
\ No newline at end of file
+}
+// Names that key ids resolve to for maps that only have the ids, like the ones built with
+// `insert_key_id`. Maps keep the names of their own fields, names are only registered here on
+// request and the registry is bounded.
+pub const MAX_FIELD_NAMES: usize = 1 << 16;
+
+lazy_static! {
+    static ref FIELD_NAMES: RwLock<HashMap<u64, String>> = RwLock::new(HashMap::new());
+}
+
+// Records the name behind a field key id. Fails if the id already belongs to another name or
+// the registry is full.
+pub fn register_field_name(name: &str) -> Result<u64, String> {
+    let id = key_hash(name);
+    if let Some(existing) = FIELD_NAMES.read().unwrap().get(&id) {
+        return check_collision(id, existing, name);
+    }
+    let mut names = FIELD_NAMES.write().unwrap();
+    if names.len() >= MAX_FIELD_NAMES && !names.contains_key(&id) {
+        return Err(format!(
+            "Cannot register field '{}', there are {} field names already",
+            name, MAX_FIELD_NAMES
+        ));
+    }
+    let existing = names.entry(id).or_insert_with(|| name.to_string());
+    check_collision(id, existing, name)
+}
+
+pub fn field_name(id: u64) -> Option<String> {
+    FIELD_NAMES.read().unwrap().get(&id).cloned()
+}
+
+// Name of the field a key in lisp stands for, keywords and strings name fields as they are
+pub fn key_name(key: &SExpr) -> Result<String, String> {
+    if let SExpr::Keyword(_, name) = key {
+        return Ok(name.clone());
+    }
+    match key.val() {
+        Some(SharedValue::String(s)) => Ok(s.to_string()),
+        _ => Err(format!("Expect keyword or string as key, found {:?}", key)),
+    }
+}
+
+fn check_collision(id: u64, existing: &str, name: &str) -> Result<u64, String> {
+    if existing != name {
+        return Err(format!(
+            "Field '{}' collides with '{}' on key id {}",
+            name, existing, id
+        ));
+    }
+    Ok(id)
+}
+
+// Validates a named insertion against the fields of a map, returns the key id and whether the
+// name still needs to be added to the fields
+pub fn check_field(fields: &[String], name: &str, present: bool) -> Result<(u64, bool), String> {
+    let id = key_hash(name);
+    if !present {
+        return Ok((id, true));
+    }
+    match fields.iter().find(|f| key_hash(f) == id) {
+        Some(existing) => check_collision(id, existing, name).map(|id| (id, false)),
+        None => Ok((id, true)),
+    }
+}
+
+// Key id of a named insertion that cannot fail. A colliding name replaces the value of the
+// one it collides with and the existing name is kept.
+pub fn insert_field(fields: &mut Vec<String>, name: &str, present: bool) -> u64 {
+    match check_field(fields, name, present) {
+        Ok((id, new_field)) => {
+            if new_field {
+                fields.push(name.to_string());
+            }
+            id
+        }
+        Err(_) => key_hash(name),
+    }
+}
+
+// Name of a field for display and conversion, falls back to the registered names
+pub fn resolve_field_name(fields: &[String], id: u64) -> Option<String> {
+    fields
+        .iter()
+        .find(|f| key_hash(f) == id)
+        .cloned()
+        .or_else(|| field_name(id))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::types::*;
+
+    #[test]
+    fn field_names() {
+        let mut map = OwnedMap::new();
+        map.insert_value("name", "a".to_string());
+        map.insert_value("name", "b".to_string());
+        assert_eq!(map.fields, vec!["name".to_string()]);
+        assert_eq!(field_name(key_hash("name")), None);
+
+        let id = register_field_name("nickname").unwrap();
+        assert_eq!(field_name(id), Some("nickname".to_string()));
+        let mut by_id = OwnedMap::new();
+        by_id.insert_key_id_value(id, 1u32);
+        let string_map = by_id.into_string_map();
+        assert_eq!(string_map.get("nickname"), Some(&OwnedValue::U32(1)));
+    }
+}
@@ -4,4 +4,8 @@ pub mod id;
 pub mod pos;
 pub mod map;
 pub mod owned_map;
+pub mod persistent;
+pub mod shared_array;
 pub mod shared_map;
+pub mod sketch;
+pub mod tensor;
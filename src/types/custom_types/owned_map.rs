@@ -1,6 +1,6 @@
 use crate::types::SharedMap;
 
-use super::map::Map;
+use super::map::{check_field, field_name, insert_field, resolve_field_name, Map};
 use super::{super::*, shared_map::key_hash};
 use std::collections::HashMap;
 use std::fmt;
@@ -16,6 +16,7 @@ pub struct OwnedMap {
 impl Map for OwnedMap {
 
     type Value = OwnedValue;
+    type Ref<'a> = &'a OwnedValue;
 
     fn new() -> Self {
         Self {
@@ -24,19 +25,25 @@ impl Map for OwnedMap {
         }
     }
     fn from_hash_map(map: HashMap<String, Self::Value>) -> Self {
-        let mut target_map = HashMap::new();
-        let fields = map.keys().cloned().collect();
+        let mut target_map = Self::new();
         for (key, value) in map {
-            target_map.insert(key_hash(&key), value);
-        }
-        Self {
-            map: target_map,
-            fields,
+            target_map.insert(&key, value);
         }
+        target_map
     }
+    // Names colliding on their key id replace each other's values, `try_insert` reports them
     fn insert<'a>(&mut self, key: &'a str, value: Self::Value) -> Option<Self::Value> {
-        self.fields.push(key.to_string());
-        self.insert_key_id(key_hash(key), value)
+        let present = self.map.contains_key(&key_hash(key));
+        let id = insert_field(&mut self.fields, key, present);
+        self.insert_key_id(id, value)
+    }
+    fn try_insert<'a>(&mut self, key: &'a str, value: Self::Value) -> Result<Option<Self::Value>, String> {
+        let present = self.map.contains_key(&key_hash(key));
+        let (id, new_field) = check_field(&self.fields, key, present)?;
+        if new_field {
+            self.fields.push(key.to_string());
+        }
+        Ok(self.insert_key_id(id, value))
     }
     fn insert_key_id(&mut self, key: u64, value: Self::Value) -> Option<Self::Value> {
         self.map.insert(key, value)
@@ -132,26 +139,17 @@ impl Map for OwnedMap {
         self.set_in_by_key_ids(Self::strs_to_ids(keys).iter(), value)
     }
     fn into_string_map(self) -> HashMap<String, Self::Value> {
-        let mut id_map: HashMap<u64, String> = self
-            .fields
-            .into_iter()
-            .map(|field| (key_hash(&field), field))
-            .collect();
+        let fields = self.fields;
         self.map
             .into_iter()
-            .map(|(fid, value)| (id_map.remove(&fid), value))
-            .filter(|&(ref field, _)| field.is_some())
-            .map(|(field, value)| (field.unwrap(), value))
+            .filter_map(|(fid, value)| resolve_field_name(&fields, fid).map(|name| (name, value)))
             .collect()
     }
     fn len(&self) -> usize {
         self.map.len()
     }
     fn shared<'a>(&'a self) -> SharedMap<'a> {
-        SharedMap {
-            fields: self.fields.clone(),
-            map: self.map.iter().map(|(k, v)| (*k, v.shared())).collect(),
-        }
+        SharedMap::borrowed(self)
     }
 
     fn owned(&self) -> OwnedMap {
@@ -172,6 +170,11 @@ impl OwnedMap {
     {
         self.insert_key_id(key, value.value())
     }
+    pub fn remove(&mut self, key: &str) -> Option<OwnedValue> {
+        let id = key_hash(key);
+        self.fields.retain(|f| key_hash(f) != id);
+        self.map.remove(&id)
+    }
 }
 
 impl fmt::Debug for OwnedMap {
@@ -181,6 +184,15 @@ impl fmt::Debug for OwnedMap {
             let id = key_hash(name);
             write!(f, "{}: {:?} ", name, self.map[&id])?;
         }
+        for (id, value) in &self.map {
+            if self.fields.iter().any(|name| key_hash(name) == *id) {
+                continue;
+            }
+            match field_name(*id) {
+                Some(name) => write!(f, "{}: {:?} ", name, value)?,
+                None => write!(f, "#{}: {:?} ", id, value)?,
+            }
+        }
         write!(f, ") ")
     }
 }
@@ -0,0 +1,301 @@
+use super::super::*;
+use std::cmp::Ordering;
+
+// Structurally shared map and vector. Updates return new values sharing most of their
+// nodes with the original, so clones are O(1) and assoc/conj/merge are O(log n).
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(from = "PersistentMapParts")]
+pub struct PersistentMap {
+    map: im::HashMap<u64, OwnedValue>,
+    // Field names in the order they were added, keyed by that order
+    fields: im::OrdMap<u64, String>,
+    // Where every key id is in `fields`, so they can be found and removed in O(log n)
+    #[serde(skip)]
+    positions: im::HashMap<u64, u64>,
+}
+
+#[derive(Deserialize)]
+struct PersistentMapParts {
+    map: im::HashMap<u64, OwnedValue>,
+    fields: im::OrdMap<u64, String>,
+}
+
+impl From<PersistentMapParts> for PersistentMap {
+    fn from(parts: PersistentMapParts) -> Self {
+        let positions = parts
+            .fields
+            .iter()
+            .map(|(position, field)| (key_hash(field), *position))
+            .collect();
+        Self {
+            map: parts.map,
+            fields: parts.fields,
+            positions,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, PartialOrd, Default)]
+pub struct PersistentVec {
+    pub items: im::Vector<OwnedValue>,
+}
+
+impl PersistentMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn get_by_key_id(&self, key: u64) -> &OwnedValue {
+        self.map.get(&key).unwrap_or(&NULL_OWNED_VALUE)
+    }
+
+    pub fn get(&self, key: &str) -> &OwnedValue {
+        self.get_by_key_id(key_hash(key))
+    }
+
+    // Values are updated in place, the keys and their names stay as they are
+    pub(crate) fn get_mut_by_key_id(&mut self, key: u64) -> Option<&mut OwnedValue> {
+        self.map.get_mut(&key)
+    }
+
+    pub fn iter(&self) -> im::hashmap::Iter<'_, u64, OwnedValue> {
+        self.map.iter()
+    }
+
+    pub fn keys(&self) -> im::hashmap::Keys<'_, u64, OwnedValue> {
+        self.map.keys()
+    }
+
+    pub fn values(&self) -> im::hashmap::Values<'_, u64, OwnedValue> {
+        self.map.values()
+    }
+
+    // Names of the fields in the order they were added. Keys added by id without a
+    // registered name have none.
+    pub fn fields(&self) -> im::ordmap::Values<'_, u64, String> {
+        self.fields.values()
+    }
+
+    pub fn assoc(&self, key: &str, value: OwnedValue) -> Result<Self, String> {
+        let id = key_hash(key);
+        let mut res = self.clone();
+        match self.field(id) {
+            // Keys added by id without a name are named here
+            None => res.push_field(id, key.to_string()),
+            Some(existing) if existing != key => {
+                return Err(format!(
+                    "Field '{}' collides with '{}' on key id {}",
+                    key, existing, id
+                ))
+            }
+            Some(_) => {}
+        }
+        res.map.insert(id, value);
+        Ok(res)
+    }
+
+    // New keys are named after the field name registry, if they are registered there
+    pub fn assoc_key_id(&self, key: u64, value: OwnedValue) -> Self {
+        let mut res = self.clone();
+        if !self.map.contains_key(&key) {
+            if let Some(name) = field_name(key) {
+                res.push_field(key, name);
+            }
+        }
+        res.map.insert(key, value);
+        res
+    }
+
+    pub fn dissoc(&self, key: &str) -> Self {
+        let id = key_hash(key);
+        let mut res = self.clone();
+        if res.map.remove(&id).is_some() {
+            if let Some(position) = res.positions.remove(&id) {
+                res.fields.remove(&position);
+            }
+        }
+        res
+    }
+
+    // Entries of `other` take precedence, only the entries of `other` are copied
+    pub fn merge(&self, other: &PersistentMap) -> Self {
+        let mut res = self.clone();
+        for field in other.fields.values() {
+            let id = key_hash(field);
+            if res.field(id).is_none() {
+                res.push_field(id, field.clone());
+            }
+        }
+        for (key, value) in other.map.iter() {
+            res.map.insert(*key, value.clone());
+        }
+        res
+    }
+
+    pub fn to_map(&self) -> OwnedMap {
+        OwnedMap {
+            map: self.map.iter().map(|(k, v)| (*k, v.clone())).collect(),
+            fields: self.fields.values().cloned().collect(),
+        }
+    }
+
+    pub fn field(&self, id: u64) -> Option<&String> {
+        self.positions
+            .get(&id)
+            .and_then(|position| self.fields.get(position))
+    }
+
+    fn push_field(&mut self, id: u64, field: String) {
+        let position = self
+            .fields
+            .get_max()
+            .map(|(position, _)| position + 1)
+            .unwrap_or(0);
+        self.fields.insert(position, field);
+        self.positions.insert(id, position);
+    }
+}
+
+impl From<OwnedMap> for PersistentMap {
+    fn from(map: OwnedMap) -> Self {
+        PersistentMapParts {
+            map: map.map.into_iter().collect(),
+            fields: (0u64..).zip(map.fields).collect(),
+        }
+        .into()
+    }
+}
+
+// Positions depend on what was removed before, only the order of the fields matters
+impl PartialEq for PersistentMap {
+    fn eq(&self, other: &Self) -> bool {
+        self.map == other.map && self.fields.values().eq(other.fields.values())
+    }
+}
+
+impl PartialOrd for PersistentMap {
+    fn partial_cmp(&self, _: &Self) -> Option<Ordering> {
+        None
+    }
+}
+
+impl PersistentVec {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&OwnedValue> {
+        self.items.get(index)
+    }
+
+    pub fn conj(&self, value: OwnedValue) -> Self {
+        let mut items = self.items.clone();
+        items.push_back(value);
+        Self { items }
+    }
+
+    pub fn concat(&self, other: &PersistentVec) -> Self {
+        let mut items = self.items.clone();
+        items.append(other.items.clone());
+        Self { items }
+    }
+
+    pub fn assoc(&self, index: usize, value: OwnedValue) -> Result<Self, String> {
+        if index >= self.items.len() {
+            return Err(format!(
+                "Index {} out of bound for vector of {}",
+                index,
+                self.items.len()
+            ));
+        }
+        Ok(Self {
+            items: self.items.update(index, value),
+        })
+    }
+
+    pub fn iter(&self) -> im::vector::Iter<OwnedValue> {
+        self.items.iter()
+    }
+
+    pub fn to_vec(&self) -> Vec<OwnedValue> {
+        self.items.iter().cloned().collect()
+    }
+}
+
+impl From<Vec<OwnedValue>> for PersistentVec {
+    fn from(items: Vec<OwnedValue>) -> Self {
+        Self {
+            items: items.into_iter().collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::types::*;
+
+    #[test]
+    fn structural_updates() {
+        let mut map = OwnedMap::new();
+        map.insert_value("a", 1u32);
+        let base = PersistentMap::from(map);
+        let updated = base.assoc("b", OwnedValue::U32(2)).unwrap();
+        assert_eq!(base.len(), 1);
+        assert_eq!(updated.get("b"), &OwnedValue::U32(2));
+        let overridden = updated.merge(&base.assoc("a", OwnedValue::U32(3)).unwrap());
+        assert_eq!(overridden.get("a"), &OwnedValue::U32(3));
+        assert_eq!(overridden.fields().len(), 2);
+        assert_eq!(
+            overridden.dissoc("a").to_map().fields,
+            vec!["b".to_string()]
+        );
+        let readded = overridden
+            .dissoc("a")
+            .assoc("a", OwnedValue::U32(3))
+            .unwrap();
+        assert_eq!(
+            readded.to_map().fields,
+            vec!["b".to_string(), "a".to_string()]
+        );
+        assert_eq!(readded.dissoc("a"), overridden.dissoc("a"));
+        // Positions of the fields are rebuilt when read back
+        let bytes = bifrost::utils::serde::serialize(&readded);
+        let read: PersistentMap = bifrost::utils::serde::deserialize(&bytes).unwrap();
+        assert_eq!(read, readded);
+        assert_eq!(read.dissoc("b").to_map().fields, vec!["a".to_string()]);
+
+        let vec = PersistentVec::from(vec![OwnedValue::U8(1)]);
+        let longer = vec.conj(OwnedValue::U8(2)).concat(&vec);
+        assert_eq!(vec.len(), 1);
+        assert_eq!(
+            longer.to_vec(),
+            vec![OwnedValue::U8(1), OwnedValue::U8(2), OwnedValue::U8(1)]
+        );
+        assert!(longer.assoc(3, OwnedValue::U8(0)).is_err());
+
+        // Keys added by id are named once their name is known
+        let id = register_field_name("persistent_by_id").unwrap();
+        let by_id = base.assoc_key_id(id, OwnedValue::U8(1));
+        assert_eq!(
+            by_id.to_map().fields,
+            vec!["a".to_string(), "persistent_by_id".to_string()]
+        );
+        assert_eq!(by_id.dissoc("persistent_by_id"), base);
+        let unnamed = base.assoc_key_id(key_hash("c"), OwnedValue::U8(1));
+        assert_eq!(unnamed.fields().len(), 1);
+        let named = unnamed.assoc("c", OwnedValue::U8(2)).unwrap();
+        assert_eq!(
+            named.to_map().fields,
+            vec!["a".to_string(), "c".to_string()]
+        );
+    }
+}
@@ -0,0 +1,113 @@
+use super::super::*;
+use std::fmt;
+use std::slice;
+
+// Elements of an array view. Views of an owned array borrow its elements and only turn the
+// ones that are read into shared values, like borrowed `SharedMap`s.
+#[derive(Clone)]
+pub enum SharedArray<'v> {
+    Borrowed(&'v [OwnedValue]),
+    Owned(Vec<SharedValue<'v>>),
+}
+
+pub enum SharedArrayIter<'a, 'v> {
+    Borrowed(slice::Iter<'v, OwnedValue>),
+    Owned(slice::Iter<'a, SharedValue<'v>>),
+}
+
+impl<'v> SharedArray<'v> {
+    pub fn len(&self) -> usize {
+        match self {
+            SharedArray::Borrowed(array) => array.len(),
+            SharedArray::Owned(array) => array.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, index: usize) -> Option<SharedValue<'v>> {
+        match self {
+            SharedArray::Borrowed(array) => array.get(index).map(|v| v.shared()),
+            SharedArray::Owned(array) => array.get(index).cloned(),
+        }
+    }
+
+    pub fn iter<'a>(&'a self) -> SharedArrayIter<'a, 'v> {
+        match self {
+            SharedArray::Borrowed(array) => SharedArrayIter::Borrowed(array.iter()),
+            SharedArray::Owned(array) => SharedArrayIter::Owned(array.iter()),
+        }
+    }
+
+    pub fn into_vec(self) -> Vec<SharedValue<'v>> {
+        match self {
+            SharedArray::Borrowed(array) => array.iter().map(|v| v.shared()).collect(),
+            SharedArray::Owned(array) => array,
+        }
+    }
+}
+
+impl<'a, 'v> Iterator for SharedArrayIter<'a, 'v> {
+    type Item = SharedValue<'v>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            SharedArrayIter::Borrowed(iter) => iter.next().map(|v| v.shared()),
+            SharedArrayIter::Owned(iter) => iter.next().cloned(),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self {
+            SharedArrayIter::Borrowed(iter) => iter.size_hint(),
+            SharedArrayIter::Owned(iter) => iter.size_hint(),
+        }
+    }
+}
+
+impl<'a, 'v> ExactSizeIterator for SharedArrayIter<'a, 'v> {}
+
+impl<'a, 'v> IntoIterator for &'a SharedArray<'v> {
+    type Item = SharedValue<'v>;
+    type IntoIter = SharedArrayIter<'a, 'v>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'v> From<Vec<SharedValue<'v>>> for SharedArray<'v> {
+    fn from(array: Vec<SharedValue<'v>>) -> Self {
+        SharedArray::Owned(array)
+    }
+}
+
+impl<'v> FromIterator<SharedValue<'v>> for SharedArray<'v> {
+    fn from_iter<I: IntoIterator<Item = SharedValue<'v>>>(iter: I) -> Self {
+        SharedArray::Owned(iter.into_iter().collect())
+    }
+}
+
+impl<'v> PartialEq for SharedArray<'v> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().zip(other.iter()).all(|(a, b)| a == b)
+    }
+}
+
+impl<'v> fmt::Debug for SharedArray<'v> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+// Borrowed arrays are views, only arrays built for the view are counted
+impl<'v> HeapSize for SharedArray<'v> {
+    fn heap_size(&self) -> usize {
+        match self {
+            SharedArray::Borrowed(_) => 0,
+            SharedArray::Owned(array) => array.heap_size(),
+        }
+    }
+}
@@ -1,56 +1,159 @@
 use super::super::*;
-use super::map::Map;
+use super::map::{check_field, insert_field, resolve_field_name, Map};
 use bifrost_hasher::hash_str;
-use std::collections::HashMap;
+use std::collections::{hash_map, HashMap};
+use std::fmt;
 use std::iter::Iterator;
 use std::slice::Iter;
 
-#[derive(Debug, PartialEq, Clone)]
+// A map view. Views of an `OwnedMap` borrow it and only turn the entries that are read into
+// shared values, so reading one field of a large record does not copy the record. Borrowed
+// views are materialised before the first mutation. Entries are read by value, through `get`
+// and the other `Map` getters, whatever the view holds.
+#[derive(Clone)]
 pub struct SharedMap<'v> {
-    pub map: HashMap<u64, SharedValue<'v>>,
-    pub fields: Vec<String>,
+    map: HashMap<u64, SharedValue<'v>>,
+    fields: Vec<String>,
+    source: Option<&'v OwnedMap>,
 }
+
+pub enum SharedMapIter<'a, 'v> {
+    Borrowed(hash_map::Iter<'v, u64, OwnedValue>),
+    Owned(hash_map::Iter<'a, u64, SharedValue<'v>>),
+}
+
+impl<'v> SharedMap<'v> {
+    pub fn borrowed(source: &'v OwnedMap) -> Self {
+        Self {
+            map: HashMap::new(),
+            fields: Vec::new(),
+            source: Some(source),
+        }
+    }
+
+    pub fn is_borrowed(&self) -> bool {
+        self.source.is_some()
+    }
+
+    pub fn fields(&self) -> &[String] {
+        match self.source {
+            Some(source) => &source.fields,
+            None => &self.fields,
+        }
+    }
+
+    pub fn contains_key(&self, key: u64) -> bool {
+        match self.source {
+            Some(source) => source.map.contains_key(&key),
+            None => self.map.contains_key(&key),
+        }
+    }
+
+    pub fn iter<'a>(&'a self) -> SharedMapIter<'a, 'v> {
+        match self.source {
+            Some(source) => SharedMapIter::Borrowed(source.map.iter()),
+            None => SharedMapIter::Owned(self.map.iter()),
+        }
+    }
+
+    // Copies the entries of a borrowed view out of the map it borrows
+    pub fn materialise(&mut self) {
+        if let Some(source) = self.source.take() {
+            self.map = source.map.iter().map(|(k, v)| (*k, v.shared())).collect();
+            self.fields = source.fields.clone();
+        }
+    }
+}
+
+impl<'a, 'v> Iterator for SharedMapIter<'a, 'v> {
+    type Item = (u64, SharedValue<'v>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            SharedMapIter::Borrowed(iter) => iter.next().map(|(k, v)| (*k, v.shared())),
+            SharedMapIter::Owned(iter) => iter.next().map(|(k, v)| (*k, v.clone())),
+        }
+    }
+}
+
+impl<'v> PartialEq for SharedMap<'v> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len()
+            && self.fields() == other.fields()
+            && self
+                .iter()
+                .all(|(k, v)| other.contains_key(k) && other.get_by_key_id(k) == v)
+    }
+}
+
+impl<'v> fmt::Debug for SharedMap<'v> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SharedMap")
+            .field("map", &self.iter().collect::<HashMap<_, _>>())
+            .field("fields", &self.fields())
+            .finish()
+    }
+}
+
 impl<'v> Map for SharedMap<'v> {
 
     type Value = SharedValue<'v>;
+    type Ref<'a> = SharedValue<'v> where Self: 'a;
 
     fn new() -> Self {
         Self {
             map: HashMap::new(),
             fields: Vec::new(),
+            source: None,
         }
     }
     fn from_hash_map(map: HashMap<String, Self::Value>) -> Self {
-        let mut target_map: HashMap<_, SharedValue<'v>> = HashMap::new();
-        let fields = map.keys().cloned().collect();
+        let mut target_map = Self::new();
         for (key, value) in map {
-            target_map.insert(key_hash(&key), value);
-        }
-        Self {
-            map: target_map,
-            fields,
+            target_map.insert(&key, value);
         }
+        target_map
     }
     fn owned(&self) -> OwnedMap {
+        if let Some(source) = self.source {
+            return source.clone();
+        }
         OwnedMap {
             map: self.map.iter().map(|(k, v)| (*k, v.owned())).collect(),
             fields: self.fields.clone(),
         }
     }
+    // Names colliding on their key id replace each other's values, `try_insert` reports them
     fn insert<'a>(&mut self, key: &'a str, value: Self::Value) -> Option<Self::Value> {
-        self.fields.push(key.to_string());
-        self.insert_key_id(key_hash(key), value)
+        self.materialise();
+        let present = self.map.contains_key(&key_hash(key));
+        let id = insert_field(&mut self.fields, key, present);
+        self.insert_key_id(id, value)
+    }
+    fn try_insert<'a>(&mut self, key: &'a str, value: Self::Value) -> Result<Option<Self::Value>, String> {
+        self.materialise();
+        let present = self.map.contains_key(&key_hash(key));
+        let (id, new_field) = check_field(&self.fields, key, present)?;
+        if new_field {
+            self.fields.push(key.to_string());
+        }
+        Ok(self.insert_key_id(id, value))
     }
     fn insert_key_id(&mut self, key: u64, value: Self::Value) -> Option<Self::Value> {
+        self.materialise();
         self.map.insert(key, value)
     }
-    fn get_by_key_id(&self, key: u64) -> &Self::Value {
-        self.map.get(&key).unwrap_or(&Self::Value::Null)
+    fn get_by_key_id(&self, key: u64) -> Self::Value {
+        match self.source {
+            Some(source) => source.map.get(&key).map_or(Self::Value::Null, |v| v.shared()),
+            None => self.map.get(&key).cloned().unwrap_or(Self::Value::Null),
+        }
     }
     fn get_mut_by_key_id(&mut self, key: u64) -> &mut Self::Value {
+        self.materialise();
         self.map.entry(key).or_insert(Self::Value::Null)
     }
-    fn get<'a>(&self, key: &'a str) -> &Self::Value {
+    fn get<'a>(&self, key: &'a str) -> Self::Value {
         self.get_by_key_id(key_hash(key))
     }
     fn get_mut<'a>(&mut self, key: &'a str) -> &mut Self::Value {
@@ -62,7 +165,7 @@ impl<'v> Map for SharedMap<'v> {
     fn get_in_by_ids<'a, I: Iterator<Item = &'a u64> + ExactSizeIterator>(
         &self,
         mut key_ids: I,
-    ) -> &Self::Value {
+    ) -> Self::Value {
         let current_key = key_ids.next().cloned();
         if let Some(key) = current_key {
             let value = self.get_by_key_id(key);
@@ -70,14 +173,14 @@ impl<'v> Map for SharedMap<'v> {
                 return value;
             } else {
                 match value {
-                    &Self::Value::Map(ref map) => return map.get_in_by_ids(key_ids),
+                    Self::Value::Map(ref map) => return map.get_in_by_ids(key_ids),
                     _ => {}
                 }
             }
         }
-        return &Self::Value::Null;
+        return Self::Value::Null;
     }
-    fn get_in(&self, keys: &[&'static str]) -> &Self::Value {
+    fn get_in(&self, keys: &[&'static str]) -> Self::Value {
         self.get_in_by_ids(Self::strs_to_ids(keys).iter())
     }
     fn get_in_mut_by_key_ids(&mut self, mut keys_ids: Iter<u64>) -> Option<&mut Self::Value> {
@@ -141,22 +244,20 @@ impl<'v> Map for SharedMap<'v> {
         self.set_in_by_key_ids(Self::strs_to_ids(keys).iter(), value)
     }
     
-    fn into_string_map(self) -> HashMap<String, Self::Value> {
-        let mut id_map: HashMap<u64, String> = self
-            .fields
-            .into_iter()
-            .map(|field| (key_hash(&field), field))
-            .collect();
+    fn into_string_map(mut self) -> HashMap<String, Self::Value> {
+        self.materialise();
+        let fields = self.fields;
         self.map
             .into_iter()
-            .map(|(fid, value)| (id_map.remove(&fid), value))
-            .filter(|&(ref field, _)| field.is_some())
-            .map(|(field, value)| (field.unwrap(), value))
+            .filter_map(|(fid, value)| resolve_field_name(&fields, fid).map(|name| (name, value)))
             .collect()
     }
 
     fn len(&self) -> usize {
-        self.map.len()
+        match self.source {
+            Some(source) => source.map.len(),
+            None => self.map.len(),
+        }
     }
 
     fn shared<'a>(&'a self) -> SharedMap<'a> {
@@ -164,6 +265,13 @@ impl<'v> Map for SharedMap<'v> {
     }
 }
 
+// Borrowed views own nothing until they are materialised
+impl<'v> HeapSize for SharedMap<'v> {
+    fn heap_size(&self) -> usize {
+        self.map.heap_size() + self.fields.heap_size()
+    }
+}
+
 pub fn key_hash<'a>(key: &'a str) -> u64 {
     hash_str(key)
 }
@@ -171,3 +279,46 @@ pub fn key_hash<'a>(key: &'a str) -> u64 {
 pub fn key_hashes(keys: &Vec<String>) -> Vec<u64> {
     keys.iter().map(|str| hash_str(str)).collect()
 }
+
+#[cfg(test)]
+mod test {
+    use crate::types::*;
+
+    #[test]
+    fn borrowed_view() {
+        let mut inner = OwnedMap::new();
+        inner.insert_value("score", 9u32);
+        let mut map = OwnedMap::new();
+        map.insert_value("id", 1u64);
+        map.insert("inner", OwnedValue::Map(inner));
+        let view = map.shared();
+        assert!(view.is_borrowed());
+        assert_eq!(view.get("id"), SharedValue::U64(&1));
+        assert_eq!(view.get_in(&["inner", "score"]), SharedValue::U32(&9));
+        assert_eq!(view.get("missing"), SharedValue::Null);
+        assert_eq!(view.heap_size(), 0);
+        assert_eq!(view.len(), 2);
+        assert_eq!(view.owned(), map);
+
+        let mut updated = view.clone();
+        updated.insert("name", SharedValue::Null);
+        assert!(!updated.is_borrowed());
+        assert!(view.is_borrowed());
+        assert_eq!(updated.get("id"), view.get("id"));
+        assert_eq!(updated.len(), 3);
+        assert_ne!(updated, view);
+
+        let array = OwnedValue::Array(vec![OwnedValue::U8(1), OwnedValue::Map(map.clone())]);
+        let shared = array.shared();
+        match &shared {
+            SharedValue::Array(elements @ SharedArray::Borrowed(_)) => {
+                assert_eq!(elements.len(), 2);
+                assert_eq!(elements.get(0), Some(SharedValue::U8(&1)));
+                assert_eq!(elements.get(2), None);
+            }
+            other => panic!("Expect borrowed array, found {:?}", other),
+        }
+        assert_eq!(shared.index_of(1).map().unwrap().get("id"), SharedValue::U64(&1));
+        assert_eq!(shared.owned(), array);
+    }
+}
@@ -0,0 +1,455 @@
+use super::super::*;
+use bifrost_hasher::hash_bytes;
+use std::convert::TryFrom;
+use std::hash::Hasher;
+
+// Approximate aggregates over value hashes. Every sketch can be merged with another sketch
+// of the same kind and parameters, so partial results can be computed on different nodes.
+// Deserialized sketches are checked against their parameters before they can be used.
+
+pub const DEFAULT_HLL_PRECISION: u8 = 14;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, PartialOrd)]
+pub enum Sketch {
+    HyperLogLog(HyperLogLog),
+    CountMin(CountMin),
+    Bloom(BloomFilter),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, PartialOrd)]
+#[serde(try_from = "HyperLogLogParts")]
+pub struct HyperLogLog {
+    pub precision: u8,
+    pub registers: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, PartialOrd)]
+#[serde(try_from = "CountMinParts")]
+pub struct CountMin {
+    pub width: u32,
+    pub depth: u32,
+    pub counters: Vec<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, PartialOrd)]
+#[serde(try_from = "BloomFilterParts")]
+pub struct BloomFilter {
+    pub num_bits: u64,
+    pub num_hashes: u32,
+    pub bits: Vec<u64>,
+}
+
+#[derive(Deserialize)]
+struct HyperLogLogParts {
+    precision: u8,
+    registers: Vec<u8>,
+}
+
+#[derive(Deserialize)]
+struct CountMinParts {
+    width: u32,
+    depth: u32,
+    counters: Vec<u64>,
+}
+
+#[derive(Deserialize)]
+struct BloomFilterParts {
+    num_bits: u64,
+    num_hashes: u32,
+    bits: Vec<u64>,
+}
+
+impl TryFrom<HyperLogLogParts> for HyperLogLog {
+    type Error = String;
+
+    fn try_from(parts: HyperLogLogParts) -> Result<Self, String> {
+        let hll = Self {
+            precision: parts.precision,
+            registers: parts.registers,
+        };
+        hll.check()?;
+        Ok(hll)
+    }
+}
+
+impl TryFrom<CountMinParts> for CountMin {
+    type Error = String;
+
+    fn try_from(parts: CountMinParts) -> Result<Self, String> {
+        let cms = Self {
+            width: parts.width,
+            depth: parts.depth,
+            counters: parts.counters,
+        };
+        cms.check()?;
+        Ok(cms)
+    }
+}
+
+impl TryFrom<BloomFilterParts> for BloomFilter {
+    type Error = String;
+
+    fn try_from(parts: BloomFilterParts) -> Result<Self, String> {
+        let bloom = Self {
+            num_bits: parts.num_bits,
+            num_hashes: parts.num_hashes,
+            bits: parts.bits,
+        };
+        bloom.check()?;
+        Ok(bloom)
+    }
+}
+
+// Value hashes of primitives are their big endian bytes, rehash and mix them so that
+// every bit of the result is usable for register and bit selection
+pub fn sketch_hash(val: &SharedValue) -> u64 {
+    mix(hash_bytes(&val.hash()))
+}
+
+fn check_precision(precision: u8) -> Result<(), String> {
+    if !(4..=16).contains(&precision) {
+        return Err(format!(
+            "HyperLogLog precision should between 4 and 16, found {}",
+            precision
+        ));
+    }
+    Ok(())
+}
+
+// Finalizer of splitmix64
+fn mix(mut h: u64) -> u64 {
+    h = (h ^ (h >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    h = (h ^ (h >> 27)).wrapping_mul(0x94d049bb133111eb);
+    h ^ (h >> 31)
+}
+
+// Double hashing, derives the n-th hash from the mixed value of the first one
+fn nth_hash(hash: u64, n: u64, modulo: u64) -> u64 {
+    let h2 = mix(hash) | 1;
+    hash.wrapping_add(n.wrapping_mul(h2)) % modulo
+}
+
+impl HyperLogLog {
+    pub fn new(precision: u8) -> Result<Self, String> {
+        check_precision(precision)?;
+        Ok(Self {
+            precision,
+            registers: vec![0; 1 << precision],
+        })
+    }
+
+    // Registers should match the precision
+    pub fn check(&self) -> Result<(), String> {
+        check_precision(self.precision)?;
+        if self.registers.len() != 1 << self.precision {
+            return Err(format!(
+                "HyperLogLog of precision {} should have {} registers, found {}",
+                self.precision,
+                1 << self.precision,
+                self.registers.len()
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn add_hash(&mut self, hash: u64) {
+        let p = self.precision as u32;
+        let index = (hash >> (64 - p)) as usize;
+        // Guard bit makes sure the rank never exceeds 64 - p + 1
+        let rest = (hash << p) | (1 << (p - 1));
+        let rank = rest.leading_zeros() as u8 + 1;
+        if rank > self.registers[index] {
+            self.registers[index] = rank;
+        }
+    }
+
+    pub fn count(&self) -> u64 {
+        let m = self.registers.len() as f64;
+        let alpha = match self.registers.len() {
+            16 => 0.673,
+            32 => 0.697,
+            64 => 0.709,
+            _ => 0.7213 / (1.0 + 1.079 / m),
+        };
+        let sum: f64 = self.registers.iter().map(|r| 2f64.powi(-(*r as i32))).sum();
+        let estimate = alpha * m * m / sum;
+        let zeros = self.registers.iter().filter(|r| **r == 0).count();
+        if estimate <= 2.5 * m && zeros > 0 {
+            // Linear counting for small cardinalities
+            return (m * (m / zeros as f64).ln()).round() as u64;
+        }
+        estimate.round() as u64
+    }
+
+    pub fn merge(&mut self, other: &Self) -> Result<(), String> {
+        self.check()?;
+        other.check()?;
+        if self.precision != other.precision {
+            return Err(format!(
+                "Cannot merge HyperLogLog with precision {} and {}",
+                self.precision, other.precision
+            ));
+        }
+        for (r, o) in self.registers.iter_mut().zip(&other.registers) {
+            if *o > *r {
+                *r = *o;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl CountMin {
+    pub fn new(width: u32, depth: u32) -> Result<Self, String> {
+        if width == 0 || depth == 0 {
+            return Err(format!(
+                "Count-Min width and depth should be positive, found {} and {}",
+                width, depth
+            ));
+        }
+        Ok(Self {
+            width,
+            depth,
+            counters: vec![0; width as usize * depth as usize],
+        })
+    }
+
+    // Counters should fill every row
+    pub fn check(&self) -> Result<(), String> {
+        let cells = (self.width as usize).checked_mul(self.depth as usize);
+        if self.width == 0 || self.depth == 0 || cells != Some(self.counters.len()) {
+            return Err(format!(
+                "Count-Min of {}x{} cannot have {} counters",
+                self.width,
+                self.depth,
+                self.counters.len()
+            ));
+        }
+        Ok(())
+    }
+
+    fn cell(&self, hash: u64, row: u32) -> usize {
+        row as usize * self.width as usize + nth_hash(hash, row as u64, self.width as u64) as usize
+    }
+
+    pub fn add_hash(&mut self, hash: u64, count: u64) {
+        for row in 0..self.depth {
+            let cell = self.cell(hash, row);
+            self.counters[cell] = self.counters[cell].saturating_add(count);
+        }
+    }
+
+    pub fn estimate(&self, hash: u64) -> u64 {
+        (0..self.depth)
+            .map(|row| self.counters[self.cell(hash, row)])
+            .min()
+            .unwrap_or(0)
+    }
+
+    pub fn merge(&mut self, other: &Self) -> Result<(), String> {
+        self.check()?;
+        other.check()?;
+        if self.width != other.width || self.depth != other.depth {
+            return Err(format!(
+                "Cannot merge Count-Min of {}x{} and {}x{}",
+                self.width, self.depth, other.width, other.depth
+            ));
+        }
+        for (c, o) in self.counters.iter_mut().zip(&other.counters) {
+            *c = c.saturating_add(*o);
+        }
+        Ok(())
+    }
+}
+
+impl BloomFilter {
+    pub fn new(num_bits: u64, num_hashes: u32) -> Result<Self, String> {
+        if num_bits == 0 || num_hashes == 0 {
+            return Err(format!(
+                "Bloom filter bits and hashes should be positive, found {} and {}",
+                num_bits, num_hashes
+            ));
+        }
+        let words = ((num_bits + 63) / 64) as usize;
+        Ok(Self {
+            num_bits: words as u64 * 64,
+            num_hashes,
+            bits: vec![0; words],
+        })
+    }
+
+    // Words should hold exactly the bits
+    pub fn check(&self) -> Result<(), String> {
+        let words = (self.num_bits + 63) / 64;
+        if self.num_bits == 0 || self.num_hashes == 0 || words != self.bits.len() as u64 {
+            return Err(format!(
+                "Bloom filter of {} bits and {} hashes cannot have {} words",
+                self.num_bits,
+                self.num_hashes,
+                self.bits.len()
+            ));
+        }
+        Ok(())
+    }
+
+    // Size the filter for the expected number of items and false positive rate
+    pub fn with_rate(expected: u64, fpp: f64) -> Result<Self, String> {
+        if expected == 0 || !(fpp > 0.0 && fpp < 1.0) {
+            return Err(format!(
+                "Invalid bloom filter capacity {} or false positive rate {}",
+                expected, fpp
+            ));
+        }
+        let ln2 = 2f64.ln();
+        let num_bits = (-(expected as f64) * fpp.ln() / (ln2 * ln2)).ceil() as u64;
+        let num_hashes = ((num_bits as f64 / expected as f64) * ln2).round().max(1.0) as u32;
+        Self::new(num_bits, num_hashes)
+    }
+
+    pub fn add_hash(&mut self, hash: u64) {
+        for n in 0..self.num_hashes {
+            let bit = nth_hash(hash, n as u64, self.num_bits);
+            self.bits[(bit / 64) as usize] |= 1 << (bit % 64);
+        }
+    }
+
+    pub fn contains_hash(&self, hash: u64) -> bool {
+        (0..self.num_hashes).all(|n| {
+            let bit = nth_hash(hash, n as u64, self.num_bits);
+            self.bits[(bit / 64) as usize] & (1 << (bit % 64)) != 0
+        })
+    }
+
+    pub fn merge(&mut self, other: &Self) -> Result<(), String> {
+        self.check()?;
+        other.check()?;
+        if self.num_bits != other.num_bits || self.num_hashes != other.num_hashes {
+            return Err(format!(
+                "Cannot merge bloom filter of {} bits {} hashes and {} bits {} hashes",
+                self.num_bits, self.num_hashes, other.num_bits, other.num_hashes
+            ));
+        }
+        for (b, o) in self.bits.iter_mut().zip(&other.bits) {
+            *b |= *o;
+        }
+        Ok(())
+    }
+}
+
+impl Sketch {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Sketch::HyperLogLog(_) => "hyperloglog",
+            Sketch::CountMin(_) => "count-min",
+            Sketch::Bloom(_) => "bloom",
+        }
+    }
+
+    pub fn add_hash(&mut self, hash: u64) {
+        match self {
+            Sketch::HyperLogLog(hll) => hll.add_hash(hash),
+            Sketch::CountMin(cms) => cms.add_hash(hash, 1),
+            Sketch::Bloom(bloom) => bloom.add_hash(hash),
+        }
+    }
+
+    pub fn add(&mut self, val: &SharedValue) {
+        self.add_hash(sketch_hash(val))
+    }
+
+    pub fn hash(&self) -> [u8; 8] {
+        let mut hasher = twox_hash::XxHash::default();
+        hasher.write(self.kind().as_bytes());
+        match self {
+            Sketch::HyperLogLog(hll) => {
+                hasher.write_u8(hll.precision);
+                hasher.write(&hll.registers);
+            }
+            Sketch::CountMin(cms) => {
+                hasher.write_u32(cms.width);
+                hasher.write_u32(cms.depth);
+                cms.counters.iter().for_each(|c| hasher.write_u64(*c));
+            }
+            Sketch::Bloom(bloom) => {
+                hasher.write_u64(bloom.num_bits);
+                hasher.write_u32(bloom.num_hashes);
+                bloom.bits.iter().for_each(|b| hasher.write_u64(*b));
+            }
+        }
+        u64_io::feature(&hasher.finish())
+    }
+
+    pub fn merge(&mut self, other: &Sketch) -> Result<(), String> {
+        match (self, other) {
+            (Sketch::HyperLogLog(a), Sketch::HyperLogLog(b)) => a.merge(b),
+            (Sketch::CountMin(a), Sketch::CountMin(b)) => a.merge(b),
+            (Sketch::Bloom(a), Sketch::Bloom(b)) => a.merge(b),
+            (a, b) => Err(format!("Cannot merge {} with {}", a.kind(), b.kind())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::types::*;
+    use bifrost::utils::serde::{deserialize, serialize};
+
+    #[test]
+    fn sketches() {
+        let mut a = HyperLogLog::new(12).unwrap();
+        let mut b = HyperLogLog::new(12).unwrap();
+        for i in 0..5000u64 {
+            a.add_hash(sketch_hash(&OwnedValue::U64(i).shared()));
+            b.add_hash(sketch_hash(&OwnedValue::U64(i + 2500).shared()));
+        }
+        a.merge(&b).unwrap();
+        let count = a.count() as f64;
+        assert!((count - 7500.0).abs() / 7500.0 < 0.05, "{}", count);
+        assert!(a.merge(&HyperLogLog::new(10).unwrap()).is_err());
+
+        let mut cms = CountMin::new(256, 4).unwrap();
+        let x = sketch_hash(&OwnedValue::String("x".to_string()).shared());
+        cms.add_hash(x, 3);
+        cms.add_hash(x, 2);
+        assert!(cms.estimate(x) >= 5);
+
+        let mut bloom = BloomFilter::with_rate(100, 0.01).unwrap();
+        for i in 0..100u32 {
+            bloom.add_hash(sketch_hash(&OwnedValue::U32(i).shared()));
+        }
+        assert!(bloom.contains_hash(sketch_hash(&OwnedValue::U32(42).shared())));
+        let false_positives = (1000..2000u32)
+            .filter(|i| bloom.contains_hash(sketch_hash(&OwnedValue::U32(*i).shared())))
+            .count();
+        assert!(false_positives < 50);
+    }
+
+    #[test]
+    fn invalid_sketches() {
+        let hll = Sketch::HyperLogLog(HyperLogLog::new(4).unwrap());
+        let data = serialize(&hll);
+        assert_eq!(deserialize::<Sketch>(&data).unwrap(), hll);
+        let bad = Sketch::HyperLogLog(HyperLogLog {
+            precision: 10,
+            registers: vec![0; 16],
+        });
+        let data = serialize(&bad);
+        assert!(deserialize::<Sketch>(&data).is_none());
+        let bad = CountMin {
+            width: 4,
+            depth: 4,
+            counters: vec![0; 3],
+        };
+        let data = serialize(&bad);
+        assert!(deserialize::<CountMin>(&data).is_none());
+        let mut bloom = BloomFilter::new(64, 2).unwrap();
+        let bad = BloomFilter {
+            num_bits: 64,
+            num_hashes: 2,
+            bits: vec![],
+        };
+        assert!(bloom.merge(&bad).is_err());
+        let data = serialize(&bad);
+        assert!(deserialize::<BloomFilter>(&data).is_none());
+    }
+}
@@ -0,0 +1,810 @@
+use super::super::*;
+use std::ops::{Add, Div, Mul, Sub};
+
+// Encoded tensor header: element type id, number of dimensions, padding and u32 for each dimension.
+// The header is padded to 8 bytes so the elements that follow stay aligned.
+const TENSOR_HEADER_ALIGN: usize = 8;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, PartialOrd)]
+#[serde(try_from = "TensorLayoutParts")]
+pub struct TensorLayout {
+    shape: Vec<usize>,
+    strides: Vec<usize>,
+    offset: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, PartialOrd)]
+#[serde(try_from = "OwnedTensorParts")]
+pub struct OwnedTensor {
+    data: OwnedPrimArray,
+    layout: TensorLayout,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SharedTensor<'a> {
+    data: SharedPrimArray<'a>,
+    layout: TensorLayout,
+}
+
+#[derive(Deserialize)]
+struct TensorLayoutParts {
+    shape: Vec<usize>,
+    strides: Vec<usize>,
+    offset: usize,
+}
+
+#[derive(Deserialize)]
+struct OwnedTensorParts {
+    data: OwnedPrimArray,
+    layout: TensorLayout,
+}
+
+impl TryFrom<TensorLayoutParts> for TensorLayout {
+    type Error = String;
+
+    fn try_from(parts: TensorLayoutParts) -> Result<Self, String> {
+        if parts.shape.len() != parts.strides.len() {
+            return Err(format!(
+                "Tensor of shape {:?} has strides {:?}",
+                parts.shape, parts.strides
+            ));
+        }
+        shape_len(&parts.shape)?;
+        Ok(Self {
+            shape: parts.shape,
+            strides: parts.strides,
+            offset: parts.offset,
+        })
+    }
+}
+
+impl TryFrom<OwnedTensorParts> for OwnedTensor {
+    type Error = String;
+
+    fn try_from(parts: OwnedTensorParts) -> Result<Self, String> {
+        SharedTensor::new(parts.data.shared(), parts.layout.clone())?;
+        Ok(Self {
+            data: parts.data,
+            layout: parts.layout,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TensorOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TensorReduce {
+    Sum,
+    Min,
+    Max,
+    Mean,
+}
+
+pub trait TensorElement:
+    Copy
+    + PartialOrd
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+{
+    fn as_f64(self) -> f64;
+    fn zero() -> Self;
+    // Integer results out of range and division by zero are None
+    fn checked_add(self, other: Self) -> Option<Self>;
+    fn checked_sub(self, other: Self) -> Option<Self>;
+    fn checked_mul(self, other: Self) -> Option<Self>;
+    fn checked_div(self, other: Self) -> Option<Self>;
+    fn slice_of<'a>(arr: &SharedPrimArray<'a>) -> Option<&'a [Self]>;
+    fn from_value(val: &SharedValue) -> Option<Self>;
+    fn into_prim_array(vec: Vec<Self>) -> OwnedPrimArray;
+    fn into_value(self) -> OwnedValue;
+}
+
+macro_rules! checked_ops {
+    (int, $t: ty) => {
+        fn checked_add(self, other: Self) -> Option<Self> {
+            <$t>::checked_add(self, other)
+        }
+        fn checked_sub(self, other: Self) -> Option<Self> {
+            <$t>::checked_sub(self, other)
+        }
+        fn checked_mul(self, other: Self) -> Option<Self> {
+            <$t>::checked_mul(self, other)
+        }
+        fn checked_div(self, other: Self) -> Option<Self> {
+            <$t>::checked_div(self, other)
+        }
+    };
+    (float, $t: ty) => {
+        fn checked_add(self, other: Self) -> Option<Self> {
+            Some(self + other)
+        }
+        fn checked_sub(self, other: Self) -> Option<Self> {
+            Some(self - other)
+        }
+        fn checked_mul(self, other: Self) -> Option<Self> {
+            Some(self * other)
+        }
+        fn checked_div(self, other: Self) -> Option<Self> {
+            Some(self / other)
+        }
+    };
+}
+
+macro_rules! tensor_elements {
+    ($($e: ident, $t: ty, $kind: ident);*) => {
+        $(
+            impl TensorElement for $t {
+                fn as_f64(self) -> f64 {
+                    self as f64
+                }
+                fn zero() -> Self {
+                    0 as $t
+                }
+                checked_ops!($kind, $t);
+                fn slice_of<'a>(arr: &SharedPrimArray<'a>) -> Option<&'a [Self]> {
+                    match arr {
+                        SharedPrimArray::$e(slice) => Some(*slice),
+                        _ => None,
+                    }
+                }
+                fn from_value(val: &SharedValue) -> Option<Self> {
+                    match val {
+                        SharedValue::$e(v) => Some(**v),
+                        _ => None,
+                    }
+                }
+                fn into_prim_array(vec: Vec<Self>) -> OwnedPrimArray {
+                    OwnedPrimArray::$e(vec)
+                }
+                fn into_value(self) -> OwnedValue {
+                    OwnedValue::$e(self)
+                }
+            }
+        )*
+    };
+}
+
+tensor_elements!(
+    U8, u8, int;
+    U16, u16, int;
+    U32, u32, int;
+    U64, u64, int;
+    I8, i8, int;
+    I16, i16, int;
+    I32, i32, int;
+    I64, i64, int;
+    F32, f32, float;
+    F64, f64, float
+);
+
+// Number of elements of a shape, an error if it does not fit in usize
+fn shape_len(shape: &[usize]) -> Result<usize, String> {
+    if shape.contains(&0) {
+        return Ok(0);
+    }
+    shape
+        .iter()
+        .try_fold(1usize, |len, dim| len.checked_mul(*dim))
+        .ok_or_else(|| format!("Tensor of shape {:?} has too many elements", shape))
+}
+
+impl TensorLayout {
+    pub fn contiguous(shape: Vec<usize>) -> Result<Self, String> {
+        shape_len(&shape)?;
+        Ok(Self::packed(shape))
+    }
+    // Layout of a shape whose elements exist, so its size is known to fit
+    fn packed(shape: Vec<usize>) -> Self {
+        let mut strides = vec![0; shape.len()];
+        let mut stride = 1usize;
+        for (axis, dim) in shape.iter().enumerate().rev() {
+            strides[axis] = stride;
+            // Only empty shapes saturate, no stride of theirs is ever followed
+            stride = stride.saturating_mul(*dim);
+        }
+        Self {
+            shape,
+            strides,
+            offset: 0,
+        }
+    }
+    pub fn shape(&self) -> &Vec<usize> {
+        &self.shape
+    }
+    pub fn strides(&self) -> &Vec<usize> {
+        &self.strides
+    }
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+    pub fn ndim(&self) -> usize {
+        self.shape.len()
+    }
+    // Saturates, layouts of larger shapes are rejected when tensors are built
+    pub fn len(&self) -> usize {
+        self.shape
+            .iter()
+            .fold(1usize, |len, dim| len.saturating_mul(*dim))
+    }
+    pub fn is_contiguous(&self) -> bool {
+        let mut expected = 1usize;
+        for (dim, stride) in self.shape.iter().zip(self.strides.iter()).rev() {
+            if *dim != 1 && *stride != expected {
+                return false;
+            }
+            expected = expected.saturating_mul(*dim);
+        }
+        true
+    }
+    pub fn position(&self, index: &[usize]) -> Option<usize> {
+        if index.len() != self.ndim() {
+            return None;
+        }
+        let mut pos = self.offset;
+        for ((i, dim), stride) in index.iter().zip(&self.shape).zip(&self.strides) {
+            if i >= dim {
+                return None;
+            }
+            pos += i * stride;
+        }
+        Some(pos)
+    }
+    // Buffer positions of every element, in row-major order of the logical shape
+    pub fn positions(&self) -> Vec<usize> {
+        let len = self.len();
+        if self.is_contiguous() {
+            return (self.offset..self.offset + len).collect();
+        }
+        let ndim = self.ndim();
+        let mut res = Vec::with_capacity(len);
+        let mut index = vec![0usize; ndim];
+        let mut pos = self.offset;
+        for _ in 0..len {
+            res.push(pos);
+            let mut axis = ndim;
+            while axis > 0 {
+                axis -= 1;
+                index[axis] += 1;
+                pos += self.strides[axis];
+                if index[axis] < self.shape[axis] {
+                    break;
+                }
+                pos -= self.strides[axis] * index[axis];
+                index[axis] = 0;
+            }
+        }
+        res
+    }
+    pub fn slice(&self, axis: usize, start: usize, end: usize) -> Result<Self, String> {
+        if axis >= self.ndim() {
+            return Err(format!(
+                "Axis {} out of bound for tensor with {} dimensions",
+                axis,
+                self.ndim()
+            ));
+        }
+        if start > end || end > self.shape[axis] {
+            return Err(format!(
+                "Invalid slice range {}..{} for dimension of size {}",
+                start, end, self.shape[axis]
+            ));
+        }
+        let mut layout = self.clone();
+        layout.offset += start * self.strides[axis];
+        layout.shape[axis] = end - start;
+        Ok(layout)
+    }
+    pub fn transpose(&self, perm: Option<&[usize]>) -> Result<Self, String> {
+        let ndim = self.ndim();
+        let perm: Vec<usize> = match perm {
+            Some(perm) => perm.to_vec(),
+            None => (0..ndim).rev().collect(),
+        };
+        let mut seen = vec![false; ndim];
+        if perm.len() != ndim {
+            return Err(format!(
+                "Transpose permutation {:?} does not match {} dimensions",
+                perm, ndim
+            ));
+        }
+        for axis in &perm {
+            if *axis >= ndim || seen[*axis] {
+                return Err(format!("Invalid transpose permutation {:?}", perm));
+            }
+            seen[*axis] = true;
+        }
+        Ok(Self {
+            shape: perm.iter().map(|axis| self.shape[*axis]).collect(),
+            strides: perm.iter().map(|axis| self.strides[*axis]).collect(),
+            offset: self.offset,
+        })
+    }
+    pub fn reshape(&self, shape: Vec<usize>) -> Result<Self, String> {
+        if shape_len(&shape)? != self.len() {
+            return Err(format!(
+                "Cannot reshape tensor of shape {:?} into {:?}",
+                self.shape, shape
+            ));
+        }
+        if !self.is_contiguous() {
+            return Err("Cannot reshape a non-contiguous tensor view".to_string());
+        }
+        let mut layout = Self::packed(shape);
+        layout.offset = self.offset;
+        Ok(layout)
+    }
+    fn check_buffer(&self, buffer_len: usize) -> Result<(), String> {
+        if self.strides.len() != self.shape.len() {
+            return Err(format!(
+                "Tensor of shape {:?} has strides {:?}",
+                self.shape, self.strides
+            ));
+        }
+        if shape_len(&self.shape)? == 0 {
+            return Ok(());
+        }
+        let last =
+            self.shape
+                .iter()
+                .zip(&self.strides)
+                .try_fold(self.offset, |last, (dim, stride)| {
+                    (dim - 1)
+                        .checked_mul(*stride)
+                        .and_then(|step| last.checked_add(step))
+                });
+        if !matches!(last, Some(last) if last < buffer_len) {
+            return Err(format!(
+                "Tensor layout {:?} exceeds buffer of {} elements",
+                self.shape, buffer_len
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn header_size(ndim: usize) -> usize {
+    let raw = u8_io::type_size() * 2 + 2 + u32_io::type_size() * ndim;
+    (raw + TENSOR_HEADER_ALIGN - 1) / TENSOR_HEADER_ALIGN * TENSOR_HEADER_ALIGN
+}
+
+fn gather<T: Copy>(data: &[T], layout: &TensorLayout) -> Vec<T> {
+    layout
+        .positions()
+        .into_iter()
+        .map(|pos| data[pos])
+        .collect()
+}
+
+fn apply<T: TensorElement>(op: TensorOp, a: T, b: T) -> Result<T, String> {
+    let res = match op {
+        TensorOp::Add => a.checked_add(b),
+        TensorOp::Sub => a.checked_sub(b),
+        TensorOp::Mul => a.checked_mul(b),
+        TensorOp::Div => a.checked_div(b),
+    };
+    res.ok_or_else(|| match op {
+        TensorOp::Div => "Tensor division by zero or overflow".to_string(),
+        op => format!("Tensor {:?} overflow", op),
+    })
+}
+
+fn binary_typed<T: TensorElement>(
+    a: &[T],
+    a_layout: &TensorLayout,
+    b: &SharedTensor,
+    op: TensorOp,
+) -> Result<OwnedTensor, String> {
+    if a_layout.shape != b.layout.shape {
+        return Err(format!(
+            "Tensor shape not match, {:?} and {:?}",
+            a_layout.shape, b.layout.shape
+        ));
+    }
+    let b_data = T::slice_of(&b.data).ok_or_else(|| {
+        format!(
+            "Tensor element type not match, found {}",
+            get_type(b.data.base_type())
+        )
+    })?;
+    let mut res = Vec::with_capacity(a_layout.len());
+    for (pa, pb) in a_layout.positions().into_iter().zip(b.layout.positions()) {
+        res.push(apply(op, a[pa], b_data[pb])?);
+    }
+    Ok(OwnedTensor::contiguous(
+        T::into_prim_array(res),
+        a_layout.shape.clone(),
+    ))
+}
+
+fn scalar_typed<T: TensorElement>(
+    a: &[T],
+    layout: &TensorLayout,
+    scalar: &SharedValue,
+    op: TensorOp,
+) -> Result<OwnedTensor, String> {
+    let scalar = T::from_value(scalar)
+        .ok_or_else(|| format!("Scalar {:?} does not match tensor element type", scalar))?;
+    let mut res = Vec::with_capacity(layout.len());
+    for pos in layout.positions() {
+        res.push(apply(op, a[pos], scalar)?);
+    }
+    Ok(OwnedTensor::contiguous(
+        T::into_prim_array(res),
+        layout.shape.clone(),
+    ))
+}
+
+// The sum of no elements is zero, they have no minimum, maximum or mean
+pub(crate) fn reduce_values<T: TensorElement>(
+    values: &[T],
+    op: TensorReduce,
+) -> Result<OwnedValue, String> {
+    let (first, rest) = match (values.split_first(), op) {
+        (Some(split), _) => split,
+        (None, TensorReduce::Sum) => return Ok(T::zero().into_value()),
+        (None, op) => return Err(format!("Cannot take {:?} of no elements", op)),
+    };
+    let mut acc = *first;
+    match op {
+        TensorReduce::Sum => {
+            for v in rest {
+                acc = apply(TensorOp::Add, acc, *v)?;
+            }
+        }
+        TensorReduce::Min => rest.iter().for_each(|v| {
+            if *v < acc {
+                acc = *v
+            }
+        }),
+        TensorReduce::Max => rest.iter().for_each(|v| {
+            if *v > acc {
+                acc = *v
+            }
+        }),
+        TensorReduce::Mean => {
+            let sum: f64 = values.iter().map(|v| v.as_f64()).sum();
+            return Ok(OwnedValue::F64(sum / values.len() as f64));
+        }
+    }
+    Ok(acc.into_value())
+}
+
+fn reduce_typed<T: TensorElement>(
+    data: &[T],
+    layout: &TensorLayout,
+    op: TensorReduce,
+    axis: Option<usize>,
+) -> Result<OwnedValue, String> {
+    let axis = match axis {
+        None => return reduce_values(&gather(data, layout), op),
+        Some(axis) => axis,
+    };
+    if axis >= layout.ndim() {
+        return Err(format!(
+            "Axis {} out of bound for tensor with {} dimensions",
+            axis,
+            layout.ndim()
+        ));
+    }
+    // Move the reduced axis to the end so every run of `dim` gathered elements forms one group
+    let mut perm: Vec<usize> = (0..layout.ndim()).filter(|a| *a != axis).collect();
+    perm.push(axis);
+    let moved = layout.transpose(Some(&perm))?;
+    let dim = layout.shape[axis];
+    let values = gather(data, &moved);
+    let mut shape = layout.shape.clone();
+    shape.remove(axis);
+    let mut res = Vec::new();
+    for group in 0..shape_len(&shape)? {
+        res.push(reduce_values(&values[group * dim..(group + 1) * dim], op)?);
+    }
+    let res = match (res.is_empty(), op) {
+        (false, _) => OwnedPrimArray::from_values(res)?,
+        (true, TensorReduce::Mean) => OwnedPrimArray::F64(vec![]),
+        (true, _) => T::into_prim_array(vec![]),
+    };
+    Ok(OwnedValue::Tensor(OwnedTensor::contiguous(res, shape)))
+}
+
+impl OwnedTensor {
+    pub fn new(data: OwnedPrimArray, shape: Vec<usize>) -> Result<Self, String> {
+        let layout = TensorLayout::contiguous(shape)?;
+        if layout.len() != data.len() {
+            return Err(format!(
+                "Tensor of shape {:?} need {} elements, found {}",
+                layout.shape,
+                layout.len(),
+                data.len()
+            ));
+        }
+        numeric_dispatch!(data.shared(), _slice => Ok(()))?;
+        Ok(Self { data, layout })
+    }
+    fn contiguous(data: OwnedPrimArray, shape: Vec<usize>) -> Self {
+        Self {
+            data,
+            layout: TensorLayout::packed(shape),
+        }
+    }
+    pub fn shared<'a>(&'a self) -> SharedTensor<'a> {
+        SharedTensor {
+            data: self.data.shared(),
+            layout: self.layout.clone(),
+        }
+    }
+    // Buffer the layout views, elements outside the view are kept
+    pub fn data(&self) -> &OwnedPrimArray {
+        &self.data
+    }
+    pub fn into_data(self) -> OwnedPrimArray {
+        self.data
+    }
+    pub fn layout(&self) -> &TensorLayout {
+        &self.layout
+    }
+    pub fn shape(&self) -> &Vec<usize> {
+        &self.layout.shape
+    }
+    pub fn len(&self) -> usize {
+        self.layout.len()
+    }
+    pub fn reshape(self, shape: Vec<usize>) -> Result<Self, String> {
+        let tensor = if self.layout.is_contiguous() {
+            self
+        } else {
+            self.shared().owned()
+        };
+        let layout = tensor.layout.reshape(shape)?;
+        Ok(Self {
+            data: tensor.data,
+            layout,
+        })
+    }
+    pub fn transpose(self, perm: Option<&[usize]>) -> Result<Self, String> {
+        let layout = self.layout.transpose(perm)?;
+        Ok(Self {
+            data: self.data,
+            layout,
+        })
+    }
+    pub fn slice(self, axis: usize, start: usize, end: usize) -> Result<Self, String> {
+        let layout = self.layout.slice(axis, start, end)?;
+        Ok(Self {
+            data: self.data,
+            layout,
+        })
+    }
+}
+
+impl<'a> SharedTensor<'a> {
+    pub fn new(data: SharedPrimArray<'a>, layout: TensorLayout) -> Result<Self, String> {
+        layout.check_buffer(data.len())?;
+        numeric_dispatch!(&data, _slice => Ok(()))?;
+        Ok(Self { data, layout })
+    }
+    pub fn from_prim_array(data: SharedPrimArray<'a>) -> Result<Self, String> {
+        let layout = TensorLayout::packed(vec![data.len()]);
+        Self::new(data, layout)
+    }
+    pub fn data(&self) -> &SharedPrimArray<'a> {
+        &self.data
+    }
+    pub fn layout(&self) -> &TensorLayout {
+        &self.layout
+    }
+    pub fn shape(&self) -> &Vec<usize> {
+        &self.layout.shape
+    }
+    pub fn ndim(&self) -> usize {
+        self.layout.ndim()
+    }
+    pub fn len(&self) -> usize {
+        self.layout.len()
+    }
+    pub fn get(&self, index: &[usize]) -> Option<OwnedValue> {
+        let pos = self.layout.position(index)?;
+        numeric_dispatch!(&self.data, slice => Ok(slice[pos].into_value())).ok()
+    }
+    pub fn slice(&self, axis: usize, start: usize, end: usize) -> Result<Self, String> {
+        Ok(Self {
+            data: self.data.clone(),
+            layout: self.layout.slice(axis, start, end)?,
+        })
+    }
+    pub fn transpose(&self, perm: Option<&[usize]>) -> Result<Self, String> {
+        Ok(Self {
+            data: self.data.clone(),
+            layout: self.layout.transpose(perm)?,
+        })
+    }
+    pub fn reshape(&self, shape: Vec<usize>) -> Result<Self, String> {
+        Ok(Self {
+            data: self.data.clone(),
+            layout: self.layout.reshape(shape)?,
+        })
+    }
+    // Compact the viewed elements into a new contiguous tensor
+    pub fn owned(&self) -> OwnedTensor {
+        let data = self.data.gather(&self.layout.positions());
+        OwnedTensor::contiguous(data, self.layout.shape.clone())
+    }
+    pub fn features(&self) -> Vec<[u8; 8]> {
+        self.owned().data.features()
+    }
+    pub fn hashes(&self) -> Vec<[u8; 8]> {
+        self.owned().data.hashes()
+    }
+    pub fn binary(&self, other: &SharedTensor, op: TensorOp) -> Result<OwnedTensor, String> {
+        numeric_dispatch!(&self.data, slice => binary_typed(slice, &self.layout, other, op))
+    }
+    pub fn scalar(&self, scalar: &SharedValue, op: TensorOp) -> Result<OwnedTensor, String> {
+        numeric_dispatch!(&self.data, slice => scalar_typed(slice, &self.layout, scalar, op))
+    }
+    pub fn reduce(&self, op: TensorReduce, axis: Option<usize>) -> Result<OwnedValue, String> {
+        numeric_dispatch!(&self.data, slice => reduce_typed(slice, &self.layout, op, axis))
+    }
+    pub fn encoded_size(&self) -> usize {
+        header_size(self.ndim()) + self.len() * size_of_type(self.data.base_type())
+    }
+    // Checked before anything is written, the header has a byte for the number of
+    // dimensions and u32 for each of them
+    pub fn write(&self, mem_ptr: usize) -> Result<(), String> {
+        let ndim = u8::try_from(self.ndim())
+            .map_err(|_| format!("Tensor of {} dimensions cannot be encoded", self.ndim()))?;
+        let dims = self
+            .shape()
+            .iter()
+            .map(|dim| u32::try_from(*dim))
+            .collect::<Result<Vec<u32>, _>>()
+            .map_err(|_| format!("Tensor of shape {:?} cannot be encoded", self.shape()))?;
+        let t = self.data.base_type();
+        u8_io::write(&t.id(), mem_ptr);
+        u8_io::write(&ndim, mem_ptr + u8_io::type_size());
+        let mut dim_ptr = mem_ptr + u8_io::type_size() * 2 + 2;
+        for dim in &dims {
+            u32_io::write(dim, dim_ptr);
+            dim_ptr += u32_io::type_size();
+        }
+        let compact = OwnedValue::PrimArray(self.owned().data);
+        set_val(t, &compact, mem_ptr + header_size(self.ndim()));
+        Ok(())
+    }
+    pub fn read(mem_ptr: usize) -> Self {
+        let t = Type::from_id(*u8_io::read(mem_ptr));
+        let ndim = *u8_io::read(mem_ptr + u8_io::type_size()) as usize;
+        let dims_ptr = mem_ptr + u8_io::type_size() * 2 + 2;
+        let shape = (0..ndim)
+            .map(|i| *u32_io::read(dims_ptr + i * u32_io::type_size()) as usize)
+            .collect();
+        // Written from a tensor whose elements existed
+        let layout = TensorLayout::packed(shape);
+        let mut data_ptr = mem_ptr + header_size(ndim);
+        let data = get_shared_prim_array_val(t, layout.len(), &mut data_ptr)
+            .expect("Tensor element type is not a primitive type");
+        Self { data, layout }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::types::*;
+    use bifrost::utils::serde::{deserialize, serialize};
+
+    fn matrix() -> OwnedTensor {
+        OwnedTensor::new(OwnedPrimArray::U32(vec![1, 2, 3, 4, 5, 6]), vec![2, 3]).unwrap()
+    }
+
+    #[test]
+    fn views() {
+        let tensor = matrix();
+        let shared = tensor.shared();
+        let transposed = shared.transpose(None).unwrap();
+        assert_eq!(transposed.shape(), &vec![3, 2]);
+        assert_eq!(transposed.get(&[2, 1]), Some(OwnedValue::U32(6)));
+        assert!(transposed.reshape(vec![6]).is_err());
+        assert_eq!(
+            transposed.owned().data,
+            OwnedPrimArray::U32(vec![1, 4, 2, 5, 3, 6])
+        );
+        let column = shared.slice(1, 1, 2).unwrap();
+        assert_eq!(column.owned().data, OwnedPrimArray::U32(vec![2, 5]));
+    }
+
+    #[test]
+    fn arithmetic() {
+        let tensor = matrix();
+        let shared = tensor.shared();
+        let doubled = shared.binary(&shared, TensorOp::Add).unwrap();
+        assert_eq!(doubled.data, OwnedPrimArray::U32(vec![2, 4, 6, 8, 10, 12]));
+        let scaled = shared
+            .scalar(&SharedValue::U32(&10), TensorOp::Mul)
+            .unwrap();
+        assert_eq!(
+            scaled.data,
+            OwnedPrimArray::U32(vec![10, 20, 30, 40, 50, 60])
+        );
+        assert_eq!(
+            shared.reduce(TensorReduce::Sum, None).unwrap(),
+            OwnedValue::U32(21)
+        );
+        let col_max = shared.reduce(TensorReduce::Max, Some(0)).unwrap();
+        assert_eq!(
+            col_max.tensor().unwrap().data,
+            OwnedPrimArray::U32(vec![4, 5, 6])
+        );
+        assert!(shared.scalar(&SharedValue::U32(&0), TensorOp::Div).is_err());
+        assert!(shared
+            .scalar(&SharedValue::U32(&u32::MAX), TensorOp::Add)
+            .is_err());
+        let large = OwnedTensor::new(OwnedPrimArray::U8(vec![200, 100]), vec![2]).unwrap();
+        assert!(large.shared().reduce(TensorReduce::Sum, None).is_err());
+        assert!(OwnedTensor::new(OwnedPrimArray::U8(vec![]), vec![usize::MAX, 2]).is_err());
+        assert!(OwnedTensor::new(OwnedPrimArray::U8(vec![]), vec![usize::MAX, 0]).is_ok());
+        assert!(shared.reshape(vec![usize::MAX, 3]).is_err());
+    }
+
+    #[test]
+    fn encoding() {
+        let tensor = matrix();
+        let shared = tensor.shared().transpose(None).unwrap();
+        let mut buffer = vec![0u64; shared.encoded_size() / 8 + 1];
+        let mem_ptr = buffer.as_mut_ptr() as usize;
+        shared.write(mem_ptr).unwrap();
+        let decoded = SharedTensor::read(mem_ptr);
+        assert_eq!(decoded.shape(), &vec![3, 2]);
+        assert_eq!(decoded.owned(), shared.owned());
+    }
+
+    #[test]
+    fn empty_axes() {
+        let empty = OwnedTensor::new(OwnedPrimArray::U32(vec![]), vec![2, 0]).unwrap();
+        let shared = empty.shared();
+        assert_eq!(
+            shared.reduce(TensorReduce::Sum, None).unwrap(),
+            OwnedValue::U32(0)
+        );
+        let sums = shared.reduce(TensorReduce::Sum, Some(1)).unwrap();
+        assert_eq!(
+            sums.tensor().unwrap().data(),
+            &OwnedPrimArray::U32(vec![0, 0])
+        );
+        assert!(shared.reduce(TensorReduce::Max, Some(1)).is_err());
+        assert!(shared.reduce(TensorReduce::Mean, None).is_err());
+        let maxes = shared.reduce(TensorReduce::Max, Some(0)).unwrap();
+        assert_eq!(maxes.tensor().unwrap().shape(), &vec![0]);
+        assert_eq!(maxes.tensor().unwrap().data(), &OwnedPrimArray::U32(vec![]));
+        let means = shared.reduce(TensorReduce::Mean, Some(0)).unwrap();
+        assert_eq!(means.tensor().unwrap().data(), &OwnedPrimArray::F64(vec![]));
+    }
+
+    #[test]
+    fn invalid_tensors() {
+        let tensor = matrix();
+        let data = serialize(&tensor);
+        assert_eq!(deserialize::<OwnedTensor>(&data).unwrap(), tensor);
+        let mut bad = tensor.clone();
+        bad.layout.offset = 1;
+        assert!(deserialize::<OwnedTensor>(&serialize(&bad)).is_none());
+        let mut bad = tensor.clone();
+        bad.layout.strides.pop();
+        assert!(deserialize::<OwnedTensor>(&serialize(&bad)).is_none());
+        let mut bad = tensor.clone();
+        bad.layout.shape = vec![usize::MAX, 2];
+        assert!(deserialize::<TensorLayout>(&serialize(&bad.layout)).is_none());
+
+        let wide = OwnedTensor::new(OwnedPrimArray::U8(vec![]), vec![0, 1 << 33]).unwrap();
+        let mut buffer = vec![0u64; wide.shared().encoded_size() / 8 + 1];
+        assert!(wide.shared().write(buffer.as_mut_ptr() as usize).is_err());
+        let deep = OwnedTensor::new(OwnedPrimArray::U8(vec![]), vec![0; 256]).unwrap();
+        let mut buffer = vec![0u64; deep.shared().encoded_size() / 8 + 1];
+        assert!(deep.shared().write(buffer.as_mut_ptr() as usize).is_err());
+    }
+}
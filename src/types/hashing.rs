@@ -0,0 +1,81 @@
+use super::*;
+use std::hash::Hasher;
+
+// Hashes of composite values, built from the hashes of their elements.
+// Owned and shared values with the same content always produce the same hash.
+
+const SEQUENCE_TAG: u8 = 1;
+const MAP_TAG: u8 = 2;
+const TENSOR_TAG: u8 = 3;
+
+// Arrays and primitive arrays share the tag, so they hash the same for the same elements
+pub fn hash_sequence<I: IntoIterator<Item = [u8; 8]>>(hashes: I) -> [u8; 8] {
+    let mut hasher = twox_hash::XxHash::default();
+    hasher.write_u8(SEQUENCE_TAG);
+    for hash in hashes {
+        hasher.write(&hash);
+    }
+    u64_io::feature(&hasher.finish())
+}
+
+// Every entry is hashed on its own and then summed up, so the order of fields does not matter
+pub fn hash_entries<I: IntoIterator<Item = (u64, [u8; 8])>>(entries: I) -> [u8; 8] {
+    let mut sum = 0u64;
+    let mut count = 0u64;
+    for (key, hash) in entries {
+        let mut hasher = twox_hash::XxHash::default();
+        hasher.write_u64(key);
+        hasher.write(&hash);
+        sum = sum.wrapping_add(hasher.finish());
+        count += 1;
+    }
+    let mut hasher = twox_hash::XxHash::default();
+    hasher.write_u8(MAP_TAG);
+    hasher.write_u64(count);
+    hasher.write_u64(sum);
+    u64_io::feature(&hasher.finish())
+}
+
+pub fn hash_tensor(shape: &[usize], hashes: Vec<[u8; 8]>) -> [u8; 8] {
+    let mut hasher = twox_hash::XxHash::default();
+    hasher.write_u8(TENSOR_TAG);
+    for dim in shape {
+        hasher.write_u64(*dim as u64);
+    }
+    hasher.write(&hash_sequence(hashes));
+    u64_io::feature(&hasher.finish())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::types::*;
+
+    #[test]
+    fn composite_hashes() {
+        let mut a = OwnedMap::new();
+        a.insert_value("x", 1u32);
+        a.insert_value("y", vec![1u8, 2u8]);
+        let mut b = OwnedMap::new();
+        b.insert_value("y", vec![1u8, 2u8]);
+        b.insert_value("x", 1u32);
+        let (a, b) = (OwnedValue::Map(a), OwnedValue::Map(b));
+        assert_eq!(a.hash(), b.hash());
+        assert_eq!(a.hash(), a.shared().hash());
+        assert_eq!(a.feature(), a.hash());
+        assert_ne!(a.hash(), OwnedValue::Map(OwnedMap::new()).hash());
+
+        let array = OwnedValue::Array(vec![OwnedValue::U8(1), OwnedValue::U8(2)]);
+        let prim_array = OwnedValue::PrimArray(OwnedPrimArray::U8(vec![1, 2]));
+        let reversed = OwnedValue::PrimArray(OwnedPrimArray::U8(vec![2, 1]));
+        assert_eq!(array.hash(), prim_array.hash());
+        assert_ne!(prim_array.hash(), reversed.hash());
+        assert_eq!(prim_array.hashes().len(), 2);
+
+        assert_eq!(OwnedValue::U32(1).hashes(), vec![OwnedValue::U32(1).hash()]);
+        assert_eq!(OwnedValue::Null.features(), vec![[0u8; 8]]);
+        let empty = OwnedValue::Array(vec![]);
+        assert_eq!(empty.base_type(), Type::NA);
+        assert_eq!(empty.shared().base_type(), Type::NA);
+        assert_eq!(empty.hash(), empty.shared().hash());
+    }
+}
@@ -0,0 +1,165 @@
+use super::*;
+use std::collections::HashMap;
+use std::mem::size_of;
+
+// Bytes allocated on the heap by a value, excluding the size of the value itself.
+// The total memory footprint of `v` is `size_of_val(&v) + v.heap_size()`.
+pub trait HeapSize {
+    fn heap_size(&self) -> usize;
+}
+
+macro_rules! no_heap {
+    ($($t: ty),*) => {
+        $(
+            impl HeapSize for $t {
+                fn heap_size(&self) -> usize {
+                    0
+                }
+            }
+        )*
+    };
+}
+
+no_heap!(
+    bool, char, i8, i16, i32, i64, u8, u16, u32, u64, f32, f64, usize, Pos2d32, Pos2d64,
+    Pos3d32, Pos3d64, Id
+);
+
+impl HeapSize for String {
+    fn heap_size(&self) -> usize {
+        self.capacity()
+    }
+}
+
+impl HeapSize for Bytes {
+    fn heap_size(&self) -> usize {
+        self.data.capacity()
+    }
+}
+
+impl HeapSize for SmallBytes {
+    fn heap_size(&self) -> usize {
+        self.data.capacity()
+    }
+}
+
+impl<T: HeapSize> HeapSize for Vec<T> {
+    fn heap_size(&self) -> usize {
+        self.capacity() * size_of::<T>() + self.iter().map(|v| v.heap_size()).sum::<usize>()
+    }
+}
+
+// Estimated from the bucket layout of the swiss table: power of two buckets at 7/8 load,
+// one control byte per bucket plus a trailing group of control bytes
+impl<V: HeapSize> HeapSize for HashMap<u64, V> {
+    fn heap_size(&self) -> usize {
+        let capacity = self.capacity();
+        let table = if capacity == 0 {
+            0
+        } else {
+            let buckets = (capacity * 8 / 7).next_power_of_two();
+            buckets * (size_of::<(u64, V)>() + 1) + 16
+        };
+        table + self.values().map(|v| v.heap_size()).sum::<usize>()
+    }
+}
+
+impl HeapSize for OwnedMap {
+    fn heap_size(&self) -> usize {
+        self.map.heap_size() + self.fields.heap_size()
+    }
+}
+
+// Nodes shared with other versions are counted as if they were owned by this one
+impl HeapSize for PersistentMap {
+    fn heap_size(&self) -> usize {
+        self.len() * size_of::<(u64, OwnedValue)>()
+            + self.values().map(|v| v.heap_size()).sum::<usize>()
+            + self.fields().len() * size_of::<(u64, String)>()
+            + self.fields().map(|f| f.heap_size()).sum::<usize>()
+            + self.fields().len() * size_of::<(u64, u64)>()
+    }
+}
+
+impl HeapSize for PersistentVec {
+    fn heap_size(&self) -> usize {
+        self.len() * size_of::<OwnedValue>() + self.iter().map(|v| v.heap_size()).sum::<usize>()
+    }
+}
+
+impl HeapSize for TensorLayout {
+    fn heap_size(&self) -> usize {
+        self.shape().heap_size() + self.strides().heap_size()
+    }
+}
+
+impl HeapSize for OwnedTensor {
+    fn heap_size(&self) -> usize {
+        self.data().heap_size() + self.layout().heap_size()
+    }
+}
+
+// Shared tensors borrow their elements, only the layout is owned
+impl<'a> HeapSize for SharedTensor<'a> {
+    fn heap_size(&self) -> usize {
+        self.layout().heap_size()
+    }
+}
+
+impl HeapSize for Sketch {
+    fn heap_size(&self) -> usize {
+        match self {
+            Sketch::HyperLogLog(hll) => hll.registers.heap_size(),
+            Sketch::CountMin(cms) => cms.counters.heap_size(),
+            Sketch::Bloom(bloom) => bloom.bits.heap_size(),
+        }
+    }
+}
+
+impl OwnedMap {
+    // Fields are laid out by the schema, so only the values take space
+    pub fn encoded_size(&self) -> usize {
+        self.map.values().map(|v| v.encoded_size()).sum()
+    }
+}
+
+impl<'a> SharedMap<'a> {
+    pub fn encoded_size(&self) -> usize {
+        self.iter().map(|(_, v)| v.encoded_size()).sum()
+    }
+}
+
+impl Sketch {
+    // Kind tag, parameters and the payload
+    pub fn encoded_size(&self) -> usize {
+        1 + match self {
+            Sketch::HyperLogLog(hll) => 1 + hll.registers.len(),
+            Sketch::CountMin(cms) => 8 + cms.counters.len() * 8,
+            Sketch::Bloom(bloom) => 12 + bloom.bits.len() * 8,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::types::*;
+
+    #[test]
+    fn sizes() {
+        let name = "Dovahkiin".to_string();
+        let mut map = OwnedMap::new();
+        map.insert_value("name", name.clone());
+        map.insert_value("scores", vec![1u32, 2u32, 3u32]);
+        let value = OwnedValue::Map(map);
+        assert_eq!(value.encoded_size(), 4 + name.len() + 4 + 3 * 4);
+        assert_eq!(value.shared().encoded_size(), value.encoded_size());
+        assert!(value.heap_size() >= name.capacity() + 3 * 4 + "name".len() + "scores".len());
+        // Shared values borrow the string and the array
+        assert!(value.shared().heap_size() < value.heap_size());
+
+        let nested = OwnedValue::Array(vec![value.clone(), value.clone()]);
+        assert_eq!(nested.encoded_size(), 4 + value.encoded_size() * 2);
+        assert!(nested.heap_size() > value.heap_size());
+        assert_eq!(OwnedValue::U64(1).heap_size(), 0);
+    }
+}
@@ -243,6 +243,27 @@ macro_rules! get_from_val_fn {
     };
 }
 
+macro_rules! numeric_dispatch {
+    ($arr: expr, $slice: ident => $body: expr) => {
+        match $arr {
+            SharedPrimArray::U8($slice) => $body,
+            SharedPrimArray::U16($slice) => $body,
+            SharedPrimArray::U32($slice) => $body,
+            SharedPrimArray::U64($slice) => $body,
+            SharedPrimArray::I8($slice) => $body,
+            SharedPrimArray::I16($slice) => $body,
+            SharedPrimArray::I32($slice) => $body,
+            SharedPrimArray::I64($slice) => $body,
+            SharedPrimArray::F32($slice) => $body,
+            SharedPrimArray::F64($slice) => $body,
+            other => Err(format!(
+                "Only numeric elements are supported, found {}",
+                get_type(other.base_type())
+            )),
+        }
+    };
+}
+
 macro_rules! define_types {
     (
         $(
@@ -364,6 +385,60 @@ macro_rules! define_types {
                 }
                 res
             }
+            pub fn base_type(&self) -> Type {
+                match self {
+                    $(
+                        OwnedPrimArray::$e(_) => Type::$e,
+                    )*
+                }
+            }
+            pub fn get(&self, index: usize) -> Option<SharedValue> {
+                match self {
+                    $(
+                        OwnedPrimArray::$e(ref vec) => vec.get(index).map(|v| SharedValue::$e(v)),
+                    )*
+                }
+            }
+            pub fn get_owned(&self, index: usize) -> Option<OwnedValue> {
+                match self {
+                    $(
+                        OwnedPrimArray::$e(ref vec) => vec.get(index).map(|v| OwnedValue::$e(v.clone())),
+                    )*
+                }
+            }
+            pub fn shared<'a>(&'a self) -> SharedPrimArray<'a> {
+                match self {
+                    $(
+                        OwnedPrimArray::$e(ref vec) => SharedPrimArray::$e($io::vec_to_read_ref(vec)),
+                    )*
+                }
+            }
+            pub fn from_values(values: Vec<OwnedValue>) -> Result<Self, String> {
+                let t = match values.first() {
+                    Some(first) => first.base_type(),
+                    None => return Err("Cannot infer element type of an empty array".to_string())
+                };
+                Self::from_typed_values(t, values)
+            }
+            pub fn from_typed_values(t: Type, values: Vec<OwnedValue>) -> Result<Self, String> {
+                match t {
+                    $(
+                        Type::$e => {
+                            let mut vec: Vec<$t> = Vec::with_capacity(values.len());
+                            for val in values {
+                                match val {
+                                    OwnedValue::$e(v) => vec.push(v),
+                                    _ => return Err(format!(
+                                        "Type not match, expect {} found {:?}", get_type(t), val
+                                    ))
+                                }
+                            }
+                            Ok(OwnedPrimArray::$e(vec))
+                        }
+                    )*
+                    _ => Err(format!("Type {:?} cannot be packed into primitive array", t))
+                }
+            }
             $(
                 pub fn $fn(&self) -> Option<&Vec<$t>> {
                     match self {
@@ -384,7 +459,11 @@ macro_rules! define_types {
             Array(Vec<OwnedValue>),
             PrimArray(OwnedPrimArray),
             Null,
-            NA
+            NA,
+            Tensor(OwnedTensor),
+            Sketch(Sketch),
+            PMap(PersistentMap),
+            PVec(PersistentVec)
         }
 
         impl OwnedValue {
@@ -398,11 +477,15 @@ macro_rules! define_types {
                             SharedValue::$e(v)
                         }
                     ),*,
-                    OwnedValue::Array(ref array) => SharedValue::Array(array.iter().map(|v| v.shared()).collect()),
+                    OwnedValue::Array(ref array) => SharedValue::Array(SharedArray::Borrowed(array)),
                     $(
                         OwnedValue::PrimArray(OwnedPrimArray::$e(ref vec)) => SharedValue::PrimArray(SharedPrimArray::$e($io::vec_to_read_ref(vec))),
                     )*
                     OwnedValue::Map(ref map) => SharedValue::Map(map.shared()),
+                    OwnedValue::Tensor(ref tensor) => SharedValue::Tensor(tensor.shared()),
+                    OwnedValue::Sketch(ref sketch) => SharedValue::Sketch(sketch),
+                    OwnedValue::PMap(ref map) => SharedValue::PMap(map),
+                    OwnedValue::PVec(ref vec) => SharedValue::PVec(vec),
                     OwnedValue::Null => SharedValue::Null,
                     OwnedValue::NA => SharedValue::NA,
                 }
@@ -437,6 +520,9 @@ macro_rules! define_types {
                 match self {
                     OwnedValue::Array(ref array) => Some(array.len()),
                     OwnedValue::Map(ref map) => Some(map.len()),
+                    OwnedValue::Tensor(ref tensor) => Some(tensor.len()),
+                    OwnedValue::PMap(ref map) => Some(map.len()),
+                    OwnedValue::PVec(ref vec) => Some(vec.len()),
                     $(OwnedValue::PrimArray(OwnedPrimArray::$e(ref vec)) => Some(vec.len()),)*
                     _ => None
                 }
@@ -446,7 +532,10 @@ macro_rules! define_types {
                     $(
                         &OwnedValue::$e(v) => $io::feature(&v)
                     ),*,
-                    &OwnedValue::Map(_) | &OwnedValue::Array(_) | &OwnedValue::PrimArray(_) => unreachable!(),
+                    // Composite values have no natural order, their features are their hashes
+                    &OwnedValue::Map(_) | &OwnedValue::Array(_) | &OwnedValue::PrimArray(_) |
+                    &OwnedValue::Tensor(_) | &OwnedValue::Sketch(_) |
+                    &OwnedValue::PMap(_) | &OwnedValue::PVec(_) => self.hash(),
                     _ => [0u8; 8]
                 }
             }
@@ -463,7 +552,13 @@ macro_rules! define_types {
                     OwnedValue::PrimArray(ref prim_arr) => {
                         prim_arr.features()
                     },
-                    _ => unreachable!()
+                    OwnedValue::PVec(ref vec) => {
+                        vec.iter().map(|v| v.feature()).collect()
+                    },
+                    OwnedValue::Tensor(ref tensor) => {
+                        tensor.shared().features()
+                    },
+                    _ => vec![self.feature()]
                 }
             }
 
@@ -472,7 +567,11 @@ macro_rules! define_types {
                     $(
                         &OwnedValue::$e(v) => $io::hash(&v)
                     ),*,
-                    OwnedValue::Map(_) | OwnedValue::Array(_) | OwnedValue::PrimArray(_) => panic!(),
+                    OwnedValue::Map(ref map) => hash_entries(map.map.iter().map(|(k, v)| (*k, v.hash()))),
+                    OwnedValue::PMap(ref map) => hash_entries(map.iter().map(|(k, v)| (*k, v.hash()))),
+                    OwnedValue::Array(_) | OwnedValue::PrimArray(_) | OwnedValue::PVec(_) => hash_sequence(self.hashes()),
+                    OwnedValue::Tensor(ref tensor) => hash_tensor(tensor.shape(), self.hashes()),
+                    OwnedValue::Sketch(ref sketch) => sketch.hash(),
                     _ => [0u8; 8]
                 }
             }
@@ -489,7 +588,13 @@ macro_rules! define_types {
                     OwnedValue::PrimArray(ref prim_arr) => {
                         prim_arr.hashes()
                     },
-                    _ => unreachable!()
+                    OwnedValue::PVec(ref vec) => {
+                        vec.iter().map(|v| v.hash()).collect()
+                    },
+                    OwnedValue::Tensor(ref tensor) => {
+                        tensor.shared().hashes()
+                    },
+                    _ => vec![self.hash()]
                 }
             }
             pub fn base_type(&self) -> Type {
@@ -500,8 +605,13 @@ macro_rules! define_types {
                     $(
                         OwnedValue::PrimArray(OwnedPrimArray::$e(_)) => Type::$e,
                     )*
-                    &OwnedValue::Array(ref v) => v[0].base_type(),
+                    &OwnedValue::Array(ref v) => v.first().map(|x| x.base_type()).unwrap_or(Type::NA),
                     &OwnedValue::Map(_) => Type::Map,
+                    &OwnedValue::Tensor(ref t) => t.data().base_type(),
+                    // Sketches are opaque to schemas
+                    &OwnedValue::Sketch(_) => Type::NA,
+                    &OwnedValue::PMap(_) => Type::Map,
+                    &OwnedValue::PVec(ref v) => v.get(0).map(|v| v.base_type()).unwrap_or(Type::NA),
                     &OwnedValue::Null => Type::Null,
                     &OwnedValue::NA => Type::NA,
                 }
@@ -512,6 +622,78 @@ macro_rules! define_types {
                     _ => None
                 }
             }
+            pub fn tensor(&self) -> Option<&OwnedTensor> {
+                match self {
+                    &OwnedValue::Tensor(ref t) => Some(t),
+                    _ => None
+                }
+            }
+            pub fn sketch(&self) -> Option<&Sketch> {
+                match self {
+                    &OwnedValue::Sketch(ref s) => Some(s),
+                    _ => None
+                }
+            }
+            pub fn pmap(&self) -> Option<&PersistentMap> {
+                match self {
+                    &OwnedValue::PMap(ref m) => Some(m),
+                    _ => None
+                }
+            }
+            pub fn pvec(&self) -> Option<&PersistentVec> {
+                match self {
+                    &OwnedValue::PVec(ref v) => Some(v),
+                    _ => None
+                }
+            }
+            // Size in the binary record layout, arrays are prefixed with their u32 length
+            pub fn encoded_size(&self) -> usize {
+                match self {
+                    $(
+                        OwnedValue::$e(ref v) => $io::val_size(v),
+                    )*
+                    OwnedValue::Map(ref map) => map.encoded_size(),
+                    OwnedValue::Array(ref array) => {
+                        u32_io::type_size() + array.iter().map(|v| v.encoded_size()).sum::<usize>()
+                    },
+                    OwnedValue::PrimArray(ref array) => u32_io::type_size() + array.size(),
+                    OwnedValue::Tensor(ref tensor) => tensor.shared().encoded_size(),
+                    OwnedValue::Sketch(ref sketch) => sketch.encoded_size(),
+                    OwnedValue::PMap(ref map) => map.values().map(|v| v.encoded_size()).sum(),
+                    OwnedValue::PVec(ref vec) => {
+                        u32_io::type_size() + vec.iter().map(|v| v.encoded_size()).sum::<usize>()
+                    },
+                    OwnedValue::Null | OwnedValue::NA => 0,
+                }
+            }
+        }
+
+        impl HeapSize for OwnedPrimArray {
+            fn heap_size(&self) -> usize {
+                match self {
+                    $(
+                        OwnedPrimArray::$e(ref vec) => vec.heap_size(),
+                    )*
+                }
+            }
+        }
+
+        impl HeapSize for OwnedValue {
+            fn heap_size(&self) -> usize {
+                match self {
+                    $(
+                        OwnedValue::$e(ref v) => v.heap_size(),
+                    )*
+                    OwnedValue::Map(ref map) => map.heap_size(),
+                    OwnedValue::Array(ref array) => array.heap_size(),
+                    OwnedValue::PrimArray(ref array) => array.heap_size(),
+                    OwnedValue::Tensor(ref tensor) => tensor.heap_size(),
+                    OwnedValue::Sketch(ref sketch) => sketch.heap_size(),
+                    OwnedValue::PMap(ref map) => map.heap_size(),
+                    OwnedValue::PVec(ref vec) => vec.heap_size(),
+                    OwnedValue::Null | OwnedValue::NA => 0,
+                }
+            }
         }
         pub fn get_type_id (name: String) -> u8 {
            match name.as_ref() {
@@ -723,6 +905,38 @@ macro_rules! define_types {
                 }
                 res
             }
+            pub fn base_type(&self) -> Type {
+                match self {
+                    $(
+                        SharedPrimArray::$e(_) => Type::$e,
+                    )*
+                }
+            }
+            pub fn owned(&self) -> OwnedPrimArray {
+                match self {
+                    $(
+                        SharedPrimArray::$e(ref vec) => OwnedPrimArray::$e(vec
+                            .iter()
+                            .map(|v| {
+                                (*v).to_owned().into()
+                            })
+                            .collect()),
+                    )*
+                }
+            }
+            // Elements at the positions, in their order
+            pub fn gather(&self, positions: &[usize]) -> OwnedPrimArray {
+                match self {
+                    $(
+                        SharedPrimArray::$e(ref vec) => OwnedPrimArray::$e(positions
+                            .iter()
+                            .map(|pos| {
+                                vec[*pos].to_owned().into()
+                            })
+                            .collect()),
+                    )*
+                }
+            }
             $(
                 pub fn $fn(&self) -> Option<& $io::Slice> {
                     match self {
@@ -733,16 +947,25 @@ macro_rules! define_types {
             )*
         }
 
+        // Maps and arrays borrowed from owned values turn their entries into shared values as
+        // they are read, so there is no `SharedValue` to hand out a reference to. That is why
+        // `SharedValue` has no `Index` impls and `Array` holds a `SharedArray` instead of a
+        // `Vec`: read entries by value with `index_of` and `Map::get_by_key_id`, and get the
+        // elements of an array as a `Vec` with `SharedArray::into_vec`.
         #[derive(Debug, PartialEq, Clone)]
         pub enum SharedValue<'a> {
             $(
                 $e($io::ReadRef<'a>),
             )*
             Map(SharedMap<'a>),
-            Array(Vec<SharedValue<'a>>),
+            Array(SharedArray<'a>),
             PrimArray(SharedPrimArray<'a>),
             Null,
-            NA
+            NA,
+            Tensor(SharedTensor<'a>),
+            Sketch(&'a Sketch),
+            PMap(&'a PersistentMap),
+            PVec(&'a PersistentVec)
         }
         impl <'a> SharedValue <'a> {
             $(
@@ -767,6 +990,10 @@ macro_rules! define_types {
                         .collect())),
                     )*
                     SharedValue::Map(ref map) => OwnedValue::Map(map.owned()),
+                    SharedValue::Tensor(ref tensor) => OwnedValue::Tensor(tensor.owned()),
+                    SharedValue::Sketch(sketch) => OwnedValue::Sketch((*sketch).clone()),
+                    SharedValue::PMap(map) => OwnedValue::PMap((*map).clone()),
+                    SharedValue::PVec(vec) => OwnedValue::PVec((*vec).clone()),
                     SharedValue::Null => OwnedValue::Null,
                     SharedValue::NA => OwnedValue::NA,
                 }
@@ -786,6 +1013,9 @@ macro_rules! define_types {
                 match self {
                     SharedValue::Array(ref array) => Some(array.len()),
                     SharedValue::Map(ref map) => Some(map.len()),
+                    SharedValue::Tensor(ref tensor) => Some(tensor.len()),
+                    SharedValue::PMap(map) => Some(map.len()),
+                    SharedValue::PVec(vec) => Some(vec.len()),
                     $(SharedValue::PrimArray(SharedPrimArray::$e(ref vec)) => Some(vec.len()),)*
                     _ => None
                 }
@@ -795,7 +1025,9 @@ macro_rules! define_types {
                     $(
                         SharedValue::$e(ref v) => $io::feature(v)
                     ),*,
-                    SharedValue::Map(_) | SharedValue::Array(_) | SharedValue::PrimArray(_) => unreachable!(),
+                    SharedValue::Map(_) | SharedValue::Array(_) | SharedValue::PrimArray(_) |
+                    SharedValue::Tensor(_) | SharedValue::Sketch(_) |
+                    SharedValue::PMap(_) | SharedValue::PVec(_) => self.hash(),
                     _ => [0u8; 8]
                 }
             }
@@ -812,7 +1044,13 @@ macro_rules! define_types {
                     SharedValue::PrimArray(ref prim_arr) => {
                         prim_arr.features()
                     },
-                    _ => unreachable!()
+                    SharedValue::PVec(vec) => {
+                        vec.iter().map(|v| v.feature()).collect()
+                    },
+                    SharedValue::Tensor(ref tensor) => {
+                        tensor.features()
+                    },
+                    _ => vec![self.feature()]
                 }
             }
 
@@ -821,7 +1059,11 @@ macro_rules! define_types {
                     $(
                         &SharedValue::$e(v) => $io::hash(v)
                     ),*,
-                    SharedValue::Map(_) | SharedValue::Array(_) | SharedValue::PrimArray(_) => panic!(),
+                    SharedValue::Map(ref map) => hash_entries(map.iter().map(|(k, v)| (k, v.hash()))),
+                    SharedValue::PMap(map) => hash_entries(map.iter().map(|(k, v)| (*k, v.hash()))),
+                    SharedValue::Array(_) | SharedValue::PrimArray(_) | SharedValue::PVec(_) => hash_sequence(self.hashes()),
+                    SharedValue::Tensor(ref tensor) => hash_tensor(tensor.shape(), self.hashes()),
+                    SharedValue::Sketch(sketch) => sketch.hash(),
                     _ => [0u8; 8]
                 }
             }
@@ -838,7 +1080,13 @@ macro_rules! define_types {
                     SharedValue::PrimArray(ref prim_arr) => {
                         prim_arr.hashes()
                     },
-                    _ => unreachable!()
+                    SharedValue::PVec(vec) => {
+                        vec.iter().map(|v| v.hash()).collect()
+                    },
+                    SharedValue::Tensor(ref tensor) => {
+                        tensor.hashes()
+                    },
+                    _ => vec![self.hash()]
                 }
             }
             pub fn base_type(&self) -> Type {
@@ -849,8 +1097,12 @@ macro_rules! define_types {
                     $(
                         SharedValue::PrimArray(SharedPrimArray::$e(_)) => Type::$e,
                     )*
-                    &SharedValue::Array(ref v) => v[0].base_type(),
+                    &SharedValue::Array(ref v) => v.get(0).map(|v| v.base_type()).unwrap_or(Type::NA),
                     &SharedValue::Map(_) => Type::Map,
+                    &SharedValue::Tensor(ref t) => t.data().base_type(),
+                    &SharedValue::Sketch(_) => Type::NA,
+                    &SharedValue::PMap(_) => Type::Map,
+                    &SharedValue::PVec(v) => v.get(0).map(|v| v.base_type()).unwrap_or(Type::NA),
                     &SharedValue::Null => Type::Null,
                     &SharedValue::NA => Type::NA
                 }
@@ -861,6 +1113,61 @@ macro_rules! define_types {
                     _ => None
                 }
             }
+            pub fn tensor(&self) -> Option<&SharedTensor<'a>> {
+                match self {
+                    &SharedValue::Tensor(ref t) => Some(t),
+                    _ => None
+                }
+            }
+            pub fn sketch(&self) -> Option<&'a Sketch> {
+                match self {
+                    &SharedValue::Sketch(s) => Some(s),
+                    _ => None
+                }
+            }
+            pub fn pmap(&self) -> Option<&'a PersistentMap> {
+                match self {
+                    &SharedValue::PMap(m) => Some(m),
+                    _ => None
+                }
+            }
+            pub fn pvec(&self) -> Option<&'a PersistentVec> {
+                match self {
+                    &SharedValue::PVec(v) => Some(v),
+                    _ => None
+                }
+            }
+            pub fn encoded_size(&self) -> usize {
+                match self {
+                    $(
+                        SharedValue::$e(ref v) => $io::val_size(v),
+                    )*
+                    SharedValue::Map(ref map) => map.encoded_size(),
+                    SharedValue::Array(ref array) => {
+                        u32_io::type_size() + array.iter().map(|v| v.encoded_size()).sum::<usize>()
+                    },
+                    SharedValue::PrimArray(ref array) => u32_io::type_size() + array.size(),
+                    SharedValue::Tensor(ref tensor) => tensor.encoded_size(),
+                    SharedValue::Sketch(sketch) => sketch.encoded_size(),
+                    SharedValue::PMap(map) => map.values().map(|v| v.encoded_size()).sum(),
+                    SharedValue::PVec(vec) => {
+                        u32_io::type_size() + vec.iter().map(|v| v.encoded_size()).sum::<usize>()
+                    },
+                    SharedValue::Null | SharedValue::NA => 0,
+                }
+            }
+        }
+
+        // Shared values borrow their data, only containers built for the view are counted
+        impl <'a> HeapSize for SharedValue<'a> {
+            fn heap_size(&self) -> usize {
+                match self {
+                    SharedValue::Map(ref map) => map.heap_size(),
+                    SharedValue::Array(ref array) => array.heap_size(),
+                    SharedValue::Tensor(ref tensor) => tensor.heap_size(),
+                    _ => 0,
+                }
+            }
         }
 
         impl <'a> Eq for SharedValue<'a> {
@@ -872,16 +1179,16 @@ macro_rules! define_types {
             $(
                 fn $fn(&self) -> Option<$t>;
             )*
-            fn get_in_by_ids(&self, ids: &Vec<u64>) -> &Self;
+            fn get_in_by_ids(&self, ids: &Vec<u64>) -> <Self::Map as Map>::Ref<'_>;
             fn feature(&self) -> [u8; 8];
             fn features(&self) -> Vec<[u8; 8]>;
             fn hash(&self) -> [u8; 8];
             fn hashes(&self) -> Vec<[u8; 8]>;
             fn base_type(&self) -> Type;
-            fn index_of(&self, index: usize) -> &Self;
+            fn index_of(&self, index: usize) -> <Self::Map as Map>::Ref<'_>;
             fn base_size(&self) -> usize;
             fn prim_array_data_size(&self) -> Option<u8>;
-            fn uni_array(&self) -> Option<Vec<&Self>>;
+            fn uni_array(&self) -> Option<Vec<<Self::Map as Map>::Ref<'_>>>;
             fn map(&self) -> Option<&Self::Map>;
         }
 
@@ -989,8 +1296,12 @@ macro_rules! define_types {
             fn base_type(&self) -> Type {
                 SharedValue::base_type(&self)
             }
-            fn index_of(&self, index: usize) -> &Self {
-                &self[index]
+            fn index_of(&self, index: usize) -> Self {
+                match self {
+                    Self::Array(ref array) => array.get(index).unwrap_or(Self::Null),
+                    Self::Map(ref map) => map.get_by_key_id(index as u64),
+                    _ => Self::Null,
+                }
             }
             fn base_size(&self) -> usize {
                SharedValue::base_size(self)
@@ -1001,17 +1312,17 @@ macro_rules! define_types {
                     _ => None,
                 }
             }
-            fn uni_array(&self) -> Option<Vec<&Self>> {
+            fn uni_array(&self) -> Option<Vec<Self>> {
                 match self {
-                    SharedValue::Array(arr) => Some(arr.iter().map(|v| v as &Self).collect()),
+                    SharedValue::Array(arr) => Some(arr.iter().collect()),
                     _ => None
                 }
             }
-            fn get_in_by_ids(&self, ids: &Vec<u64>) -> &Self {
+            fn get_in_by_ids(&self, ids: &Vec<u64>) -> Self {
                 if let SharedValue::Map(map) = &self {
                     map.get_in_by_ids(ids.iter())
                 } else {
-                    &SharedValue::Null
+                    SharedValue::Null
                 }
             }
             fn map(&self) -> Option<&SharedMap<'a>> {
@@ -1022,33 +1333,142 @@ macro_rules! define_types {
             }
         }
 
-        impl <'a> Index<usize> for SharedValue<'a> {
-            type Output = Self;
+        impl <'a> Default for SharedValue<'a> {
+            fn default() -> Self {
+                Self::NA
+            }
+        }
 
-            fn index(&self, index: usize) -> &Self::Output {
+        // Callbacks for `walk`. Scalars are passed as their shared references whichever
+        // representation is walked, map entries and array elements are announced before
+        // their values are walked.
+        pub trait ValueVisitor<'a> {
+            $(
+                fn $fn(&mut self, _value: $io::ReadRef<'a>) {}
+            )*
+            fn null(&mut self) {}
+            fn na(&mut self) {}
+            fn enter_map(&mut self, _len: usize) {}
+            fn map_entry(&mut self, _key: u64, _name: Option<&str>) {}
+            fn exit_map(&mut self) {}
+            fn enter_array(&mut self, _len: usize) {}
+            fn array_element(&mut self, _index: usize) {}
+            fn exit_array(&mut self) {}
+            fn prim_array(&mut self, _array: SharedPrimArray<'a>) {}
+            fn tensor(&mut self, _tensor: SharedTensor<'a>) {}
+            fn sketch(&mut self, _sketch: &'a Sketch) {}
+        }
+
+        pub trait ValueVisitorMut {
+            $(
+                fn $fn(&mut self, _value: &mut $t) {}
+            )*
+            fn null(&mut self) {}
+            fn na(&mut self) {}
+            fn enter_map(&mut self, _len: usize) {}
+            fn map_entry(&mut self, _key: u64, _name: Option<&str>) {}
+            fn exit_map(&mut self) {}
+            fn enter_array(&mut self, _len: usize) {}
+            fn array_element(&mut self, _index: usize) {}
+            fn exit_array(&mut self) {}
+            fn prim_array(&mut self, _array: &mut OwnedPrimArray) {}
+            fn tensor(&mut self, _tensor: &mut OwnedTensor) {}
+            fn sketch(&mut self, _sketch: &mut Sketch) {}
+        }
+
+        impl <'a> Walk<'a> for &'a OwnedValue {
+            fn walk<V: ValueVisitor<'a>>(self, visitor: &mut V) {
                 match self {
-                    &Self::Array(ref array) => array.get(index).unwrap_or(&NULL_SHARED_VALUE),
-                    &Self::Map(ref map) => map.get_by_key_id(index as u64),
-                    _ => &NULL_SHARED_VALUE,
+                    $(
+                        OwnedValue::$e(ref v) => visitor.$fn(v),
+                    )*
+                    OwnedValue::Map(ref map) => walk_owned_map(map, visitor),
+                    OwnedValue::Array(ref array) => walk_elements(array.iter(), visitor),
+                    OwnedValue::PrimArray(ref array) => visitor.prim_array(array.shared()),
+                    OwnedValue::Tensor(ref tensor) => visitor.tensor(tensor.shared()),
+                    OwnedValue::Sketch(ref sketch) => visitor.sketch(sketch),
+                    OwnedValue::PMap(ref map) => walk_persistent_map(map, visitor),
+                    OwnedValue::PVec(ref vec) => walk_elements(vec.iter(), visitor),
+                    OwnedValue::Null => visitor.null(),
+                    OwnedValue::NA => visitor.na(),
                 }
             }
         }
 
-        impl <'a> Index<u64> for SharedValue<'a> {
-            type Output = Self;
-
-            fn index(&self, index: u64) -> &Self::Output {
+        impl <'a, 'b> Walk<'a> for &'b SharedValue<'a> {
+            fn walk<V: ValueVisitor<'a>>(self, visitor: &mut V) {
                 match self {
-                    &Self::Map(ref map) => map.get_by_key_id(index),
-                    &Self::Array(ref array) => array.get(index as usize).unwrap_or(&NULL_SHARED_VALUE),
-                    _ => &NULL_SHARED_VALUE,
+                    $(
+                        SharedValue::$e(v) => visitor.$fn(*v),
+                    )*
+                    SharedValue::Map(ref map) => walk_shared_map(map, visitor),
+                    SharedValue::Array(ref array) => walk_elements(array.iter(), visitor),
+                    SharedValue::PrimArray(ref array) => visitor.prim_array(array.clone()),
+                    SharedValue::Tensor(ref tensor) => visitor.tensor(tensor.clone()),
+                    SharedValue::Sketch(sketch) => visitor.sketch(sketch),
+                    SharedValue::PMap(map) => walk_persistent_map(map, visitor),
+                    SharedValue::PVec(vec) => walk_elements(vec.iter(), visitor),
+                    SharedValue::Null => visitor.null(),
+                    SharedValue::NA => visitor.na(),
                 }
             }
         }
 
-        impl <'a> Default for SharedValue<'a> {
-            fn default() -> Self {
-                Self::NA
+        // Elements and entries read from views are walked by value
+        impl <'a> Walk<'a> for SharedValue<'a> {
+            fn walk<V: ValueVisitor<'a>>(self, visitor: &mut V) {
+                (&self).walk(visitor)
+            }
+        }
+
+        pub fn walk_mut<V: ValueVisitorMut>(value: &mut OwnedValue, visitor: &mut V) {
+            match value {
+                $(
+                    OwnedValue::$e(ref mut v) => visitor.$fn(v),
+                )*
+                OwnedValue::Map(ref mut map) => {
+                    let keys = ordered_keys(map.fields.iter(), map.map.keys().cloned());
+                    visitor.enter_map(keys.len());
+                    for (key, name) in keys {
+                        visitor.map_entry(key, name.as_ref().map(|n| n.as_str()));
+                        walk_mut(map.map.get_mut(&key).unwrap(), visitor);
+                    }
+                    visitor.exit_map();
+                }
+                OwnedValue::Array(ref mut array) => {
+                    visitor.enter_array(array.len());
+                    for (index, value) in array.iter_mut().enumerate() {
+                        visitor.array_element(index);
+                        walk_mut(value, visitor);
+                    }
+                    visitor.exit_array();
+                }
+                OwnedValue::PrimArray(ref mut array) => visitor.prim_array(array),
+                OwnedValue::Tensor(ref mut tensor) => visitor.tensor(tensor),
+                OwnedValue::Sketch(ref mut sketch) => visitor.sketch(sketch),
+                OwnedValue::PMap(ref mut map) => {
+                    // Names are copied out, the values are borrowed mutably from the same map
+                    let keys: Vec<(u64, Option<String>)> = ordered_keys(map.fields(), map.keys().cloned())
+                        .into_iter()
+                        .map(|(key, name)| (key, name.cloned()))
+                        .collect();
+                    visitor.enter_map(keys.len());
+                    for (key, name) in keys {
+                        visitor.map_entry(key, name.as_deref());
+                        walk_mut(map.get_mut_by_key_id(key).unwrap(), visitor);
+                    }
+                    visitor.exit_map();
+                }
+                OwnedValue::PVec(ref mut vec) => {
+                    visitor.enter_array(vec.len());
+                    for (index, value) in vec.items.iter_mut().enumerate() {
+                        visitor.array_element(index);
+                        walk_mut(value, visitor);
+                    }
+                    visitor.exit_array();
+                }
+                OwnedValue::Null => visitor.null(),
+                OwnedValue::NA => visitor.na(),
             }
         }
 
@@ -1,19 +1,36 @@
 #[macro_use]
 mod macros;
 pub mod custom_types;
+pub mod hashing;
+pub mod heap_size;
 pub mod owned_value;
+pub mod record_batch;
+pub mod schema;
+pub mod similarity;
+pub mod vectorized;
+pub mod visitor;
 
 use serde::Deserialize;
 use std::{ops::Index, vec::IntoIter};
+use visitor::{ordered_keys, walk_elements, walk_owned_map, walk_persistent_map, walk_shared_map};
 
 pub use crate::types::custom_types::any::*;
+pub use crate::types::hashing::*;
+pub use crate::types::heap_size::*;
 pub use crate::types::custom_types::bytes::*;
 pub use crate::types::custom_types::id::*;
 pub use crate::types::custom_types::owned_map::*;
+pub use crate::types::custom_types::persistent::*;
 pub use crate::types::custom_types::pos::*;
+pub use crate::types::custom_types::shared_array::*;
 pub use crate::types::custom_types::shared_map::*;
+pub use crate::types::custom_types::sketch::*;
+pub use crate::types::custom_types::tensor::*;
 pub use crate::types::owned_value::*;
-pub use crate::types::custom_types::map::Map;
+pub use crate::types::record_batch::*;
+pub use crate::types::schema::*;
+pub use crate::types::visitor::{walk, Walk};
+pub use crate::types::custom_types::map::{field_name, key_name, register_field_name, Map};
 
 gen_primitive_types_io!(
     bool:   bool_io       big_end_cast!();
@@ -295,13 +312,3 @@ pub static NULL_SHARED_VALUE: SharedValue = SharedValue::Null;
 pub const ARRAY_LEN_TYPE: Type = Type::U32; //u32
 pub const TYPE_CODE_TYPE: Type = Type::U8; //u32
 
-impl<'a, 'v> Index<&'a str> for SharedValue<'v> {
-    type Output = Self;
-
-    fn index(&self, index: &'a str) -> &Self::Output {
-        match self {
-            &Self::Map(ref map) => map.get(index),
-            _ => &NULL_SHARED_VALUE,
-        }
-    }
-}
@@ -0,0 +1,319 @@
+use super::*;
+use std::collections::HashSet;
+
+// Columnar layout for a set of records. Columns holding values of a single primitive type
+// are stored as primitive arrays so vectorized operators can work on them without copying.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bitmap {
+    bits: Vec<u64>,
+    len: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ColumnData {
+    Prim(OwnedPrimArray),
+    Values(Vec<OwnedValue>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Column {
+    pub key: u64,
+    pub name: Option<String>,
+    pub data: ColumnData,
+    pub validity: Bitmap,
+    // Rows having the field, null or not, so explicit nulls are told apart from absent fields
+    pub present: Bitmap,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordBatch {
+    pub columns: Vec<Column>,
+    pub num_rows: usize,
+}
+
+impl Bitmap {
+    pub fn new(len: usize, valid: bool) -> Self {
+        let fill = if valid { !0 } else { 0 };
+        let mut bitmap = Self {
+            bits: vec![fill; (len + 63) / 64],
+            len,
+        };
+        bitmap.clear_tail();
+        bitmap
+    }
+    fn clear_tail(&mut self) {
+        if self.len % 64 != 0 {
+            if let Some(last) = self.bits.last_mut() {
+                *last &= (1 << (self.len % 64)) - 1;
+            }
+        }
+    }
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    pub fn get(&self, index: usize) -> bool {
+        index < self.len && self.bits[index / 64] & (1 << (index % 64)) != 0
+    }
+    pub fn set(&mut self, index: usize, valid: bool) {
+        let mask = 1 << (index % 64);
+        if valid {
+            self.bits[index / 64] |= mask;
+        } else {
+            self.bits[index / 64] &= !mask;
+        }
+    }
+    pub fn count_valid(&self) -> usize {
+        self.bits.iter().map(|w| w.count_ones() as usize).sum()
+    }
+}
+
+impl Column {
+    fn from_values(
+        key: u64,
+        name: Option<String>,
+        values: Vec<OwnedValue>,
+        present: Bitmap,
+    ) -> Self {
+        let mut validity = Bitmap::new(values.len(), true);
+        let mut filler = None;
+        for (row, value) in values.iter().enumerate() {
+            match value {
+                // NA is kept as it is, only columns without it can be primitive
+                OwnedValue::Null | OwnedValue::NA => validity.set(row, false),
+                _ if filler.is_none() => filler = Some(value.clone()),
+                _ => {}
+            }
+        }
+        // Null slots of a primitive column are filled with the first valid value and masked
+        // out by the validity bitmap. Arrays, maps and other composite values, empty or not,
+        // stay in a mixed column.
+        let data = match filler {
+            Some(filler) if filler.len().is_none() && !matches!(filler, OwnedValue::Sketch(_)) => {
+                let packed = values
+                    .iter()
+                    .enumerate()
+                    .map(|(row, v)| {
+                        if validity.get(row) || *v == OwnedValue::NA {
+                            v.clone()
+                        } else {
+                            filler.clone()
+                        }
+                    })
+                    .collect();
+                match OwnedPrimArray::from_values(packed) {
+                    Ok(array) => ColumnData::Prim(array),
+                    Err(_) => ColumnData::Values(values),
+                }
+            }
+            _ => ColumnData::Values(values),
+        };
+        Self {
+            key,
+            name,
+            data,
+            validity,
+            present,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.validity.len()
+    }
+
+    pub fn is_valid(&self, row: usize) -> bool {
+        self.validity.get(row)
+    }
+
+    pub fn is_present(&self, row: usize) -> bool {
+        self.present.get(row)
+    }
+
+    // Zero-copy view of a primitive column, null slots are present but hold filler values
+    pub fn view<'a>(&'a self) -> Option<SharedPrimArray<'a>> {
+        match &self.data {
+            ColumnData::Prim(array) => Some(array.shared()),
+            ColumnData::Values(_) => None,
+        }
+    }
+
+    pub fn value<'a>(&'a self, row: usize) -> SharedValue<'a> {
+        if !self.is_valid(row) {
+            return SharedValue::Null;
+        }
+        match &self.data {
+            ColumnData::Prim(array) => array.get(row).unwrap_or(SharedValue::Null),
+            ColumnData::Values(values) => values[row].shared(),
+        }
+    }
+
+    pub fn owned_value(&self, row: usize) -> OwnedValue {
+        if !self.is_valid(row) {
+            return OwnedValue::Null;
+        }
+        match &self.data {
+            ColumnData::Prim(array) => array.get_owned(row).unwrap_or(OwnedValue::Null),
+            ColumnData::Values(values) => values[row].clone(),
+        }
+    }
+}
+
+impl RecordBatch {
+    pub fn from_rows(rows: &[OwnedMap]) -> Self {
+        let mut order: Vec<(u64, Option<String>)> = vec![];
+        let mut seen: HashSet<u64> = HashSet::new();
+        for row in rows {
+            for name in &row.fields {
+                let key = key_hash(name);
+                if row.map.contains_key(&key) && seen.insert(key) {
+                    order.push((key, Some(name.clone())));
+                }
+            }
+        }
+        // Fields inserted by key id only have no name
+        for row in rows {
+            let mut unnamed: Vec<_> = row
+                .map
+                .keys()
+                .filter(|k| !seen.contains(k))
+                .cloned()
+                .collect();
+            unnamed.sort();
+            for key in unnamed {
+                seen.insert(key);
+                order.push((key, None));
+            }
+        }
+        let columns = order
+            .into_iter()
+            .map(|(key, name)| {
+                let mut present = Bitmap::new(rows.len(), false);
+                let values = rows
+                    .iter()
+                    .enumerate()
+                    .map(|(index, row)| match row.map.get(&key) {
+                        Some(value) => {
+                            present.set(index, true);
+                            value.clone()
+                        }
+                        None => OwnedValue::Null,
+                    })
+                    .collect();
+                Column::from_values(key, name, values, present)
+            })
+            .collect();
+        Self {
+            columns,
+            num_rows: rows.len(),
+        }
+    }
+
+    pub fn to_rows(&self) -> Vec<OwnedMap> {
+        (0..self.num_rows).map(|row| self.row(row)).collect()
+    }
+
+    // Fields absent from the row are left out, null and NA ones are kept
+    pub fn row(&self, row: usize) -> OwnedMap {
+        let mut map = OwnedMap::new();
+        for column in &self.columns {
+            if !column.is_present(row) {
+                continue;
+            }
+            let value = match &column.data {
+                ColumnData::Values(values) => values[row].clone(),
+                ColumnData::Prim(_) => column.owned_value(row),
+            };
+            match &column.name {
+                Some(name) => map.insert(name, value),
+                None => map.insert_key_id(column.key, value),
+            };
+        }
+        map
+    }
+
+    pub fn column(&self, key: u64) -> Option<&Column> {
+        self.columns.iter().find(|c| c.key == key)
+    }
+
+    pub fn column_by_name(&self, name: &str) -> Option<&Column> {
+        self.column(key_hash(name))
+    }
+
+    pub fn project(&self, keys: &[u64]) -> Result<RecordBatch, String> {
+        let mut columns = Vec::with_capacity(keys.len());
+        for key in keys {
+            match self.column(*key) {
+                Some(column) => columns.push(column.clone()),
+                None => return Err(format!("Column with key id {} not found", key)),
+            }
+        }
+        Ok(Self {
+            columns,
+            num_rows: self.num_rows,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::types::*;
+
+    #[test]
+    fn round_trip() {
+        let mut rows = vec![];
+        for i in 0..3u32 {
+            let mut map = OwnedMap::new();
+            map.insert_value("id", i);
+            if i != 1 {
+                map.insert_value("score", i as f64 * 1.5);
+            }
+            map.insert_value(
+                "tag",
+                if i == 0 {
+                    OwnedValue::U8(1)
+                } else {
+                    OwnedValue::String("x".to_string())
+                },
+            );
+            rows.push(map);
+        }
+        let batch = RecordBatch::from_rows(&rows);
+        assert_eq!(batch.num_rows, 3);
+        let score = batch.column_by_name("score").unwrap();
+        assert_eq!(score.validity.count_valid(), 2);
+        assert_eq!(score.value(1), SharedValue::Null);
+        assert_eq!(
+            batch.column_by_name("id").unwrap().view(),
+            Some(OwnedPrimArray::U32(vec![0, 1, 2]).shared())
+        );
+        assert!(batch.column_by_name("tag").unwrap().view().is_none());
+        assert_eq!(batch.to_rows(), rows);
+
+        // Explicit nulls and NA are not absent fields
+        let mut with_nulls = rows.clone();
+        with_nulls[0].insert("score", OwnedValue::Null);
+        with_nulls[2].insert("id", OwnedValue::NA);
+        let batch = RecordBatch::from_rows(&with_nulls);
+        let score = batch.column_by_name("score").unwrap();
+        assert_eq!(score.validity.count_valid(), 1);
+        assert!(score.view().is_some());
+        assert!(batch.column_by_name("id").unwrap().view().is_none());
+        assert!(!score.is_present(1));
+        assert_eq!(batch.to_rows(), with_nulls);
+
+        // Columns of arrays are mixed, even when the first of them is empty
+        let mut rows = vec![];
+        for items in [vec![], vec![OwnedValue::U8(1)]] {
+            let mut map = OwnedMap::new();
+            map.insert_value("items", OwnedValue::Array(items));
+            rows.push(map);
+        }
+        let arrays = RecordBatch::from_rows(&rows);
+        assert!(arrays.column_by_name("items").unwrap().view().is_none());
+        assert_eq!(arrays.to_rows(), rows);
+
+        let projected = batch.project(&[key_hash("score")]).unwrap();
+        assert_eq!(projected.columns.len(), 1);
+        assert!(batch.project(&[key_hash("missing")]).is_err());
+    }
+}
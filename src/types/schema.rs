@@ -0,0 +1,614 @@
+use super::*;
+use bifrost::utils::serde::{deserialize, serialize};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+// Versioned record schemas. Fields are identified by the `key_hash` of their names, so a
+// field only keeps its data across versions when renamed explicitly. A new version is
+// derived from the latest one by a list of changes, which are checked when the version is
+// registered so migrating records between registered versions cannot fail on types.
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Field {
+    pub name: String,
+    pub key: u64,
+    pub data_type: Type,
+    pub is_array: bool,
+    pub nullable: bool,
+    pub constraints: Vec<Constraint>,
+    // Value of the field when a record leaves it out
+    pub default: Option<OwnedValue>,
+    // Lisp expression deriving the field from the rest of the record, as in
+    // `(str :first " " :last)`
+    pub computed: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Schema {
+    pub name: String,
+    pub version: u32,
+    pub fields: Vec<Field>,
+    // Predicates over the whole record
+    pub constraints: Vec<String>,
+}
+
+// Checks on the value of a field. They are evaluated as lisp predicates with the field
+// bound to `value` and the record to `record`, keywords in predicates stand for fields of
+// the record as in `(> :age 0u8)`. Ranges and enumerations apply to each element of an
+// array field, null values are only checked for nullability.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Constraint {
+    // Inclusive bounds
+    Range(Option<OwnedValue>, Option<OwnedValue>),
+    // Inclusive bounds on the size of a string or an array
+    Length(Option<usize>, Option<usize>),
+    OneOf(Vec<OwnedValue>),
+    Predicate(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum SchemaChange {
+    // Records of earlier versions do not have the field, so it has to be nullable or have
+    // a default to fill them with
+    AddField(Field),
+    RemoveField(String),
+    RenameField(String, String),
+    // Widens the type of the field, or of the elements of an array field
+    WidenField(String, Type),
+    // Turns the field into an array holding the former value
+    WrapInArray(String),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SchemaRegistry {
+    // Versions of every schema in order, with the changes from the version before
+    schemas: HashMap<String, Vec<(Schema, Vec<SchemaChange>)>>,
+}
+
+impl Field {
+    pub fn new(name: &str, data_type: Type) -> Self {
+        Self {
+            name: name.to_string(),
+            key: key_hash(name),
+            data_type,
+            is_array: false,
+            nullable: false,
+            constraints: vec![],
+            default: None,
+            computed: None,
+        }
+    }
+
+    pub fn array(mut self) -> Self {
+        self.is_array = true;
+        self
+    }
+
+    pub fn nullable(mut self) -> Self {
+        self.nullable = true;
+        self
+    }
+
+    pub fn constraint(mut self, constraint: Constraint) -> Self {
+        self.constraints.push(constraint);
+        self
+    }
+
+    pub fn default(mut self, value: OwnedValue) -> Self {
+        self.default = Some(value);
+        self
+    }
+
+    pub fn computed(mut self, expr: &str) -> Self {
+        self.computed = Some(expr.to_string());
+        self
+    }
+}
+
+impl Schema {
+    pub fn new(name: &str, fields: Vec<Field>) -> Result<Self, String> {
+        let schema = Self {
+            name: name.to_string(),
+            version: 1,
+            fields,
+            constraints: vec![],
+        };
+        schema.check()?;
+        Ok(schema)
+    }
+
+    pub fn constraint(mut self, predicate: &str) -> Self {
+        self.constraints.push(predicate.to_string());
+        self
+    }
+
+    pub fn field(&self, name: &str) -> Option<&Field> {
+        self.field_by_key(key_hash(name))
+    }
+
+    pub fn field_by_key(&self, key: u64) -> Option<&Field> {
+        self.fields.iter().find(|f| f.key == key)
+    }
+
+    fn field_mut(&mut self, name: &str) -> Result<&mut Field, String> {
+        let schema = self.name.clone();
+        let key = key_hash(name);
+        self.fields
+            .iter_mut()
+            .find(|f| f.key == key)
+            .ok_or_else(|| format!("Schema {} has no field {}", schema, name))
+    }
+
+    fn check(&self) -> Result<(), String> {
+        let mut keys: HashMap<u64, &str> = HashMap::new();
+        for field in &self.fields {
+            if field.key != key_hash(&field.name) {
+                return Err(format!(
+                    "Key of field {} does not match its name",
+                    field.name
+                ));
+            }
+            match keys.insert(field.key, &field.name) {
+                Some(name) if name == field.name => {
+                    return Err(format!("Field {} is defined more than once", field.name));
+                }
+                Some(name) => {
+                    return Err(format!(
+                        "Field '{}' collides with '{}' on key id {}",
+                        field.name, name, field.key
+                    ));
+                }
+                None => {}
+            }
+            if field.default.is_some() && field.computed.is_some() {
+                return Err(format!(
+                    "Field {} cannot have both a default and a computed value",
+                    field.name
+                ));
+            }
+            if let Some(default) = &field.default {
+                let matches = match default {
+                    OwnedValue::PrimArray(array) => {
+                        field.is_array && array.base_type() == field.data_type
+                    }
+                    OwnedValue::Array(values) => {
+                        field.is_array && values.iter().all(|v| v.base_type() == field.data_type)
+                    }
+                    value => !field.is_array && value.base_type() == field.data_type,
+                };
+                if !matches {
+                    return Err(format!(
+                        "Default of field {} does not match its type, found {:?}",
+                        field.name, default
+                    ));
+                }
+            }
+            for constraint in &field.constraints {
+                let values: Vec<&OwnedValue> = match constraint {
+                    Constraint::Range(min, max) => min.iter().chain(max.iter()).collect(),
+                    Constraint::OneOf(values) => values.iter().collect(),
+                    Constraint::Length(_, _) | Constraint::Predicate(_) => vec![],
+                };
+                if let Some(value) = values.iter().find(|v| v.base_type() != field.data_type) {
+                    return Err(format!(
+                        "Constraint on field {} does not match its type, found {:?}",
+                        field.name, value
+                    ));
+                }
+            }
+            if field.data_type.size().is_none() && !Self::variable_sized(field.data_type) {
+                return Err(format!(
+                    "Field {} has no primitive type, found {:?}",
+                    field.name, field.data_type
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn variable_sized(t: Type) -> bool {
+        matches!(t, Type::String | Type::Bytes | Type::SmallBytes)
+    }
+
+    // The next version, rejecting changes that would lose data or need a value to be made up
+    pub fn apply(&self, changes: &[SchemaChange]) -> Result<Schema, String> {
+        let mut next = self.clone();
+        next.version += 1;
+        for change in changes {
+            match change {
+                SchemaChange::AddField(field) => {
+                    if next.field_by_key(field.key).is_some() {
+                        return Err(format!("Field {} already exists", field.name));
+                    }
+                    if !field.nullable && field.default.is_none() {
+                        return Err(format!(
+                            "Added field {} must be nullable or have a default, earlier records do not have it",
+                            field.name
+                        ));
+                    }
+                    next.fields.push(field.clone());
+                }
+                SchemaChange::RemoveField(name) => {
+                    next.field_mut(name)?;
+                    next.fields.retain(|f| f.name != *name);
+                }
+                SchemaChange::RenameField(from, to) => {
+                    if next.field(to).is_some() {
+                        return Err(format!("Cannot rename {} to existing field {}", from, to));
+                    }
+                    let field = next.field_mut(from)?;
+                    field.name = to.clone();
+                    field.key = key_hash(to);
+                }
+                SchemaChange::WidenField(name, to) => {
+                    let field = next.field_mut(name)?;
+                    if !is_widening(field.data_type, *to) {
+                        return Err(format!(
+                            "Cannot change field {} from {} to {}, only widening is allowed",
+                            name,
+                            get_type(field.data_type),
+                            get_type(*to)
+                        ));
+                    }
+                    field.data_type = *to;
+                    if let Some(default) = field.default.take() {
+                        field.default = Some(widen_field_value(default, *to)?);
+                    }
+                    for constraint in &mut field.constraints {
+                        widen_constraint(constraint, *to)?;
+                    }
+                }
+                SchemaChange::WrapInArray(name) => {
+                    let field = next.field_mut(name)?;
+                    if field.is_array {
+                        return Err(format!("Field {} is already an array", name));
+                    }
+                    field.is_array = true;
+                    field.default = field.default.take().map(wrap_in_array);
+                }
+            }
+        }
+        next.check()?;
+        Ok(next)
+    }
+}
+
+// Widens a value or every element of an array value
+fn widen_field_value(value: OwnedValue, to: Type) -> Result<OwnedValue, String> {
+    Ok(match value {
+        OwnedValue::PrimArray(array) => {
+            let values = (0..array.len())
+                .map(|i| widen_value(array.get_owned(i).unwrap(), to))
+                .collect::<Result<Vec<_>, _>>()?;
+            OwnedValue::PrimArray(OwnedPrimArray::from_typed_values(to, values)?)
+        }
+        OwnedValue::Array(values) => OwnedValue::Array(
+            values
+                .into_iter()
+                .map(|v| widen_value(v, to))
+                .collect::<Result<Vec<_>, _>>()?,
+        ),
+        value => widen_value(value, to)?,
+    })
+}
+
+// Bounds and enumerated values follow the elements they are compared with
+fn widen_constraint(constraint: &mut Constraint, to: Type) -> Result<(), String> {
+    let values: Vec<&mut OwnedValue> = match constraint {
+        Constraint::Range(min, max) => min.iter_mut().chain(max.iter_mut()).collect(),
+        Constraint::OneOf(values) => values.iter_mut().collect(),
+        Constraint::Length(_, _) | Constraint::Predicate(_) => vec![],
+    };
+    for value in values {
+        *value = widen_value(std::mem::replace(value, OwnedValue::Null), to)?;
+    }
+    Ok(())
+}
+
+// Composite values, empty arrays included, have no element type to pack them with
+fn wrap_in_array(value: OwnedValue) -> OwnedValue {
+    if value.len().is_none() {
+        let t = value.base_type();
+        if let Ok(array) = OwnedPrimArray::from_typed_values(t, vec![value.clone()]) {
+            return OwnedValue::PrimArray(array);
+        }
+    }
+    OwnedValue::Array(vec![value])
+}
+
+// Whether every value of the type is represented exactly by the wider one
+pub fn is_widening(from: Type, to: Type) -> bool {
+    use Type::*;
+    if from == to {
+        return true;
+    }
+    match from {
+        U8 => matches!(to, U16 | U32 | U64 | I16 | I32 | I64 | F32 | F64),
+        U16 => matches!(to, U32 | U64 | I32 | I64 | F32 | F64),
+        U32 => matches!(to, U64 | I64 | F64),
+        I8 => matches!(to, I16 | I32 | I64 | F32 | F64),
+        I16 => matches!(to, I32 | I64 | F32 | F64),
+        I32 => matches!(to, I64 | F64),
+        F32 => to == F64,
+        Pos2d32 => to == Pos2d64,
+        Pos3d32 => to == Pos3d64,
+        Char => to == String,
+        SmallBytes => to == Bytes,
+        _ => false,
+    }
+}
+
+fn integer(value: &OwnedValue) -> Option<i128> {
+    match value {
+        OwnedValue::I8(n) => Some(*n as i128),
+        OwnedValue::I16(n) => Some(*n as i128),
+        OwnedValue::I32(n) => Some(*n as i128),
+        OwnedValue::I64(n) => Some(*n as i128),
+        OwnedValue::U8(n) => Some(*n as i128),
+        OwnedValue::U16(n) => Some(*n as i128),
+        OwnedValue::U32(n) => Some(*n as i128),
+        OwnedValue::U64(n) => Some(*n as i128),
+        _ => None,
+    }
+}
+
+macro_rules! widen_int {
+    ($e: ident, $t: ty, $n: expr) => {
+        <$t>::try_from($n)
+            .map(OwnedValue::$e)
+            .map_err(|e| e.to_string())
+    };
+}
+
+// Converts a value to a type accepted by `is_widening`
+pub fn widen_value(value: OwnedValue, to: Type) -> Result<OwnedValue, String> {
+    if value.base_type() == to || value == OwnedValue::Null {
+        return Ok(value);
+    }
+    if let Some(n) = integer(&value) {
+        return match to {
+            Type::I16 => widen_int!(I16, i16, n),
+            Type::I32 => widen_int!(I32, i32, n),
+            Type::I64 => widen_int!(I64, i64, n),
+            Type::U16 => widen_int!(U16, u16, n),
+            Type::U32 => widen_int!(U32, u32, n),
+            Type::U64 => widen_int!(U64, u64, n),
+            Type::F32 => Ok(OwnedValue::F32(n as f32)),
+            Type::F64 => Ok(OwnedValue::F64(n as f64)),
+            _ => Err(format!("Cannot widen {:?} to {}", value, get_type(to))),
+        };
+    }
+    match (value, to) {
+        (OwnedValue::F32(n), Type::F64) => Ok(OwnedValue::F64(n as f64)),
+        (OwnedValue::Pos2d32(p), Type::Pos2d64) => Ok(OwnedValue::Pos2d64(Pos2d64 {
+            x: p.x as f64,
+            y: p.y as f64,
+        })),
+        (OwnedValue::Pos3d32(p), Type::Pos3d64) => Ok(OwnedValue::Pos3d64(Pos3d64 {
+            x: p.x as f64,
+            y: p.y as f64,
+            z: p.z as f64,
+        })),
+        (OwnedValue::Char(c), Type::String) => Ok(OwnedValue::String(c.to_string())),
+        (OwnedValue::SmallBytes(b), Type::Bytes) => Ok(OwnedValue::Bytes(Bytes::from_vec(b.data))),
+        (value, to) => Err(format!("Cannot widen {:?} to {}", value, get_type(to))),
+    }
+}
+
+fn migrate_field(record: &mut OwnedMap, change: &SchemaChange) -> Result<(), String> {
+    match change {
+        SchemaChange::AddField(field) => {
+            if let Some(default) = &field.default {
+                if record.get(&field.name) == &OwnedValue::Null {
+                    record.try_insert(&field.name, default.clone())?;
+                }
+            }
+        }
+        SchemaChange::RemoveField(name) => {
+            record.remove(name);
+        }
+        SchemaChange::RenameField(from, to) => {
+            if let Some(value) = record.remove(from) {
+                record.try_insert(to, value)?;
+            }
+        }
+        SchemaChange::WidenField(name, to) => {
+            let value = match record.remove(name) {
+                Some(value) => value,
+                None => return Ok(()),
+            };
+            record.try_insert(name, widen_field_value(value, *to)?)?;
+        }
+        SchemaChange::WrapInArray(name) => {
+            let value = match record.remove(name) {
+                Some(OwnedValue::Null) | None => return Ok(()),
+                Some(value) => value,
+            };
+            record.try_insert(name, wrap_in_array(value))?;
+        }
+    }
+    Ok(())
+}
+
+impl SchemaRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Registers the first version of a schema
+    pub fn register(&mut self, schema: Schema) -> Result<u32, String> {
+        if self.schemas.contains_key(&schema.name) {
+            return Err(format!("Schema {} is already registered", schema.name));
+        }
+        schema.check()?;
+        let version = schema.version;
+        self.schemas
+            .insert(schema.name.clone(), vec![(schema, vec![])]);
+        Ok(version)
+    }
+
+    // Registers a new version of the schema made by the changes
+    pub fn evolve(&mut self, name: &str, changes: Vec<SchemaChange>) -> Result<u32, String> {
+        let versions = self
+            .schemas
+            .get_mut(name)
+            .ok_or_else(|| format!("Schema {} is not registered", name))?;
+        let next = versions.last().unwrap().0.apply(&changes)?;
+        let version = next.version;
+        versions.push((next, changes));
+        Ok(version)
+    }
+
+    pub fn get(&self, name: &str, version: u32) -> Option<&Schema> {
+        self.schemas
+            .get(name)?
+            .iter()
+            .map(|(schema, _)| schema)
+            .find(|schema| schema.version == version)
+    }
+
+    pub fn latest(&self, name: &str) -> Option<&Schema> {
+        self.schemas.get(name)?.last().map(|(schema, _)| schema)
+    }
+
+    // Upgrades a record of version `from` to version `to`
+    pub fn migrate(
+        &self,
+        name: &str,
+        mut record: OwnedMap,
+        from: u32,
+        to: u32,
+    ) -> Result<OwnedMap, String> {
+        let versions = self
+            .schemas
+            .get(name)
+            .ok_or_else(|| format!("Schema {} is not registered", name))?;
+        if from > to {
+            return Err(format!(
+                "Cannot migrate {} down from version {} to {}",
+                name, from, to
+            ));
+        }
+        if !versions.iter().any(|(schema, _)| schema.version == from) {
+            return Err(format!("Schema {} has no version {}", name, from));
+        }
+        for version in from + 1..=to {
+            let (_, changes) = versions
+                .iter()
+                .find(|(schema, _)| schema.version == version)
+                .ok_or_else(|| format!("Schema {} has no version {}", name, version))?;
+            for change in changes {
+                migrate_field(&mut record, change)?;
+            }
+        }
+        Ok(record)
+    }
+
+    // Upgrades a record serialized as an owned value
+    pub fn migrate_encoded(
+        &self,
+        name: &str,
+        data: &[u8],
+        from: u32,
+        to: u32,
+    ) -> Result<Vec<u8>, String> {
+        let record = match deserialize::<OwnedValue>(data) {
+            Some(OwnedValue::Map(map)) => map,
+            Some(other) => return Err(format!("Expect record, found {:?}", other)),
+            None => return Err("Cannot decode record".to_string()),
+        };
+        let record = self.migrate(name, record, from, to)?;
+        Ok(serialize(&OwnedValue::Map(record)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::types::*;
+    use bifrost::utils::serde::{deserialize, serialize};
+
+    #[test]
+    fn evolution() {
+        let mut registry = SchemaRegistry::new();
+        let schema = Schema::new(
+            "person",
+            vec![
+                Field::new("name", Type::String),
+                Field::new("age", Type::U8)
+                    .constraint(Constraint::Range(Some(OwnedValue::U8(1)), None)),
+                Field::new("score", Type::U16).array(),
+                Field::new("nick", Type::String).nullable(),
+            ],
+        )
+        .unwrap();
+        assert_eq!(registry.register(schema).unwrap(), 1);
+        let changes = vec![
+            SchemaChange::RenameField("name".to_string(), "full_name".to_string()),
+            SchemaChange::WidenField("age".to_string(), Type::U32),
+            SchemaChange::WidenField("score".to_string(), Type::F64),
+            SchemaChange::RemoveField("nick".to_string()),
+            SchemaChange::AddField(Field::new("email", Type::String).nullable()),
+        ];
+        assert_eq!(registry.evolve("person", changes).unwrap(), 2);
+        assert_eq!(
+            registry
+                .latest("person")
+                .unwrap()
+                .field("age")
+                .unwrap()
+                .constraints,
+            vec![Constraint::Range(Some(OwnedValue::U32(1)), None)]
+        );
+        let changes = vec![
+            SchemaChange::WrapInArray("age".to_string()),
+            SchemaChange::AddField(Field::new("level", Type::U8).default(OwnedValue::U8(1))),
+        ];
+        assert_eq!(registry.evolve("person", changes).unwrap(), 3);
+
+        // Rejected when registered
+        let narrowing = vec![SchemaChange::WidenField("full_name".to_string(), Type::U8)];
+        assert!(registry.evolve("person", narrowing).is_err());
+        let required = vec![SchemaChange::AddField(Field::new("phone", Type::String))];
+        assert!(registry.evolve("person", required).is_err());
+        let mismatched = Field::new("rank", Type::U16)
+            .nullable()
+            .constraint(Constraint::OneOf(vec![OwnedValue::U8(1)]));
+        assert!(registry
+            .evolve("person", vec![SchemaChange::AddField(mismatched)])
+            .is_err());
+        let missing = vec![SchemaChange::RemoveField("name".to_string())];
+        assert!(registry.evolve("person", missing).is_err());
+        assert_eq!(registry.latest("person").unwrap().version, 3);
+
+        let mut record = OwnedMap::new();
+        record.insert_value("name", "Dova".to_string());
+        record.insert_value("age", 30u8);
+        record.insert_value("score", vec![1u16, 2]);
+        record.insert_value("nick", "D".to_string());
+        let migrated = registry.migrate("person", record.clone(), 1, 3).unwrap();
+        let mut expected = OwnedMap::new();
+        expected.insert_value("score", vec![1f64, 2f64]);
+        expected.insert_value("full_name", "Dova".to_string());
+        expected.insert_value("age", vec![30u32]);
+        expected.insert_value("level", 1u8);
+        assert_eq!(migrated.len(), 4);
+        for field in &["full_name", "age", "score", "level"] {
+            assert_eq!(migrated.get(field), expected.get(field));
+        }
+        assert!(registry.migrate("person", migrated, 3, 1).is_err());
+        assert!(registry.migrate("person", record.clone(), 0, 3).is_err());
+        // Composite values are wrapped as they are, empty arrays included
+        let mut nested = OwnedMap::new();
+        nested.insert_value("age", OwnedValue::Array(vec![]));
+        let wrapped = registry.migrate("person", nested, 2, 3).unwrap();
+        assert_eq!(
+            wrapped.get("age"),
+            &OwnedValue::Array(vec![OwnedValue::Array(vec![])])
+        );
+
+        let encoded = serialize(&OwnedValue::Map(record));
+        let encoded = registry.migrate_encoded("person", &encoded, 1, 2).unwrap();
+        let decoded: OwnedValue = deserialize(&encoded).unwrap();
+        assert_eq!(decoded.Map().unwrap().get("age"), &OwnedValue::U32(30));
+    }
+}
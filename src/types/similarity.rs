@@ -0,0 +1,240 @@
+use super::*;
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    Cosine,
+    Dot,
+    L2,
+}
+
+trait Float: Copy {
+    fn as_f64(self) -> f64;
+    fn from_f64(val: f64) -> Self;
+}
+
+impl Float for f32 {
+    fn as_f64(self) -> f64 {
+        self as f64
+    }
+    fn from_f64(val: f64) -> Self {
+        val as f32
+    }
+}
+
+impl Float for f64 {
+    fn as_f64(self) -> f64 {
+        self
+    }
+    fn from_f64(val: f64) -> Self {
+        val
+    }
+}
+
+macro_rules! float_pair {
+    ($a: expr, $b: expr, $x: ident, $y: ident => $body: expr) => {
+        match ($a, $b) {
+            (SharedPrimArray::F32($x), SharedPrimArray::F32($y)) => $body,
+            (SharedPrimArray::F64($x), SharedPrimArray::F64($y)) => $body,
+            (a, b) => Err(format!(
+                "Vector operations require f32 or f64 arrays of the same type, found {} and {}",
+                get_type(a.base_type()),
+                get_type(b.base_type())
+            )),
+        }
+    };
+}
+
+fn check_dims(a: usize, b: usize) -> Result<(), String> {
+    if a != b {
+        return Err(format!("Vector dimensions not match, {} and {}", a, b));
+    }
+    Ok(())
+}
+
+fn dot_typed<T: Float>(a: &[T], b: &[T]) -> Result<f64, String> {
+    check_dims(a.len(), b.len())?;
+    Ok(a.iter().zip(b).map(|(x, y)| x.as_f64() * y.as_f64()).sum())
+}
+
+fn l2_typed<T: Float>(a: &[T], b: &[T]) -> Result<f64, String> {
+    check_dims(a.len(), b.len())?;
+    let sum: f64 = a
+        .iter()
+        .zip(b)
+        .map(|(x, y)| {
+            let d = x.as_f64() - y.as_f64();
+            d * d
+        })
+        .sum();
+    Ok(sum.sqrt())
+}
+
+fn cosine_typed<T: Float>(a: &[T], b: &[T]) -> Result<f64, String> {
+    check_dims(a.len(), b.len())?;
+    let (mut dot, mut norm_a, mut norm_b) = (0f64, 0f64, 0f64);
+    for (x, y) in a.iter().zip(b) {
+        let (x, y) = (x.as_f64(), y.as_f64());
+        dot += x * y;
+        norm_a += x * x;
+        norm_b += y * y;
+    }
+    if norm_a == 0f64 || norm_b == 0f64 {
+        return Ok(0f64);
+    }
+    Ok(dot / (norm_a.sqrt() * norm_b.sqrt()))
+}
+
+fn normalize_typed<T: Float>(a: &[T]) -> Vec<T> {
+    let norm = a
+        .iter()
+        .map(|x| x.as_f64() * x.as_f64())
+        .sum::<f64>()
+        .sqrt();
+    if norm == 0f64 {
+        return a.to_vec();
+    }
+    a.iter().map(|x| T::from_f64(x.as_f64() / norm)).collect()
+}
+
+pub fn dot(a: &SharedPrimArray, b: &SharedPrimArray) -> Result<f64, String> {
+    float_pair!(a, b, x, y => dot_typed(x, y))
+}
+
+pub fn cosine_similarity(a: &SharedPrimArray, b: &SharedPrimArray) -> Result<f64, String> {
+    float_pair!(a, b, x, y => cosine_typed(x, y))
+}
+
+pub fn l2_distance(a: &SharedPrimArray, b: &SharedPrimArray) -> Result<f64, String> {
+    float_pair!(a, b, x, y => l2_typed(x, y))
+}
+
+pub fn normalize(a: &SharedPrimArray) -> Result<OwnedPrimArray, String> {
+    match a {
+        SharedPrimArray::F32(x) => Ok(OwnedPrimArray::F32(normalize_typed(x))),
+        SharedPrimArray::F64(x) => Ok(OwnedPrimArray::F64(normalize_typed(x))),
+        _ => Err(format!(
+            "Only f32 or f64 vector can be normalized, found {}",
+            get_type(a.base_type())
+        )),
+    }
+}
+
+impl Metric {
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        match name {
+            "cosine" => Ok(Metric::Cosine),
+            "dot" => Ok(Metric::Dot),
+            "l2" => Ok(Metric::L2),
+            _ => Err(format!("Unknown similarity metric '{}'", name)),
+        }
+    }
+    // Higher score means more similar for every metric, so L2 distances are negated
+    pub fn score(&self, a: &SharedPrimArray, b: &SharedPrimArray) -> Result<f64, String> {
+        match self {
+            Metric::Cosine => cosine_similarity(a, b),
+            Metric::Dot => dot(a, b),
+            Metric::L2 => l2_distance(a, b).map(|d| -d),
+        }
+    }
+}
+
+#[derive(PartialEq)]
+struct Scored(f64, usize);
+
+impl Eq for Scored {}
+
+impl PartialOrd for Scored {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Scored {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Ties prefer the earlier candidate
+        self.0
+            .total_cmp(&other.0)
+            .then_with(|| other.1.cmp(&self.1))
+    }
+}
+
+// Indices and scores of the k candidates most similar to the query, best first.
+// Candidates without a vector are skipped.
+pub fn top_k<'a, I>(
+    query: &SharedPrimArray,
+    candidates: I,
+    k: usize,
+    metric: Metric,
+) -> Result<Vec<(usize, f64)>, String>
+where
+    I: IntoIterator<Item = Option<SharedPrimArray<'a>>>,
+{
+    let mut heap = BinaryHeap::with_capacity(k + 1);
+    if k == 0 {
+        return Ok(vec![]);
+    }
+    for (index, candidate) in candidates.into_iter().enumerate() {
+        if let Some(vector) = candidate {
+            let score = metric.score(query, &vector)?;
+            heap.push(Reverse(Scored(score, index)));
+            if heap.len() > k {
+                heap.pop();
+            }
+        }
+    }
+    Ok(heap
+        .into_sorted_vec()
+        .into_iter()
+        .map(|Reverse(Scored(score, index))| (index, score))
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::types::similarity::*;
+
+    #[test]
+    fn metrics() {
+        let a = OwnedPrimArray::F32(vec![1.0, 0.0]);
+        let b = OwnedPrimArray::F32(vec![3.0, 4.0]);
+        assert_eq!(dot(&a.shared(), &b.shared()).unwrap(), 3.0);
+        assert_eq!(l2_distance(&a.shared(), &b.shared()).unwrap(), 20f64.sqrt());
+        assert!((cosine_similarity(&a.shared(), &b.shared()).unwrap() - 0.6).abs() < 1e-9);
+        assert_eq!(
+            normalize(&b.shared()).unwrap(),
+            OwnedPrimArray::F32(vec![0.6, 0.8])
+        );
+        let c = OwnedPrimArray::F64(vec![1.0, 0.0]);
+        assert!(dot(&a.shared(), &c.shared()).is_err());
+    }
+
+    #[test]
+    fn ranking() {
+        let query = OwnedPrimArray::F64(vec![1.0, 0.0]);
+        let candidates = vec![
+            OwnedPrimArray::F64(vec![0.0, 1.0]),
+            OwnedPrimArray::F64(vec![1.0, 0.1]),
+            OwnedPrimArray::F64(vec![1.0, 1.0]),
+        ];
+        let ranked = top_k(
+            &query.shared(),
+            candidates.iter().map(|c| Some(c.shared())),
+            2,
+            Metric::Cosine,
+        )
+        .unwrap();
+        let indices: Vec<_> = ranked.iter().map(|(i, _)| *i).collect();
+        assert_eq!(indices, vec![1, 2]);
+        let ranked = top_k(
+            &query.shared(),
+            candidates.iter().map(|c| Some(c.shared())),
+            3,
+            Metric::L2,
+        )
+        .unwrap();
+        let indices: Vec<_> = ranked.iter().map(|(i, _)| *i).collect();
+        assert_eq!(indices, vec![1, 2, 0]);
+    }
+}
@@ -0,0 +1,172 @@
+use super::*;
+
+// Element-wise kernels over primitive arrays. Operands must share the element type and
+// either have the same length or one of them holds a single element to be broadcasted.
+// Arithmetic and reductions are those of tensors.
+
+// Orderings only, `=` and `!=` compare arrays as whole values
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareOp {
+    Lt,
+    Lte,
+    Gt,
+    Gte,
+}
+
+fn other_slice<'a, T: TensorElement>(
+    a: &SharedPrimArray,
+    b: &SharedPrimArray<'a>,
+) -> Result<&'a [T], String> {
+    T::slice_of(b).ok_or_else(|| {
+        format!(
+            "Array element type not match, {} and {}",
+            get_type(a.base_type()),
+            get_type(b.base_type())
+        )
+    })
+}
+
+fn broadcast<T: Copy, R, F>(a: &[T], b: &[T], mut f: F) -> Result<Vec<R>, String>
+where
+    F: FnMut(T, T) -> R,
+{
+    match (a.len(), b.len()) {
+        (x, y) if x == y => Ok(a.iter().zip(b).map(|(x, y)| f(*x, *y)).collect()),
+        (1, _) => {
+            let x = a[0];
+            Ok(b.iter().map(|y| f(x, *y)).collect())
+        }
+        (_, 1) => {
+            let y = b[0];
+            Ok(a.iter().map(|x| f(*x, y)).collect())
+        }
+        (x, y) => Err(format!("Array length not match, {} and {}", x, y)),
+    }
+}
+
+// Failed elements are flagged and left zero, so every op is a plain loop of its own
+fn flag<T: TensorElement>(res: Option<T>, failed: &mut bool) -> T {
+    res.unwrap_or_else(|| {
+        *failed = true;
+        T::zero()
+    })
+}
+
+fn arith_typed<T: TensorElement>(a: &[T], b: &[T], op: TensorOp) -> Result<Vec<T>, String> {
+    let mut failed = false;
+    let res = match op {
+        TensorOp::Add => broadcast(a, b, |x, y| flag(x.checked_add(y), &mut failed)),
+        TensorOp::Sub => broadcast(a, b, |x, y| flag(x.checked_sub(y), &mut failed)),
+        TensorOp::Mul => broadcast(a, b, |x, y| flag(x.checked_mul(y), &mut failed)),
+        TensorOp::Div => broadcast(a, b, |x, y| flag(x.checked_div(y), &mut failed)),
+    }?;
+    match (failed, op) {
+        (false, _) => Ok(res),
+        (true, TensorOp::Div) => Err("Array division by zero or overflow".to_string()),
+        (true, op) => Err(format!("Array {:?} overflow", op)),
+    }
+}
+
+fn compare_typed<T: TensorElement>(a: &[T], b: &[T], op: CompareOp) -> Result<Vec<bool>, String> {
+    match op {
+        CompareOp::Lt => broadcast(a, b, |x, y| x < y),
+        CompareOp::Lte => broadcast(a, b, |x, y| x <= y),
+        CompareOp::Gt => broadcast(a, b, |x, y| x > y),
+        CompareOp::Gte => broadcast(a, b, |x, y| x >= y),
+    }
+}
+
+pub fn arith(
+    a: &SharedPrimArray,
+    b: &SharedPrimArray,
+    op: TensorOp,
+) -> Result<OwnedPrimArray, String> {
+    numeric_dispatch!(a, x => Ok(TensorElement::into_prim_array(
+        arith_typed(x, other_slice(a, b)?, op)?
+    )))
+}
+
+// Produces a bool mask of the comparison between each pair of elements
+pub fn compare(
+    a: &SharedPrimArray,
+    b: &SharedPrimArray,
+    op: CompareOp,
+) -> Result<OwnedPrimArray, String> {
+    numeric_dispatch!(a, x => Ok(OwnedPrimArray::Bool(
+        compare_typed(x, other_slice(a, b)?, op)?
+    )))
+}
+
+pub fn reduce(a: &SharedPrimArray, op: TensorReduce) -> Result<OwnedValue, String> {
+    numeric_dispatch!(a, x => reduce_values(x, op))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::types::vectorized::*;
+
+    #[test]
+    fn kernels() {
+        let a = OwnedPrimArray::U32(vec![1, 2, 3]);
+        let b = OwnedPrimArray::U32(vec![4, 5, 6]);
+        let two = OwnedPrimArray::U32(vec![2]);
+        assert_eq!(
+            arith(&a.shared(), &b.shared(), TensorOp::Add).unwrap(),
+            OwnedPrimArray::U32(vec![5, 7, 9])
+        );
+        assert_eq!(
+            arith(&two.shared(), &b.shared(), TensorOp::Mul).unwrap(),
+            OwnedPrimArray::U32(vec![8, 10, 12])
+        );
+        assert_eq!(
+            compare(&a.shared(), &two.shared(), CompareOp::Gte).unwrap(),
+            OwnedPrimArray::Bool(vec![false, true, true])
+        );
+        assert!(arith(
+            &a.shared(),
+            &OwnedPrimArray::U32(vec![1, 0]).shared(),
+            TensorOp::Add
+        )
+        .is_err());
+        assert!(arith(
+            &a.shared(),
+            &OwnedPrimArray::U32(vec![0]).shared(),
+            TensorOp::Div
+        )
+        .is_err());
+        assert!(arith(
+            &a.shared(),
+            &OwnedPrimArray::U64(vec![1]).shared(),
+            TensorOp::Add
+        )
+        .is_err());
+        assert_eq!(
+            reduce(&b.shared(), TensorReduce::Sum).unwrap(),
+            OwnedValue::U32(15)
+        );
+        assert!(arith(
+            &OwnedPrimArray::U8(vec![200]).shared(),
+            &OwnedPrimArray::U8(vec![100]).shared(),
+            TensorOp::Add
+        )
+        .is_err());
+        assert!(reduce(
+            &OwnedPrimArray::I8(vec![100, 100]).shared(),
+            TensorReduce::Sum
+        )
+        .is_err());
+        assert_eq!(
+            reduce(&b.shared(), TensorReduce::Min).unwrap(),
+            OwnedValue::U32(4)
+        );
+        assert_eq!(
+            reduce(&b.shared(), TensorReduce::Mean).unwrap(),
+            OwnedValue::F64(5.0)
+        );
+        assert!(reduce(&OwnedPrimArray::F32(vec![]).shared(), TensorReduce::Max).is_err());
+        assert_eq!(
+            reduce(&OwnedPrimArray::F32(vec![]).shared(), TensorReduce::Sum).unwrap(),
+            OwnedValue::F32(0.0)
+        );
+    }
+}
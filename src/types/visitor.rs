@@ -0,0 +1,163 @@
+use super::*;
+use std::collections::HashSet;
+
+// Values that can be walked with a `ValueVisitor`, implemented for both representations
+pub trait Walk<'a> {
+    fn walk<V: ValueVisitor<'a>>(self, visitor: &mut V);
+}
+
+pub fn walk<'a, W: Walk<'a>, V: ValueVisitor<'a>>(value: W, visitor: &mut V) {
+    value.walk(visitor)
+}
+
+// Map keys in the order of the field names, keys without a name follow in ascending order
+pub(crate) fn ordered_keys<'f, F, K>(fields: F, keys: K) -> Vec<(u64, Option<&'f String>)>
+where
+    F: Iterator<Item = &'f String>,
+    K: Iterator<Item = u64>,
+{
+    let mut keys: Vec<u64> = keys.collect();
+    let present: HashSet<u64> = keys.iter().cloned().collect();
+    let mut named = HashSet::new();
+    let mut res = Vec::with_capacity(keys.len());
+    for name in fields {
+        let key = key_hash(name);
+        if present.contains(&key) && named.insert(key) {
+            res.push((key, Some(name)));
+        }
+    }
+    keys.retain(|k| !named.contains(k));
+    keys.sort();
+    res.extend(keys.into_iter().map(|k| (k, None)));
+    res
+}
+
+pub(crate) fn walk_elements<'a, W, I, V>(elements: I, visitor: &mut V)
+where
+    W: Walk<'a>,
+    I: ExactSizeIterator<Item = W>,
+    V: ValueVisitor<'a>,
+{
+    visitor.enter_array(elements.len());
+    for (index, element) in elements.enumerate() {
+        visitor.array_element(index);
+        element.walk(visitor);
+    }
+    visitor.exit_array();
+}
+
+pub(crate) fn walk_owned_map<'a, V: ValueVisitor<'a>>(map: &'a OwnedMap, visitor: &mut V) {
+    let keys = ordered_keys(map.fields.iter(), map.map.keys().cloned());
+    visitor.enter_map(keys.len());
+    for (key, name) in keys {
+        visitor.map_entry(key, name.map(|n| n.as_str()));
+        map.map[&key].walk(visitor);
+    }
+    visitor.exit_map();
+}
+
+pub(crate) fn walk_shared_map<'a, V: ValueVisitor<'a>>(map: &SharedMap<'a>, visitor: &mut V) {
+    let keys = ordered_keys(map.fields().iter(), map.iter().map(|(k, _)| k));
+    visitor.enter_map(keys.len());
+    for (key, name) in keys {
+        visitor.map_entry(key, name.map(|n| n.as_str()));
+        map.get_by_key_id(key).walk(visitor);
+    }
+    visitor.exit_map();
+}
+
+pub(crate) fn walk_persistent_map<'a, V: ValueVisitor<'a>>(
+    map: &'a PersistentMap,
+    visitor: &mut V,
+) {
+    let keys = ordered_keys(map.fields(), map.keys().cloned());
+    visitor.enter_map(keys.len());
+    for (key, name) in keys {
+        visitor.map_entry(key, name.map(|n| n.as_str()));
+        map.get_by_key_id(key).walk(visitor);
+    }
+    visitor.exit_map();
+}
+
+#[cfg(test)]
+mod test {
+    use crate::types::*;
+
+    // Renders values in a compact text form to check the order of the callbacks
+    #[derive(Default)]
+    struct Printer {
+        out: String,
+    }
+
+    impl<'a> ValueVisitor<'a> for Printer {
+        fn u32(&mut self, value: &'a u32) {
+            self.out.push_str(&value.to_string());
+        }
+        fn string(&mut self, value: &'a str) {
+            self.out.push_str(&format!("{:?}", value));
+        }
+        fn null(&mut self) {
+            self.out.push_str("null");
+        }
+        fn enter_map(&mut self, _len: usize) {
+            self.out.push('{');
+        }
+        fn map_entry(&mut self, _key: u64, name: Option<&str>) {
+            if !self.out.ends_with('{') {
+                self.out.push(',');
+            }
+            self.out.push_str(name.unwrap_or("?"));
+            self.out.push(':');
+        }
+        fn exit_map(&mut self) {
+            self.out.push('}');
+        }
+        fn enter_array(&mut self, _len: usize) {
+            self.out.push('[');
+        }
+        fn array_element(&mut self, index: usize) {
+            if index > 0 {
+                self.out.push(',');
+            }
+        }
+        fn exit_array(&mut self) {
+            self.out.push(']');
+        }
+        fn prim_array(&mut self, array: SharedPrimArray<'a>) {
+            self.out.push_str(&format!("<{}>", array.len()));
+        }
+    }
+
+    struct Upper;
+
+    impl ValueVisitorMut for Upper {
+        fn string(&mut self, value: &mut String) {
+            *value = value.to_uppercase();
+        }
+    }
+
+    #[test]
+    fn walking() {
+        let mut map = OwnedMap::new();
+        map.insert_value("name", "dova".to_string());
+        map.insert_value("tags", vec![1u8, 2u8]);
+        map.insert(
+            "items",
+            OwnedValue::Array(vec![OwnedValue::U32(1), OwnedValue::Null]),
+        );
+        let mut value = OwnedValue::Map(map);
+        let expected = r#"{name:"dova",tags:<2>,items:[1,null]}"#;
+
+        let mut printer = Printer::default();
+        walk(&value, &mut printer);
+        assert_eq!(printer.out, expected);
+        let mut printer = Printer::default();
+        walk(&value.shared(), &mut printer);
+        assert_eq!(printer.out, expected);
+
+        walk_mut(&mut value, &mut Upper);
+        let mut printer = Printer::default();
+        walk(&value, &mut printer);
+        assert_eq!(printer.out, r#"{name:"DOVA",tags:<2>,items:[1,null]}"#);
+    }
+}
@@ -1,6 +1,16 @@
+use dovahkiin::error::{Arity, DovahkiinError, Expected};
+use dovahkiin::expr::serde::Expr;
+use dovahkiin::expr::limits::{LimitExceeded, Limits};
+use dovahkiin::expr::record_source::MemorySource;
 use dovahkiin::expr::{SExpr, Value};
 use dovahkiin::integrated::lisp;
-use dovahkiin::types::{OwnedValue, Map};
+use dovahkiin::lexer::lisp as lexer;
+use dovahkiin::lexer::Span;
+use dovahkiin::types::{Id, Map, OwnedMap, OwnedPrimArray, OwnedValue, Type};
+
+use std::rc::Rc;
+use std::thread;
+use std::time::Duration;
 
 extern crate dovahkiin;
 
@@ -132,13 +142,65 @@ pub fn scoping() {
     let str_function = "(def x 1u32)\n
                         (defunc y [] x)\n
                         (let [x 2u32] (y))";
-    // 2 for dynamic scoping, 1 for lexical scoping. Dovahkiin is dynamic scoping
+    // 2 for dynamic scoping, 1 for lexical scoping. Dovahkiin is dynamic scoping by default
     assert_eq!(
         lisp::eval_string(&mut interpreter, str_function).unwrap(),
         SExpr::owned_value(OwnedValue::U32(2))
     );
 }
 
+#[test]
+pub fn lexical_scoping() {
+    let mut interpreter = lisp::get_lexical_interpreter();
+    let str_function = "(def x 1u32)\n
+                        (defunc y [] x)\n
+                        (let [x 2u32] (y))";
+    assert_eq!(
+        lisp::eval_string(&mut interpreter, str_function).unwrap(),
+        SExpr::owned_value(OwnedValue::U32(1))
+    );
+    // Locals of the caller are not visible in the function
+    let str_function = "(defunc f [] z) (let [z 1u32] (f))";
+    assert_ne!(
+        lisp::eval_string(&mut interpreter, str_function).unwrap(),
+        SExpr::owned_value(OwnedValue::U32(1))
+    );
+    // Host bindings made before the switch become definitions
+    let mut interpreter = lisp::get_interpreter();
+    interpreter.bind("w", SExpr::owned_value(OwnedValue::U32(3)));
+    interpreter.set_lexical_scoping(true);
+    assert_eq!(
+        lisp::eval_string(&mut interpreter, "(def w 4u32) (defunc g [] w) (g)").unwrap(),
+        SExpr::owned_value(OwnedValue::U32(4))
+    );
+}
+
+#[test]
+pub fn closures() {
+    let mut interpreter = lisp::get_lexical_interpreter();
+    let str_function = "(defunc adder [n] (lambda [x] (+ x n)))\n
+                        (def add2 (adder 2u32))\n
+                        (let [n 10u32] (map add2 [1u32 2u32]))";
+    assert_eq!(
+        lisp::eval_string(&mut interpreter, str_function).unwrap(),
+        SExpr::Vec(vec![
+            SExpr::owned_value(OwnedValue::U32(3)),
+            SExpr::owned_value(OwnedValue::U32(4))
+        ])
+    );
+    let str_function = "(let [f (let [y 5u32] (lambda [x] (* x y))) y 0u32] (f (+ 1u32 1u32)))";
+    assert_eq!(
+        lisp::eval_string(&mut interpreter, str_function).unwrap(),
+        SExpr::owned_value(OwnedValue::U32(10))
+    );
+    // Recursion goes through the global definition
+    let str_function = "(defunc fact [n] (if (= n 0u64) 1u64 (* n (fact (- n 1u64))))) (fact 5u64)";
+    assert_eq!(
+        lisp::eval_string(&mut interpreter, str_function).unwrap(),
+        SExpr::owned_value(OwnedValue::U64(120))
+    );
+}
+
 #[test]
 pub fn or() {
     let mut interpreter = lisp::get_interpreter();
@@ -162,4 +224,501 @@ pub fn keyword() {
     assert_eq!(
         map.get("y").u64().unwrap(), &456
     );
-}
\ No newline at end of file
+}
+#[test]
+pub fn tensor() {
+    let mut interpreter = lisp::get_interpreter();
+    let str_exp = "(let [m (tensor [1u32 2u32 3u32 4u32 5u32 6u32] [2u32 3u32])] \
+                     (tensor-sum (tensor-add (transpose m) 1u32) 1u32))";
+    let res = lisp::eval_string(&mut interpreter, str_exp).unwrap();
+    let tensor = res.val().unwrap();
+    let tensor = tensor.tensor().unwrap();
+    assert_eq!(tensor.shape(), &vec![3]);
+    assert_eq!(
+        tensor.owned().data(),
+        &OwnedPrimArray::U32(vec![7, 9, 11])
+    );
+    let str_exp = "(shape (reshape (tensor [1u8 2u8 3u8 4u8] [4u8]) [2u8 2u8]))";
+    assert_eq!(
+        lisp::eval_string(&mut interpreter, str_exp).unwrap(),
+        SExpr::Vec(vec![
+            SExpr::owned_value(OwnedValue::U64(2)),
+            SExpr::owned_value(OwnedValue::U64(2))
+        ])
+    );
+}
+
+#[test]
+pub fn top_k() {
+    let mut interpreter = lisp::get_interpreter();
+    let mut records = vec![];
+    for (name, embedding) in [("a", vec![0f32, 1f32]), ("b", vec![1f32, 0.1f32]), ("c", vec![1f32, 1f32])] {
+        let mut map = OwnedMap::new();
+        map.insert_value("name", name);
+        map.insert_value("embedding", embedding);
+        records.push(OwnedValue::Map(map));
+    }
+    interpreter.bind("records", SExpr::owned_value(OwnedValue::Array(records)));
+    let str_exp = "(top-k 2u8 [1.0f32 0.0f32] records :embedding)";
+    let ranked = lisp::eval_string(&mut interpreter, str_exp).unwrap();
+    let names: Vec<_> = match ranked {
+        SExpr::Vec(records) => records
+            .into_iter()
+            .map(|r| r.owned_val().unwrap()["name"].clone())
+            .collect(),
+        _ => panic!(),
+    };
+    assert_eq!(
+        names,
+        vec![
+            OwnedValue::String("b".to_string()),
+            OwnedValue::String("c".to_string())
+        ]
+    );
+    let str_exp = "(dot [1.0f32 2.0f32] [3.0f32 4.0f32])";
+    assert_eq!(
+        lisp::eval_string(&mut interpreter, str_exp).unwrap(),
+        SExpr::owned_value(OwnedValue::F64(11f64))
+    );
+}
+
+#[test]
+pub fn vectorized() {
+    let mut interpreter = lisp::get_interpreter();
+    let str_exp = "(+ [1u32 2u32] [3u32 4u32])";
+    assert_eq!(
+        lisp::eval_string(&mut interpreter, str_exp).unwrap(),
+        SExpr::owned_value(OwnedValue::PrimArray(OwnedPrimArray::U32(vec![4, 6])))
+    );
+    let str_exp = "(* 2.0f64 (- [1.0f64 2.0f64 3.0f64] 1.0f64))";
+    assert_eq!(
+        lisp::eval_string(&mut interpreter, str_exp).unwrap(),
+        SExpr::owned_value(OwnedValue::PrimArray(OwnedPrimArray::F64(vec![0.0, 2.0, 4.0])))
+    );
+    let str_exp = "(> [1i32 5i32 3i32] 2i32)";
+    assert_eq!(
+        lisp::eval_string(&mut interpreter, str_exp).unwrap(),
+        SExpr::owned_value(OwnedValue::PrimArray(OwnedPrimArray::Bool(vec![false, true, true])))
+    );
+    let str_exp = "(sum (map (lambda [x] (inc x)) (+ [1u32 2u32] 1u32)))";
+    assert_eq!(
+        lisp::eval_string(&mut interpreter, str_exp).unwrap(),
+        SExpr::owned_value(OwnedValue::U32(7))
+    );
+    let str_exp = "(max 3u8 9u8 4u8)";
+    assert_eq!(
+        lisp::eval_string(&mut interpreter, str_exp).unwrap(),
+        SExpr::owned_value(OwnedValue::U8(9))
+    );
+    assert!(lisp::eval_string(&mut interpreter, "(+ [1u32 2u32] [1u32 2u32 3u32])").is_err());
+}
+
+#[test]
+pub fn sketches() {
+    let mut interpreter = lisp::get_interpreter();
+    let str_exp = "(let [a (hll-add (hll 12u8) 1u32 2u32 3u32) \
+                         b (hll-add (hll 12u8) 3u32 4u32)] \
+                     (hll-count (merge a b)))";
+    assert_eq!(
+        lisp::eval_string(&mut interpreter, str_exp).unwrap(),
+        SExpr::owned_value(OwnedValue::U64(4))
+    );
+    for (item, expected) in [("2u32", true), ("100u32", false)] {
+        let str_exp = format!(
+            "(bloom-contains? (bloom-add (bloom 100u32 0.01f64) 1u32 2u32) {})",
+            item
+        );
+        assert_eq!(
+            lisp::eval_string(&mut interpreter, &str_exp).unwrap(),
+            SExpr::owned_value(OwnedValue::Bool(expected))
+        );
+    }
+    let str_exp = "(cms-count (cms-add (count-min 64u32 4u32) 7u8 7u8 8u8) 7u8)";
+    assert_eq!(
+        lisp::eval_string(&mut interpreter, str_exp).unwrap(),
+        SExpr::owned_value(OwnedValue::U64(2))
+    );
+    assert!(lisp::eval_string(&mut interpreter, "(merge (hll 12u8) (hll 10u8))").is_err());
+}
+
+#[test]
+pub fn persistent_collections() {
+    let mut interpreter = lisp::get_interpreter();
+    let bindings = "[base (persistent (hash-map [:x 1u32 :y 2u32])) \
+                     updated (assoc base :x 3u32 :z 4u32)]";
+    for (body, expected) in [
+        ("(get base :x)", OwnedValue::U32(1)),
+        ("(get updated :x)", OwnedValue::U32(3)),
+        ("(get updated :z)", OwnedValue::U32(4)),
+        ("(size (dissoc updated :y))", OwnedValue::U64(2)),
+        ("(get (merge updated base) :x)", OwnedValue::U32(1)),
+        ("(get base :z)", OwnedValue::Null),
+    ] {
+        let str_exp = format!("(let {} {})", bindings, body);
+        assert_eq!(
+            lisp::eval_string(&mut interpreter, &str_exp).unwrap(),
+            SExpr::owned_value(expected)
+        );
+    }
+    let str_exp = "(let [v (persistent (to_array [1u8 2u8]))] (to_vec (assoc (conj v 3u8) 0u8 9u8)))";
+    assert_eq!(
+        lisp::eval_string(&mut interpreter, str_exp).unwrap(),
+        SExpr::Vec(vec![
+            SExpr::owned_value(OwnedValue::U8(9)),
+            SExpr::owned_value(OwnedValue::U8(2)),
+            SExpr::owned_value(OwnedValue::U8(3)),
+        ])
+    );
+    assert!(lisp::eval_string(&mut interpreter, "(assoc (persistent [1u8]) 1u8 2u8)").is_err());
+}
+
+#[test]
+pub fn ids() {
+    let mut interpreter = lisp::get_interpreter();
+    let id = Id::new(1, 255);
+    let str_exp = format!("(= #id\"{}\" #id\"{}\")", id, id.to_base32());
+    assert_eq!(
+        lisp::eval_string(&mut interpreter, &str_exp).unwrap(),
+        SExpr::owned_value(OwnedValue::Bool(true))
+    );
+    assert_eq!(
+        lisp::eval_string(&mut interpreter, "(id-of \"dova\")").unwrap(),
+        SExpr::owned_value(OwnedValue::Id(Id::from_obj(&OwnedValue::String(
+            "dova".to_string()
+        ))))
+    );
+    assert_eq!(
+        lisp::eval_string(&mut interpreter, "(< (new-id) (new-id))").unwrap(),
+        SExpr::owned_value(OwnedValue::Bool(true))
+    );
+    assert!(lisp::eval_string(&mut interpreter, "#id\"xyz\"").is_err());
+    // Only known tags start literals, other names starting with '#' are symbols
+    assert_eq!(
+        lisp::eval_string(&mut interpreter, "(let [#n 2u8 #id 1u8] (+ #n #id))").unwrap(),
+        SExpr::owned_value(OwnedValue::U8(3))
+    );
+}
+
+#[test]
+pub fn linked_records() {
+    let source = Rc::new(MemorySource::new());
+    let ids: Vec<Id> = (1..=4).map(|i| Id::new(0, i)).collect();
+    for (i, id) in ids.iter().enumerate() {
+        let mut record = OwnedMap::new();
+        record.insert_value("name", format!("node-{}", i + 1));
+        // 1 -> 2 -> 3 -> 4, 3 links back to 1
+        let mut edges = vec![OwnedValue::Id(ids[(i + 1) % 4])];
+        if i == 2 {
+            edges.push(OwnedValue::Id(ids[0]));
+        }
+        record.insert("edges", OwnedValue::Array(edges));
+        record.insert("owner", OwnedValue::Id(ids[(i + 1) % 4]));
+        source.insert(*id, OwnedValue::Map(record));
+    }
+    let mut interpreter = lisp::get_interpreter();
+    assert!(lisp::eval_string(&mut interpreter, &format!("(deref #id\"{}\")", ids[0])).is_err());
+    interpreter.set_record_source(source.clone());
+
+    let name_of = |interpreter: &mut _, code: String| match lisp::eval_string(interpreter, &code)
+        .unwrap()
+    {
+        SExpr::Value(Value::Owned(OwnedValue::String(name))) => name,
+        other => panic!("Unexpected {:?}", other),
+    };
+    assert_eq!(
+        name_of(&mut interpreter, format!("(get-in-linked (deref #id\"{}\") [:name])", ids[1])),
+        "node-2"
+    );
+    assert_eq!(
+        name_of(
+            &mut interpreter,
+            format!("(get-in-linked #id\"{}\" [:owner :owner :name])", ids[0])
+        ),
+        "node-3"
+    );
+    let str_exp = format!("(get-in-linked #id\"{}\" [:missing :name])", ids[0]);
+    assert_eq!(
+        lisp::eval_string(&mut interpreter, &str_exp).unwrap(),
+        SExpr::owned_value(OwnedValue::Null)
+    );
+    let str_exp = format!("(size (deref [#id\"{}\" #id\"{}\"]))", ids[0], ids[3]);
+    assert_eq!(
+        lisp::eval_string(&mut interpreter, &str_exp).unwrap(),
+        SExpr::owned_value(OwnedValue::U64(2))
+    );
+    for (depth, expected) in [(0, 1), (1, 2), (2, 3), (5, 4)] {
+        let str_exp = format!("(size (traverse #id\"{}\" :edges {}u8))", ids[0], depth);
+        assert_eq!(
+            lisp::eval_string(&mut interpreter, &str_exp).unwrap(),
+            SExpr::owned_value(OwnedValue::U64(expected))
+        );
+    }
+}
+
+#[test]
+pub fn loop_recur() {
+    let mut interpreter = lisp::get_interpreter();
+    let str_function = "(loop [i 0u64 acc 0u64] (if (= i 20000u64) acc (recur (+ i 1u64) (+ acc i))))";
+    assert_eq!(
+        lisp::eval_string(&mut interpreter, str_function).unwrap(),
+        SExpr::owned_value(OwnedValue::U64(199990000))
+    );
+    let str_function = "(defunc count-down [n] (if (= n 0u64) :done (recur (- n 1u64)))) (count-down 20000u64)";
+    match lisp::eval_string(&mut interpreter, str_function).unwrap() {
+        SExpr::Keyword(_, name) => assert_eq!(name, "done"),
+        other => panic!("Expect keyword, found {:?}", other),
+    }
+    assert!(lisp::eval_string(&mut interpreter, "(loop [i 0u64] (+ 1u64 (recur i)))").is_err());
+}
+
+#[test]
+pub fn tail_calls() {
+    for mut interpreter in vec![lisp::get_interpreter(), lisp::get_lexical_interpreter()] {
+        let str_function = "(defunc even? [n] (if (= n 0u64) 1u8 (odd? (- n 1u64))))\n
+                            (defunc odd? [n] (cond (= n 0u64) 0u8 :else (even? (- n 1u64))))\n
+                            (even? 20001u64)";
+        assert_eq!(
+            lisp::eval_string(&mut interpreter, str_function).unwrap(),
+            SExpr::owned_value(OwnedValue::U8(0))
+        );
+        let str_function = "(defunc total [n acc] (if (= n 0u64) acc (let [m (- n 1u64)] (total m (+ acc n)))))\n
+                            (total 20000u64 0u64)";
+        assert_eq!(
+            lisp::eval_string(&mut interpreter, str_function).unwrap(),
+            SExpr::owned_value(OwnedValue::U64(200010000))
+        );
+    }
+    // Callees see the bindings of their callers whether they are called in tail position
+    // or not
+    let mut interpreter = lisp::get_interpreter();
+    let str_function = "(defunc inner [y] (+ x y))\n
+                        (defunc tail [x] (let [z 1u8] (inner z)))\n
+                        (defunc non-tail [x] (+ 0u8 (inner 1u8)))";
+    lisp::eval_string(&mut interpreter, str_function).unwrap();
+    for call in ["(tail 2u8)", "(non-tail 2u8)"] {
+        assert_eq!(
+            lisp::eval_string(&mut interpreter, call).unwrap(),
+            SExpr::owned_value(OwnedValue::U8(3))
+        );
+    }
+}
+
+#[test]
+pub fn deep_recursion() {
+    let mut interpreter = lisp::get_interpreter();
+    let str_function = "(defunc total [n] (if (= n 0u64) 0u64 (+ n (total (- n 1u64)))))";
+    lisp::eval_string(&mut interpreter, str_function).unwrap();
+    let err = lisp::eval_string(&mut interpreter, "(total 100000u64)").unwrap_err();
+    assert!(matches!(
+        err,
+        DovahkiinError::Limit(LimitExceeded::Depth { .. })
+    ));
+    assert!(err.to_string().starts_with("Evaluation too deep"));
+    // Nothing is left bound after the error
+    assert_eq!(
+        lisp::eval_string(&mut interpreter, "(total 10u64)").unwrap(),
+        SExpr::owned_value(OwnedValue::U64(55))
+    );
+}
+
+#[test]
+pub fn limits() {
+    let mut interpreter = lisp::get_interpreter();
+    let functions = "(defunc forever [x] (forever x)) \
+                     (defunc deeper [x] (+ 1u64 (deeper x))) \
+                     (defunc grow [v] (grow (concat v v))) \
+                     (defunc longer [s] (longer (str s s))) \
+                     (defunc pile [v] (pile (conj v \"0123456789abcdef\")))";
+    lisp::eval_string(&mut interpreter, functions).unwrap();
+    interpreter.set_limits(
+        Limits::new()
+            .steps(10_000)
+            .max_depth(100)
+            .max_size(1_000)
+            .max_bytes(1 << 20),
+    );
+    let mut run = |code| interpreter.eval(lisp::parse_to_sexpr(code).unwrap());
+    assert_eq!(
+        run("(forever 1u8)"),
+        Err(DovahkiinError::Limit(LimitExceeded::Steps(10_000)))
+    );
+    assert!(matches!(
+        run("(deeper 1u8)"),
+        Err(DovahkiinError::Limit(LimitExceeded::Depth { .. }))
+    ));
+    assert_eq!(
+        run("(grow [1u8])"),
+        Err(DovahkiinError::Limit(LimitExceeded::Size {
+            size: 1024,
+            max: 1_000
+        }))
+    );
+    assert!(matches!(
+        run("(longer \"ab\")"),
+        Err(DovahkiinError::Limit(LimitExceeded::Bytes { .. }))
+    ));
+    // Other errors are not limits, and every evaluation has its own steps
+    assert!(matches!(run("(+ 1u8 1u16)"), Err(DovahkiinError::Type { .. })));
+    assert_eq!(
+        run("(loop [i 0u64] (if (< i 1000u64) (recur (+ i 1u64)) i))"),
+        Ok(SExpr::owned_value(OwnedValue::U64(1000)))
+    );
+    // Collections growing an element at a time are caught too
+    interpreter.set_limits(Limits::new().max_bytes(1 << 16));
+    assert!(matches!(
+        interpreter.eval(lisp::parse_to_sexpr("(pile [])").unwrap()),
+        Err(DovahkiinError::Limit(LimitExceeded::Bytes { .. }))
+    ));
+    // Short collections of large values after long ones of small values
+    interpreter.set_limits(Limits::new().max_bytes(1 << 17));
+    let small = vec![SExpr::owned_value(OwnedValue::U8(1)); 500];
+    interpreter.bind("small", SExpr::Vec(small));
+    let large = OwnedValue::String("x".repeat(70_000));
+    interpreter.bind("large", SExpr::owned_value(large));
+    let code = "(do (concat small small) (conj [] large large))";
+    assert!(matches!(
+        interpreter.eval(lisp::parse_to_sexpr(code).unwrap()),
+        Err(DovahkiinError::Limit(LimitExceeded::Bytes { .. }))
+    ));
+
+    interpreter.set_limits(Limits::new().timeout(Duration::from_millis(50)));
+    assert_eq!(
+        interpreter.eval(lisp::parse_to_sexpr("(forever 1u8)").unwrap()),
+        Err(DovahkiinError::Limit(LimitExceeded::Timeout(Duration::from_millis(
+            50
+        ))))
+    );
+
+    interpreter.set_limits(Limits::new());
+    let handle = interpreter.cancel_handle();
+    let canceller = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(50));
+        handle.cancel();
+    });
+    assert_eq!(
+        interpreter.eval(lisp::parse_to_sexpr("(loop [i 0u8] (recur i))").unwrap()),
+        Err(DovahkiinError::Limit(LimitExceeded::Cancelled))
+    );
+    canceller.join().unwrap();
+    interpreter.cancel_handle().reset();
+    assert_eq!(
+        lisp::eval_string(&mut interpreter, "(+ 1u8 2u8)").unwrap(),
+        SExpr::owned_value(OwnedValue::U8(3))
+    );
+}
+
+#[test]
+pub fn errors() {
+    let mut interpreter = lisp::get_interpreter();
+    let mut eval = |code| lisp::eval_string(&mut interpreter, code).unwrap_err();
+    let line = |start, end, column| {
+        Some(Span {
+            start,
+            end,
+            line: 1,
+            column,
+        })
+    };
+    let err = eval("(+ 1u8 \"abc");
+    assert!(matches!(err, DovahkiinError::Lex { .. }));
+    assert_eq!(err.span(), line(11, 11, 12));
+    let err = eval("(+ 1u8 2u8");
+    assert!(matches!(err, DovahkiinError::Parse { .. }));
+    assert_eq!(err.span(), line(0, 1, 1));
+    assert_eq!(
+        eval("(frobnicate 1u8)"),
+        DovahkiinError::UnboundSymbol {
+            name: "frobnicate".to_string(),
+            span: line(0, 16, 1)
+        }
+    );
+    assert_eq!(
+        eval("(if 1u8)"),
+        DovahkiinError::Arity {
+            expected: Arity::AtLeast(2),
+            found: 1,
+            span: line(0, 8, 1)
+        }
+    );
+    assert_eq!(
+        eval("(< 1u8 2u16)"),
+        DovahkiinError::Type {
+            expected: Type::U8.into(),
+            found: Type::U16,
+            span: line(0, 12, 1)
+        }
+    );
+    let err = eval("(concat 1u8 [2u8])");
+    assert!(matches!(err, DovahkiinError::Runtime { .. }));
+    assert!(matches!(
+        eval("(shape 1u8)"),
+        DovahkiinError::Type {
+            expected: Expected::Tensor,
+            found: Type::U8,
+            ..
+        }
+    ));
+    assert!(matches!(
+        eval("((lambda [x] x) 1u8 2u8)"),
+        DovahkiinError::Arity {
+            expected: Arity::AtMost(1),
+            found: 2,
+            ..
+        }
+    ));
+    let err: Box<dyn std::error::Error> = Box::new(eval("(< 1u8 2u16)"));
+    assert_eq!(
+        err.to_string(),
+        "Type not match, expect U8 found U16 at line 1, column 1"
+    );
+}
+
+#[test]
+pub fn spans() {
+    let tokens = lexer::tokenize_str("(+ 1u8\n  \"é\")").unwrap();
+    let spans: Vec<_> = tokens
+        .iter()
+        .map(|(_, span)| (span.start, span.end, span.line, span.column))
+        .collect();
+    assert_eq!(
+        spans,
+        vec![(0, 1, 1, 1), (1, 2, 1, 2), (3, 6, 1, 4), (9, 13, 2, 3), (13, 14, 2, 6)]
+    );
+
+    let mut interpreter = lisp::get_interpreter();
+    let source = "(defunc half [n]\n  (/ n 2u8))\n(half\n  (+ 1u8 2u16))";
+    let err = lisp::eval_string(&mut interpreter, source).unwrap_err();
+    assert_eq!(
+        err.report(source),
+        "Type not match, expect U8 found U16 at line 4, column 3\n  \
+         |\n4 |   (+ 1u8 2u16))\n  |   ^^^^^^^^^^^^"
+    );
+    // Forms in tail position have their spans too
+    let source = "(defunc inc [x]\n  (if x (+ x 1u16) x))\n(inc 1u8)";
+    let err = lisp::eval_string(&mut interpreter, source).unwrap_err();
+    assert_eq!(err.span().map(|span| (span.line, span.column)), Some((2, 9)));
+    assert!(err.report(source).ends_with("(if x (+ x 1u16) x))\n  |         ^^^^^^^^^^"));
+
+    // Lists parsed into serializable expressions keep their spans, and they don't take part
+    // in equality
+    let parsed = lisp::parse_to_serde_expr("(+ 1u8 2u8)").unwrap();
+    assert!(matches!(&parsed[0], Expr::List(_, Some(_))));
+    let code = lisp::parse_to_sexpr("(+ 1u8 2u8)").unwrap();
+    let moved = lisp::parse_to_sexpr("\n  (+ 1u8 2u8)").unwrap();
+    assert_eq!(code, moved);
+}
+
+#[test]
+pub fn encoded_sizes() {
+    let source = "(defunc f [x] [x :a (+ x 1u8)])";
+    let parsed = lisp::parse_to_serde_expr(source).unwrap();
+    let code = lisp::parse_to_sexpr(source).unwrap();
+    assert_eq!(parsed[0].encoded_size(), code[0].encoded_size());
+    // A list is its tag and length followed by its items
+    let items = lisp::parse_to_serde_expr("1u8 x").unwrap();
+    let list = lisp::parse_to_serde_expr("(1u8 x)").unwrap();
+    assert_eq!(
+        list[0].encoded_size(),
+        4 + 8 + items.iter().map(Expr::encoded_size).sum::<usize>()
+    );
+}